@@ -1,6 +1,6 @@
-use bytes::BufMut;
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use serde::{Deserialize, Serialize};
+use serde::{de::IgnoredAny, Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct A<'a> {
@@ -45,6 +45,30 @@ fn benchmark(c: &mut Criterion) {
     c.bench_function("deserialize: serde_bson", |b| {
         b.iter(|| serde_bson::de::from_bytes::<A>(black_box(data)));
     });
+
+    c.bench_function("deserialize: serde_bson, streaming", |b| {
+        b.iter(|| serde_bson::de::from_bytes_streaming::<A>(black_box(data)));
+    });
+
+    let strings = (0..1_000)
+        .map(|i| {
+            (
+                i.to_string(),
+                "lorem ipsum dolor sit amet ".repeat(i % 50 + 1),
+            )
+        })
+        .collect::<BTreeMap<_, _>>();
+    let data = serde_bson::to_bytes(&strings).unwrap();
+
+    c.bench_function("deserialize strings: serde_bson", |b| {
+        b.iter(|| serde_bson::de::from_bytes::<IgnoredAny>(black_box(&data)));
+    });
+
+    // SAFETY: the input was just written by us
+    let options = unsafe { serde_bson::de::Options::new().assume_valid_utf8(true) };
+    c.bench_function("deserialize strings: serde_bson, assume valid utf-8", |b| {
+        b.iter(|| serde_bson::de::from_bytes_with_options::<IgnoredAny>(black_box(&data), options));
+    });
 }
 
 criterion_group!(benches, benchmark);
@@ -1,4 +1,3 @@
-use bytes::BufMut;
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
 #[derive(serde::Serialize)]
@@ -12,6 +11,7 @@ pub struct A {
     f: Vec<u8>,
 }
 
+#[allow(clippy::excessive_precision)]
 fn benchmark(c: &mut Criterion) {
     let val = A {
         a: "Now this is a story all about how
@@ -56,5 +56,37 @@ fn benchmark(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, benchmark);
+fn benchmark_array(c: &mut Criterion) {
+    #[derive(serde::Serialize)]
+    pub struct A {
+        a: Vec<i32>,
+    }
+
+    let val = A {
+        a: (0..1000).collect(),
+    };
+
+    c.bench_function("serialize array: mongodb's bson", |b| {
+        let mut theirs = Vec::new();
+
+        b.iter(|| {
+            bson::ser::to_document(black_box(&val))
+                .unwrap()
+                .to_writer(&mut theirs)
+                .unwrap();
+            theirs.clear();
+        })
+    });
+
+    c.bench_function("serialize array: serde_bson", |b| {
+        let mut out = bytes::BytesMut::new();
+
+        b.iter(|| {
+            serde_bson::to_string(black_box(&val), &mut out).unwrap();
+            drop(out.split());
+        });
+    });
+}
+
+criterion_group!(benches, benchmark, benchmark_array);
 criterion_main!(benches);
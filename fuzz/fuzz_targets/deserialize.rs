@@ -0,0 +1,26 @@
+//! Reads arbitrary input every way it can be read. None of them should panic, and anything
+//! [`de::validate`] accepts should read as a document.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use serde_bson::{de, debug, Document, Value};
+
+fuzz_target!(|data: &[u8]| {
+    let valid = de::validate(data).is_ok();
+
+    let _ = de::from_bytes::<Value>(data);
+    let _ = de::from_bytes_with_options::<Value>(
+        data,
+        de::Options::default().reject_duplicate_keys(true),
+    );
+    let _ = de::project(data, &["a", "a.b", "a.0"]);
+    let _ = debug::dump(data);
+    let _ = debug::hex_dump(data);
+
+    let document = Document::from_bytes(data);
+    assert!(!valid || document.is_ok(), "valid input should read as a document");
+    if let Ok(document) = document {
+        let _ = document.to_vec();
+    }
+});
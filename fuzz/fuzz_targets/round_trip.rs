@@ -0,0 +1,13 @@
+//! Writes arbitrary documents and checks they read back exactly as they were.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use serde_bson::{de, Document};
+
+fuzz_target!(|document: Document| {
+    let encoded = document.to_vec().expect("documents always serialise");
+
+    de::validate(&encoded).expect("serialised documents are valid");
+    assert_eq!(Document::from_bytes(&encoded).unwrap(), document);
+});
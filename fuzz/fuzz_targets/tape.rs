@@ -0,0 +1,12 @@
+//! Tokenises arbitrary input, which should only ever fail with an error.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use serde_bson::{bumpalo, de};
+
+fuzz_target!(|data: &[u8]| {
+    let bump = bumpalo::Bump::new();
+    let mut tape = bumpalo::collections::Vec::new_in(&bump);
+    let _ = de::to_tape(data, &mut tape, de::Options::default());
+});
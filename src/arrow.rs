@@ -0,0 +1,374 @@
+//! Conversion between documents and Apache Arrow record batches, for handing bson data such as
+//! `mongodump` output to columnar engines.
+//!
+//! Documents are read by walking their raw elements straight into column builders, without
+//! deserialising each into an intermediate struct. Only flat schemas are supported, with each
+//! field mapping to a top-level key:
+//!
+//! | Arrow type                     | bson type                            |
+//! |--------------------------------|--------------------------------------|
+//! | `Boolean`                      | boolean                              |
+//! | `Int32`                        | int32                                |
+//! | `Int64`                        | int64, or int32 when reading         |
+//! | `Float64`                      | double, or int32/int64 when reading  |
+//! | `Utf8`                         | string                               |
+//! | `Binary`                       | binary, of any subtype when reading  |
+//! | `Timestamp(Millisecond, _)`    | datetime                             |
+//! | `FixedSizeBinary(12)`          | ObjectId                             |
+//!
+//! Missing keys and bson nulls are read as Arrow nulls, and Arrow nulls are left out of the
+//! documents written.
+
+use crate::{de, raw::RawBytes, scan::Elements, RawBson};
+use arrow_array::{
+    builder::{
+        BinaryBuilder, BooleanBuilder, FixedSizeBinaryBuilder, Float64Builder, Int32Builder,
+        Int64Builder, StringBuilder, TimestampMillisecondBuilder,
+    },
+    cast::AsArray,
+    types::{Float64Type, Int32Type, Int64Type, TimestampMillisecondType},
+    Array, ArrayRef, RecordBatch,
+};
+use arrow_schema::{ArrowError, DataType, SchemaRef, TimeUnit};
+use bytes::Bytes;
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use std::{convert::TryInto, sync::Arc};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Read(#[from] de::Error),
+    #[error(transparent)]
+    Write(#[from] crate::Error),
+    #[error(transparent)]
+    Arrow(#[from] ArrowError),
+    #[error("arrow type {1} of field {0:?} has no bson equivalent")]
+    UnsupportedType(String, DataType),
+    #[error("element type {element_type:#04x} can't be read into field {field:?} in document {document}")]
+    TypeMismatch {
+        field: String,
+        element_type: u8,
+        document: usize,
+    },
+}
+
+/// Reads `documents` into a single record batch with the given schema. Keys that aren't in the
+/// schema are ignored.
+pub fn to_record_batch<'a, I>(documents: I, schema: SchemaRef) -> Result<RecordBatch, Error>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    let mut columns = schema
+        .fields()
+        .iter()
+        .map(|field| Column::new(field.name(), field.data_type()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut values = vec![None; columns.len()];
+
+    for (document, data) in documents.into_iter().enumerate() {
+        for element in Elements::new(data, 0)? {
+            let element = element?;
+
+            if let Some((i, _)) = schema.fields().find(element.key) {
+                values[i] = Some((element.value, element.value_offset));
+            }
+        }
+
+        for (i, column) in columns.iter_mut().enumerate() {
+            let value = values[i].take();
+
+            if !column.append(value)? {
+                return Err(Error::TypeMismatch {
+                    field: schema.field(i).name().clone(),
+                    element_type: value.map_or(0x0A, |(value, _)| value.element_type()),
+                    document,
+                });
+            }
+        }
+    }
+
+    let columns = columns.into_iter().map(Column::finish).collect();
+    Ok(RecordBatch::try_new(schema, columns)?)
+}
+
+/// Writes each row of `batch` out as a document.
+pub fn from_record_batch(batch: &RecordBatch) -> Result<Vec<Bytes>, Error> {
+    let schema = batch.schema();
+
+    for field in schema.fields() {
+        if !writable(field.data_type()) {
+            return Err(Error::UnsupportedType(
+                field.name().clone(),
+                field.data_type().clone(),
+            ));
+        }
+    }
+
+    (0..batch.num_rows())
+        .map(|row| Ok(crate::to_bytes(&Row { batch, row })?))
+        .collect()
+}
+
+fn writable(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Boolean
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::Float64
+            | DataType::Utf8
+            | DataType::Binary
+            | DataType::Timestamp(TimeUnit::Millisecond, _)
+            | DataType::FixedSizeBinary(12)
+    )
+}
+
+enum Column {
+    Boolean(BooleanBuilder),
+    Int32(Int32Builder),
+    Int64(Int64Builder),
+    Float64(Float64Builder),
+    Utf8(StringBuilder),
+    Binary(BinaryBuilder),
+    DateTime(TimestampMillisecondBuilder),
+    ObjectId(FixedSizeBinaryBuilder),
+}
+
+impl Column {
+    fn new(name: &str, data_type: &DataType) -> Result<Self, Error> {
+        Ok(match data_type {
+            DataType::Boolean => Self::Boolean(BooleanBuilder::new()),
+            DataType::Int32 => Self::Int32(Int32Builder::new()),
+            DataType::Int64 => Self::Int64(Int64Builder::new()),
+            DataType::Float64 => Self::Float64(Float64Builder::new()),
+            DataType::Utf8 => Self::Utf8(StringBuilder::new()),
+            DataType::Binary => Self::Binary(BinaryBuilder::new()),
+            DataType::Timestamp(TimeUnit::Millisecond, timezone) => Self::DateTime(
+                TimestampMillisecondBuilder::new().with_timezone_opt(timezone.clone()),
+            ),
+            DataType::FixedSizeBinary(12) => Self::ObjectId(FixedSizeBinaryBuilder::new(12)),
+            other => return Err(Error::UnsupportedType(name.to_string(), other.clone())),
+        })
+    }
+
+    /// Appends `value`, found at `offset` in the input, to the column. Returns `false` if it's
+    /// of a type that can't be stored in the column.
+    fn append(&mut self, value: Option<(RawBson<'_>, usize)>) -> Result<bool, de::Error> {
+        let (value, offset) = match value {
+            Some((value, offset)) if value.element_type() != 0x0A => (value, offset),
+            _ => {
+                self.append_null();
+                return Ok(true);
+            }
+        };
+
+        let bytes = value.as_bytes();
+
+        match (self, value.element_type()) {
+            (Self::Boolean(builder), 0x08) => builder.append_value(bytes[0] != 0),
+            (Self::Int32(builder), 0x10) => builder.append_value(i32_at(bytes)),
+            (Self::Int64(builder), 0x10) => builder.append_value(i64::from(i32_at(bytes))),
+            (Self::Int64(builder), 0x12) => builder.append_value(i64_at(bytes)),
+            (Self::Float64(builder), 0x01) => {
+                builder.append_value(f64::from_le_bytes(bytes.try_into().unwrap()))
+            }
+            (Self::Float64(builder), 0x10) => builder.append_value(f64::from(i32_at(bytes))),
+            (Self::Float64(builder), 0x12) => builder.append_value(i64_at(bytes) as f64),
+            (Self::Utf8(builder), 0x02) => {
+                // scanning doesn't look inside values, so this is the first time it's checked
+                let string = crate::utf8::from_utf8(&bytes[4..bytes.len() - 1])
+                    .map_err(|_| de::Error::InvalidUtf8(offset + 4))?;
+                builder.append_value(string);
+            }
+            (Self::Binary(builder), 0x05) => builder.append_value(&bytes[5..]),
+            (Self::DateTime(builder), 0x09) => builder.append_value(i64_at(bytes)),
+            (Self::ObjectId(builder), 0x07) => builder.append_value(bytes).unwrap(),
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    fn append_null(&mut self) {
+        match self {
+            Self::Boolean(builder) => builder.append_null(),
+            Self::Int32(builder) => builder.append_null(),
+            Self::Int64(builder) => builder.append_null(),
+            Self::Float64(builder) => builder.append_null(),
+            Self::Utf8(builder) => builder.append_null(),
+            Self::Binary(builder) => builder.append_null(),
+            Self::DateTime(builder) => builder.append_null(),
+            Self::ObjectId(builder) => builder.append_null(),
+        }
+    }
+
+    fn finish(self) -> ArrayRef {
+        match self {
+            Self::Boolean(mut builder) => Arc::new(builder.finish()),
+            Self::Int32(mut builder) => Arc::new(builder.finish()),
+            Self::Int64(mut builder) => Arc::new(builder.finish()),
+            Self::Float64(mut builder) => Arc::new(builder.finish()),
+            Self::Utf8(mut builder) => Arc::new(builder.finish()),
+            Self::Binary(mut builder) => Arc::new(builder.finish()),
+            Self::DateTime(mut builder) => Arc::new(builder.finish()),
+            Self::ObjectId(mut builder) => Arc::new(builder.finish()),
+        }
+    }
+}
+
+fn i32_at(bytes: &[u8]) -> i32 {
+    i32::from_le_bytes(bytes.try_into().unwrap())
+}
+
+fn i64_at(bytes: &[u8]) -> i64 {
+    i64::from_le_bytes(bytes.try_into().unwrap())
+}
+
+/// A single row of a record batch, serialised as a document. Column types are checked up front
+/// by [`from_record_batch`].
+struct Row<'a> {
+    batch: &'a RecordBatch,
+    row: usize,
+}
+
+impl Serialize for Row<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let schema = self.batch.schema();
+        let row = self.row;
+        let mut map = serializer.serialize_map(None)?;
+
+        for (field, column) in schema.fields().iter().zip(self.batch.columns()) {
+            if column.is_null(row) {
+                continue;
+            }
+
+            let key = field.name().as_str();
+
+            match field.data_type() {
+                DataType::Boolean => map.serialize_entry(key, &column.as_boolean().value(row))?,
+                DataType::Int32 => {
+                    map.serialize_entry(key, &column.as_primitive::<Int32Type>().value(row))?
+                }
+                DataType::Int64 => {
+                    map.serialize_entry(key, &column.as_primitive::<Int64Type>().value(row))?
+                }
+                DataType::Float64 => {
+                    map.serialize_entry(key, &column.as_primitive::<Float64Type>().value(row))?
+                }
+                DataType::Utf8 => map.serialize_entry(key, column.as_string::<i32>().value(row))?,
+                DataType::Binary => {
+                    map.serialize_entry(key, &RawBytes(column.as_binary::<i32>().value(row)))?
+                }
+                DataType::Timestamp(..) => {
+                    let millis = column
+                        .as_primitive::<TimestampMillisecondType>()
+                        .value(row)
+                        .to_le_bytes();
+                    map.serialize_entry(key, &RawBson::new(0x09, &millis))?
+                }
+                DataType::FixedSizeBinary(_) => map.serialize_entry(
+                    key,
+                    &RawBson::new(0x07, column.as_fixed_size_binary().value(row)),
+                )?,
+                _ => unreachable!("column types are checked before serialising"),
+            }
+        }
+
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use arrow_array::{
+        cast::AsArray,
+        types::{Float64Type, Int64Type},
+        Array,
+    };
+    use arrow_schema::{DataType, Field, Schema, TimeUnit};
+    use std::sync::Arc;
+
+    #[test]
+    fn round_trip() {
+        let documents = [
+            bson::doc! {
+                "name": "a",
+                "count": 1,
+                "score": 1.5,
+                "when": bson::DateTime::from_millis(1000),
+                "id": bson::oid::ObjectId::from_bytes([1; 12]),
+                "data": bson::Binary { subtype: bson::spec::BinarySubtype::Generic, bytes: vec![1, 2] },
+                "active": true,
+                "ignored": [1, 2, 3],
+            },
+            bson::doc! { "name": "b", "count": 2_i64, "score": 3, "active": bson::Bson::Null },
+        ];
+        let encoded = documents
+            .iter()
+            .map(|doc| bson::to_vec(doc).unwrap())
+            .collect::<Vec<_>>();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("name", DataType::Utf8, false),
+            Field::new("count", DataType::Int64, false),
+            Field::new("score", DataType::Float64, false),
+            Field::new(
+                "when",
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                true,
+            ),
+            Field::new("id", DataType::FixedSizeBinary(12), true),
+            Field::new("data", DataType::Binary, true),
+            Field::new("active", DataType::Boolean, true),
+        ]));
+
+        let batch =
+            super::to_record_batch(encoded.iter().map(Vec::as_slice), schema.clone()).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(
+            batch.column(1).as_primitive::<Int64Type>().values(),
+            &[1, 2]
+        );
+        assert_eq!(
+            batch.column(2).as_primitive::<Float64Type>().values(),
+            &[1.5, 3.0]
+        );
+        assert!(batch.column(3).is_null(1));
+        assert!(batch.column(6).is_null(1));
+
+        let written = super::from_record_batch(&batch).unwrap();
+        assert_eq!(
+            bson::Document::from_reader(&written[0][..]).unwrap(),
+            bson::doc! {
+                "name": "a",
+                "count": 1_i64,
+                "score": 1.5,
+                "when": bson::DateTime::from_millis(1000),
+                "id": bson::oid::ObjectId::from_bytes([1; 12]),
+                "data": bson::Binary { subtype: bson::spec::BinarySubtype::Generic, bytes: vec![1, 2] },
+                "active": true,
+            }
+        );
+        assert_eq!(
+            bson::Document::from_reader(&written[1][..]).unwrap(),
+            bson::doc! { "name": "b", "count": 2_i64, "score": 3.0 }
+        );
+
+        let mismatch = bson::to_vec(&bson::doc! { "name": 1, "count": 1, "score": 1.0 }).unwrap();
+        let error = super::to_record_batch(std::iter::once(&mismatch[..]), schema).unwrap_err();
+        assert!(matches!(
+            error,
+            super::Error::TypeMismatch {
+                element_type: 0x10,
+                document: 0,
+                ..
+            }
+        ));
+
+        let unsupported = Arc::new(Schema::new(vec![Field::new("a", DataType::Date32, true)]));
+        assert!(matches!(
+            super::to_record_batch(std::iter::empty(), unsupported),
+            Err(super::Error::UnsupportedType(..))
+        ));
+    }
+}
@@ -1,17 +1,39 @@
+use alloc::vec::Vec;
 use bytes::{BufMut, BytesMut};
 
+/// An output sink the serialiser can write to, implemented for `BytesMut` and `Vec<u8>` out of the
+/// box. Implement this to serialise straight into your own buffer types with [`crate::to_buf`].
+///
+/// Documents in bson are prefixed with their length, which we don't know until the whole
+/// document has been written. A placeholder is written in its place and the offset of the
+/// document is kept hold of, once the document is complete its length is patched in using
+/// [`BytesLikeBuf::byte_mut`].
 pub trait BytesLikeBuf {
-    type Out: BytesLikeBuf;
-
     fn put_u8(&mut self, v: u8);
     fn put_i32_le(&mut self, v: i32);
     fn put_i64_le(&mut self, v: i64);
     fn put_f64_le(&mut self, v: f64);
     fn put_slice(&mut self, s: &[u8]);
-    fn split_off(&mut self, at: usize) -> Self::Out;
-    fn unsplit(&mut self, other: Self::Out);
+    /// The number of bytes written to the buffer.
     fn len(&mut self) -> usize;
+    fn is_empty(&mut self) -> bool {
+        self.len() == 0
+    }
+    /// A reference to a byte that's already been written, used to back-patch lengths and
+    /// element types.
     fn byte_mut(&mut self, at: usize) -> &mut u8;
+    /// Everything written to the buffer so far, or `None` if the buffer doesn't retain what's
+    /// written to it. Serialising with [`crate::ser::Options::sort_keys`] reorders documents
+    /// through this, and fails with [`crate::Error::UnsortableOutput`] if it returns `None`.
+    fn as_mut_slice(&mut self) -> Option<&mut [u8]>;
+    /// Whether the buffer deliberately drops what's written to it, such as when only counting
+    /// the bytes, in which case there's nothing to reorder for `sort_keys` either.
+    #[doc(hidden)]
+    fn discards_output(&self) -> bool {
+        false
+    }
+    /// Hints that at least `additional` more bytes are about to be written.
+    fn reserve(&mut self, _additional: usize) {}
 }
 
 macro_rules! deref_impl {
@@ -21,8 +43,6 @@ macro_rules! deref_impl {
         }
     ) => {
         impl $trait for $ty {
-            type Out = $ty;
-
             $(
                 fn $func(&mut self, $($param_name$(: $param_ty)?,)*)$( -> $ret)? {
                     <Self$( as $deref)?>::$func(self, $($param_name,)*)
@@ -32,6 +52,10 @@ macro_rules! deref_impl {
             fn byte_mut(&mut self, at: usize) -> &mut u8 {
                 &mut self[at]
             }
+
+            fn as_mut_slice(&mut self) -> Option<&mut [u8]> {
+                Some(&mut self[..])
+            }
         }
     };
 }
@@ -43,15 +67,89 @@ deref_impl!(
         fn put_i64_le(&mut self, v: i64) where Self: BufMut;
         fn put_f64_le(&mut self, v: f64) where Self: BufMut;
         fn put_slice(&mut self, s: &[u8]) where Self: BufMut;
-        fn split_off(&mut self, at: usize) -> BytesMut;
-        fn unsplit(&mut self, other: Self);
         fn len(&mut self,) -> usize;
+        fn reserve(&mut self, additional: usize);
     }
 );
 
-impl<B: BytesLikeBuf> BytesLikeBuf for &mut B {
-    type Out = <B as BytesLikeBuf>::Out;
+impl BytesLikeBuf for Vec<u8> {
+    fn put_u8(&mut self, v: u8) {
+        self.push(v);
+    }
+
+    fn put_i32_le(&mut self, v: i32) {
+        self.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn put_i64_le(&mut self, v: i64) {
+        self.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn put_f64_le(&mut self, v: f64) {
+        self.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn put_slice(&mut self, s: &[u8]) {
+        self.extend_from_slice(s);
+    }
 
+    fn len(&mut self) -> usize {
+        Vec::len(self)
+    }
+
+    fn byte_mut(&mut self, at: usize) -> &mut u8 {
+        &mut self[at]
+    }
+
+    fn as_mut_slice(&mut self) -> Option<&mut [u8]> {
+        Some(self)
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        Vec::reserve(self, additional)
+    }
+}
+
+#[cfg(feature = "smallvec")]
+impl<A: smallvec::Array<Item = u8>> BytesLikeBuf for smallvec::SmallVec<A> {
+    fn put_u8(&mut self, v: u8) {
+        self.push(v);
+    }
+
+    fn put_i32_le(&mut self, v: i32) {
+        self.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn put_i64_le(&mut self, v: i64) {
+        self.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn put_f64_le(&mut self, v: f64) {
+        self.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn put_slice(&mut self, s: &[u8]) {
+        self.extend_from_slice(s);
+    }
+
+    fn len(&mut self) -> usize {
+        smallvec::SmallVec::len(self)
+    }
+
+    fn byte_mut(&mut self, at: usize) -> &mut u8 {
+        &mut self[at]
+    }
+
+    fn as_mut_slice(&mut self) -> Option<&mut [u8]> {
+        Some(self)
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        smallvec::SmallVec::reserve(self, additional)
+    }
+}
+
+impl<B: BytesLikeBuf> BytesLikeBuf for &mut B {
     fn put_u8(&mut self, v: u8) {
         B::put_u8(self, v)
     }
@@ -72,20 +170,195 @@ impl<B: BytesLikeBuf> BytesLikeBuf for &mut B {
         B::put_slice(self, s)
     }
 
-    fn split_off(&mut self, at: usize) -> Self::Out {
-        B::split_off(self, at)
+    fn len(&mut self) -> usize {
+        B::len(self)
+    }
+
+    fn byte_mut(&mut self, at: usize) -> &mut u8 {
+        B::byte_mut(self, at)
+    }
+
+    fn as_mut_slice(&mut self) -> Option<&mut [u8]> {
+        B::as_mut_slice(self)
+    }
+
+    fn discards_output(&self) -> bool {
+        B::discards_output(self)
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        B::reserve(self, additional)
+    }
+}
+
+/// Writes into a fixed-size, caller-provided slice rather than allocating, see
+/// [`crate::to_slice`]. Writes past the end of the slice are dropped and flagged rather than
+/// panicking.
+pub struct SliceBuf<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    overflowed: bool,
+    fake_byte: u8,
+}
+
+impl<'a> SliceBuf<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self {
+            buf,
+            len: 0,
+            overflowed: false,
+            fake_byte: 0,
+        }
+    }
+
+    /// The number of bytes written, or that would've been written had the slice been big enough.
+    pub fn written(&self) -> usize {
+        self.len
     }
 
-    fn unsplit(&mut self, other: Self::Out) {
-        B::unsplit(self, other)
+    /// Whether any writes have been dropped due to the slice being too small.
+    pub fn overflowed(&self) -> bool {
+        self.overflowed
+    }
+
+    fn put(&mut self, v: &[u8]) {
+        let at = self.len;
+
+        match self.buf.get_mut(at..at + v.len()) {
+            Some(dst) if !self.overflowed => dst.copy_from_slice(v),
+            _ => self.overflowed = true,
+        }
+
+        self.len += v.len();
+    }
+}
+
+impl<'a> BytesLikeBuf for SliceBuf<'a> {
+    fn put_u8(&mut self, v: u8) {
+        self.put(&[v]);
+    }
+
+    fn put_i32_le(&mut self, v: i32) {
+        self.put(&v.to_le_bytes());
+    }
+
+    fn put_i64_le(&mut self, v: i64) {
+        self.put(&v.to_le_bytes());
+    }
+
+    fn put_f64_le(&mut self, v: f64) {
+        self.put(&v.to_le_bytes());
+    }
+
+    fn put_slice(&mut self, s: &[u8]) {
+        self.put(s);
     }
 
     fn len(&mut self) -> usize {
-        B::len(self)
+        self.len
     }
 
     fn byte_mut(&mut self, at: usize) -> &mut u8 {
-        B::byte_mut(self, at)
+        if at < self.buf.len() {
+            &mut self.buf[at]
+        } else {
+            self.fake_byte = 0;
+            &mut self.fake_byte
+        }
+    }
+
+    fn as_mut_slice(&mut self) -> Option<&mut [u8]> {
+        if self.overflowed {
+            None
+        } else {
+            Some(&mut self.buf[..self.len])
+        }
+    }
+
+    fn discards_output(&self) -> bool {
+        // the output is thrown away with `BufferTooSmall` once the slice has filled up
+        self.overflowed
+    }
+}
+
+/// Keeps only the bytes written between `start` and the end of `window`, dropping everything
+/// either side of it. Back-patches landing in the window are applied as usual, so a full pass
+/// of the serialiser leaves the window holding exactly that stretch of the output.
+#[cfg(feature = "std")]
+pub(crate) struct WindowBuf<'a> {
+    start: usize,
+    window: &'a mut [u8],
+    len: usize,
+    fake_byte: u8,
+}
+
+#[cfg(feature = "std")]
+impl<'a> WindowBuf<'a> {
+    pub fn new(start: usize, window: &'a mut [u8]) -> Self {
+        Self {
+            start,
+            window,
+            len: 0,
+            fake_byte: 0,
+        }
+    }
+
+    fn put(&mut self, v: &[u8]) {
+        let end = self.start + self.window.len();
+        let from = self.len.max(self.start);
+        let to = (self.len + v.len()).min(end);
+
+        if from < to {
+            self.window[from - self.start..to - self.start]
+                .copy_from_slice(&v[from - self.len..to - self.len]);
+        }
+
+        self.len += v.len();
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a> BytesLikeBuf for WindowBuf<'a> {
+    fn put_u8(&mut self, v: u8) {
+        self.put(&[v]);
+    }
+
+    fn put_i32_le(&mut self, v: i32) {
+        self.put(&v.to_le_bytes());
+    }
+
+    fn put_i64_le(&mut self, v: i64) {
+        self.put(&v.to_le_bytes());
+    }
+
+    fn put_f64_le(&mut self, v: f64) {
+        self.put(&v.to_le_bytes());
+    }
+
+    fn put_slice(&mut self, s: &[u8]) {
+        self.put(s);
+    }
+
+    fn len(&mut self) -> usize {
+        self.len
+    }
+
+    fn byte_mut(&mut self, at: usize) -> &mut u8 {
+        match at.checked_sub(self.start) {
+            Some(at) if at < self.window.len() => &mut self.window[at],
+            _ => {
+                self.fake_byte = 0;
+                &mut self.fake_byte
+            }
+        }
+    }
+
+    fn as_mut_slice(&mut self) -> Option<&mut [u8]> {
+        None
+    }
+
+    fn discards_output(&self) -> bool {
+        true
     }
 }
 
@@ -96,37 +369,24 @@ pub struct CountingBytes {
 }
 
 impl BytesLikeBuf for CountingBytes {
-    type Out = CountingBytes;
-
     fn put_u8(&mut self, _v: u8) {
-        self.bytes += std::mem::size_of::<u8>();
+        self.bytes += core::mem::size_of::<u8>();
     }
 
     fn put_i32_le(&mut self, _v: i32) {
-        self.bytes += std::mem::size_of::<i32>();
+        self.bytes += core::mem::size_of::<i32>();
     }
 
     fn put_i64_le(&mut self, _v: i64) {
-        self.bytes += std::mem::size_of::<i64>();
+        self.bytes += core::mem::size_of::<i64>();
     }
 
     fn put_f64_le(&mut self, _v: f64) {
-        self.bytes += std::mem::size_of::<f64>();
+        self.bytes += core::mem::size_of::<f64>();
     }
 
     fn put_slice(&mut self, s: &[u8]) {
-        self.bytes += std::mem::size_of_val(s);
-    }
-
-    fn split_off(&mut self, _at: usize) -> Self {
-        CountingBytes {
-            bytes: 0,
-            fake_byte: 0,
-        }
-    }
-
-    fn unsplit(&mut self, other: Self) {
-        self.bytes += other.bytes;
+        self.bytes += core::mem::size_of_val(s);
     }
 
     fn len(&mut self) -> usize {
@@ -137,4 +397,12 @@ impl BytesLikeBuf for CountingBytes {
         self.fake_byte = 0;
         &mut self.fake_byte
     }
+
+    fn as_mut_slice(&mut self) -> Option<&mut [u8]> {
+        None
+    }
+
+    fn discards_output(&self) -> bool {
+        true
+    }
 }
@@ -0,0 +1,135 @@
+use crate::{ser, serialised_size_of_with_options, Error};
+use bytes::BytesMut;
+use serde::Serialize;
+use std::{any::TypeId, collections::HashMap};
+
+/// Remembers the serialised size of each type it's seen, so hot loops serialising values of the
+/// same shape can skip the counting pass [`crate::to_string`] does up front and reserve the
+/// last observed size instead.
+///
+/// If a value turns out to be bigger than the last one the output buffer simply grows as it's
+/// written to, so a bad guess costs an extra allocation rather than an error.
+#[derive(Default, Debug)]
+pub struct SizeCache {
+    sizes: HashMap<TypeId, usize>,
+}
+
+impl SizeCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn to_string<T: Serialize + 'static>(
+        &mut self,
+        val: &T,
+        output: &mut BytesMut,
+    ) -> Result<(), Error> {
+        self.to_string_with_options(val, output, ser::Options::default())
+    }
+
+    pub fn to_string_with_options<T: Serialize + 'static>(
+        &mut self,
+        val: &T,
+        output: &mut BytesMut,
+        options: ser::Options,
+    ) -> Result<(), Error> {
+        let size_hint = match self.sizes.get(&TypeId::of::<T>()) {
+            Some(size) => *size,
+            None => serialised_size_of_with_options(val, options)?,
+        };
+
+        output.reserve(size_hint);
+        let start = output.len();
+
+        let res = val.serialize(ser::Serializer {
+            key: None,
+            output: &mut *output,
+            options,
+        });
+
+        if res.is_err() {
+            // we may have skipped the counting pass which would've caught the error before we
+            // wrote anything, so we'll need to clean up after ourselves
+            output.truncate(start);
+        } else {
+            self.sizes.insert(TypeId::of::<T>(), output.len() - start);
+        }
+
+        res
+    }
+
+    /// The last observed size of `T`, if one has been serialised through this cache.
+    pub fn size_of<T: 'static>(&self) -> Option<usize> {
+        self.sizes.get(&TypeId::of::<T>()).copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SizeCache;
+    use bytes::BytesMut;
+    use serde::Serialize;
+
+    #[test]
+    fn cached_size() {
+        #[derive(Serialize)]
+        struct A {
+            a: String,
+            b: u64,
+        }
+
+        let mut cache = SizeCache::new();
+        let mut output = BytesMut::new();
+
+        let small = A {
+            a: "hello".to_string(),
+            b: 0,
+        };
+        cache.to_string(&small, &mut output).unwrap_err();
+        assert!(output.is_empty());
+        assert_eq!(cache.size_of::<A>(), None);
+
+        #[derive(Serialize)]
+        struct B {
+            a: String,
+        }
+
+        let small = B {
+            a: "hello".to_string(),
+        };
+        cache.to_string(&small, &mut output).unwrap();
+
+        let mut expected = BytesMut::new();
+        crate::to_string(&small, &mut expected).unwrap();
+        assert_eq!(output, expected);
+        assert_eq!(cache.size_of::<B>(), Some(expected.len()));
+
+        // bigger than the cached size, so the buffer needs to grow while writing
+        let big = B {
+            a: "hello".repeat(100),
+        };
+        let mut output = BytesMut::new();
+        cache.to_string(&big, &mut output).unwrap();
+
+        let mut expected = BytesMut::new();
+        crate::to_string(&big, &mut expected).unwrap();
+        assert_eq!(output, expected);
+        assert_eq!(cache.size_of::<B>(), Some(expected.len()));
+
+        // and the cached path cleans up after itself if it fails partway through
+        #[derive(Serialize)]
+        struct C {
+            a: i32,
+            b: Option<u64>,
+        }
+
+        let mut output = BytesMut::new();
+        cache.to_string(&C { a: 1, b: None }, &mut output).unwrap();
+
+        let mut output = BytesMut::new();
+        cache
+            .to_string(&C { a: 1, b: Some(1) }, &mut output)
+            .unwrap_err();
+        assert!(output.is_empty());
+    }
+}
@@ -0,0 +1,180 @@
+//! CRC-32C checksums, as used by OP_MSG's `checksumPresent` flag and handy for detecting
+//! corruption in stored documents.
+//!
+//! OP_MSG messages are laid out as a 16 byte header (`messageLength`, `requestID`, `responseTo`
+//! and `opCode`), a `u32` of flag bits then the sections. When bit 0 of the flags is set, the
+//! last four bytes of the message are the checksum of everything before them.
+
+use std::convert::{TryFrom, TryInto};
+
+const OP_MSG: i32 = 2013;
+const HEADER_LENGTH: usize = 16;
+const CHECKSUM_PRESENT: u32 = 1;
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    #[error("message is too short to be an OP_MSG")]
+    Truncated,
+    #[error("message length {0} doesn't match the {1} bytes given")]
+    LengthMismatch(i32, usize),
+    #[error("opcode {0} isn't OP_MSG")]
+    NotOpMsg(i32),
+    #[error("checksum {actual:#010x} doesn't match expected {expected:#010x}")]
+    Mismatch { expected: u32, actual: u32 },
+}
+
+/// Lookup table for the Castagnoli polynomial, reflected.
+const TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0x82F6_3B78
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+
+        table[i] = crc;
+        i += 1;
+    }
+
+    table
+};
+
+/// The CRC-32C of `data`, typically a whole document.
+pub fn checksum(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &byte| {
+        TABLE[usize::from((crc as u8) ^ byte)] ^ (crc >> 8)
+    })
+}
+
+/// Sets the `checksumPresent` flag on the OP_MSG in `message` and appends its checksum, fixing
+/// up the message length to match. Messages that already have a checksum are left as they are.
+pub fn add_op_msg_checksum(message: &mut Vec<u8>) -> Result<(), Error> {
+    let flags = op_msg_flags(message)?;
+
+    if flags & CHECKSUM_PRESENT != 0 {
+        return Ok(());
+    }
+
+    let length = i32::try_from(message.len() + 4).map_err(|_| Error::Truncated)?;
+    message[..4].copy_from_slice(&length.to_le_bytes());
+    message[HEADER_LENGTH..HEADER_LENGTH + 4]
+        .copy_from_slice(&(flags | CHECKSUM_PRESENT).to_le_bytes());
+
+    let checksum = checksum(message);
+    message.extend_from_slice(&checksum.to_le_bytes());
+
+    Ok(())
+}
+
+/// Checks the checksum of the OP_MSG in `message`, if it has one. Returns whether a checksum
+/// was present.
+pub fn verify_op_msg(message: &[u8]) -> Result<bool, Error> {
+    let flags = op_msg_flags(message)?;
+
+    if flags & CHECKSUM_PRESENT == 0 {
+        return Ok(false);
+    }
+
+    let (body, expected) = message.split_at(message.len() - 4);
+
+    if body.len() < HEADER_LENGTH + 4 {
+        return Err(Error::Truncated);
+    }
+
+    let expected = u32::from_le_bytes(expected.try_into().unwrap());
+    let actual = checksum(body);
+
+    if expected != actual {
+        return Err(Error::Mismatch { expected, actual });
+    }
+
+    Ok(true)
+}
+
+/// Checks the header of the OP_MSG in `message`, returning its flag bits.
+fn op_msg_flags(message: &[u8]) -> Result<u32, Error> {
+    if message.len() < HEADER_LENGTH + 4 {
+        return Err(Error::Truncated);
+    }
+
+    let length = i32::from_le_bytes(message[..4].try_into().unwrap());
+    if usize::try_from(length).ok() != Some(message.len()) {
+        return Err(Error::LengthMismatch(length, message.len()));
+    }
+
+    let opcode = i32::from_le_bytes(message[12..16].try_into().unwrap());
+    if opcode != OP_MSG {
+        return Err(Error::NotOpMsg(opcode));
+    }
+
+    Ok(u32::from_le_bytes(
+        message[HEADER_LENGTH..HEADER_LENGTH + 4]
+            .try_into()
+            .unwrap(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::Error;
+
+    #[test]
+    fn checksum() {
+        assert_eq!(super::checksum(b""), 0);
+        assert_eq!(super::checksum(b"123456789"), 0xE306_9283);
+        assert_eq!(super::checksum(&[0; 32]), 0x8A91_36AA);
+    }
+
+    #[test]
+    fn op_msg() {
+        let body = bson::to_vec(&bson::doc! { "ping": 1, "$db": "admin" }).unwrap();
+
+        let mut message = Vec::new();
+        message.extend_from_slice(&((16 + 4 + 1 + body.len()) as i32).to_le_bytes());
+        message.extend_from_slice(&1_i32.to_le_bytes());
+        message.extend_from_slice(&0_i32.to_le_bytes());
+        message.extend_from_slice(&2013_i32.to_le_bytes());
+        message.extend_from_slice(&0_u32.to_le_bytes());
+        message.push(0);
+        message.extend_from_slice(&body);
+
+        assert_eq!(super::verify_op_msg(&message), Ok(false));
+
+        super::add_op_msg_checksum(&mut message).unwrap();
+        assert_eq!(message[16], 1);
+        assert_eq!(super::verify_op_msg(&message), Ok(true));
+
+        // adding it again is a no-op
+        let before = message.clone();
+        super::add_op_msg_checksum(&mut message).unwrap();
+        assert_eq!(message, before);
+
+        let last = message.len() - 6;
+        message[last] ^= 0xff;
+        assert!(matches!(
+            super::verify_op_msg(&message),
+            Err(Error::Mismatch { .. })
+        ));
+
+        assert_eq!(
+            super::verify_op_msg(&message[..message.len() - 1]),
+            Err(Error::LengthMismatch(
+                message.len() as i32,
+                message.len() - 1
+            ))
+        );
+        assert_eq!(super::verify_op_msg(&message[..4]), Err(Error::Truncated));
+
+        message[12..16].copy_from_slice(&2004_i32.to_le_bytes());
+        assert_eq!(super::verify_op_msg(&message), Err(Error::NotOpMsg(2004)));
+    }
+}
@@ -0,0 +1,296 @@
+use crate::{byte::WindowBuf, ser, serialised_size_of_with_options, Error};
+use bytes::Bytes;
+use serde::Serialize;
+
+/// Serialises a value a chunk at a time, so it can be written out to a slow writer without the
+/// whole document ever being held in memory.
+///
+/// Serialisation can't be paused part way through, and the lengths at the start of each
+/// document aren't known until the document's been written. Each chunk is instead produced by a
+/// full pass of the serialiser that keeps only the bytes falling in that chunk, trading CPU for
+/// memory: the value is serialised roughly `size / chunk_size` times, so chunks should be
+/// large relative to the document.
+///
+/// The value must serialise to the same bytes every time. Values written with
+/// [`ser::Options::sort_keys`] need the whole document to reorder it, so they're serialised once
+/// up front and handed out from that buffer instead.
+pub struct ChunkedSerializer<'a, T> {
+    val: &'a T,
+    options: ser::Options,
+    buffered: Option<Bytes>,
+    size: usize,
+    chunk_size: usize,
+    position: usize,
+    chunk: Vec<u8>,
+    sent: usize,
+}
+
+impl<'a, T: Serialize> ChunkedSerializer<'a, T> {
+    pub fn new(val: &'a T, chunk_size: usize) -> Result<Self, Error> {
+        Self::with_options(val, chunk_size, ser::Options::default())
+    }
+
+    pub fn with_options(
+        val: &'a T,
+        chunk_size: usize,
+        options: ser::Options,
+    ) -> Result<Self, Error> {
+        assert!(chunk_size > 0, "chunk size must be greater than zero");
+
+        let buffered = if options.sort_keys {
+            Some(crate::to_bytes_with_options(val, options)?)
+        } else {
+            None
+        };
+
+        let size = match &buffered {
+            Some(buffered) => buffered.len(),
+            None => serialised_size_of_with_options(val, options)?,
+        };
+
+        Ok(Self {
+            val,
+            options,
+            buffered,
+            size,
+            chunk_size,
+            position: 0,
+            chunk: Vec::new(),
+            sent: 0,
+        })
+    }
+
+    /// The size of the whole document.
+    pub fn serialised_size(&self) -> usize {
+        self.size
+    }
+
+    /// The number of bytes yet to be handed out.
+    pub fn remaining(&self) -> usize {
+        self.size - self.position + (self.chunk.len() - self.sent)
+    }
+
+    /// The next `chunk_size` bytes of the document, the last chunk may be shorter. Whatever's left
+    /// of a chunk partly written by `poll_write_next` is returned first.
+    pub fn next_chunk(&mut self) -> Result<Option<&[u8]>, Error> {
+        if self.sent == self.chunk.len() && !self.fill()? {
+            return Ok(None);
+        }
+
+        let unsent = self.sent;
+        self.sent = self.chunk.len();
+        Ok(Some(&self.chunk[unsent..]))
+    }
+
+    /// Writes as much of the document to `writer` as it'll currently accept, only serialising
+    /// the next chunk once the last has been written out in full. Like `poll_write`, returns the
+    /// number of bytes written with zero meaning the whole document has been written.
+    #[cfg(feature = "futures")]
+    pub fn poll_write_next<W: futures_io::AsyncWrite + ?Sized>(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+        writer: std::pin::Pin<&mut W>,
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        use std::{io, task::Poll};
+
+        if self.sent == self.chunk.len() {
+            match self.fill() {
+                Ok(true) => {}
+                Ok(false) => return Poll::Ready(Ok(0)),
+                Err(e) => return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData, e))),
+            }
+        }
+
+        match writer.poll_write(cx, &self.chunk[self.sent..]) {
+            Poll::Ready(Ok(0)) => Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+            Poll::Ready(Ok(written)) => {
+                self.sent += written;
+                Poll::Ready(Ok(written))
+            }
+            other => other,
+        }
+    }
+
+    /// Serialises the next chunk into `self.chunk`, returning false if the document has already
+    /// been handed out in full.
+    fn fill(&mut self) -> Result<bool, Error> {
+        if self.position == self.size {
+            return Ok(false);
+        }
+
+        let start = self.position;
+        let end = self.size.min(start + self.chunk_size);
+
+        self.chunk.clear();
+
+        if let Some(buffered) = &self.buffered {
+            self.chunk.extend_from_slice(&buffered[start..end]);
+        } else {
+            self.chunk.resize(end - start, 0);
+
+            let mut window = WindowBuf::new(start, &mut self.chunk);
+            self.val.serialize(ser::Serializer {
+                key: None,
+                output: &mut window,
+                options: self.options,
+            })?;
+
+            if crate::BytesLikeBuf::len(&mut window) != self.size {
+                return Err(Error::Serde(
+                    "value serialised to a different size between chunks".to_string(),
+                ));
+            }
+        }
+
+        self.position = end;
+        self.sent = 0;
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ChunkedSerializer;
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    struct A {
+        name: String,
+        nested: BTreeMap<String, Vec<i64>>,
+        items: Vec<B>,
+    }
+
+    #[derive(Serialize)]
+    struct B {
+        z: i32,
+        a: Option<&'static str>,
+    }
+
+    fn value() -> A {
+        A {
+            name: "chunked".repeat(20),
+            nested: (0..20)
+                .map(|i| (format!("key{}", i), (0..i).collect()))
+                .collect(),
+            items: (0..30)
+                .map(|i| B {
+                    z: i,
+                    a: Some("a").filter(|_| i % 2 == 0),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn chunks() {
+        let value = value();
+
+        for options in [
+            crate::ser::Options::default(),
+            crate::ser::Options::default().sort_keys(true),
+        ] {
+            let mut expected = Vec::new();
+            crate::to_vec_with_options(&value, &mut expected, options).unwrap();
+
+            for chunk_size in [1, 7, 100, expected.len(), expected.len() * 2] {
+                let mut chunks =
+                    ChunkedSerializer::with_options(&value, chunk_size, options).unwrap();
+                assert_eq!(chunks.serialised_size(), expected.len());
+
+                let mut output = Vec::new();
+                while let Some(chunk) = chunks.next_chunk().unwrap() {
+                    assert!(chunk.len() <= chunk_size);
+                    output.extend_from_slice(chunk);
+                }
+
+                assert_eq!(output, expected);
+                assert_eq!(chunks.remaining(), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn errors_up_front() {
+        assert!(ChunkedSerializer::new(&1_u64, 10).is_err());
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn poll_write_next() {
+        use std::{
+            future::Future,
+            io,
+            pin::Pin,
+            task::{Context, Poll, Waker},
+        };
+
+        /// Accepts a few bytes at a time, and only on every other poll.
+        #[derive(Default)]
+        struct Slow {
+            written: Vec<u8>,
+            ready: bool,
+        }
+
+        impl futures_io::AsyncWrite for Slow {
+            fn poll_write(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<io::Result<usize>> {
+                self.ready = !self.ready;
+                if !self.ready {
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+
+                let written = buf.len().min(5);
+                self.written.extend_from_slice(&buf[..written]);
+                Poll::Ready(Ok(written))
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+
+            fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+        }
+
+        let value = value();
+        let mut expected = Vec::new();
+        crate::to_vec(&value, &mut expected).unwrap();
+
+        let mut chunks = ChunkedSerializer::new(&value, 64).unwrap();
+        let mut writer = Slow::default();
+        let mut cx = Context::from_waker(Waker::noop());
+
+        loop {
+            match chunks.poll_write_next(&mut cx, Pin::new(&mut writer)) {
+                Poll::Ready(Ok(0)) => break,
+                Poll::Ready(Ok(_)) => {
+                    // never more than a chunk ahead of the writer
+                    assert_eq!(chunks.remaining() + writer.written.len(), expected.len());
+                    assert!(chunks.chunk.len() <= 64);
+                }
+                Poll::Ready(Err(e)) => panic!("{}", e),
+                Poll::Pending => {}
+            }
+        }
+
+        assert_eq!(writer.written, expected);
+
+        let mut output = Vec::new();
+        let mut future = Box::pin(crate::to_async_writer(&value, &mut output));
+        loop {
+            if let Poll::Ready(res) = future.as_mut().poll(&mut cx) {
+                res.unwrap();
+                break;
+            }
+        }
+        drop(future);
+        assert_eq!(output, expected);
+    }
+}
@@ -0,0 +1,1005 @@
+use crate::{scan::value_length, BsonColumn, RawBson};
+use std::convert::{TryFrom, TryInto};
+
+/// Terminates the column.
+const EOO: u8 = 0x00;
+/// Starts a run of documents whose fields are each compressed as their own stream.
+const INTERLEAVED_START: [u8; 3] = [0xf0, 0xf1, 0xf2];
+/// The high nibble of the control byte starting a run of Simple8b blocks, by scale index. Doubles
+/// are multiplied by the scale before taking deltas of them, or have deltas taken of their bit
+/// patterns with [`MEMORY_AS_INTEGER`], which is what every other type uses.
+const SIMPLE8B_CONTROL: [u8; 6] = [0x90, 0xa0, 0xb0, 0xc0, 0xd0, 0x80];
+const SCALE_MULTIPLIER: [f64; 5] = [1.0, 10.0, 100.0, 10_000.0, 100_000_000.0];
+const MEMORY_AS_INTEGER: usize = 5;
+/// The low nibble of a Simple8b control byte holds the number of blocks following it, less one.
+const MAX_BLOCKS: usize = 16;
+
+const RLE_SELECTOR: u64 = 15;
+/// Runs repeat the last value in multiples of this, up to 16 times.
+const RLE_MULTIPLIER: usize = 120;
+
+/// The base selectors the encoder packs blocks with, as `(selector, bits, values)`. Selectors 7
+/// and 8 are left out as their low bits are shared with the extended selectors.
+const PACKING: [(u64, u32, usize); 12] = [
+    (1, 1, 60),
+    (2, 2, 30),
+    (3, 3, 20),
+    (4, 4, 15),
+    (5, 5, 12),
+    (6, 6, 10),
+    (9, 10, 6),
+    (10, 12, 5),
+    (11, 15, 4),
+    (12, 20, 3),
+    (13, 30, 2),
+    (14, 60, 1),
+];
+
+/// Bits per value of the base selectors 1 through 14, each filling as much of the 60 bits after
+/// the selector as it can.
+const BASE_BITS: [u32; 14] = [1, 2, 3, 4, 5, 6, 7, 8, 10, 12, 15, 20, 30, 60];
+
+/// Bits per value of selector 7's extensions 1 through 9, which store trailing zeros in 4 bits.
+const SEVEN_BITS: [u32; 9] = [2, 3, 4, 5, 7, 10, 14, 24, 52];
+/// Bits per value of selector 8's extensions 1 through 7, which store trailing zero nibbles in
+/// 4 bits.
+const EIGHT_SMALL_BITS: [u32; 7] = [4, 5, 7, 10, 14, 24, 52];
+/// Bits per value of selector 8's extensions 8 through 13, which store trailing zero nibbles in
+/// 5 bits.
+const EIGHT_LARGE_BITS: [u32; 6] = [4, 6, 9, 13, 23, 51];
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidColumn {
+    #[error("column ends at {0} without a terminator")]
+    Truncated(usize),
+    #[error("unknown control byte {0:#04x} at {1}")]
+    UnknownControl(u8, usize),
+    #[error("literal at {0} isn't a valid element")]
+    InvalidLiteral(usize),
+    #[error("unknown Simple8b selector in the block at {0}")]
+    UnknownSelector(usize),
+    #[error("the block at {0} holds a value but there's no earlier value to apply it to")]
+    MissingReference(usize),
+    #[error("the block at {1} holds a value that can't be applied to a value of type {0:#04x}")]
+    InvalidDelta(u8, usize),
+    #[error("interleaved documents at {0} aren't supported")]
+    Interleaved(usize),
+    #[error("128-bit blocks for values of type {0:#04x} at {1} aren't supported")]
+    WideBlocks(u8, usize),
+    #[error("value of type {0:#04x} can't be written to a column")]
+    InvalidValue(u8),
+}
+
+/// A value read out of a [`BsonColumn`], kept as its encoded bytes. It can be deserialized with
+/// `de::from_raw` or written into a document through [`ColumnValue::as_raw`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ColumnValue {
+    element_type: u8,
+    bytes: Vec<u8>,
+}
+
+impl ColumnValue {
+    pub fn element_type(&self) -> u8 {
+        self.element_type
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn as_raw(&self) -> RawBson<'_> {
+        RawBson::new(self.element_type, &self.bytes)
+    }
+
+    /// Checks that `value` is a single well-formed value before taking a copy of it.
+    fn from_raw(value: RawBson<'_>) -> Result<Self, InvalidColumn> {
+        let bytes = value.as_bytes();
+
+        match value_length(bytes, 0, value.element_type()) {
+            Ok(Some(length)) if length == bytes.len() => Ok(Self {
+                element_type: value.element_type(),
+                bytes: bytes.to_vec(),
+            }),
+            _ => Err(InvalidColumn::InvalidValue(value.element_type())),
+        }
+    }
+
+    fn double(&self) -> f64 {
+        f64::from_le_bytes(self.bytes[..8].try_into().unwrap())
+    }
+
+    fn object_id(&self) -> [u8; 12] {
+        self.bytes[..12].try_into().unwrap()
+    }
+}
+
+impl BsonColumn {
+    /// Compresses `values`, `None` being a value that's missing, eg. from a measurement that
+    /// didn't have the field.
+    pub fn from_values<'a>(
+        values: impl IntoIterator<Item = Option<RawBson<'a>>>,
+    ) -> Result<Self, InvalidColumn> {
+        let mut builder = BsonColumnBuilder::new();
+
+        for value in values {
+            match value {
+                Some(value) => builder.push(value)?,
+                None => builder.push_missing(),
+            }
+        }
+
+        Ok(builder.finish())
+    }
+
+    /// Decompresses the values in the column, see [`BsonColumnValues`] for the columns that
+    /// can't be read.
+    pub fn values(&self) -> BsonColumnValues<'_> {
+        BsonColumnValues::new(&self.0)
+    }
+}
+
+/// The values in a [`BsonColumn`], as stored by time-series buckets. `None` is a missing value,
+/// where the measurement at that position didn't have the field.
+///
+/// Columns are a run of control bytes. A control byte holding a bson type is followed by a
+/// literal value of that type, and the rest are followed by up to 16 Simple8b blocks. Each block
+/// packs zigzag encoded deltas from the previous value into 60 bits, or repeats the last delta
+/// in runs of 120. Timestamps and ObjectIds store deltas of their deltas, and doubles can be
+/// scaled up by a power of ten so they're deltas of integers.
+///
+/// # Unsupported columns
+///
+/// Interleaved columns, which compress documents and arrays field by field, aren't supported,
+/// and neither are the 128-bit blocks holding deltas of strings, binaries and decimals. The
+/// iterator ends with [`InvalidColumn::Interleaved`] or [`InvalidColumn::WideBlocks`] when it
+/// reaches one. Time-series buckets use interleaved columns for object and array fields (such as
+/// the `meta` field) and 128-bit blocks for string, binary and decimal fields, so those columns
+/// can't be read. Their values can only be read when they're written as literals, and
+/// [`BsonColumnBuilder`] only ever writes them that way.
+pub struct BsonColumnValues<'a> {
+    data: &'a [u8],
+    position: usize,
+    /// Blocks left to read after the last control byte.
+    blocks: usize,
+    /// The scale of the blocks being read.
+    scale: usize,
+    block: u64,
+    /// Offset of the block being read, for errors.
+    block_offset: usize,
+    layout: Option<Layout>,
+    slot: usize,
+    /// Values left in a run of the last value.
+    repeat: usize,
+    /// The last value read from a block, repeated by runs.
+    last_slot: Option<u64>,
+    reference: Reference,
+    done: bool,
+}
+
+/// The value deltas are being applied to.
+#[derive(Default)]
+struct Reference {
+    value: Option<ColumnValue>,
+    /// The value as an integer, which deltas are applied to.
+    encoded: i64,
+    /// The last delta, for types storing deltas of deltas.
+    delta: i64,
+    /// The scale `encoded` is in, for doubles.
+    scale: Option<usize>,
+}
+
+/// Where the values are in a Simple8b block and how to read them.
+#[derive(Clone, Copy)]
+struct Layout {
+    /// The bit the first value starts at.
+    offset: u32,
+    bits: u32,
+    /// Bits below each value counting its trailing zeros, and what to multiply the count by.
+    trailing_zero_bits: u32,
+    trailing_zero_multiplier: u32,
+    count: usize,
+}
+
+impl Layout {
+    fn new(block: u64) -> Option<Self> {
+        let selector = block & 0xf;
+        let extension = ((block >> 4) & 0xf) as usize;
+
+        let extended = |offset: usize, bits: &[u32], trailing_zero_bits, multiplier| {
+            let bits = *bits.get(extension.checked_sub(offset)?)?;
+
+            Some(Self {
+                offset: 8,
+                bits,
+                trailing_zero_bits,
+                trailing_zero_multiplier: multiplier,
+                count: (56 / (bits + trailing_zero_bits)) as usize,
+            })
+        };
+
+        match (selector, extension) {
+            (0, _) | (RLE_SELECTOR, _) => None,
+            (7, 1..=15) => extended(1, &SEVEN_BITS, 4, 1),
+            (8, 1..=7) => extended(1, &EIGHT_SMALL_BITS, 4, 4),
+            (8, 8..=15) => extended(8, &EIGHT_LARGE_BITS, 5, 4),
+            (selector, _) => {
+                let bits = BASE_BITS[selector as usize - 1];
+
+                Some(Self {
+                    // the extension of selectors 7 and 8 is zero, with the values after it
+                    offset: if selector == 7 || selector == 8 { 8 } else { 4 },
+                    bits,
+                    trailing_zero_bits: 0,
+                    trailing_zero_multiplier: 0,
+                    count: (60 / bits) as usize,
+                })
+            }
+        }
+    }
+
+    /// The value in the `i`th slot, `None` if it's missing or `Err` if it doesn't fit in 64 bits.
+    fn read(self, block: u64, i: usize) -> Result<Option<u64>, ()> {
+        let width = self.bits + self.trailing_zero_bits;
+        let slot = (block >> (self.offset + i as u32 * width)) & mask(width);
+
+        let value = slot >> self.trailing_zero_bits;
+        if value == mask(self.bits) {
+            return Ok(None);
+        }
+
+        let shift = (slot & mask(self.trailing_zero_bits)) as u32 * self.trailing_zero_multiplier;
+        match value.checked_shl(shift) {
+            Some(shifted) if shifted >> shift == value => Ok(Some(shifted)),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The low `bits` bits set.
+fn mask(bits: u32) -> u64 {
+    match bits {
+        0 => 0,
+        bits => u64::MAX >> (64 - bits),
+    }
+}
+
+impl<'a> BsonColumnValues<'a> {
+    /// Reads the values out of the payload of a column binary.
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            position: 0,
+            blocks: 0,
+            scale: MEMORY_AS_INTEGER,
+            block: 0,
+            block_offset: 0,
+            layout: None,
+            slot: 0,
+            repeat: 0,
+            last_slot: Some(0),
+            reference: Reference::default(),
+            done: false,
+        }
+    }
+
+    fn read_next(&mut self) -> Result<Option<Option<ColumnValue>>, InvalidColumn> {
+        loop {
+            if self.repeat > 0 {
+                self.repeat -= 1;
+                return self.apply(self.last_slot).map(Some);
+            }
+
+            if let Some(layout) = self.layout.filter(|layout| self.slot < layout.count) {
+                let slot = layout
+                    .read(self.block, self.slot)
+                    .map_err(|()| self.invalid_delta())?;
+                self.slot += 1;
+                self.last_slot = slot;
+                return self.apply(slot).map(Some);
+            }
+
+            if self.blocks > 0 {
+                self.read_block()?;
+                continue;
+            }
+
+            let at = self.position;
+            let control = *self.data.get(at).ok_or(InvalidColumn::Truncated(at))?;
+            self.position += 1;
+
+            match control {
+                EOO => return Ok(None),
+                c if INTERLEAVED_START.contains(&c) => return Err(InvalidColumn::Interleaved(at)),
+                c if c & 0x80 != 0 && c != 0xff => {
+                    self.scale = SIMPLE8B_CONTROL
+                        .iter()
+                        .position(|&high| high == c & 0xf0)
+                        .ok_or(InvalidColumn::UnknownControl(c, at))?;
+                    self.blocks = usize::from(c & 0x0f) + 1;
+
+                    match &self.reference.value {
+                        Some(value) if is_wide(value.element_type) => {
+                            return Err(InvalidColumn::WideBlocks(value.element_type, at));
+                        }
+                        _ => {}
+                    }
+                }
+                element_type => return self.read_literal(element_type, at).map(Some),
+            }
+        }
+    }
+
+    fn read_block(&mut self) -> Result<(), InvalidColumn> {
+        let at = self.position;
+        let block = self
+            .data
+            .get(at..at + 8)
+            .ok_or(InvalidColumn::Truncated(at))?;
+
+        self.block = u64::from_le_bytes(block.try_into().unwrap());
+        self.block_offset = at;
+        self.position += 8;
+        self.blocks -= 1;
+
+        if self.block & 0xf == RLE_SELECTOR {
+            self.layout = None;
+            self.repeat = (((self.block >> 4) & 0xf) as usize + 1) * RLE_MULTIPLIER;
+        } else {
+            self.layout = Some(Layout::new(self.block).ok_or(InvalidColumn::UnknownSelector(at))?);
+            self.slot = 0;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the literal of `element_type` at `at`, which is an element with an empty key.
+    fn read_literal(
+        &mut self,
+        element_type: u8,
+        at: usize,
+    ) -> Result<Option<ColumnValue>, InvalidColumn> {
+        let start = at + 2;
+
+        let length = match (
+            self.data.get(at + 1),
+            value_length(self.data, start, element_type),
+        ) {
+            (Some(0), Ok(Some(length))) if start + length <= self.data.len() => length,
+            (None, _) => return Err(InvalidColumn::Truncated(at)),
+            (Some(0), Ok(None)) => return Err(InvalidColumn::UnknownControl(element_type, at)),
+            _ => return Err(InvalidColumn::InvalidLiteral(at)),
+        };
+
+        let value = ColumnValue {
+            element_type,
+            bytes: self.data[start..start + length].to_vec(),
+        };
+        self.position = start + length;
+
+        self.reference = Reference {
+            encoded: encode_literal(&value),
+            value: Some(value.clone()),
+            ..Reference::default()
+        };
+        self.last_slot = Some(0);
+
+        Ok(Some(value))
+    }
+
+    /// Applies the value from a block to the reference, `None` being a missing value.
+    fn apply(&mut self, slot: Option<u64>) -> Result<Option<ColumnValue>, InvalidColumn> {
+        let Some(slot) = slot else {
+            return Ok(None);
+        };
+
+        let delta = zigzag_decode(slot);
+        let reference = &mut self.reference;
+        let last = reference
+            .value
+            .as_ref()
+            .ok_or(InvalidColumn::MissingReference(self.block_offset))?;
+        let element_type = last.element_type;
+        let invalid = InvalidColumn::InvalidDelta(element_type, self.block_offset);
+
+        if self.scale != MEMORY_AS_INTEGER && element_type != 0x01 {
+            return Err(invalid);
+        }
+
+        let bytes = match element_type {
+            0x01 => {
+                if reference.scale != Some(self.scale) {
+                    reference.encoded = encode_double(last.double(), self.scale).ok_or(invalid)?;
+                    reference.scale = Some(self.scale);
+                }
+
+                reference.encoded = reference.encoded.wrapping_add(delta);
+                decode_double(reference.encoded, self.scale)
+                    .to_le_bytes()
+                    .to_vec()
+            }
+            0x07 | 0x11 => {
+                reference.delta = reference.delta.wrapping_add(delta);
+                reference.encoded = reference.encoded.wrapping_add(reference.delta);
+
+                match element_type {
+                    0x07 => decode_object_id(reference.encoded, &last.object_id())
+                        .ok_or(invalid)?
+                        .to_vec(),
+                    _ => reference.encoded.to_le_bytes().to_vec(),
+                }
+            }
+            0x08 | 0x09 | 0x10 | 0x12 => {
+                reference.encoded = reference.encoded.wrapping_add(delta);
+
+                match element_type {
+                    0x10 => i32::try_from(reference.encoded)
+                        .map_err(|_| invalid)?
+                        .to_le_bytes()
+                        .to_vec(),
+                    0x09 | 0x12 => reference.encoded.to_le_bytes().to_vec(),
+                    _ => match reference.encoded {
+                        0 | 1 => vec![reference.encoded as u8],
+                        _ => return Err(invalid),
+                    },
+                }
+            }
+            // everything else can only be repeated
+            _ if delta == 0 => last.bytes.clone(),
+            _ => return Err(invalid),
+        };
+
+        let value = ColumnValue {
+            element_type,
+            bytes,
+        };
+        reference.value = Some(value.clone());
+        Ok(Some(value))
+    }
+
+    fn invalid_delta(&self) -> InvalidColumn {
+        let element_type = self
+            .reference
+            .value
+            .as_ref()
+            .map_or(0x00, ColumnValue::element_type);
+
+        InvalidColumn::InvalidDelta(element_type, self.block_offset)
+    }
+}
+
+impl Iterator for BsonColumnValues<'_> {
+    type Item = Result<Option<ColumnValue>, InvalidColumn>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let res = self.read_next().transpose();
+
+        // there's no way to carry on after a malformed block, and nothing after the terminator
+        if !matches!(res, Some(Ok(_))) {
+            self.done = true;
+        }
+
+        res
+    }
+}
+
+/// Builds a [`BsonColumn`] a value at a time.
+///
+/// Consecutive doubles, integers, dates, timestamps, booleans and ObjectIds from the same
+/// process are stored as deltas where they fit, and repeats of any other value aside from
+/// strings, binaries, decimals, documents and arrays are stored as a delta of zero. Everything
+/// else is written as a literal.
+#[derive(Default)]
+pub struct BsonColumnBuilder {
+    data: Vec<u8>,
+    /// Values waiting to be packed into Simple8b blocks, `None` for missing values.
+    slots: Vec<Option<u64>>,
+    reference: Reference,
+}
+
+impl BsonColumnBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `value` to the column, which has to be a single well-formed value of its type.
+    pub fn push(&mut self, value: RawBson<'_>) -> Result<(), InvalidColumn> {
+        let value = ColumnValue::from_raw(value)?;
+
+        if let Some(slot) = self.delta(&value) {
+            self.slots.push(Some(slot));
+            return Ok(());
+        }
+
+        self.flush();
+        self.data.extend_from_slice(&[value.element_type, 0x00]);
+        self.data.extend_from_slice(&value.bytes);
+
+        self.reference = Reference {
+            encoded: encode_literal(&value),
+            value: Some(value),
+            ..Reference::default()
+        };
+
+        Ok(())
+    }
+
+    /// Marks the value at this position as missing.
+    pub fn push_missing(&mut self) {
+        self.slots.push(None);
+    }
+
+    pub fn finish(mut self) -> BsonColumn {
+        self.flush();
+        self.data.push(EOO);
+        BsonColumn(self.data)
+    }
+
+    /// Moves the reference on to `value` if it can be stored as a delta from it, returning the
+    /// delta to store.
+    fn delta(&mut self, value: &ColumnValue) -> Option<u64> {
+        let reference = &self.reference;
+        let last = reference.value.as_ref()?;
+
+        if last.element_type != value.element_type {
+            return None;
+        }
+
+        let (encoded, delta, scale) = match value.element_type {
+            0x01 => {
+                let (last, v) = (last.double(), value.double());
+                let at_scale = |scale| Some((encode_double(last, scale)?, encode_exact(v, scale)?));
+
+                // stick with the current scale if the value fits it, otherwise start a new run
+                // of blocks at the smallest scale both values fit
+                let (scale, (from, to)) =
+                    match reference.scale.and_then(|scale| {
+                        Some((scale, (reference.encoded, encode_exact(v, scale)?)))
+                    }) {
+                        Some(current) => current,
+                        None => (0..=MEMORY_AS_INTEGER)
+                            .find_map(|scale| Some((scale, at_scale(scale)?)))?,
+                    };
+
+                (to, to.wrapping_sub(from), Some(scale))
+            }
+            0x07 | 0x11 => {
+                if value.element_type == 0x07 && last.bytes[4..9] != value.bytes[4..9] {
+                    return None;
+                }
+
+                let encoded = encode_literal(value);
+                let delta = encoded.wrapping_sub(reference.encoded);
+                (encoded, delta.wrapping_sub(reference.delta), None)
+            }
+            0x08 | 0x09 | 0x10 | 0x12 => {
+                let encoded = encode_literal(value);
+                (encoded, encoded.wrapping_sub(reference.encoded), None)
+            }
+            element_type
+                if !is_wide(element_type)
+                    && element_type != 0x03
+                    && element_type != 0x04
+                    && last.bytes == value.bytes =>
+            {
+                (0, 0, None)
+            }
+            _ => return None,
+        };
+
+        let slot = zigzag_encode(delta);
+        if slot >= mask(60) {
+            return None;
+        }
+
+        // blocks are all read at the same scale, so a new scale needs a new control byte
+        if scale.is_some() && reference.scale.is_some() && scale != reference.scale {
+            self.flush();
+        }
+
+        let reference = &mut self.reference;
+        if matches!(value.element_type, 0x07 | 0x11) {
+            reference.delta = encoded.wrapping_sub(reference.encoded);
+        }
+        reference.encoded = encoded;
+        reference.scale = scale.or(reference.scale);
+        reference.value = Some(value.clone());
+
+        Some(slot)
+    }
+
+    /// Packs the pending values into blocks.
+    fn flush(&mut self) {
+        if self.slots.is_empty() {
+            return;
+        }
+
+        let mut blocks = Vec::new();
+        let mut previous = None;
+        let mut i = 0;
+
+        while i < self.slots.len() {
+            let remaining = &self.slots[i..];
+
+            if let Some(previous) = previous {
+                let run = remaining
+                    .iter()
+                    .take(RLE_MULTIPLIER * MAX_BLOCKS)
+                    .take_while(|slot| **slot == previous)
+                    .count()
+                    / RLE_MULTIPLIER;
+
+                if run > 0 {
+                    blocks.push(RLE_SELECTOR | ((run as u64 - 1) << 4));
+                    i += run * RLE_MULTIPLIER;
+                    continue;
+                }
+            }
+
+            // every value fits in the last selector, the encoder never makes one that doesn't
+            let (selector, bits, count) = *PACKING
+                .iter()
+                .find(|(_, bits, count)| {
+                    *count <= remaining.len()
+                        && remaining[..*count]
+                            .iter()
+                            .all(|slot| slot.is_none_or(|v| v < mask(*bits)))
+                })
+                .unwrap();
+
+            let block = remaining[..count]
+                .iter()
+                .enumerate()
+                .fold(selector, |block, (n, slot)| {
+                    block | (slot.unwrap_or(mask(bits)) << (4 + n as u32 * bits))
+                });
+
+            blocks.push(block);
+            previous = Some(remaining[count - 1]);
+            i += count;
+        }
+
+        let scale = self.reference.scale.unwrap_or(MEMORY_AS_INTEGER);
+        for run in blocks.chunks(MAX_BLOCKS) {
+            self.data
+                .push(SIMPLE8B_CONTROL[scale] | (run.len() as u8 - 1));
+
+            for block in run {
+                self.data.extend_from_slice(&block.to_le_bytes());
+            }
+        }
+
+        self.slots.clear();
+    }
+}
+
+/// Strings, binaries, decimals and the like have their deltas stored in 128-bit blocks.
+fn is_wide(element_type: u8) -> bool {
+    matches!(element_type, 0x02 | 0x05 | 0x0d | 0x0e | 0x13)
+}
+
+/// The integer deltas are taken from for each type, doubles are encoded once the scale of the
+/// blocks following them is known.
+fn encode_literal(value: &ColumnValue) -> i64 {
+    let bytes = &value.bytes;
+
+    match value.element_type {
+        0x10 => i64::from(i32::from_le_bytes(bytes[..4].try_into().unwrap())),
+        0x09 | 0x11 | 0x12 => i64::from_le_bytes(bytes[..8].try_into().unwrap()),
+        0x08 => i64::from(bytes[0]),
+        0x07 => encode_object_id(&value.object_id()),
+        _ => 0,
+    }
+}
+
+fn zigzag_encode(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn zigzag_decode(v: u64) -> i64 {
+    ((v >> 1) as i64) ^ -((v & 1) as i64)
+}
+
+/// `v` multiplied up by the scale, if that makes it an integer.
+fn encode_double(v: f64, scale: usize) -> Option<i64> {
+    if scale == MEMORY_AS_INTEGER {
+        return Some(v.to_bits() as i64);
+    }
+
+    let scaled = (v * SCALE_MULTIPLIER[scale]).round();
+    if !(-9.2e18..9.2e18).contains(&scaled) {
+        return None;
+    }
+
+    Some(scaled as i64).filter(|&encoded| decode_double(encoded, scale) == v)
+}
+
+/// Like [`encode_double`], but doesn't lose the sign of negative zero.
+fn encode_exact(v: f64, scale: usize) -> Option<i64> {
+    encode_double(v, scale)
+        .filter(|&encoded| decode_double(encoded, scale).to_bits() == v.to_bits())
+}
+
+fn decode_double(encoded: i64, scale: usize) -> f64 {
+    match SCALE_MULTIPLIER.get(scale) {
+        Some(multiplier) => encoded as f64 / multiplier,
+        None => f64::from_bits(encoded as u64),
+    }
+}
+
+/// Order of the bytes of an ObjectId in its encoded form, least significant first. The counter
+/// and timestamp are interleaved so that ids generated close together have small deltas, the
+/// process unique bytes are carried over from the last literal.
+const OBJECT_ID_BYTES: [usize; 7] = [11, 3, 10, 2, 9, 1, 0];
+
+fn encode_object_id(id: &[u8; 12]) -> i64 {
+    let mut encoded = [0; 8];
+    for (byte, i) in encoded.iter_mut().zip(OBJECT_ID_BYTES) {
+        *byte = id[i];
+    }
+
+    i64::from_le_bytes(encoded)
+}
+
+fn decode_object_id(encoded: i64, last: &[u8; 12]) -> Option<[u8; 12]> {
+    let encoded = encoded.to_le_bytes();
+    if encoded[7] != 0 {
+        return None;
+    }
+
+    let mut id = *last;
+    for (byte, i) in encoded.iter().zip(OBJECT_ID_BYTES) {
+        id[i] = *byte;
+    }
+
+    Some(id)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BsonColumnBuilder, BsonColumnValues, ColumnValue, InvalidColumn};
+    use crate::{BsonColumn, RawBson};
+
+    fn values(data: &[u8]) -> Vec<Option<ColumnValue>> {
+        BsonColumnValues::new(data)
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn value(element_type: u8, bytes: impl AsRef<[u8]>) -> Option<ColumnValue> {
+        Some(ColumnValue {
+            element_type,
+            bytes: bytes.as_ref().to_vec(),
+        })
+    }
+
+    fn from_values(values: &[Option<ColumnValue>]) -> BsonColumn {
+        BsonColumn::from_values(values.iter().map(|v| v.as_ref().map(ColumnValue::as_raw))).unwrap()
+    }
+
+    #[test]
+    fn int32s() {
+        #[rustfmt::skip]
+        let column = [
+            // literal 1
+            0x10, 0x00, 0x01, 0x00, 0x00, 0x00,
+            // one block of four 15 bit values: +1, +1, missing, +2
+            0x80, 0x2b, 0x00, 0x10, 0x00, 0xfc, 0xff, 0x09, 0x00,
+            0x00,
+        ];
+
+        let expected = [
+            value(0x10, 1_i32.to_le_bytes()),
+            value(0x10, 2_i32.to_le_bytes()),
+            value(0x10, 3_i32.to_le_bytes()),
+            None,
+            value(0x10, 5_i32.to_le_bytes()),
+        ];
+
+        assert_eq!(values(&column), expected);
+        assert_eq!(from_values(&expected), BsonColumn(column.to_vec()));
+
+        let ints = BsonColumn(column.to_vec())
+            .values()
+            .map(|v| v.unwrap().map(|v| crate::de::from_raw(v.as_raw()).unwrap()))
+            .collect::<Vec<Option<i32>>>();
+        assert_eq!(ints, [Some(1), Some(2), Some(3), None, Some(5)]);
+    }
+
+    #[test]
+    fn scaled_doubles() {
+        #[rustfmt::skip]
+        let column = [
+            // literal 1.5
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf8, 0x3f,
+            // multiplied by 10, one block of three 20 bit values: +1, +1, missing
+            0xa0, 0x2c, 0x00, 0x00, 0x02, 0x00, 0xf0, 0xff, 0xff,
+            0x00,
+        ];
+
+        let expected = [
+            value(0x01, 1.5_f64.to_le_bytes()),
+            value(0x01, 1.6_f64.to_le_bytes()),
+            value(0x01, 1.7_f64.to_le_bytes()),
+            None,
+        ];
+
+        assert_eq!(values(&column), expected);
+        assert_eq!(from_values(&expected), BsonColumn(column.to_vec()));
+    }
+
+    #[test]
+    fn extended_selectors_and_runs() {
+        #[rustfmt::skip]
+        let column = [
+            // literal epoch
+            0x09, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x82,
+            // selector 7, five 7 bit values with 4 bits of trailing zeros: +1000 each
+            0x57, 0xd4, 0xa7, 0x3e, 0xf5, 0xa9, 0x4f, 0x7d,
+            // selector 8, five 7 bit values with 4 bits of trailing zero nibbles: +1000 each
+            0x38, 0xd1, 0x8f, 0x7e, 0xf4, 0xa3, 0x1f, 0x7d,
+            // 120 more +1000s
+            0x0f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00,
+        ];
+
+        let expected = (0..131_i64)
+            .map(|i| value(0x09, (i * 1000).to_le_bytes()))
+            .collect::<Vec<_>>();
+        assert_eq!(values(&column), expected);
+    }
+
+    #[test]
+    fn round_trip() {
+        // ids from the same process are deltas of each other, crossing the counter's lowest byte
+        let object_id = |process: [u8; 5], counter: u32| {
+            let mut id = [0; 12];
+            id[..4].copy_from_slice(&1_700_000_000_u32.to_be_bytes());
+            id[4..9].copy_from_slice(&process);
+            id[9..].copy_from_slice(&counter.to_be_bytes()[1..]);
+            value(0x07, id)
+        };
+        let ids = (250..270).map(|counter| object_id([1, 2, 3, 4, 5], counter));
+
+        let string = [2, 0, 0, 0, b'a', 0];
+        let document = [12, 0, 0, 0, 0x10, b'a', 0, 1, 0, 0, 0, 0];
+
+        let expected = vec![
+            value(0x12, i64::MIN.to_le_bytes()),
+            value(0x12, i64::MAX.to_le_bytes()),
+            value(0x12, 0_i64.to_le_bytes()),
+            value(0x10, i32::MAX.to_le_bytes()),
+            value(0x10, i32::MIN.to_le_bytes()),
+            value(0x01, 0.1_f64.to_le_bytes()),
+            value(0x01, 0.25_f64.to_le_bytes()),
+            value(0x01, (-0.0_f64).to_le_bytes()),
+            value(0x01, f64::NAN.to_le_bytes()),
+            value(0x01, 1e300_f64.to_le_bytes()),
+            value(0x01, 1e-300_f64.to_le_bytes()),
+            value(0x08, [1]),
+            value(0x08, [0]),
+            value(0x11, (1_u64 << 32 | 1).to_le_bytes()),
+            value(0x11, (1_u64 << 32 | 2).to_le_bytes()),
+            value(0x11, (2_u64 << 32).to_le_bytes()),
+            value(0x02, string),
+            value(0x02, string),
+            value(0x0a, []),
+            value(0x0a, []),
+            value(0x03, document),
+            value(0x03, document),
+            value(0xff, []),
+            value(0x7f, []),
+        ]
+        .into_iter()
+        .chain(std::iter::repeat_n(None, 500))
+        .chain(ids)
+        .chain(vec![object_id([5, 4, 3, 2, 1], 0), None])
+        .chain((0..1000_i64).map(|i| value(0x12, (i * 3).to_le_bytes())))
+        .collect::<Vec<_>>();
+
+        let column = from_values(&expected);
+        assert_eq!(
+            column.values().collect::<Result<Vec<_>, _>>().unwrap(),
+            expected
+        );
+
+        // long runs are repeated rather than written out, the int64s alone would take 50 blocks
+        assert!(column.0.len() < 400, "{}", column.0.len());
+
+        assert_eq!(BsonColumnBuilder::new().finish(), BsonColumn(vec![0x00]));
+    }
+
+    #[test]
+    fn invalid_values() {
+        let push = |element_type, bytes: &[u8]| {
+            BsonColumnBuilder::new().push(RawBson::new(element_type, bytes))
+        };
+
+        assert_eq!(
+            push(0x10, &[1, 0, 0]),
+            Err(InvalidColumn::InvalidValue(0x10))
+        );
+        assert_eq!(
+            push(0x10, &[1, 0, 0, 0, 0]),
+            Err(InvalidColumn::InvalidValue(0x10))
+        );
+        assert_eq!(
+            push(0x02, &[5, 0, 0, 0, b'a', 0]),
+            Err(InvalidColumn::InvalidValue(0x02))
+        );
+        assert_eq!(push(0x14, &[]), Err(InvalidColumn::InvalidValue(0x14)));
+        assert_eq!(push(0x10, &[1, 0, 0, 0]), Ok(()));
+    }
+
+    #[test]
+    fn invalid() {
+        let error = |data: &[u8]| {
+            let mut values = BsonColumnValues::new(data);
+            let error = values.find_map(Result::err);
+            // nothing is read after an error
+            assert!(values.next().is_none());
+            error
+        };
+
+        assert_eq!(error(&[]), Some(InvalidColumn::Truncated(0)));
+        assert_eq!(
+            error(&[0x10, 0x00, 0x01, 0x00, 0x00, 0x00]),
+            Some(InvalidColumn::Truncated(6))
+        );
+        assert_eq!(
+            error(&[0x10, 0x00, 0x01]),
+            Some(InvalidColumn::InvalidLiteral(0))
+        );
+        assert_eq!(
+            error(&[0x10, b'a', 0x00]),
+            Some(InvalidColumn::InvalidLiteral(0))
+        );
+        assert_eq!(
+            error(&[0x80, 0x0e, 0, 0, 0, 0, 0]),
+            Some(InvalidColumn::Truncated(1))
+        );
+        assert_eq!(error(&[0xe0]), Some(InvalidColumn::UnknownControl(0xe0, 0)));
+        assert_eq!(
+            error(&[0x14, 0x00]),
+            Some(InvalidColumn::UnknownControl(0x14, 0))
+        );
+        assert_eq!(error(&[0xf0]), Some(InvalidColumn::Interleaved(0)));
+        assert_eq!(
+            error(&[0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0x00]),
+            Some(InvalidColumn::UnknownSelector(1))
+        );
+
+        // a delta of 0 with nothing before it
+        assert_eq!(
+            error(&[0x80, 0x0e, 0, 0, 0, 0, 0, 0, 0, 0x00]),
+            Some(InvalidColumn::MissingReference(1))
+        );
+
+        // but missing values are fine
+        assert_eq!(
+            values(&[0x80, 0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00]),
+            [None]
+        );
+
+        // +1 past the largest int32
+        assert_eq!(
+            error(&[0x10, 0x00, 0xff, 0xff, 0xff, 0x7f, 0x80, 0x2e, 0, 0, 0, 0, 0, 0, 0, 0x00]),
+            Some(InvalidColumn::InvalidDelta(0x10, 7))
+        );
+
+        // scaled deltas of an integer
+        assert_eq!(
+            error(&[0x10, 0x00, 0x01, 0x00, 0x00, 0x00, 0x90, 0x0e, 0, 0, 0, 0, 0, 0, 0, 0x00]),
+            Some(InvalidColumn::InvalidDelta(0x10, 7))
+        );
+
+        assert_eq!(
+            error(&[0x02, 0x00, 0x02, 0x00, 0x00, 0x00, b'a', 0x00, 0x80]),
+            Some(InvalidColumn::WideBlocks(0x02, 8))
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! Runs the MongoDB BSON corpus from the specifications repository against the serializer and
+//! deserializer. The corpus isn't vendored, fetch it into `test/bson-corpus` with
+//! `test/fetch-bson-corpus.sh` (or point `BSON_CORPUS_DIR` at a copy) then run
+//! `cargo test --features corpus corpus -- --nocapture` for a report of every case.
+//!
+//! Each valid case is read with [`Document::from_bytes`] and written back out, which must give
+//! the canonical bytes exactly, and each decode error case must be rejected by both
+//! [`de::validate`] and [`Document::from_bytes`]. These are what the test asserts on.
+//!
+//! Cases are also read into a `bson::Document` through our deserializer then written back out
+//! through our serializer. That only works for types serde can carry between the two crates,
+//! so those results are reported but not asserted on.
+
+use crate::{de, text::from_hex, Document};
+use serde::Deserialize;
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+#[derive(Deserialize)]
+struct Suite {
+    bson_type: String,
+    #[serde(default)]
+    valid: Vec<Valid>,
+    #[serde(default, rename = "decodeErrors")]
+    decode_errors: Vec<DecodeError>,
+}
+
+#[derive(Deserialize)]
+struct Valid {
+    description: String,
+    canonical_bson: String,
+    degenerate_bson: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DecodeError {
+    description: String,
+    bson: String,
+}
+
+#[derive(Default)]
+struct Report {
+    out: String,
+    /// Cases failing a check that's asserted on.
+    failures: usize,
+    /// Cases only failing through serde.
+    serde_failures: usize,
+    passed: usize,
+}
+
+impl Report {
+    fn record(
+        &mut self,
+        bson_type: &str,
+        description: &str,
+        exact: Result<(), String>,
+        serde: Result<(), String>,
+    ) {
+        let status = match (&exact, &serde) {
+            (Ok(()), Ok(())) => {
+                self.passed += 1;
+                "ok".to_string()
+            }
+            (Ok(()), Err(e)) => {
+                self.serde_failures += 1;
+                format!("ok, but not through serde: {}", e)
+            }
+            (Err(e), _) => {
+                self.failures += 1;
+                format!("FAILED: {}", e)
+            }
+        };
+
+        writeln!(self.out, "{} / {}: {}", bson_type, description, status).unwrap();
+    }
+}
+
+fn run(suite: &Suite, report: &mut Report) {
+    for case in &suite.valid {
+        let exact = check_valid(case);
+        let serde = from_hex(&case.canonical_bson)
+            .ok_or_else(|| "canonical_bson isn't hex".to_string())
+            .and_then(|canonical| check_serde(&canonical));
+
+        report.record(&suite.bson_type, &case.description, exact, serde);
+    }
+
+    for case in &suite.decode_errors {
+        let exact = check_decode_error(case);
+        let serde = from_hex(&case.bson)
+            .filter(|bson| de::from_bytes::<bson::Document>(bson).is_ok())
+            .map_or(Ok(()), |_| Err("deserialised".to_string()));
+
+        let description = format!("decode error: {}", case.description);
+        report.record(&suite.bson_type, &description, exact, serde);
+    }
+}
+
+fn check_valid(case: &Valid) -> Result<(), String> {
+    let canonical = from_hex(&case.canonical_bson).ok_or("canonical_bson isn't hex")?;
+
+    de::validate(&canonical).map_err(|e| format!("didn't validate: {}", e))?;
+    let document = Document::from_bytes(&canonical).map_err(|e| format!("didn't read: {}", e))?;
+    let written = document
+        .to_vec()
+        .map_err(|e| format!("didn't write: {}", e))?;
+
+    if written != canonical {
+        return Err(format!("wrote {}", crate::text::hex(&written)));
+    }
+
+    if let Some(degenerate) = &case.degenerate_bson {
+        let degenerate = from_hex(degenerate).ok_or("degenerate_bson isn't hex")?;
+        de::validate(&degenerate).map_err(|e| format!("degenerate didn't validate: {}", e))?;
+        Document::from_bytes(&degenerate).map_err(|e| format!("degenerate didn't read: {}", e))?;
+    }
+
+    Ok(())
+}
+
+fn check_decode_error(case: &DecodeError) -> Result<(), String> {
+    let bson = from_hex(&case.bson).ok_or("bson isn't hex")?;
+
+    if de::validate(&bson).is_ok() {
+        return Err("validated".to_string());
+    }
+    if Document::from_bytes(&bson).is_ok() {
+        return Err("read".to_string());
+    }
+
+    Ok(())
+}
+
+fn check_serde(canonical: &[u8]) -> Result<(), String> {
+    let document = de::from_bytes::<bson::Document>(canonical).map_err(|e| e.to_string())?;
+
+    let mut written = Vec::new();
+    crate::to_vec(&document, &mut written).map_err(|e| e.to_string())?;
+
+    if written != canonical {
+        return Err(format!("wrote {}", crate::text::hex(&written)));
+    }
+
+    Ok(())
+}
+
+fn corpus_dir() -> PathBuf {
+    std::env::var_os("BSON_CORPUS_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("test/bson-corpus"))
+}
+
+#[test]
+fn corpus() {
+    let dir = corpus_dir();
+
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        eprintln!(
+            "skipping the bson corpus, nothing found at {}. Fetch it with \
+             test/fetch-bson-corpus.sh",
+            dir.display()
+        );
+        return;
+    };
+
+    let mut files = entries
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect::<Vec<_>>();
+    files.sort();
+
+    let mut report = Report::default();
+
+    for file in files {
+        let suite: Suite = serde_json::from_slice(&std::fs::read(&file).unwrap())
+            .unwrap_or_else(|e| panic!("{}: {}", file.display(), e));
+        run(&suite, &mut report);
+    }
+
+    println!(
+        "{}\n{} passed, {} only failed through serde, {} failed",
+        report.out, report.passed, report.serde_failures, report.failures
+    );
+    assert_eq!(report.failures, 0, "{}", report.out);
+}
+
+/// Checks the runner itself against a suite in the corpus format.
+#[test]
+fn runner() {
+    let suite: Suite = serde_json::from_str(
+        r#"{
+            "description": "Int32 type",
+            "bson_type": "0x10",
+            "test_key": "i",
+            "valid": [
+                { "description": "1", "canonical_bson": "0C0000001069000100000000" }
+            ],
+            "decodeErrors": [
+                { "description": "length too long", "bson": "0D0000001069000100000000" },
+                { "description": "truncated", "bson": "0C00000010690001000000" }
+            ]
+        }"#,
+    )
+    .unwrap();
+
+    let mut report = Report::default();
+    run(&suite, &mut report);
+    assert_eq!((report.passed, report.failures), (3, 0), "{}", report.out);
+
+    // and that failures are caught
+    let suite = Suite {
+        bson_type: "0x10".to_string(),
+        valid: vec![Valid {
+            description: "truncated".to_string(),
+            canonical_bson: "0C00000010690001000000".to_string(),
+            degenerate_bson: None,
+        }],
+        decode_errors: vec![DecodeError {
+            description: "valid".to_string(),
+            bson: "0C0000001069000100000000".to_string(),
+        }],
+    };
+
+    let mut report = Report::default();
+    run(&suite, &mut report);
+    assert_eq!(report.failures, 2, "{}", report.out);
+}
@@ -1,28 +1,146 @@
+use crate::{
+    types::{binary_subtype_for, MAX_KEY_NAME, MIN_KEY_NAME, UNDEFINED_NAME},
+    Decimal128, Path, RawBson, Stats,
+};
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use bytes::{Bytes, BytesMut};
+#[cfg(feature = "std")]
+use core::cell::RefCell;
+use core::{
+    convert::{TryFrom, TryInto},
+    fmt::{self, Display},
+    marker::PhantomData,
+    num::NonZeroUsize,
+};
 use memchr::memchr;
-use std::{cell::RefCell, convert::TryInto, fmt::Display};
 
 use serde::{
     de::{
-        value::BorrowedStrDeserializer, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
-        VariantAccess, Visitor,
+        value::{
+            BorrowedBytesDeserializer, BorrowedStrDeserializer, MapDeserializer, SeqDeserializer,
+        },
+        Deserialize, DeserializeOwned, DeserializeSeed, EnumAccess, IgnoredAny, IntoDeserializer,
+        MapAccess, SeqAccess, VariantAccess, Visitor,
     },
     forward_to_deserialize_any, Deserializer,
 };
 
-#[derive(thiserror::Error, Debug)]
+#[derive(Debug)]
 pub enum Error {
-    #[error("unexpected map end")]
     UnexpectedMapEnd,
-    #[error("unexpected key")]
     UnexpectedKey,
-    #[error("end of file")]
     EndOfFile,
-    #[error("custom: {0}")]
     Custom(String),
-    #[error("malformed map, missing key or document end")]
     MalformedMapMissingKey,
-    #[error("unexpected enum")]
     UnexpectedEnum,
+    ExpectedChar,
+    ExpectedInteger,
+    IntegerOutOfRange(i64, &'static str),
+    Decimal128OutOfRange(&'static str),
+    InvalidExtendedJson,
+    UnexpectedKeySentinel(&'static str),
+    UnknownElementType(u8, usize),
+    DuplicateKey(String, usize),
+    InvalidLength(usize),
+    UnterminatedCString(usize),
+    InvalidUtf8(usize),
+    InvalidBoolean(usize),
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+    Located {
+        offset: usize,
+        element_type: u8,
+        path: Path,
+        error: Box<Error>,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedMapEnd => write!(f, "unexpected map end"),
+            Self::UnexpectedKey => write!(f, "unexpected key"),
+            Self::EndOfFile => write!(f, "end of file"),
+            Self::Custom(msg) => write!(f, "custom: {}", msg),
+            Self::MalformedMapMissingKey => {
+                write!(f, "malformed map, missing key or document end")
+            }
+            Self::UnexpectedEnum => write!(f, "unexpected enum"),
+            Self::ExpectedChar => write!(f, "expected a single character string"),
+            Self::ExpectedInteger => write!(f, "expected an integer"),
+            Self::IntegerOutOfRange(v, ty) => write!(f, "integer {} out of range for {}", v, ty),
+            Self::Decimal128OutOfRange(ty) => {
+                write!(f, "decimal128 is not an integer in range for {}", ty)
+            }
+            Self::InvalidExtendedJson => write!(f, "invalid extended json wrapper"),
+            Self::UnexpectedKeySentinel(name) => write!(f, "expected {}", name),
+            Self::UnknownElementType(ty, offset) => {
+                write!(f, "unknown element type {:#04x} at offset {}", ty, offset)
+            }
+            Self::DuplicateKey(key, offset) => {
+                write!(f, "duplicate key {:?} at offset {}", key, offset)
+            }
+            Self::InvalidLength(offset) => write!(f, "invalid length at offset {}", offset),
+            Self::UnterminatedCString(offset) => {
+                write!(f, "unterminated c-string at offset {}", offset)
+            }
+            Self::InvalidUtf8(offset) => write!(f, "invalid utf-8 at offset {}", offset),
+            Self::InvalidBoolean(offset) => write!(f, "invalid boolean at offset {}", offset),
+            #[cfg(feature = "std")]
+            Self::Io(e) => write!(f, "io error: {}", e),
+            Self::Located {
+                offset,
+                element_type,
+                path,
+                error,
+            } => write!(
+                f,
+                "{} at {} (offset {}, element type {:#04x})",
+                error, path, offset, element_type
+            ),
+        }
+    }
+}
+
+// `std::error::Error` with std, or serde's stand-in for it without
+impl serde::de::StdError for Error {}
+
+impl Error {
+    /// The byte offset into the input that the error occurred at, if known.
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            Self::UnknownElementType(_, offset)
+            | Self::DuplicateKey(_, offset)
+            | Self::InvalidLength(offset)
+            | Self::UnterminatedCString(offset)
+            | Self::InvalidUtf8(offset)
+            | Self::InvalidBoolean(offset)
+            | Self::Located { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// The path to the field that caused the error, if known.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Located { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// The underlying error with any location information stripped off.
+    pub fn inner(&self) -> &Error {
+        match self {
+            Self::Located { error, .. } => error.inner(),
+            other => other,
+        }
+    }
 }
 
 impl serde::de::Error for Error {
@@ -34,365 +152,3858 @@ impl serde::de::Error for Error {
     }
 }
 
+#[cfg(feature = "std")]
 thread_local! {
     static ALLOCATOR: RefCell<bumpalo::Bump> = RefCell::new(bumpalo::Bump::new());
 }
 
+/// Options controlling how input is parsed by [`from_bytes_with_options`].
+#[derive(Clone, Copy, Debug)]
+pub struct Options {
+    reject_duplicate_keys: bool,
+    max_retained_arena_bytes: Option<usize>,
+    undefined_as_null: bool,
+    invalid_utf8: InvalidUtf8,
+    assume_valid_utf8: bool,
+    human_readable: bool,
+    bson_types: BsonTypes,
+    redact_sensitive: bool,
+    coerce_numbers: bool,
+    null_as_default: bool,
+    extended_json_scalars: bool,
+}
+
+/// What to do with string values that aren't valid UTF-8, see [`Options::invalid_utf8`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InvalidUtf8 {
+    /// Fail deserialization with [`Error::InvalidUtf8`], pointing at the offending string.
+    #[default]
+    Error,
+    /// Replace invalid sequences with `U+FFFD REPLACEMENT CHARACTER`. The string can no longer
+    /// be borrowed from the input so is handed to the visitor as an owned `String`.
+    Replace,
+}
+
+/// How values of types that serde has no equivalent for are handed to self-describing types such
+/// as `serde_json::Value`, see [`Options::bson_types`]. Types that ask for a specific
+/// representation, such as [`Decimal128`] asking for bytes, are unaffected.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BsonTypes {
+    /// ObjectIds, binaries and decimal128s as bytes, datetimes as milliseconds since the epoch,
+    /// timestamps as a `u64` and the rest as whichever of serde's types they're closest to.
+    #[default]
+    Native,
+    /// The same as [`BsonTypes::Native`], except ObjectIds are hex strings, binaries are base64
+    /// strings, datetimes are RFC 3339 strings where the year is between 0 and 9999 and
+    /// decimal128s are strings.
+    Strings,
+    /// Extended JSON wrapper objects such as `{"$oid": "..."}` and
+    /// `{"$date": {"$numberLong": "..."}}`, so the exact type survives a round trip through JSON.
+    /// Numbers are left as plain numbers, as they are in relaxed Extended JSON.
+    ExtendedJson,
+}
+
+impl BsonTypes {
+    /// Whether `value` is visited differently than it would be natively.
+    #[inline(always)]
+    fn wraps(self, value: &Tape<'_>) -> bool {
+        match self {
+            Self::Native => false,
+            Self::Strings => matches!(
+                value,
+                Tape::ObjectId(_) | Tape::Binary(..) | Tape::UtcDateTime(_) | Tape::Decimal128(_)
+            ),
+            Self::ExtendedJson => matches!(
+                value,
+                Tape::ObjectId(_)
+                    | Tape::Binary(..)
+                    | Tape::UtcDateTime(_)
+                    | Tape::Decimal128(_)
+                    | Tape::Timestamp(_)
+                    | Tape::Regex { .. }
+                    | Tape::Code(_)
+                    | Tape::Symbol(_)
+                    | Tape::Undefined
+                    | Tape::MinKey
+                    | Tape::MaxKey
+            ),
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            reject_duplicate_keys: false,
+            max_retained_arena_bytes: None,
+            undefined_as_null: true,
+            invalid_utf8: InvalidUtf8::Error,
+            assume_valid_utf8: false,
+            human_readable: false,
+            bson_types: BsonTypes::Native,
+            redact_sensitive: false,
+            coerce_numbers: false,
+            null_as_default: false,
+            extended_json_scalars: false,
+        }
+    }
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return an error when the same key appears more than once in a single document. Disabled
+    /// by default.
+    pub fn reject_duplicate_keys(mut self, reject: bool) -> Self {
+        self.reject_duplicate_keys = reject;
+        self
+    }
+
+    /// Frees the thread-local arena used by [`from_bytes`] once deserialization finishes if it
+    /// has grown beyond the given number of bytes, otherwise the arena holds onto its peak
+    /// capacity for the life of the thread. Unbounded by default.
+    pub fn max_retained_arena_bytes(mut self, max: Option<usize>) -> Self {
+        self.max_retained_arena_bytes = max;
+        self
+    }
+
+    /// Treat the deprecated undefined element type the same as null, disable this to
+    /// deserialise them into [`crate::Undefined`] instead. Enabled by default.
+    pub fn undefined_as_null(mut self, undefined_as_null: bool) -> Self {
+        self.undefined_as_null = undefined_as_null;
+        self
+    }
+
+    /// How to handle string values that aren't valid UTF-8, by default the whole document is
+    /// rejected. Keys must always be valid UTF-8.
+    pub fn invalid_utf8(mut self, invalid_utf8: InvalidUtf8) -> Self {
+        self.invalid_utf8 = invalid_utf8;
+        self
+    }
+
+    /// Skip UTF-8 validation of keys and strings, for input that's already known to be valid
+    /// such as documents written by this crate or validated further upstream. Disabled by
+    /// default.
+    ///
+    /// # Safety
+    ///
+    /// Every key and string in the input passed alongside these options must be valid UTF-8,
+    /// they're handed out as `&str`s without being checked.
+    pub unsafe fn assume_valid_utf8(mut self, assume_valid_utf8: bool) -> Self {
+        self.assume_valid_utf8 = assume_valid_utf8;
+        self
+    }
+
+    /// What [`serde::Deserializer::is_human_readable`] reports, this should match the
+    /// [`crate::ser::Options::human_readable`] the input was written with so types read back the
+    /// same representation they wrote. Disabled by default.
+    pub fn human_readable(mut self, human_readable: bool) -> Self {
+        self.human_readable = human_readable;
+        self
+    }
+
+    /// How ObjectIds, binaries, datetimes and the like are represented to types that'll take
+    /// anything, such as `serde_json::Value`. By default they're handed over as the closest
+    /// native type, which isn't always something JSON can hold.
+    pub fn bson_types(mut self, bson_types: BsonTypes) -> Self {
+        self.bson_types = bson_types;
+        self
+    }
+
+    /// Hand sensitive binaries (subtype 0x08) to types that'll take anything as the string
+    /// [`crate::redact::REDACTED`] rather than their contents, for deserialising documents that
+    /// are about to be logged. [`crate::Sensitive`] still receives the payload. Disabled by
+    /// default.
+    pub fn redact_sensitive(mut self, redact_sensitive: bool) -> Self {
+        self.redact_sensitive = redact_sensitive;
+        self
+    }
+
+    /// Read doubles into integer types when they hold a whole number that fits, such as `3.0`
+    /// into a `u32`, rather than failing. JavaScript has a single number type so documents
+    /// written from it often hold doubles where an integer was meant. Anything with a
+    /// fractional part is still rejected. Integers are always accepted by floating point types.
+    /// Disabled by default.
+    pub fn coerce_numbers(mut self, coerce_numbers: bool) -> Self {
+        self.coerce_numbers = coerce_numbers;
+        self
+    }
+
+    /// Read nulls into types other than `Option` as their empty value, such as `0`, `false`,
+    /// an empty string or an empty `Vec`, rather than failing, for documents that use null and
+    /// a missing field interchangeably. A null struct is read as an empty document, so only
+    /// succeeds if every field has a default. Types that'll take anything, such as
+    /// `serde_json::Value`, still receive the null. Disabled by default.
+    pub fn null_as_default(mut self, null_as_default: bool) -> Self {
+        self.null_as_default = null_as_default;
+        self
+    }
+
+    /// Read the Extended JSON wrappers `{"$numberLong": "..."}`, `{"$date": ...}` and
+    /// `{"$oid": "..."}` into integer types and types asking for an ObjectId's bytes, as if they
+    /// were an int64, datetime or ObjectId, for documents that went through a JSON conversion
+    /// that didn't understand them. Datetimes can be given as a `$numberLong`, an RFC 3339
+    /// string or a plain integer. Disabled by default.
+    pub fn extended_json_scalars(mut self, extended_json_scalars: bool) -> Self {
+        self.extended_json_scalars = extended_json_scalars;
+        self
+    }
+}
+
+/// The value held by an Extended JSON wrapper, see [`Options::extended_json_scalars`].
+enum ExtendedJsonScalar {
+    Integer(i64),
+    ObjectId([u8; 12]),
+}
+
 pub fn from_bytes<'de, D: serde::de::Deserialize<'de>>(data: &'de [u8]) -> Result<D, Error> {
+    from_bytes_with_options(data, Options::default())
+}
+
+pub fn from_bytes_with_options<'de, D: serde::de::Deserialize<'de>>(
+    data: &'de [u8],
+    options: Options,
+) -> Result<D, Error> {
+    from_bytes_seed_with_options(data, PhantomData::<D>, options)
+}
+
+/// Deserializes `data` into a type that doesn't borrow from it, so the input can be dropped as
+/// soon as this returns. Strings and bytes are copied out of the input as they're visited.
+pub fn from_slice_owned<D: DeserializeOwned>(data: &[u8]) -> Result<D, Error> {
+    from_slice_owned_with_options(data, Options::default())
+}
+
+pub fn from_slice_owned_with_options<D: DeserializeOwned>(
+    data: &[u8],
+    options: Options,
+) -> Result<D, Error> {
+    from_bytes_with_options(data, options)
+}
+
+/// Deserializes `data` the same as [`from_slice_owned`], but [`crate::SharedStr`]s and fields
+/// using [`crate::shared`] are slices of `data` rather than copies, keeping it alive until
+/// they're dropped.
+#[cfg(feature = "std")]
+pub fn from_bytes_shared<D: DeserializeOwned>(data: Bytes) -> Result<D, Error> {
+    from_bytes_shared_with_options(data, Options::default())
+}
+
+#[cfg(feature = "std")]
+pub fn from_bytes_shared_with_options<D: DeserializeOwned>(
+    data: Bytes,
+    options: Options,
+) -> Result<D, Error> {
+    crate::shared::with_source(&data, || from_bytes_with_options(&data, options))
+}
+
+/// Deserializes `data` using the given [`DeserializeSeed`], for when deserialization needs some
+/// state passed into it, such as an arena or interner.
+pub fn from_bytes_seed<'de, S: DeserializeSeed<'de>>(
+    data: &'de [u8],
+    seed: S,
+) -> Result<S::Value, Error> {
+    from_bytes_seed_with_options(data, seed, Options::default())
+}
+
+pub fn from_bytes_seed_with_options<'de, S: DeserializeSeed<'de>>(
+    data: &'de [u8],
+    seed: S,
+    options: Options,
+) -> Result<S::Value, Error> {
+    with_arena(options, |bump| {
+        from_bytes_seed_in(bump, data, seed, options)
+    })
+}
+
+/// Deserializes `data` the same as [`from_bytes_with_options`], adding what was read to `stats`
+/// if it succeeds. See [`Stats`] for what's gathered.
+pub fn from_bytes_with_stats<'de, D: serde::de::Deserialize<'de>>(
+    data: &'de [u8],
+    options: Options,
+    stats: &mut Stats,
+) -> Result<D, Error> {
+    with_arena(options, |bump| {
+        deserialize_in(bump, data, PhantomData::<D>, options, Some(stats))
+    })
+}
+
+/// Calls `f` with the thread-local arena, freshly reset.
+fn with_arena<R>(options: Options, f: impl FnOnce(&bumpalo::Bump) -> R) -> R {
+    // there's nowhere to keep an arena between calls without thread locals
+    #[cfg(not(feature = "std"))]
+    return {
+        let _ = options;
+        f(&bumpalo::Bump::new())
+    };
+
+    #[cfg(feature = "std")]
     ALLOCATOR.with_borrow_mut(|allocator| {
         allocator.reset();
+        let res = f(allocator);
 
-        let mut tape = bumpalo::collections::Vec::new_in(allocator);
-        to_tape(data, &mut tape);
-        D::deserialize(&mut BsonDeserializer { tape: &tape })
+        if let Some(max) = options.max_retained_arena_bytes {
+            if allocator.allocated_bytes() > max {
+                *allocator = bumpalo::Bump::new();
+            }
+        }
+
+        res
     })
 }
 
-struct BsonDeserializer<'a, 'de> {
-    tape: &'a [Tape<'de>],
+/// Deserializes `data` using `bump` for scratch space rather than the thread-local arena used
+/// by [`from_bytes`], the arena is never reset so it's up to the caller to decide when to reclaim
+/// the memory.
+pub fn from_bytes_in<'de, D: serde::de::Deserialize<'de>>(
+    bump: &bumpalo::Bump,
+    data: &'de [u8],
+) -> Result<D, Error> {
+    from_bytes_in_with_options(bump, data, Options::default())
 }
 
-impl<'a, 'de> BsonDeserializer<'a, 'de> {
-    fn next_item(&mut self) -> Option<&'a Tape<'de>> {
-        let (next, rest) = self.tape.split_first()?;
-        self.tape = rest;
-        Some(next)
-    }
+pub fn from_bytes_in_with_options<'de, D: serde::de::Deserialize<'de>>(
+    bump: &bumpalo::Bump,
+    data: &'de [u8],
+    options: Options,
+) -> Result<D, Error> {
+    from_bytes_seed_in(bump, data, PhantomData::<D>, options)
 }
 
-impl<'de> Deserializer<'de> for &mut BsonDeserializer<'_, 'de> {
-    type Error = Error;
+pub fn from_bytes_seed_in<'de, S: DeserializeSeed<'de>>(
+    bump: &bumpalo::Bump,
+    data: &'de [u8],
+    seed: S,
+    options: Options,
+) -> Result<S::Value, Error> {
+    deserialize_in(bump, data, seed, options, None)
+}
 
-    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
-    where
-        V: Visitor<'de>,
-    {
-        match self.next_item() {
-            Some(Tape::DocumentStart) => visitor.visit_map(self),
-            Some(Tape::DocumentEnd) => Err(Error::UnexpectedMapEnd),
-            Some(Tape::Key(_)) => Err(Error::UnexpectedKey),
-            Some(Tape::Double(value)) => visitor.visit_f64(*value),
-            Some(Tape::String(value)) => visitor.visit_borrowed_str(value),
-            Some(Tape::ArrayStart) => self.deserialize_seq(visitor),
-            Some(Tape::Binary(value, _)) => visitor.visit_borrowed_bytes(value),
-            Some(Tape::Boolean(value)) => visitor.visit_bool(*value),
-            Some(Tape::UtcDateTime(value)) => visitor.visit_i64(*value),
-            Some(Tape::Null) => visitor.visit_none(),
-            Some(Tape::I32(value)) => visitor.visit_i32(*value),
-            Some(Tape::Timestamp(value)) => visitor.visit_u64(*value),
-            Some(Tape::I64(value)) => visitor.visit_i64(*value),
-            None => Err(Error::EndOfFile),
+fn deserialize_in<'de, S: DeserializeSeed<'de>>(
+    bump: &bumpalo::Bump,
+    data: &'de [u8],
+    seed: S,
+    options: Options,
+    stats: Option<&mut Stats>,
+) -> Result<S::Value, Error> {
+    crate::trace::deserialize(data, || {
+        let mut deserializer = BsonDeserializer {
+            tape: LazyTape::new_in(data, options, bump)?,
+            options,
+        };
+
+        if stats.is_some() {
+            deserializer.tape.tokenizer.tracking = Some(Tracking::new());
         }
-    }
 
-    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
-    where
-        V: Visitor<'de>,
-    {
-        if let Some(Tape::ArrayStart) = self.tape.first() {
-            self.tape = &self.tape[1..];
+        let value = seed.deserialize(&mut deserializer).map_err(|error| {
+            // point at the last entry we took off the tape, which is the one that we failed on
+            let index = deserializer.tape.taken.saturating_sub(1);
+            locate_error(data, options, index, error)
+        })?;
+
+        if let (Some(stats), Some(tracking)) = (stats, deserializer.tape.tokenizer.tracking) {
+            stats.document(deserializer.tape.tokenizer.input.len());
+            stats.merge(&tracking.stats);
         }
 
-        let res = visitor.visit_seq(&mut *self)?;
+        Ok(value)
+    })
+}
 
-        let Some(Tape::DocumentEnd) = self.next_item() else {
-            return Err(Error::UnexpectedMapEnd);
+/// Deserializes `data` in a single pass, reading elements out of the input as they're asked for
+/// rather than building a tape of the whole document up front like [`from_bytes`] does. Memory
+/// use no longer grows with the size of the document, at the cost of some speed.
+pub fn from_bytes_streaming<'de, D: serde::de::Deserialize<'de>>(
+    data: &'de [u8],
+) -> Result<D, Error> {
+    from_bytes_streaming_with_options(data, Options::default())
+}
+
+pub fn from_bytes_streaming_with_options<'de, D: serde::de::Deserialize<'de>>(
+    data: &'de [u8],
+    options: Options,
+) -> Result<D, Error> {
+    // only used to keep track of seen keys if we've been asked to
+    let bump = bumpalo::Bump::new();
+
+    crate::trace::deserialize(data, || {
+        let mut deserializer = BsonDeserializer {
+            tape: Streaming {
+                tokenizer: Tokenizer::new_in(data, options, &bump)?,
+                queued: Some(Tape::DocumentStart),
+                peeked: None,
+                taken: 0,
+            },
+            options,
         };
 
-        Ok(res)
-    }
+        D::deserialize(&mut deserializer).map_err(|error| {
+            let index = deserializer.tape.taken.saturating_sub(1);
+            locate_error(data, options, index, error)
+        })
+    })
+}
 
-    fn deserialize_enum<V>(
-        self,
-        _name: &'static str,
-        _variants: &'static [&'static str],
-        visitor: V,
-    ) -> Result<V::Value, Self::Error>
-    where
-        V: Visitor<'de>,
-    {
-        match self.next_item() {
-            Some(Tape::String(s)) => visitor.visit_enum(s.into_deserializer()),
-            Some(Tape::DocumentStart) => {
-                let data = visitor.visit_enum(&mut EnumDeserializer { deser: &mut *self })?;
+/// Picks out the values at each of `paths` in the document `data`, where paths are dot separated
+/// keys or array indexes such as `items.0.id`. Elements that aren't on the way to one of the
+/// paths are stepped over using their length without being looked at, so this is much cheaper
+/// than deserializing the whole document when only a couple of fields are needed.
+///
+/// Values are returned in the same order as `paths`, with `None` for paths that don't exist, and
+/// can be deserialized with [`from_raw`].
+pub fn project<'a>(data: &'a [u8], paths: &[&str]) -> Result<Vec<Option<RawBson<'a>>>, Error> {
+    paths
+        .iter()
+        .map(|path| crate::scan::find(data, path.split('.')))
+        .collect()
+}
 
-                let Some(Tape::DocumentEnd) = self.next_item() else {
-                    return Err(Error::UnexpectedMapEnd);
-                };
+/// Deserializes the value of `key` from the top level of the document `data` without looking at
+/// any of the document's other elements, or `None` if it doesn't have the key. Handy for peeking
+/// at an `_id` or a type tag before deciding what to do with the rest of the document.
+pub fn get_field<'de, D: serde::de::Deserialize<'de>>(
+    data: &'de [u8],
+    key: &str,
+) -> Result<Option<D>, Error> {
+    crate::scan::find(data, [key])?.map(from_raw).transpose()
+}
 
-                Ok(data)
-            }
-            Some(Tape::ArrayStart) => {
-                let data = visitor.visit_enum(&mut EnumDeserializer { deser: &mut *self })?;
+/// Deserializes a single raw value, such as one returned from [`project`].
+pub fn from_raw<'de, D: serde::de::Deserialize<'de>>(raw: RawBson<'de>) -> Result<D, Error> {
+    from_raw_with_options(raw, Options::default())
+}
 
-                let Some(Tape::DocumentEnd) = self.next_item() else {
-                    return Err(Error::UnexpectedMapEnd);
-                };
+pub fn from_raw_with_options<'de, D: serde::de::Deserialize<'de>>(
+    raw: RawBson<'de>,
+    options: Options,
+) -> Result<D, Error> {
+    // only used to keep track of seen keys if we've been asked to
+    let bump = bumpalo::Bump::new();
 
-                Ok(data)
-            }
-            _ => Err(Error::UnexpectedEnum),
-        }
-    }
+    let mut tokenizer = Tokenizer::new_value_in(raw.as_bytes(), options, &bump);
+    let value = tokenizer.take_value(raw.element_type())?;
 
-    forward_to_deserialize_any! {
-        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes
-        byte_buf option unit unit_struct newtype_struct tuple tuple_struct
-        map struct identifier ignored_any
-    }
+    let mut deserializer = BsonDeserializer {
+        tape: Streaming {
+            tokenizer,
+            queued: Some(value),
+            peeked: None,
+            taken: 0,
+        },
+        options,
+    };
+
+    D::deserialize(&mut deserializer)
 }
 
-struct EnumDeserializer<'a, 'b, 'de> {
-    deser: &'b mut BsonDeserializer<'a, 'de>,
+/// Reads the length prefix of the document at the start of `data`, without looking any further
+/// into it or checking that `data` holds all of it. `None` if there's less than four bytes or
+/// the length is too small for a document.
+pub fn document_len(data: &[u8]) -> Option<NonZeroUsize> {
+    let length = i32::from_le_bytes(data.get(..4)?.try_into().unwrap());
+
+    usize::try_from(length)
+        .ok()
+        .filter(|length| *length >= 5)
+        .and_then(NonZeroUsize::new)
 }
 
-impl<'de> Deserializer<'de> for &mut EnumDeserializer<'_, '_, 'de> {
-    type Error = Error;
+/// Splits `data` into back-to-back documents using only their length prefixes, stopping at the
+/// first one that's malformed or cut short. Use [`documents`] to find out if that happened.
+pub fn split_documents(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    documents(data).map_while(Result::ok)
+}
 
-    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
-    where
-        V: Visitor<'de>,
-    {
-        if let Some(Tape::Key(key)) = self.deser.tape.first() {
-            self.deser.tape = &self.deser.tape[1..];
-            visitor.visit_borrowed_str(key)
-        } else {
-            self.deser.deserialize_any(visitor)
-        }
+/// Iterates over `data` as a series of back-to-back documents, as found in the dumps written by
+/// `mongodump`, without copying them. Each item is the raw bytes of a document which can be
+/// passed into [`from_bytes`], iteration stops after the first error.
+pub fn documents(data: &[u8]) -> Documents<'_> {
+    Documents {
+        data,
+        position: 0,
+        failed: false,
     }
+}
 
-    forward_to_deserialize_any! {
-        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes
-        byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct
-        map struct enum identifier ignored_any
-    }
+/// Iterator returned by [`documents`].
+#[derive(Debug, Clone)]
+pub struct Documents<'a> {
+    data: &'a [u8],
+    position: usize,
+    failed: bool,
 }
 
-impl<'de> VariantAccess<'de> for &mut EnumDeserializer<'_, '_, 'de> {
-    type Error = Error;
+impl<'a> Iterator for Documents<'a> {
+    type Item = Result<&'a [u8], Error>;
 
-    fn unit_variant(self) -> Result<(), Self::Error> {
-        unreachable!()
-    }
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.position == self.data.len() {
+            return None;
+        }
 
-    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
-    where
-        T: serde::de::DeserializeSeed<'de>,
-    {
-        seed.deserialize(self)
-    }
+        let rest = &self.data[self.position..];
+        let document = document_len(rest)
+            .map(NonZeroUsize::get)
+            .filter(|length| *length <= rest.len())
+            .map(|length| &rest[..length]);
 
-    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
-    where
-        V: Visitor<'de>,
-    {
-        self.deserialize_seq(visitor)
-    }
+        let Some(document) = document else {
+            self.failed = true;
+            return Some(Err(Error::InvalidLength(self.position)));
+        };
 
-    fn struct_variant<V>(
-        self,
-        _fields: &'static [&'static str],
-        visitor: V,
-    ) -> Result<V::Value, Self::Error>
-    where
-        V: Visitor<'de>,
-    {
-        self.deserialize_map(visitor)
+        self.position += document.len();
+        Some(Ok(document))
     }
 }
 
-impl<'de> EnumAccess<'de> for &mut EnumDeserializer<'_, '_, 'de> {
-    type Error = Error;
-    type Variant = Self;
+/// Memory-maps the file at `path` for iterating over the documents in it, see [`MappedFile`].
+#[cfg(feature = "mmap")]
+pub fn from_file(path: impl AsRef<std::path::Path>) -> std::io::Result<MappedFile> {
+    let file = std::fs::File::open(path)?;
 
-    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
-    where
-        V: serde::de::DeserializeSeed<'de>,
-    {
-        let value = seed.deserialize(&mut *self)?;
+    // SAFETY: see the caveats on MappedFile
+    let map = unsafe { memmap2::Mmap::map(&file)? };
 
-        Ok((value, self))
+    Ok(MappedFile { map })
+}
+
+/// A memory-mapped file of back-to-back documents returned by [`from_file`], for working with
+/// dumps that are too large to comfortably read into memory.
+///
+/// The file must not be modified while it's mapped, the documents borrowed from it may change
+/// underneath us otherwise.
+#[cfg(feature = "mmap")]
+#[derive(Debug)]
+pub struct MappedFile {
+    map: memmap2::Mmap,
+}
+
+#[cfg(feature = "mmap")]
+impl MappedFile {
+    pub fn documents(&self) -> Documents<'_> {
+        documents(&self.map)
     }
 }
 
-impl<'de> MapAccess<'de> for BsonDeserializer<'_, 'de> {
-    type Error = Error;
+#[cfg(feature = "mmap")]
+impl std::ops::Deref for MappedFile {
+    type Target = [u8];
 
-    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
-    where
-        K: serde::de::DeserializeSeed<'de>,
-    {
-        let data = match self.next_item() {
-            Some(Tape::DocumentEnd) => return Ok(None),
-            Some(Tape::Key(key)) => key,
-            _ => return Err(Error::MalformedMapMissingKey),
-        };
+    fn deref(&self) -> &[u8] {
+        &self.map
+    }
+}
 
-        seed.deserialize(BorrowedStrDeserializer::new(data))
-            .map(Some)
+/// Incrementally splits back-to-back documents out of bytes as they're fed in, without doing any
+/// IO itself, so it can be driven by whatever event loop the bytes come from. Complete documents
+/// are split off the internal buffer without copying and can be passed to
+/// [`from_bytes_shared`].
+///
+/// ```
+/// # use serde_bson::de::Parser;
+/// # let mut bytes = Vec::new();
+/// # serde_bson::to_vec(&std::collections::BTreeMap::from([("a", 1)]), &mut bytes).unwrap();
+/// let mut parser = Parser::new();
+///
+/// for chunk in bytes.chunks(3) {
+///     parser.feed(chunk);
+///
+///     while let Some(document) = parser.next_document()? {
+///         let value: std::collections::BTreeMap<String, i32> =
+///             serde_bson::de::from_bytes_shared(document)?;
+///     }
+/// }
+///
+/// parser.finish()?;
+/// # Ok::<(), serde_bson::de::Error>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct Parser {
+    buffer: BytesMut,
+    position: usize,
+    failed: bool,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
-    where
-        V: serde::de::DeserializeSeed<'de>,
-    {
-        seed.deserialize(self)
+    /// Appends `data` to the bytes waiting to be parsed.
+    pub fn feed(&mut self, data: &[u8]) {
+        if !self.failed {
+            self.buffer.extend_from_slice(data);
+        }
     }
-}
 
-impl<'de> SeqAccess<'de> for BsonDeserializer<'_, 'de> {
-    type Error = Error;
+    /// Splits off the next document if all of it has been fed in. Once a document with an
+    /// invalid length is found every call returns that error, as there's no way of finding the
+    /// start of the next one.
+    pub fn next_document(&mut self) -> Result<Option<Bytes>, Error> {
+        if self.failed {
+            return Err(Error::InvalidLength(self.position));
+        }
 
-    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
-    where
-        T: serde::de::DeserializeSeed<'de>,
-    {
-        if let Some(Tape::DocumentEnd) = self.tape.first() {
+        if self.buffer.len() < 4 {
             return Ok(None);
         }
 
-        let Some(Tape::Key(_)) = self.next_item() else {
-            return Err(Error::MalformedMapMissingKey);
+        let Some(length) = document_len(&self.buffer).map(NonZeroUsize::get) else {
+            self.failed = true;
+            self.buffer.clear();
+            return Err(Error::InvalidLength(self.position));
         };
 
-        seed.deserialize(self).map(Some)
+        if self.buffer.len() < length {
+            self.buffer.reserve(length - self.buffer.len());
+            return Ok(None);
+        }
+
+        self.position += length;
+        Ok(Some(self.buffer.split_to(length).freeze()))
+    }
+
+    /// The number of bytes fed in that aren't yet part of a complete document.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Checks the input ended cleanly between documents rather than part way through one.
+    pub fn finish(&self) -> Result<(), Error> {
+        if self.failed {
+            Err(Error::InvalidLength(self.position))
+        } else if !self.buffer.is_empty() {
+            Err(Error::EndOfFile)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Reads back-to-back documents from `reader` as they arrive, deserialising each once it's been
+/// read in full. Documents split across reads are buffered until the rest turns up, the stream
+/// ends after the first error.
+#[cfg(feature = "futures")]
+pub fn document_stream<R, D>(reader: R) -> DocumentStream<R, D>
+where
+    R: futures_io::AsyncRead + Unpin,
+    D: DeserializeOwned,
+{
+    document_stream_with_options(reader, Options::default())
+}
+
+#[cfg(feature = "futures")]
+pub fn document_stream_with_options<R, D>(reader: R, options: Options) -> DocumentStream<R, D>
+where
+    R: futures_io::AsyncRead + Unpin,
+    D: DeserializeOwned,
+{
+    DocumentStream {
+        reader,
+        options,
+        parser: Parser::new(),
+        chunk: vec![0; 8 * 1024],
+        done: false,
+        marker: PhantomData,
     }
 }
 
+/// Stream returned by [`document_stream`].
+#[cfg(feature = "futures")]
 #[derive(Debug)]
-pub enum Tape<'a> {
-    DocumentStart,        // start of input or 0x03
-    DocumentEnd,          // 0x00
-    Key(&'a str),         //
-    Double(f64),          // 0x01
-    String(&'a str),      // 0x02
-    ArrayStart,           // 0x04
-    Binary(&'a [u8], u8), // 0x05
-    Boolean(bool),        // 0x08
-    UtcDateTime(i64),     // 0x09
-    Null,                 // 0x0a
-    I32(i32),             // 0x10
-    Timestamp(u64),       // 0x11
-    I64(i64),             // 0x12
-}
-
-fn to_tape<'a>(input: &'a [u8], tape: &mut bumpalo::collections::Vec<'_, Tape<'a>>) {
-    let length = u32::from_le_bytes([input[0], input[1], input[2], input[3]]) as usize;
-
-    let input = &input[4..length];
-
-    let mut position = 0;
-    tape.push(Tape::DocumentStart);
+pub struct DocumentStream<R, D> {
+    reader: R,
+    options: Options,
+    parser: Parser,
+    chunk: Vec<u8>,
+    done: bool,
+    marker: PhantomData<fn() -> D>,
+}
 
-    let take_cstring = |position: &mut usize| {
-        let idx = memchr(b'\0', &input[*position..]).expect("unterminated c-string");
-        let s = simdutf8::basic::from_utf8(&input[*position..*position + idx]).unwrap();
-        *position += idx + 1;
-        s
-    };
+#[cfg(feature = "futures")]
+impl<R, D> futures_core::Stream for DocumentStream<R, D>
+where
+    R: futures_io::AsyncRead + Unpin,
+    D: DeserializeOwned,
+{
+    type Item = Result<D, Error>;
 
-    let take_bytes = |position: &mut usize, n| {
-        let res = &input[*position..*position + n];
-        *position += n;
-        res
-    };
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        use std::task::Poll;
 
-    while position < length - 4 {
-        position += 1;
-        match input[position - 1] {
-            0x00 => {
-                tape.push(Tape::DocumentEnd);
-            }
-            0x01 => {
-                let key = take_cstring(&mut position);
-                let value = f64::from_le_bytes(take_bytes(&mut position, 8).try_into().unwrap());
-                tape.push(Tape::Key(key));
-                tape.push(Tape::Double(value));
+        let this = &mut *self;
+
+        while !this.done {
+            match this.parser.next_document() {
+                Ok(Some(document)) => {
+                    let res = from_bytes_shared_with_options(document, this.options);
+                    this.done = res.is_err();
+                    return Poll::Ready(Some(res));
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
             }
-            0x02 => {
-                let key = take_cstring(&mut position);
-                let length =
-                    u32::from_le_bytes(take_bytes(&mut position, 4).try_into().unwrap()) as usize;
-                let value =
-                    simdutf8::basic::from_utf8(&input[position..position + length - 1]).unwrap();
-                position += length;
-                tape.push(Tape::Key(key));
-                tape.push(Tape::String(value));
+
+            let read = match std::pin::Pin::new(&mut this.reader).poll_read(cx, &mut this.chunk) {
+                Poll::Ready(Ok(read)) => read,
+                Poll::Ready(Err(e)) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Poll::Ready(Err(e)) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(Error::Io(e))));
+                }
+                Poll::Pending => return Poll::Pending,
+            };
+
+            if read == 0 {
+                this.done = true;
+                return Poll::Ready(this.parser.finish().err().map(Err));
             }
-            0x03 => {
-                let key = take_cstring(&mut position);
-                let _length = take_bytes(&mut position, 4);
-                tape.push(Tape::Key(key));
-                tape.push(Tape::DocumentStart);
+
+            this.parser.feed(&this.chunk[..read]);
+        }
+
+        Poll::Ready(None)
+    }
+}
+
+/// Attaches the position of the `index`th entry on the tape to `error`. We don't keep track of
+/// offsets while building the tape the first time around so we'll rebuild it here as far as
+/// `index`, which is fine since we're already on the slow path.
+fn locate_error(data: &[u8], options: Options, index: usize, error: Error) -> Error {
+    // errors from reading the input already know where they came from
+    if error.offset().is_some() {
+        return error;
+    }
+
+    let bump = bumpalo::Bump::new();
+    let mut tape = bumpalo::collections::Vec::new_in(&bump);
+    let mut offsets = vec![0];
+
+    let Ok(mut tokenizer) = Tokenizer::new_in(data, options, &bump) else {
+        return error;
+    };
+
+    tape.push(Tape::DocumentStart);
+    while tape.len() <= index {
+        if !matches!(
+            extend_tape(&mut tokenizer, &mut tape, Some(&mut offsets), 1),
+            Ok(true)
+        ) {
+            return error;
+        }
+    }
+
+    match offsets.get(index) {
+        Some(&offset) => Error::Located {
+            offset,
+            // the top level document doesn't have an element type byte, everything else points
+            // straight at it
+            element_type: if offset == 0 { 0x03 } else { data[offset] },
+            path: path_to(&tape[..=index]),
+            error: Box::new(error),
+        },
+        None => error,
+    }
+}
+
+/// Works out the path to the last entry in `tape`.
+fn path_to(tape: &[Tape<'_>]) -> Path {
+    // whether each document we're nested within is an array, and the last key we saw in it
+    let mut levels: Vec<(bool, Option<&str>)> = Vec::new();
+
+    for item in tape {
+        match item {
+            Tape::DocumentStart => levels.push((false, None)),
+            Tape::ArrayStart => levels.push((true, None)),
+            Tape::DocumentEnd => {
+                levels.pop();
             }
-            0x04 => {
-                let key = take_cstring(&mut position);
-                let _length = take_bytes(&mut position, 4);
-                tape.push(Tape::Key(key));
-                tape.push(Tape::ArrayStart);
+            Tape::Key(key) => {
+                if let Some((_, last_key)) = levels.last_mut() {
+                    *last_key = Some(key);
+                }
             }
-            0x05 => {
-                let key = take_cstring(&mut position);
-                let length =
-                    u32::from_le_bytes(take_bytes(&mut position, 4).try_into().unwrap()) as usize;
-                let subtype = input[position];
-                position += 1;
-                let value = &input[position..position + length];
-                position += length;
-                tape.push(Tape::Key(key));
-                tape.push(Tape::Binary(value, subtype));
+            _ => {}
+        }
+    }
+
+    let mut path = Path::default();
+
+    for (is_array, key) in levels {
+        match key {
+            Some(key) if is_array => path.push_index(key),
+            Some(key) => path.push_key(key),
+            None => break,
+        }
+    }
+
+    path
+}
+
+/// Checks that `data` is a well-formed bson document without deserialising any of it.
+///
+/// Length prefixes, c-string termination, UTF-8 and element types are all checked, so any input
+/// that passes is safe to hand to [`from_bytes`].
+pub fn validate(data: &[u8]) -> Result<(), Error> {
+    let read_length = |at: usize| {
+        data.get(at..at + 4)
+            .map(|v| i32::from_le_bytes(v.try_into().unwrap()))
+            .and_then(|v| usize::try_from(v).ok())
+            .ok_or(Error::InvalidLength(at))
+    };
+
+    // checks the string at `at` fits before `end`, returning the size of it including its length
+    let string_length = |at: usize, end: usize| {
+        let length = read_length(at)?;
+        let value = data
+            .get(at + 4..at + 4 + length)
+            .filter(|v| at + 4 + length <= end && v.last() == Some(&0x00))
+            .ok_or(Error::InvalidLength(at))?;
+        crate::utf8::from_utf8(&value[..value.len() - 1])
+            .map_err(|_| Error::InvalidUtf8(at + 4))?;
+        Ok(4 + length)
+    };
+
+    let length = read_length(0)?;
+    if length != data.len() || length < 5 {
+        return Err(Error::InvalidLength(0));
+    }
+
+    // offset of the end of each document we're currently nested inside of, we don't recurse
+    // so deeply nested input can't blow the stack
+    let mut ends = vec![length];
+    let mut position = 4;
+
+    while let Some(&end) = ends.last() {
+        if position >= end {
+            return Err(Error::InvalidLength(position));
+        }
+
+        let offset = position;
+        let element_type = data[position];
+        position += 1;
+
+        if element_type == 0x00 {
+            // the terminator has to be the very last byte of the document
+            if position != end {
+                return Err(Error::InvalidLength(offset));
+            }
+
+            ends.pop();
+            continue;
+        }
+
+        let key_length =
+            memchr(b'\0', &data[position..end]).ok_or(Error::UnterminatedCString(position))?;
+        crate::utf8::from_utf8(&data[position..position + key_length])
+            .map_err(|_| Error::InvalidUtf8(position))?;
+        position += key_length + 1;
+
+        let value_length = match element_type {
+            0x01 | 0x09 | 0x11 | 0x12 => 8,
+            0x02 | 0x0e => string_length(position, end)?,
+            0x03 | 0x04 => {
+                let length = read_length(position)?;
+                if length < 5 || position + length > end {
+                    return Err(Error::InvalidLength(position));
+                }
+
+                // step inside the document rather than over it
+                ends.push(position + length);
+                position += 4;
+                continue;
             }
+            // `+ 1` for the subtype
+            0x05 => 4 + 1 + read_length(position)?,
             0x08 => {
-                let key = take_cstring(&mut position);
-                let value = input[position] == 1;
-                position += 1;
-                tape.push(Tape::Key(key));
-                tape.push(Tape::Boolean(value));
-            }
-            0x09 => {
-                let key = take_cstring(&mut position);
-                let value = i64::from_le_bytes(take_bytes(&mut position, 8).try_into().unwrap());
-                tape.push(Tape::Key(key));
-                tape.push(Tape::UtcDateTime(value));
-            }
-            0x0a => {
-                let key = take_cstring(&mut position);
-                tape.push(Tape::Key(key));
-                tape.push(Tape::Null);
-            }
-            0x10 => {
-                let key = take_cstring(&mut position);
-                let value = i32::from_le_bytes(take_bytes(&mut position, 4).try_into().unwrap());
-                tape.push(Tape::Key(key));
-                tape.push(Tape::I32(value));
-            }
-            0x11 => {
-                let key = take_cstring(&mut position);
-                let value = u64::from_le_bytes(take_bytes(&mut position, 8).try_into().unwrap());
-                tape.push(Tape::Key(key));
-                tape.push(Tape::Timestamp(value));
-            }
-            0x12 => {
-                let key = take_cstring(&mut position);
-                let value = i64::from_le_bytes(take_bytes(&mut position, 8).try_into().unwrap());
-                tape.push(Tape::Key(key));
-                tape.push(Tape::I64(value));
+                match data.get(position) {
+                    Some(0x00 | 0x01) => {}
+                    _ => return Err(Error::InvalidBoolean(position)),
+                }
+                1
             }
-            _ => {}
+            0x06 | 0x0a | 0x7f | 0xff => 0,
+            0x07 => 12,
+            0x0b => {
+                let pattern_length = memchr(b'\0', &data[position..end])
+                    .ok_or(Error::UnterminatedCString(position))?;
+                let options_length = memchr(b'\0', &data[position + pattern_length + 1..end])
+                    .ok_or(Error::UnterminatedCString(position + pattern_length + 1))?;
+                crate::utf8::from_utf8(&data[position..position + pattern_length])
+                    .map_err(|_| Error::InvalidUtf8(position))?;
+                let options = position + pattern_length + 1;
+                crate::utf8::from_utf8(&data[options..options + options_length])
+                    .map_err(|_| Error::InvalidUtf8(options))?;
+                pattern_length + 1 + options_length + 1
+            }
+            0x0d => string_length(position, end)?,
+            0x0f => {
+                // the length covers the code and the scope document after it
+                let length = read_length(position)?;
+                let scope = position + 4 + string_length(position + 4, end)?;
+                let scope_length = read_length(scope)?;
+                if scope_length < 5
+                    || scope + scope_length != position + length
+                    || scope + scope_length > end
+                {
+                    return Err(Error::InvalidLength(position));
+                }
+
+                // step inside the scope the same as any other document
+                ends.push(scope + scope_length);
+                position = scope + 4;
+                continue;
+            }
+            // namespace followed by an object id
+            0x0c => string_length(position, end)? + 12,
+            0x10 => 4,
+            0x13 => 16,
+            unknown => return Err(Error::UnknownElementType(unknown, offset)),
         };
+
+        position += value_length;
+        if position > end {
+            return Err(Error::InvalidLength(offset));
+        }
     }
+
+    Ok(())
 }
 
-#[cfg(test)]
-mod test {
-    #[test]
-    fn deserialize() {
-        let f = std::fs::read("test/test.bin").unwrap();
+/// Somewhere for the deserializer to pull tape entries from, either a [`LazyTape`] or straight
+/// from a [`Tokenizer`] as it works through the input.
+trait TapeSource<'de> {
+    fn peek(&mut self) -> Result<Option<Tape<'de>>, Error>;
 
-        let bump = bumpalo::Bump::new();
-        let mut tape = bumpalo::collections::Vec::new_in(&bump);
+    fn next(&mut self) -> Result<Option<Tape<'de>>, Error>;
 
-        super::to_tape(&f, &mut tape);
-        insta::assert_debug_snapshot!(tape);
+    /// Skips over the document or array that's next up without tokenising it, returns `false`
+    /// if there isn't one or it's already been tokenised.
+    fn skip_document(&mut self) -> Result<bool, Error>;
+
+    /// The document or array that was just taken, if the tokenizer hasn't moved past its start.
+    fn document(&self) -> Option<&'de [u8]>;
+}
+
+struct BsonDeserializer<T> {
+    tape: T,
+    options: Options,
+}
+
+impl<'de, T: TapeSource<'de>> BsonDeserializer<T> {
+    fn next_item(&mut self) -> Result<Option<Tape<'de>>, Error> {
+        self.tape.next()
+    }
+
+    /// Takes the next entry off the tape if it matches `f`.
+    fn next_item_if<R>(
+        &mut self,
+        f: impl FnOnce(Tape<'de>) -> Option<R>,
+    ) -> Result<Option<R>, Error> {
+        let Some(res) = self.tape.peek()?.and_then(f) else {
+            return Ok(None);
+        };
+
+        self.tape.next()?;
+        Ok(Some(res))
+    }
+
+    fn next_integer(&mut self) -> Result<i64, Error> {
+        match self.next_item()? {
+            Some(Tape::I32(value)) => Ok(i64::from(value)),
+            Some(Tape::I64(value)) => Ok(value),
+            Some(Tape::Double(value)) if self.options.coerce_numbers => exact_integer(value),
+            Some(Tape::Null) if self.options.null_as_default => Ok(0),
+            Some(Tape::DocumentStart) if self.options.extended_json_scalars => {
+                match self.extended_json_scalar()?.1 {
+                    ExtendedJsonScalar::Integer(value) => Ok(value),
+                    ExtendedJsonScalar::ObjectId(_) => Err(Error::ExpectedInteger),
+                }
+            }
+            _ => Err(Error::ExpectedInteger),
+        }
+    }
+
+    /// Takes an Extended JSON wrapper off the tape if that's what's next and
+    /// [`Options::extended_json_scalars`] is set.
+    fn next_extended_json_scalar(&mut self) -> Result<Option<ExtendedJsonScalar>, Error> {
+        if !self.options.extended_json_scalars
+            || self
+                .next_item_if(|item| matches!(item, Tape::DocumentStart).then_some(()))?
+                .is_none()
+        {
+            return Ok(None);
+        }
+
+        self.extended_json_scalar().map(|(_, scalar)| Some(scalar))
+    }
+
+    /// Reads the key and value of an Extended JSON wrapper whose start has already been taken off
+    /// the tape, along with its end.
+    fn extended_json_scalar(&mut self) -> Result<(&'de str, ExtendedJsonScalar), Error> {
+        let Some(Tape::Key(key)) = self.next_item()? else {
+            return Err(Error::InvalidExtendedJson);
+        };
+
+        let scalar = match (key, self.next_item()?) {
+            ("$numberLong", Some(Tape::String(value))) => value.parse().ok(),
+            ("$date", Some(Tape::String(value))) => crate::text::parse_rfc3339(value),
+            ("$date", Some(Tape::I32(value))) => Some(i64::from(value)),
+            ("$date", Some(Tape::I64(value))) => Some(value),
+            ("$date", Some(Tape::DocumentStart)) => match self.extended_json_scalar()? {
+                ("$numberLong", ExtendedJsonScalar::Integer(value)) => Some(value),
+                _ => None,
+            },
+            ("$oid", Some(Tape::String(value))) => {
+                let id = crate::text::from_hex(value)
+                    .and_then(|id| <[u8; 12]>::try_from(id).ok())
+                    .ok_or(Error::InvalidExtendedJson)?;
+
+                return self.extended_json_end(key, ExtendedJsonScalar::ObjectId(id));
+            }
+            _ => None,
+        };
+
+        let scalar = scalar.ok_or(Error::InvalidExtendedJson)?;
+        self.extended_json_end(key, ExtendedJsonScalar::Integer(scalar))
+    }
+
+    fn extended_json_end(
+        &mut self,
+        key: &'de str,
+        scalar: ExtendedJsonScalar,
+    ) -> Result<(&'de str, ExtendedJsonScalar), Error> {
+        match self.next_item()? {
+            Some(Tape::DocumentEnd) => Ok((key, scalar)),
+            _ => Err(Error::InvalidExtendedJson),
+        }
+    }
+
+    /// Takes a double, null or Extended JSON wrapper off the tape as an integer if that's what's
+    /// next and [`Options::coerce_numbers`], [`Options::null_as_default`] or
+    /// [`Options::extended_json_scalars`] allow it.
+    fn next_coerced_integer(&mut self) -> Result<Option<i64>, Error> {
+        let Options {
+            coerce_numbers,
+            null_as_default,
+            extended_json_scalars,
+            ..
+        } = self.options;
+
+        if extended_json_scalars {
+            match self.next_extended_json_scalar()? {
+                Some(ExtendedJsonScalar::Integer(value)) => return Ok(Some(value)),
+                Some(ExtendedJsonScalar::ObjectId(_)) => return Err(Error::ExpectedInteger),
+                None => {}
+            }
+        }
+
+        if !coerce_numbers && !null_as_default {
+            return Ok(None);
+        }
+
+        self.next_item_if(|item| match item {
+            Tape::Double(value) if coerce_numbers => Some(exact_integer(value)),
+            Tape::Null if null_as_default => Some(Ok(0)),
+            _ => None,
+        })?
+        .transpose()
+    }
+
+    /// Takes a null off the tape if that's what's next and [`Options::null_as_default`] is set.
+    fn next_null_as_default(&mut self) -> Result<bool, Error> {
+        if !self.options.null_as_default {
+            return Ok(false);
+        }
+
+        Ok(self
+            .next_item_if(|item| matches!(item, Tape::Null).then_some(()))?
+            .is_some())
+    }
+
+    /// Visits the elements of an array whose start has already been taken off the tape.
+    fn visit_array<V>(&mut self, visitor: V, document: Option<&'de [u8]>) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        let res = visitor.visit_seq(DocumentAccess::new(self, document))?;
+
+        let Some(Tape::DocumentEnd) = self.next_item()? else {
+            return Err(Error::UnexpectedMapEnd);
+        };
+
+        Ok(res)
+    }
+}
+
+macro_rules! deserialize_unsigned {
+    ($($method:ident => $visit:ident($ty:ty),)*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: Visitor<'de>,
+            {
+                let value = self.next_integer()?;
+                let converted = <$ty>::try_from(value)
+                    .map_err(|_| Error::IntegerOutOfRange(value, stringify!($ty)))?;
+                visitor.$visit(converted)
+            }
+        )*
+    };
+}
+
+macro_rules! deserialize_signed {
+    ($($method:ident,)*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: Visitor<'de>,
+            {
+                // the visitor checks the range itself
+                match self.next_coerced_integer()? {
+                    Some(value) => visitor.visit_i64(value),
+                    None => self.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! deserialize_or_default {
+    ($($method:ident => $visit:ident($($default:expr)?),)*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: Visitor<'de>,
+            {
+                if self.next_null_as_default()? {
+                    return visitor.$visit($($default)?);
+                }
+
+                self.deserialize_any(visitor)
+            }
+        )*
+    };
+}
+
+/// Converts `value` to an integer if it's a whole number within range of an `i64`.
+fn exact_integer(value: f64) -> Result<i64, Error> {
+    // 2^63 is exactly representable as a double, whereas i64::MAX isn't
+    if value.fract() == 0.0
+        && (-9_223_372_036_854_775_808.0..9_223_372_036_854_775_808.0).contains(&value)
+    {
+        Ok(value as i64)
+    } else {
+        Err(Error::ExpectedInteger)
+    }
+}
+
+impl<'de, T: TapeSource<'de>> Deserializer<'de> for &mut BsonDeserializer<T> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.next_item()? {
+            Some(Tape::Binary(_, 0x08)) if self.options.redact_sensitive => {
+                visitor.visit_borrowed_str(crate::redact::REDACTED)
+            }
+            Some(value) if self.options.bson_types.wraps(&value) => {
+                visit_bson_type(visitor, value, self.options.bson_types)
+            }
+            Some(Tape::DocumentStart) => {
+                let document = self.tape.document();
+                visitor.visit_map(DocumentAccess::new(self, document))
+            }
+            Some(Tape::DocumentEnd) => Err(Error::UnexpectedMapEnd),
+            Some(Tape::Key(_)) => Err(Error::UnexpectedKey),
+            Some(Tape::Double(value)) => visitor.visit_f64(value),
+            Some(Tape::String(value)) => visitor.visit_borrowed_str(value),
+            Some(Tape::InvalidString(value)) => {
+                visitor.visit_string(String::from_utf8_lossy(value).into_owned())
+            }
+            Some(Tape::ArrayStart) => {
+                let document = self.tape.document();
+                self.visit_array(visitor, document)
+            }
+            Some(Tape::Binary(value, _)) => visitor.visit_borrowed_bytes(value),
+            Some(Tape::Undefined) => visitor.visit_unit(),
+            Some(Tape::ObjectId(value)) => visitor.visit_bytes(&value),
+            Some(Tape::Boolean(value)) => visitor.visit_bool(value),
+            Some(Tape::UtcDateTime(value)) => visitor.visit_i64(value),
+            Some(Tape::Null) => visitor.visit_none(),
+            Some(Tape::Regex { pattern, options }) => visitor.visit_seq(PairAccess {
+                first: Some(BorrowedStrDeserializer::new(pattern)),
+                second: Some(BorrowedStrDeserializer::new(options)),
+            }),
+            Some(Tape::DbPointer(namespace, id)) => visitor.visit_seq(PairAccess {
+                first: Some(BorrowedStrDeserializer::new(namespace)),
+                second: Some(BorrowedBytesDeserializer::new(id)),
+            }),
+            Some(Tape::Code(value)) => visitor.visit_borrowed_str(value),
+            Some(Tape::Symbol(value)) => visitor.visit_borrowed_str(value),
+            Some(Tape::CodeWithScope(code)) => {
+                let mut access = PairAccess {
+                    first: Some(BorrowedStrDeserializer::new(code)),
+                    second: Some(&mut *self),
+                };
+                let value = visitor.visit_seq(&mut access)?;
+
+                // the scope is still on the tape if the visitor didn't ask for it
+                if access.second.is_some() {
+                    IgnoredAny::deserialize(&mut *self)?;
+                }
+
+                Ok(value)
+            }
+            Some(Tape::I32(value)) => visitor.visit_i32(value),
+            Some(Tape::Timestamp(value)) => visitor.visit_u64(value),
+            Some(Tape::I64(value)) => visitor.visit_i64(value),
+            Some(Tape::Decimal128(value)) => visitor.visit_bytes(&value),
+            Some(Tape::MaxKey | Tape::MinKey) => visitor.visit_unit(),
+            None => Err(Error::EndOfFile),
+        }
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        if self.next_null_as_default()? {
+            return visitor.visit_seq(SeqDeserializer::new(core::iter::empty::<()>()));
+        }
+
+        let document =
+            match self.next_item_if(|item| matches!(item, Tape::ArrayStart).then_some(()))? {
+                Some(()) => self.tape.document(),
+                None => None,
+            };
+
+        self.visit_array(visitor, document)
+    }
+
+    deserialize_signed! {
+        deserialize_i8,
+        deserialize_i16,
+        deserialize_i32,
+        deserialize_i64,
+    }
+
+    deserialize_unsigned! {
+        deserialize_u8 => visit_u8(u8),
+        deserialize_u16 => visit_u16(u16),
+        deserialize_u32 => visit_u32(u32),
+    }
+
+    fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        // timestamps are the only unsigned type in the spec, so we'll pass them straight through
+        let timestamp = self.next_item_if(|item| match item {
+            Tape::Timestamp(value) => Some(value),
+            _ => None,
+        })?;
+
+        if let Some(value) = timestamp {
+            return visitor.visit_u64(value);
+        }
+
+        let value = self.next_integer()?;
+        let converted = u64::try_from(value).map_err(|_| Error::IntegerOutOfRange(value, "u64"))?;
+        visitor.visit_u64(converted)
+    }
+
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        if let Some(value) = self.next_item_if(decimal128)? {
+            let value = value.to_i128().ok_or(Error::Decimal128OutOfRange("i128"))?;
+            return visitor.visit_i128(value);
+        }
+
+        visitor.visit_i128(i128::from(self.next_integer()?))
+    }
+
+    fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        if let Some(value) = self.next_item_if(decimal128)? {
+            let value = value.to_u128().ok_or(Error::Decimal128OutOfRange("u128"))?;
+            return visitor.visit_u128(value);
+        }
+
+        let value = self.next_integer()?;
+        let converted =
+            u128::try_from(value).map_err(|_| Error::IntegerOutOfRange(value, "u128"))?;
+        visitor.visit_u128(converted)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        if self
+            .next_item_if(|item| matches!(item, Tape::Null).then_some(()))?
+            .is_some()
+        {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        // a null would go to `visit_none` otherwise, which unit visitors don't accept
+        if self
+            .next_item_if(|item| matches!(item, Tape::Null).then_some(()))?
+            .is_some()
+        {
+            visitor.visit_unit()
+        } else {
+            self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_unit_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        // min and max keys look the same to a visitor, so the sentinel types tell us which they
+        // want through their name
+        let expected = match name {
+            MAX_KEY_NAME => Tape::MaxKey,
+            MIN_KEY_NAME => Tape::MinKey,
+            // undefined is read as a null unless asked not to, and a null isn't an `Undefined`
+            UNDEFINED_NAME => return self.deserialize_any(visitor),
+            _ => return self.deserialize_unit(visitor),
+        };
+
+        match self.next_item()? {
+            Some(item) if item == expected => visitor.visit_unit(),
+            Some(_) => Err(Error::UnexpectedKeySentinel(name)),
+            None => Err(Error::EndOfFile),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let Some(expected) = binary_subtype_for(name) else {
+            return visitor.visit_newtype_struct(self);
+        };
+
+        match self.next_item()? {
+            Some(Tape::Binary(value, subtype)) if subtype == expected => {
+                visitor.visit_borrowed_bytes(value)
+            }
+            Some(_) => Err(Error::UnexpectedKeySentinel(name)),
+            None => Err(Error::EndOfFile),
+        }
+    }
+
+    fn deserialize_char<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        if self.next_null_as_default()? {
+            return visitor.visit_char('\0');
+        }
+
+        // chars are serialised as single character strings since bson doesn't have a char type
+        let Some(Tape::String(s)) = self.next_item()? else {
+            return Err(Error::ExpectedChar);
+        };
+
+        let mut chars = s.chars();
+
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => visitor.visit_char(c),
+            _ => Err(Error::ExpectedChar),
+        }
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        // variants are usually identified by name, but can also be identified by their index,
+        // see `ser::Variants::Index`
+        match self.next_item()? {
+            Some(Tape::String(s)) => visitor.visit_enum(s.into_deserializer()),
+            Some(Tape::I32(index)) => {
+                let index = u32::try_from(index).map_err(|_| Error::UnexpectedEnum)?;
+                visitor.visit_enum(index.into_deserializer())
+            }
+            Some(Tape::DocumentStart) => {
+                let data = visitor.visit_enum(&mut EnumDeserializer {
+                    deser: &mut *self,
+                    variants,
+                })?;
+
+                let Some(Tape::DocumentEnd) = self.next_item()? else {
+                    return Err(Error::UnexpectedMapEnd);
+                };
+
+                Ok(data)
+            }
+            _ => Err(Error::UnexpectedEnum),
+        }
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        // there's no need to look inside of documents that are going to be thrown away
+        if self.tape.skip_document()? {
+            return visitor.visit_unit();
+        }
+
+        self.deserialize_any(visitor)
+    }
+
+    deserialize_or_default! {
+        deserialize_bool => visit_bool(false),
+        deserialize_f32 => visit_f32(0.0),
+        deserialize_f64 => visit_f64(0.0),
+        deserialize_str => visit_borrowed_str(""),
+        deserialize_string => visit_borrowed_str(""),
+        deserialize_map => visit_map(MapDeserializer::new(core::iter::empty::<((), ())>())),
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        // only an `$oid` stands in for bytes, any other document (including the integer
+        // wrappers) is handed over as it is
+        if self.options.extended_json_scalars
+            && self
+                .next_item_if(|item| matches!(item, Tape::DocumentStart).then_some(()))?
+                .is_some()
+        {
+            let document = self.tape.document();
+
+            if let Some(Tape::Key("$oid")) = self.tape.peek()? {
+                let ExtendedJsonScalar::ObjectId(id) = self.extended_json_scalar()?.1 else {
+                    return Err(Error::InvalidExtendedJson);
+                };
+
+                return visitor.visit_bytes(&id);
+            }
+
+            return visitor.visit_map(DocumentAccess::new(self, document));
+        }
+
+        if self.next_null_as_default()? {
+            return visitor.visit_borrowed_bytes(&[]);
+        }
+
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
+
+    forward_to_deserialize_any! {
+        tuple tuple_struct identifier
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.options.human_readable
+    }
+}
+
+fn decimal128(item: Tape<'_>) -> Option<Decimal128> {
+    match item {
+        Tape::Decimal128(value) => Some(Decimal128::from_bytes(value)),
+        _ => None,
+    }
+}
+
+/// Hands a pair of values to a visitor as a two element sequence, used for element types that are
+/// made up of more than one value.
+struct PairAccess<A, B> {
+    first: Option<A>,
+    second: Option<B>,
+}
+
+impl<'de, A, B> SeqAccess<'de> for PairAccess<A, B>
+where
+    A: Deserializer<'de, Error = Error>,
+    B: Deserializer<'de, Error = Error>,
+{
+    type Error = Error;
+
+    fn next_element_seed<S>(&mut self, seed: S) -> Result<Option<S::Value>, Self::Error>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        if let Some(first) = self.first.take() {
+            seed.deserialize(first).map(Some)
+        } else if let Some(second) = self.second.take() {
+            seed.deserialize(second).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(usize::from(self.first.is_some()) + usize::from(self.second.is_some()))
+    }
+}
+
+/// Visits one of the values [`BsonTypes::wraps`], in the representation asked for.
+#[inline(never)]
+fn visit_bson_type<'de, V>(
+    visitor: V,
+    value: Tape<'de>,
+    bson_types: BsonTypes,
+) -> Result<V::Value, Error>
+where
+    V: Visitor<'de>,
+{
+    use crate::text::{base64, hex, rfc3339};
+
+    fn wrapped<'de, V, T>(visitor: V, key: &'static str, value: T) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+        T: IntoDeserializer<'de, Error>,
+    {
+        visitor.visit_map(MapDeserializer::new(core::iter::once((key, value))))
+    }
+
+    fn object<'de, T: IntoDeserializer<'de, Error>, const N: usize>(
+        fields: [(&'static str, T); N],
+    ) -> MapDeserializer<'de, core::array::IntoIter<(&'static str, T), N>, Error> {
+        MapDeserializer::new(IntoIterator::into_iter(fields))
+    }
+
+    match (bson_types, value) {
+        (BsonTypes::Strings, Tape::ObjectId(value)) => visitor.visit_string(hex(&value)),
+        (BsonTypes::Strings, Tape::Binary(value, _)) => visitor.visit_string(base64(value)),
+        (BsonTypes::Strings, Tape::UtcDateTime(value)) => match rfc3339(value) {
+            Some(date) => visitor.visit_string(date),
+            None => visitor.visit_i64(value),
+        },
+        (BsonTypes::Strings, Tape::Decimal128(value)) => {
+            visitor.visit_string(Decimal128::from_bytes(value).to_string())
+        }
+        (_, Tape::ObjectId(value)) => wrapped(visitor, "$oid", hex(&value)),
+        (_, Tape::Binary(value, subtype)) => wrapped(
+            visitor,
+            "$binary",
+            object([
+                ("base64", base64(value)),
+                ("subType", format!("{:02x}", subtype)),
+            ]),
+        ),
+        (_, Tape::UtcDateTime(value)) => wrapped(
+            visitor,
+            "$date",
+            object([("$numberLong", value.to_string())]),
+        ),
+        (_, Tape::Decimal128(value)) => wrapped(
+            visitor,
+            "$numberDecimal",
+            Decimal128::from_bytes(value).to_string(),
+        ),
+        (_, Tape::Timestamp(value)) => wrapped(
+            visitor,
+            "$timestamp",
+            object([("t", (value >> 32) as u32), ("i", value as u32)]),
+        ),
+        (_, Tape::Regex { pattern, options }) => wrapped(
+            visitor,
+            "$regularExpression",
+            object([("pattern", pattern), ("options", options)]),
+        ),
+        (_, Tape::Code(value)) => wrapped(visitor, "$code", value),
+        (_, Tape::Symbol(value)) => wrapped(visitor, "$symbol", value),
+        (_, Tape::Undefined) => wrapped(visitor, "$undefined", true),
+        (_, Tape::MinKey) => wrapped(visitor, "$minKey", 1),
+        (_, Tape::MaxKey) => wrapped(visitor, "$maxKey", 1),
+        _ => unreachable!("{:?} isn't wrapped by {:?}", value, bson_types),
+    }
+}
+
+struct EnumDeserializer<'a, T> {
+    deser: &'a mut BsonDeserializer<T>,
+    variants: &'static [&'static str],
+}
+
+impl<'de, T: TapeSource<'de>> Deserializer<'de> for &mut EnumDeserializer<'_, T> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let key = self.deser.next_item_if(|item| match item {
+            Tape::Key(key) => Some(key),
+            _ => None,
+        })?;
+
+        match key {
+            // a key that isn't the name of a variant might be the index of one
+            Some(key) if !self.variants.contains(&key) => match key.parse::<u32>() {
+                Ok(index) => visitor.visit_u32(index),
+                Err(_) => visitor.visit_borrowed_str(key),
+            },
+            Some(key) => visitor.visit_borrowed_str(key),
+            None => self.deser.deserialize_any(visitor),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes
+        byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct
+        map struct enum identifier ignored_any
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.deser.options.human_readable
+    }
+}
+
+impl<'de, T: TapeSource<'de>> VariantAccess<'de> for &mut EnumDeserializer<'_, T> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        // a unit variant written out as a document, such as `{"A": null}`, has nothing but null
+        // after its key
+        match self.deser.next_item()? {
+            Some(Tape::Null) => Ok(()),
+            _ => Err(Error::UnexpectedEnum),
+        }
+    }
+
+    fn newtype_variant_seed<S>(self, seed: S) -> Result<S::Value, Self::Error>
+    where
+        S: serde::de::DeserializeSeed<'de>,
+    {
+        // the variant's key has been taken by now, so the value goes straight to the outer
+        // deserializer, which keeps hints like `deserialize_enum` for a nested enum
+        seed.deserialize(&mut *self.deser)
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn struct_variant<V>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
+}
+
+impl<'de, T: TapeSource<'de>> EnumAccess<'de> for &mut EnumDeserializer<'_, T> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: serde::de::DeserializeSeed<'de>,
+    {
+        let value = seed.deserialize(&mut *self)?;
+
+        Ok((value, self))
+    }
+}
+
+/// Hands the elements of a document or array to a visitor, along with how many of them are left
+/// if we know.
+struct DocumentAccess<'a, 'de, T> {
+    deser: &'a mut BsonDeserializer<T>,
+    /// The raw document, which is only counted if the visitor asks for a size hint.
+    document: Option<&'de [u8]>,
+    taken: usize,
+}
+
+impl<'a, 'de, T> DocumentAccess<'a, 'de, T> {
+    fn new(deser: &'a mut BsonDeserializer<T>, document: Option<&'de [u8]>) -> Self {
+        Self {
+            deser,
+            document,
+            taken: 0,
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        let len = crate::scan::count_elements(self.document?, 0)?;
+        Some(len.saturating_sub(self.taken))
+    }
+}
+
+impl<'de, T: TapeSource<'de>> MapAccess<'de> for DocumentAccess<'_, 'de, T> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: serde::de::DeserializeSeed<'de>,
+    {
+        let data = match self.deser.next_item()? {
+            Some(Tape::DocumentEnd) => return Ok(None),
+            Some(Tape::Key(key)) => key,
+            _ => return Err(Error::MalformedMapMissingKey),
+        };
+
+        self.taken += 1;
+        seed.deserialize(BorrowedStrDeserializer::new(data))
+            .map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(&mut *self.deser)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        DocumentAccess::size_hint(self)
+    }
+}
+
+impl<'de, T: TapeSource<'de>> SeqAccess<'de> for DocumentAccess<'_, 'de, T> {
+    type Error = Error;
+
+    fn next_element_seed<S>(&mut self, seed: S) -> Result<Option<S::Value>, Self::Error>
+    where
+        S: serde::de::DeserializeSeed<'de>,
+    {
+        if let Some(Tape::DocumentEnd) = self.deser.tape.peek()? {
+            return Ok(None);
+        }
+
+        let Some(Tape::Key(_)) = self.deser.next_item()? else {
+            return Err(Error::MalformedMapMissingKey);
+        };
+
+        self.taken += 1;
+        seed.deserialize(&mut *self.deser).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        DocumentAccess::size_hint(self)
+    }
+}
+
+/// A flattened representation of a document, each element is represented by a `Key` followed by
+/// its value, with nested documents and arrays opened by `DocumentStart`/`ArrayStart` and closed
+/// by `DocumentEnd`.
+///
+/// This is exposed for building custom indexing or analytics over documents without going
+/// through serde, but is tied closely to the deserializer's internals and new variants may be
+/// added as more of the spec is supported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum Tape<'a> {
+    DocumentStart,                                // start of input or 0x03
+    DocumentEnd,                                  // 0x00
+    Key(&'a str),                                 //
+    Double(f64),                                  // 0x01
+    String(&'a str),                              // 0x02
+    InvalidString(&'a [u8]),                      // 0x02, see InvalidUtf8::Replace
+    ArrayStart,                                   // 0x04
+    Binary(&'a [u8], u8),                         // 0x05
+    Undefined,                                    // 0x06
+    ObjectId([u8; 12]),                           // 0x07
+    Boolean(bool),                                // 0x08
+    UtcDateTime(i64),                             // 0x09
+    Null,                                         // 0x0a
+    Regex { pattern: &'a str, options: &'a str }, // 0x0b
+    DbPointer(&'a str, &'a [u8; 12]),             // 0x0c
+    Code(&'a str),                                // 0x0d
+    Symbol(&'a str),                              // 0x0e
+    CodeWithScope(&'a str),                       // 0x0f, followed by the scope document
+    I32(i32),                                     // 0x10
+    Timestamp(u64),                               // 0x11
+    I64(i64),                                     // 0x12
+    Decimal128([u8; 16]),                         // 0x13
+    MaxKey,                                       // 0x7f
+    MinKey,                                       // 0xff
+}
+
+/// Tokenises `input` onto the end of `tape`, see [`Tape`] for the format.
+///
+/// This is the same tokenisation [`from_bytes`] does as it works through the input, the same
+/// stability caveats apply.
+pub fn to_tape<'a>(
+    input: &'a [u8],
+    tape: &mut bumpalo::collections::Vec<'_, Tape<'a>>,
+    options: Options,
+) -> Result<(), Error> {
+    let mut tokenizer = Tokenizer::new_in(input, options, tape.bump())?;
+
+    tape.push(Tape::DocumentStart);
+    while extend_tape(&mut tokenizer, tape, None, usize::MAX)? {}
+    Ok(())
+}
+
+/// Tokenises up to `limit` more elements onto the end of `tape`, returns `false` once there's
+/// nothing left in the input. If `offsets` is provided the offset of the element each entry on
+/// the tape came from is pushed to it alongside.
+///
+/// This stops early after stepping into a nested document, so its start is the last entry on the
+/// tape and the tokenizer can still skip over it if the caller doesn't need it.
+#[inline(always)]
+fn extend_tape<'a>(
+    tokenizer: &mut Tokenizer<'a, '_>,
+    tape: &mut bumpalo::collections::Vec<'_, Tape<'a>>,
+    mut offsets: Option<&mut Vec<usize>>,
+    limit: usize,
+) -> Result<bool, Error> {
+    for _ in 0..limit {
+        let Some((key, value)) = tokenizer.next_element()? else {
+            return Ok(false);
+        };
+        let offset = tokenizer.offset;
+
+        if let Some(key) = key {
+            tape.push(Tape::Key(key));
+
+            if let Some(offsets) = &mut offsets {
+                offsets.push(offset);
+            }
+        }
+
+        tape.push(value);
+        if let Some(offsets) = &mut offsets {
+            offsets.push(offset);
+        }
+
+        // the scope's elements go on the tape after the code the same as any other document
+        if let Tape::CodeWithScope(_) = value {
+            tape.push(Tape::DocumentStart);
+
+            if let Some(offsets) = &mut offsets {
+                offsets.push(offset);
+            }
+        }
+
+        if tokenizer.document_start.is_some() {
+            break;
+        }
+    }
+
+    Ok(true)
+}
+
+/// A tape that's only built as far as the deserializer has asked for, a handful of elements at a
+/// time. Once the deserializer is done with the root document we can stop without tokenising
+/// the rest of the input, and entries are dropped as soon as they've been consumed.
+struct LazyTape<'a, 'bump> {
+    tape: bumpalo::collections::Vec<'bump, Tape<'a>>,
+    tokenizer: Tokenizer<'a, 'bump>,
+    /// Index of the next entry on `tape` to hand out.
+    position: usize,
+    /// The number of entries handed out so far, for locating errors.
+    taken: usize,
+    exhausted: bool,
+}
+
+impl<'a, 'bump> LazyTape<'a, 'bump> {
+    /// The number of elements tokenised each time the tape runs dry.
+    const CHUNK: usize = 32;
+
+    fn new_in(
+        input: &'a [u8],
+        options: Options,
+        bump: &'bump bumpalo::Bump,
+    ) -> Result<Self, Error> {
+        let mut tape = bumpalo::collections::Vec::with_capacity_in(Self::CHUNK * 2, bump);
+        tape.push(Tape::DocumentStart);
+
+        Ok(Self {
+            tape,
+            tokenizer: Tokenizer::new_in(input, options, bump)?,
+            position: 0,
+            taken: 0,
+            exhausted: false,
+        })
+    }
+
+    #[inline]
+    fn fill(&mut self) -> Result<(), Error> {
+        if self.position == self.tape.len() && !self.exhausted {
+            self.refill()?;
+        }
+
+        Ok(())
+    }
+
+    #[inline(never)]
+    fn refill(&mut self) -> Result<(), Error> {
+        self.tape.clear();
+        self.position = 0;
+        self.exhausted = !extend_tape(&mut self.tokenizer, &mut self.tape, None, Self::CHUNK)?;
+        Ok(())
+    }
+}
+
+impl<'de> TapeSource<'de> for LazyTape<'de, '_> {
+    #[inline]
+    fn peek(&mut self) -> Result<Option<Tape<'de>>, Error> {
+        self.fill()?;
+        Ok(self.tape.get(self.position).copied())
+    }
+
+    #[inline]
+    fn next(&mut self) -> Result<Option<Tape<'de>>, Error> {
+        self.fill()?;
+        let item = self.tape.get(self.position).copied();
+
+        if item.is_some() {
+            self.position += 1;
+            self.taken += 1;
+        }
+
+        Ok(item)
+    }
+
+    fn skip_document(&mut self) -> Result<bool, Error> {
+        // only the last entry on the tape can be a document the tokenizer hasn't stepped into yet,
+        // and there's nothing to gain from skipping the top level document
+        if self.taken == 0
+            || self.position + 1 != self.tape.len()
+            || !matches!(
+                self.tape[self.position],
+                Tape::DocumentStart | Tape::ArrayStart
+            )
+            || !self.tokenizer.skip_document()?
+        {
+            return Ok(false);
+        }
+
+        self.position += 1;
+        self.taken += 1;
+        Ok(true)
+    }
+
+    fn document(&self) -> Option<&'de [u8]> {
+        // the tokenizer will have moved on if the document wasn't the last entry on the tape
+        if self.position == self.tape.len() {
+            self.tokenizer.document()
+        } else {
+            None
+        }
+    }
+}
+
+/// Works through the elements of a document one at a time, the top level document's start isn't
+/// handed out.
+struct Tokenizer<'a, 'bump> {
+    input: &'a [u8],
+    position: usize,
+    /// The offset of the last element handed out.
+    offset: usize,
+    /// Where the document opened by the last element starts, if it opened one. This is the top
+    /// level document until the first element is read.
+    document_start: Option<usize>,
+    options: Options,
+    seen_keys: Option<SeenKeys<'a, 'bump>>,
+    /// Only set when stats have been asked for.
+    tracking: Option<Tracking>,
+}
+
+/// The [`Stats`] gathered by a [`Tokenizer`] as it goes, along with how deeply nested it is.
+struct Tracking {
+    stats: Stats,
+    depth: usize,
+}
+
+impl Tracking {
+    /// Starts tracking from within the top level document.
+    fn new() -> Self {
+        let mut stats = Stats::new();
+        stats.depth(1);
+
+        Self { stats, depth: 1 }
+    }
+
+    #[inline]
+    fn value(&mut self, value: &Tape<'_>) {
+        match value {
+            Tape::String(v) => self.stats.string(v.len()),
+            Tape::InvalidString(v) => self.stats.string(v.len()),
+            Tape::Binary(v, _) => self.stats.binary(v.len()),
+            Tape::DocumentStart | Tape::ArrayStart | Tape::CodeWithScope(_) => {
+                self.depth += 1;
+                self.stats.depth(self.depth);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'a, 'bump> Tokenizer<'a, 'bump> {
+    fn new_in(
+        input: &'a [u8],
+        options: Options,
+        bump: &'bump bumpalo::Bump,
+    ) -> Result<Self, Error> {
+        let length = input
+            .get(..4)
+            .map(|v| u32::from_le_bytes(v.try_into().unwrap()) as usize)
+            .filter(|length| (5..=input.len()).contains(length))
+            .ok_or(Error::InvalidLength(0))?;
+
+        Ok(Self {
+            input: &input[..length],
+            position: 4,
+            offset: 0,
+            document_start: Some(0),
+            options,
+            seen_keys: options
+                .reject_duplicate_keys
+                .then(|| SeenKeys::new_in(bump)),
+            tracking: None,
+        })
+    }
+
+    /// Reads a lone value of `element_type` from the start of `input` with [`Self::take_value`],
+    /// rather than a document.
+    fn new_value_in(input: &'a [u8], options: Options, bump: &'bump bumpalo::Bump) -> Self {
+        Self {
+            input,
+            position: 0,
+            offset: 0,
+            document_start: None,
+            options,
+            seen_keys: options
+                .reject_duplicate_keys
+                .then(|| SeenKeys::new_in(bump)),
+            tracking: None,
+        }
+    }
+
+    /// Reads the next element's key and value, document ends are handed out without a key.
+    #[inline(always)]
+    fn next_element(&mut self) -> Result<Option<(Option<&'a str>, Tape<'a>)>, Error> {
+        if self.position >= self.input.len() {
+            return Ok(None);
+        }
+
+        self.offset = self.position;
+        self.document_start = None;
+        let element_type = self.input[self.position];
+        self.position += 1;
+
+        if element_type == 0x00 {
+            if let Some(seen_keys) = &mut self.seen_keys {
+                seen_keys.end_document();
+            }
+
+            if let Some(tracking) = &mut self.tracking {
+                tracking.depth -= 1;
+            }
+
+            return Ok(Some((None, Tape::DocumentEnd)));
+        }
+
+        let key = self.take_cstring()?;
+        let value = self.take_value(element_type)?;
+
+        if let Some(tracking) = &mut self.tracking {
+            tracking.value(&value);
+        }
+
+        if let Some(seen_keys) = &mut self.seen_keys {
+            if !seen_keys.insert(key) {
+                return Err(Error::DuplicateKey(key.to_string(), self.offset));
+            }
+
+            if let Tape::DocumentStart | Tape::ArrayStart | Tape::CodeWithScope(_) = value {
+                seen_keys.start_document();
+            }
+        }
+
+        Ok(Some((Some(key), value)))
+    }
+
+    #[inline(always)]
+    fn take_value(&mut self, element_type: u8) -> Result<Tape<'a>, Error> {
+        Ok(match element_type {
+            0x01 => Tape::Double(f64::from_le_bytes(self.take_array()?)),
+            0x02 => {
+                let offset = self.position + 4;
+                let value = self.take_string_bytes()?;
+
+                match self.to_str(value) {
+                    Ok(value) => Tape::String(value),
+                    Err(_) if self.options.invalid_utf8 == InvalidUtf8::Replace => {
+                        Tape::InvalidString(value)
+                    }
+                    Err(_) => return Err(Error::InvalidUtf8(offset)),
+                }
+            }
+            0x03 => {
+                self.document_start = Some(self.position);
+                self.take_length()?;
+                Tape::DocumentStart
+            }
+            0x04 => {
+                self.document_start = Some(self.position);
+                self.take_length()?;
+                Tape::ArrayStart
+            }
+            0x05 => {
+                let length = self.take_length()?;
+                let subtype = self.take_bytes(1)?[0];
+                let value = self.take_bytes(length)?;
+                Tape::Binary(value, subtype)
+            }
+            0x06 if self.options.undefined_as_null => Tape::Null,
+            0x06 => Tape::Undefined,
+            0x07 => Tape::ObjectId(self.take_array()?),
+            0x08 => Tape::Boolean(self.take_bytes(1)?[0] == 1),
+            0x09 => Tape::UtcDateTime(i64::from_le_bytes(self.take_array()?)),
+            0x0a => Tape::Null,
+            0x0b => {
+                let pattern = self.take_cstring()?;
+                let options = self.take_cstring()?;
+                Tape::Regex { pattern, options }
+            }
+            0x0c => {
+                let namespace = self.take_string()?;
+                let id = self.take_bytes(12)?.try_into().unwrap();
+                Tape::DbPointer(namespace, id)
+            }
+            0x0d => Tape::Code(self.take_string()?),
+            0x0e => Tape::Symbol(self.take_string()?),
+            0x0f => {
+                // the length covers the code and the scope document after it
+                let length_offset = self.position;
+                let length = self.take_length()?;
+                let code = self.take_string()?;
+                let scope_length = self.take_length()?;
+
+                if length_offset + length != self.position - 4 + scope_length {
+                    return Err(Error::InvalidLength(length_offset));
+                }
+
+                self.document_start = Some(self.position - 4);
+                Tape::CodeWithScope(code)
+            }
+            0x10 => Tape::I32(i32::from_le_bytes(self.take_array()?)),
+            0x11 => Tape::Timestamp(u64::from_le_bytes(self.take_array()?)),
+            0x12 => Tape::I64(i64::from_le_bytes(self.take_array()?)),
+            0x13 => Tape::Decimal128(self.take_array()?),
+            0x7f => Tape::MaxKey,
+            0xff => Tape::MinKey,
+            // there's no telling how big a type that isn't in the spec is, so there's no way for
+            // us to step over it
+            unknown => return Err(Error::UnknownElementType(unknown, self.offset)),
+        })
+    }
+
+    /// Steps straight over the document opened by the last element using its length rather than
+    /// tokenising it, returns `false` if the last element didn't open a document.
+    fn skip_document(&mut self) -> Result<bool, Error> {
+        let Some(start) = self.document_start.take() else {
+            return Ok(false);
+        };
+        // saturating so a huge length can't wrap around on 32-bit targets
+        let length = u32::from_le_bytes(self.input[start..start + 4].try_into().unwrap());
+        let end = start.saturating_add(length as usize);
+
+        // the length is all we've got to go on, so make sure it at least looks like it lands on
+        // the end of a document
+        if end <= self.position || end > self.input.len() || self.input[end - 1] != 0x00 {
+            return Err(Error::InvalidLength(self.offset));
+        }
+
+        self.position = end;
+
+        if let Some(seen_keys) = &mut self.seen_keys {
+            seen_keys.end_document();
+        }
+
+        if let Some(tracking) = &mut self.tracking {
+            tracking.depth -= 1;
+        }
+
+        Ok(true)
+    }
+
+    /// The raw bytes of the document opened by the last element.
+    fn document(&self) -> Option<&'a [u8]> {
+        let start = self.document_start?;
+        let length = u32::from_le_bytes(self.input.get(start..start + 4)?.try_into().unwrap());
+        self.input.get(start..start.checked_add(length as usize)?)
+    }
+
+    #[inline]
+    fn take_bytes(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        let res = self
+            .position
+            .checked_add(n)
+            .and_then(|end| self.input.get(self.position..end))
+            .ok_or(Error::InvalidLength(self.position))?;
+        self.position += n;
+        Ok(res)
+    }
+
+    #[inline]
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        self.take_bytes(N).map(|v| v.try_into().unwrap())
+    }
+
+    #[inline]
+    fn take_length(&mut self) -> Result<usize, Error> {
+        self.take_array().map(|v| u32::from_le_bytes(v) as usize)
+    }
+
+    #[inline]
+    fn to_str(&self, value: &'a [u8]) -> Result<&'a str, crate::utf8::Utf8Error> {
+        if self.options.assume_valid_utf8 {
+            // SAFETY: the caller promised the input is valid when enabling the option
+            Ok(unsafe { core::str::from_utf8_unchecked(value) })
+        } else {
+            crate::utf8::from_utf8(value)
+        }
+    }
+
+    #[inline]
+    fn take_cstring(&mut self) -> Result<&'a str, Error> {
+        let start = self.position;
+        let idx = memchr(b'\0', &self.input[start..]).ok_or(Error::UnterminatedCString(start))?;
+        let s = self
+            .to_str(&self.input[start..start + idx])
+            .map_err(|_| Error::InvalidUtf8(start))?;
+        self.position += idx + 1;
+        Ok(s)
+    }
+
+    #[inline]
+    fn take_string_bytes(&mut self) -> Result<&'a [u8], Error> {
+        let length_offset = self.position;
+        let length = self.take_length()?;
+
+        match self.take_bytes(length)? {
+            [value @ .., 0x00] => Ok(value),
+            _ => Err(Error::InvalidLength(length_offset)),
+        }
+    }
+
+    #[inline]
+    fn take_string(&mut self) -> Result<&'a str, Error> {
+        let offset = self.position + 4;
+        let value = self.take_string_bytes()?;
+        self.to_str(value).map_err(|_| Error::InvalidUtf8(offset))
+    }
+}
+
+/// Feeds the deserializer straight from a [`Tokenizer`], see [`from_bytes_streaming`].
+struct Streaming<'a, 'bump> {
+    tokenizer: Tokenizer<'a, 'bump>,
+    /// The rest of the last element read, still to be handed out.
+    queued: Option<Tape<'a>>,
+    peeked: Option<Tape<'a>>,
+    /// The number of entries handed out so far, for locating errors.
+    taken: usize,
+}
+
+impl<'a> Streaming<'a, '_> {
+    #[inline]
+    fn read(&mut self) -> Result<Option<Tape<'a>>, Error> {
+        if let Some(item) = self.queued.take() {
+            // the scope's elements follow the code the same as any other document
+            if let Tape::CodeWithScope(_) = item {
+                self.queued = Some(Tape::DocumentStart);
+            }
+
+            return Ok(Some(item));
+        }
+
+        match self.tokenizer.next_element()? {
+            Some((Some(key), value)) => {
+                self.queued = Some(value);
+                Ok(Some(Tape::Key(key)))
+            }
+            Some((None, value)) => Ok(Some(value)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<'de> TapeSource<'de> for Streaming<'de, '_> {
+    fn peek(&mut self) -> Result<Option<Tape<'de>>, Error> {
+        if self.peeked.is_none() {
+            self.peeked = self.read()?;
+        }
+
+        Ok(self.peeked)
+    }
+
+    fn next(&mut self) -> Result<Option<Tape<'de>>, Error> {
+        let item = match self.peeked.take() {
+            Some(item) => Some(item),
+            None => self.read()?,
+        };
+
+        self.taken += usize::from(item.is_some());
+        Ok(item)
+    }
+
+    fn skip_document(&mut self) -> Result<bool, Error> {
+        // nothing's been read past the document if it's the next thing up
+        if self.taken == 0
+            || !matches!(self.peek()?, Some(Tape::DocumentStart | Tape::ArrayStart))
+            || self.queued.is_some()
+            || !self.tokenizer.skip_document()?
+        {
+            return Ok(false);
+        }
+
+        self.peeked = None;
+        self.taken += 1;
+        Ok(true)
+    }
+
+    fn document(&self) -> Option<&'de [u8]> {
+        if self.peeked.is_none() && self.queued.is_none() {
+            self.tokenizer.document()
+        } else {
+            None
+        }
+    }
+}
+
+/// Keeps track of every key seen at each level of nesting, a linear scan over the keys in the
+/// current document is plenty fast for the size of documents we tend to see.
+struct SeenKeys<'a, 'bump> {
+    keys: bumpalo::collections::Vec<'bump, &'a str>,
+    levels: bumpalo::collections::Vec<'bump, usize>,
+}
+
+impl<'a, 'bump> SeenKeys<'a, 'bump> {
+    fn new_in(bump: &'bump bumpalo::Bump) -> Self {
+        Self {
+            keys: bumpalo::collections::Vec::new_in(bump),
+            levels: bumpalo::collections::Vec::new_in(bump),
+        }
+    }
+
+    /// Returns `false` if the key has already been seen in the current document.
+    fn insert(&mut self, key: &'a str) -> bool {
+        let start = self.levels.last().copied().unwrap_or(0);
+
+        if self.keys[start..].contains(&key) {
+            return false;
+        }
+
+        self.keys.push(key);
+        true
+    }
+
+    fn start_document(&mut self) {
+        self.levels.push(self.keys.len());
+    }
+
+    fn end_document(&mut self) {
+        let start = self.levels.pop().unwrap_or(0);
+        self.keys.truncate(start);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::{Deserialize, Serialize};
+
+    #[test]
+    fn deserialize() {
+        let f = std::fs::read("test/test.bin").unwrap();
+
+        let bump = bumpalo::Bump::new();
+        let mut tape = bumpalo::collections::Vec::new_in(&bump);
+
+        super::to_tape(&f, &mut tape, super::Options::default()).unwrap();
+        insta::assert_debug_snapshot!(tape);
+    }
+
+    #[test]
+    fn deserialize_char() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            a: char,
+            b: char,
+        }
+
+        let mut bytes = bytes::BytesMut::new();
+        crate::to_string(&A { a: 'x', b: '🦀' }, &mut bytes).unwrap();
+
+        let deserialized: A = super::from_bytes(&bytes).unwrap();
+        assert_eq!(deserialized, A { a: 'x', b: '🦀' });
+
+        #[derive(Serialize)]
+        struct B<'a> {
+            a: &'a str,
+            b: &'a str,
+        }
+
+        let mut bytes = bytes::BytesMut::new();
+        crate::to_string(&B { a: "x", b: "xy" }, &mut bytes).unwrap();
+
+        let err = super::from_bytes::<A>(&bytes).unwrap_err();
+        assert!(
+            matches!(err.inner(), super::Error::ExpectedChar),
+            "{:?}",
+            err
+        );
+        assert_eq!(err.offset(), Some(13));
+    }
+
+    #[test]
+    fn deserialize_unsigned() {
+        #[derive(Serialize)]
+        struct Signed {
+            a: i32,
+            b: i64,
+            c: i64,
+        }
+
+        #[derive(Deserialize, Debug, PartialEq, Eq)]
+        struct Unsigned {
+            a: u8,
+            b: u32,
+            c: u64,
+        }
+
+        let mut bytes = bytes::BytesMut::new();
+        crate::to_string(
+            &Signed {
+                a: 255,
+                b: 70000,
+                c: i64::MAX,
+            },
+            &mut bytes,
+        )
+        .unwrap();
+
+        let deserialized: Unsigned = super::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            deserialized,
+            Unsigned {
+                a: 255,
+                b: 70000,
+                c: i64::MAX as u64
+            }
+        );
+
+        let mut bytes = bytes::BytesMut::new();
+        crate::to_string(&Signed { a: 256, b: 0, c: 0 }, &mut bytes).unwrap();
+
+        let err = super::from_bytes::<Unsigned>(&bytes).unwrap_err();
+        assert!(
+            matches!(err.inner(), super::Error::IntegerOutOfRange(256, "u8")),
+            "{:?}",
+            err
+        );
+        assert_eq!(err.offset(), Some(4));
+
+        let mut bytes = bytes::BytesMut::new();
+        crate::to_string(&Signed { a: 0, b: 0, c: -1 }, &mut bytes).unwrap();
+
+        let err = super::from_bytes::<Unsigned>(&bytes).unwrap_err();
+        assert!(
+            matches!(err.inner(), super::Error::IntegerOutOfRange(-1, "u64")),
+            "{:?}",
+            err
+        );
+        assert_eq!(
+            err.to_string(),
+            "integer -1 out of range for u64 at c (offset 22, element type 0x12)"
+        );
+    }
+
+    #[test]
+    fn null_as_default() {
+        #[derive(Deserialize, Debug, PartialEq, Default)]
+        #[serde(default)]
+        struct Inner {
+            a: i32,
+            b: String,
+        }
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct A {
+            int: i32,
+            unsigned: u64,
+            float: f64,
+            boolean: bool,
+            string: String,
+            character: char,
+            list: Vec<i32>,
+            map: std::collections::HashMap<String, i32>,
+            inner: Inner,
+            unit: (),
+            option: Option<i32>,
+            any: serde_json::Value,
+        }
+
+        let data = bson::to_vec(&bson::doc! {
+            "int": null,
+            "unsigned": null,
+            "float": null,
+            "boolean": null,
+            "string": null,
+            "character": null,
+            "list": null,
+            "map": null,
+            "inner": null,
+            "unit": null,
+            "option": null,
+            "any": null,
+        })
+        .unwrap();
+
+        assert!(super::from_bytes::<A>(&data).is_err());
+
+        let options = super::Options::new().null_as_default(true);
+        let expected = A {
+            int: 0,
+            unsigned: 0,
+            float: 0.0,
+            boolean: false,
+            string: String::new(),
+            character: '\0',
+            list: Vec::new(),
+            map: Default::default(),
+            inner: Inner::default(),
+            unit: (),
+            option: None,
+            any: serde_json::Value::Null,
+        };
+        assert_eq!(
+            super::from_bytes_with_options::<A>(&data, options).unwrap(),
+            expected
+        );
+        assert_eq!(
+            super::from_bytes_streaming_with_options::<A>(&data, options).unwrap(),
+            expected
+        );
+
+        // a struct without defaults can't be made from nothing
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct B {
+            inner: A,
+        }
+
+        let data = bson::to_vec(&bson::doc! { "inner": null }).unwrap();
+        assert!(super::from_bytes_with_options::<B>(&data, options).is_err());
+    }
+
+    #[test]
+    fn extended_json_scalars() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct A {
+            long: i64,
+            small: u16,
+            date_long: i64,
+            date_string: i64,
+            date_int: i64,
+            #[serde(with = "crate::serde_helpers::hex_string_as_object_id")]
+            id: String,
+            #[serde(with = "crate::serde_helpers::system_time_as_bson_datetime")]
+            time: std::time::SystemTime,
+            plain: i32,
+        }
+
+        let data = bson::to_vec(&bson::doc! {
+            "long": { "$numberLong": "-9223372036854775808" },
+            "small": { "$numberLong": "12" },
+            "date_long": { "$date": { "$numberLong": "1700000000123" } },
+            "date_string": { "$date": "2023-11-14T22:13:20.123Z" },
+            "date_int": { "$date": 5 },
+            "id": { "$oid": "0102030405060708090a0b0c" },
+            "time": { "$date": "1970-01-01T00:00:01Z" },
+            "plain": 7,
+        })
+        .unwrap();
+
+        assert!(super::from_bytes::<A>(&data).is_err());
+
+        let options = super::Options::new().extended_json_scalars(true);
+        let expected = A {
+            long: i64::MIN,
+            small: 12,
+            date_long: 1_700_000_000_123,
+            date_string: 1_700_000_000_123,
+            date_int: 5,
+            id: "0102030405060708090a0b0c".to_string(),
+            time: std::time::UNIX_EPOCH + std::time::Duration::from_secs(1),
+            plain: 7,
+        };
+        assert_eq!(
+            super::from_bytes_with_options::<A>(&data, options).unwrap(),
+            expected
+        );
+        assert_eq!(
+            super::from_bytes_streaming_with_options::<A>(&data, options).unwrap(),
+            expected
+        );
+
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct B {
+            a: i64,
+        }
+
+        for invalid in [
+            bson::doc! { "a": { "$numberLong": "1.5" } },
+            bson::doc! { "a": { "$numberLong": 1 } },
+            bson::doc! { "a": { "$numberLong": "1", "extra": 1 } },
+            bson::doc! { "a": { "$date": "yesterday" } },
+            bson::doc! { "a": { "$date": { "$date": 1 } } },
+            bson::doc! { "a": { "$oid": "0102030405060708090a0b0c" } },
+            bson::doc! { "a": { "$numberInt": "1" } },
+            bson::doc! { "a": {} },
+        ]
+        .iter()
+        {
+            let data = bson::to_vec(invalid).unwrap();
+            assert!(
+                super::from_bytes_with_options::<B>(&data, options).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn extended_json_scalars_as_bytes() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct A {
+            #[serde(with = "serde_bytes")]
+            bytes: Vec<u8>,
+            id: crate::ObjectId,
+        }
+
+        let data = bson::to_vec(&bson::doc! {
+            "bytes": { "$oid": "0102030405060708090a0b0c" },
+            "id": { "$oid": "0102030405060708090a0b0c" },
+        })
+        .unwrap();
+
+        let options = super::Options::new().extended_json_scalars(true);
+        let expected = A {
+            bytes: (1..=12).collect(),
+            id: crate::ObjectId::from_bytes([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]),
+        };
+        assert_eq!(
+            super::from_bytes_with_options::<A>(&data, options).unwrap(),
+            expected
+        );
+        assert_eq!(
+            super::from_bytes_streaming_with_options::<A>(&data, options).unwrap(),
+            expected
+        );
+
+        // only an `$oid` stands in for bytes, the integer wrappers are left as documents
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct B {
+            #[serde(with = "serde_bytes")]
+            bytes: Vec<u8>,
+        }
+
+        for invalid in [
+            bson::doc! { "bytes": { "$numberLong": "5" } },
+            bson::doc! { "bytes": { "$date": { "$numberLong": "1700000000123" } } },
+        ]
+        .iter()
+        {
+            let data = bson::to_vec(invalid).unwrap();
+            assert!(
+                super::from_bytes_with_options::<B>(&data, options).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn coerce_numbers() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct A {
+            a: u32,
+            b: i8,
+            c: i64,
+            d: Option<i32>,
+            e: f64,
+        }
+
+        let options = super::Options::new().coerce_numbers(true);
+        let data = bson::to_vec(&bson::doc! {
+            "a": 3.0,
+            "b": -128.0,
+            "c": -0.0,
+            "d": 1e9,
+            "e": 7_i64,
+        })
+        .unwrap();
+
+        assert!(super::from_bytes::<A>(&data).is_err());
+        assert_eq!(
+            super::from_bytes_with_options::<A>(&data, options).unwrap(),
+            A {
+                a: 3,
+                b: -128,
+                c: 0,
+                d: Some(1_000_000_000),
+                e: 7.0,
+            }
+        );
+        assert_eq!(
+            super::from_bytes_streaming_with_options::<A>(&data, options).unwrap(),
+            super::from_bytes_with_options::<A>(&data, options).unwrap()
+        );
+
+        // only whole numbers in range are coerced
+        for (key, value) in [
+            ("a", bson::Bson::Double(3.5)),
+            ("a", bson::Bson::Double(-1.0)),
+            ("b", bson::Bson::Double(128.0)),
+            ("c", bson::Bson::Double(9.3e18)),
+            ("c", bson::Bson::Double(f64::NAN)),
+            ("c", bson::Bson::Double(f64::INFINITY)),
+        ] {
+            let mut doc = bson::doc! { "a": 1, "b": 1, "c": 1, "d": null, "e": 1.0 };
+            doc.insert(key, value.clone());
+            let data = bson::to_vec(&doc).unwrap();
+            assert!(
+                super::from_bytes_with_options::<A>(&data, options).is_err(),
+                "{} = {}",
+                key,
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn deserialize_128_bit() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct Wide {
+            a: i128,
+            b: u128,
+            c: i128,
+        }
+
+        let val = Wide {
+            a: -(10i128.pow(30)) - 1,
+            b: 10u128.pow(38),
+            c: 7,
+        };
+
+        let mut bytes = bytes::BytesMut::new();
+        crate::to_string(&val, &mut bytes).unwrap();
+
+        let theirs = bson::from_slice::<bson::Document>(&bytes).unwrap();
+        assert_eq!(
+            theirs.get_decimal128("a").unwrap().to_string(),
+            "-1000000000000000000000000000001"
+        );
+        assert_eq!(
+            theirs.get_decimal128("b").unwrap().to_string(),
+            "1.000000000000000000000000000000000E+38"
+        );
+
+        let deserialized: Wide = super::from_bytes(&bytes).unwrap();
+        assert_eq!(deserialized, val);
+
+        // plain integers are widened
+        #[derive(Serialize)]
+        struct Narrow {
+            a: i32,
+            b: i64,
+            c: i64,
+        }
+
+        let mut bytes = bytes::BytesMut::new();
+        crate::to_string(&Narrow { a: -1, b: 2, c: 3 }, &mut bytes).unwrap();
+        let deserialized: Wide = super::from_bytes(&bytes).unwrap();
+        assert_eq!(deserialized, Wide { a: -1, b: 2, c: 3 });
+
+        let err = crate::to_string(
+            &Wide {
+                a: i128::MAX,
+                b: 0,
+                c: 0,
+            },
+            &mut bytes::BytesMut::new(),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err.inner(),
+            crate::Error::IntegerTooLargeForDecimal128
+        ));
+
+        let mut bytes = bytes::BytesMut::new();
+        crate::to_string(&Wide { a: 0, b: 0, c: -1 }, &mut bytes).unwrap();
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Unsigned {
+            c: u128,
+        }
+        let err = super::from_bytes::<Unsigned>(&bytes).unwrap_err();
+        assert!(
+            matches!(err.inner(), super::Error::Decimal128OutOfRange("u128")),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn deserialize_option() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            a: Option<String>,
+            b: Option<String>,
+            c: Option<Vec<i32>>,
+            d: Option<Option<i32>>,
+        }
+
+        let val = A {
+            a: Some("hello".to_string()),
+            b: None,
+            c: Some(vec![1, 2, 3]),
+            d: None,
+        };
+
+        let mut bytes = bytes::BytesMut::new();
+        crate::to_string(&val, &mut bytes).unwrap();
+
+        let deserialized: A = super::from_bytes(&bytes).unwrap();
+        assert_eq!(deserialized, val);
+    }
+
+    #[test]
+    fn ignore_legacy_element_types() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct A {
+            before: i32,
+            after: String,
+        }
+
+        let scope = bson::doc! { "x": 1 };
+        let doc = bson::doc! {
+            "before": 1,
+            "oid": bson::oid::ObjectId::from_bytes([7; 12]),
+            "regex": bson::Regex { pattern: "^a".to_string(), options: "i".to_string() },
+            "code": bson::Bson::JavaScriptCode("f()".to_string()),
+            "scoped": bson::Bson::JavaScriptCodeWithScope(bson::JavaScriptCodeWithScope {
+                code: "g()".to_string(),
+                scope,
+            }),
+            "max": bson::Bson::MaxKey,
+            "min": bson::Bson::MinKey,
+            "after": "still here",
+        };
+        let mut data = Vec::new();
+        doc.to_writer(&mut data).unwrap();
+
+        super::validate(&data).unwrap();
+
+        let deserialized: A = super::from_bytes(&data).unwrap();
+        assert_eq!(
+            deserialized,
+            A {
+                before: 1,
+                after: "still here".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        // { "a": 1, "b": { "a": 2 }, "a": 3 }
+        let mut data = vec![0x00, 0x00, 0x00, 0x00];
+        data.extend_from_slice(&[0x10, b'a', 0x00, 0x01, 0x00, 0x00, 0x00]);
+        data.extend_from_slice(&[0x03, b'b', 0x00, 0x0c, 0x00, 0x00, 0x00]);
+        data.extend_from_slice(&[0x10, b'a', 0x00, 0x02, 0x00, 0x00, 0x00, 0x00]);
+        data.extend_from_slice(&[0x10, b'a', 0x00, 0x03, 0x00, 0x00, 0x00, 0x00]);
+        let length = data.len() as i32;
+        data[..4].copy_from_slice(&length.to_le_bytes());
+
+        let options = super::Options::new().reject_duplicate_keys(true);
+
+        let res = super::from_bytes_with_options::<serde::de::IgnoredAny>(&data, options);
+        assert!(
+            matches!(&res, Err(super::Error::DuplicateKey(key, 26)) if key == "a"),
+            "{:?}",
+            res
+        );
+
+        // the same key in a nested document is fine
+        let len = data.len();
+        data[len - 7] = b'c';
+        super::from_bytes_with_options::<serde::de::IgnoredAny>(&data, options).unwrap();
+    }
+
+    #[test]
+    fn validate() {
+        let f = std::fs::read("test/test.bin").unwrap();
+        super::validate(&f).unwrap();
+
+        // { "a": "b" }
+        let data = [
+            0x0e, 0x00, 0x00, 0x00, 0x02, b'a', 0x00, 0x02, 0x00, 0x00, 0x00, b'b', 0x00, 0x00,
+        ];
+        super::validate(&data).unwrap();
+
+        let mut bad_utf8 = data;
+        bad_utf8[11] = 0xff;
+        assert!(matches!(
+            super::validate(&bad_utf8),
+            Err(super::Error::InvalidUtf8(11))
+        ));
+
+        let mut bad_string_length = data;
+        bad_string_length[7] = 0x09;
+        assert!(matches!(
+            super::validate(&bad_string_length),
+            Err(super::Error::InvalidLength(7))
+        ));
+
+        let mut bad_element_type = data;
+        bad_element_type[4] = 0x20;
+        assert!(matches!(
+            super::validate(&bad_element_type),
+            Err(super::Error::UnknownElementType(0x20, 4))
+        ));
+
+        assert!(matches!(
+            super::validate(&data[..13]),
+            Err(super::Error::InvalidLength(0))
+        ));
+
+        // { "r": /a/i } with the options mangled
+        let regex = [
+            0x0c, 0x00, 0x00, 0x00, 0x0b, b'r', 0x00, b'a', 0x00, 0xc4, 0x00, 0x00,
+        ];
+        assert!(matches!(
+            super::validate(&regex),
+            Err(super::Error::InvalidUtf8(9))
+        ));
+    }
+
+    #[test]
+    fn malformed_input() {
+        // { "a": "b" }
+        let data = [
+            0x0e, 0x00, 0x00, 0x00, 0x02, b'a', 0x00, 0x02, 0x00, 0x00, 0x00, b'b', 0x00, 0x00,
+        ];
+
+        let mut truncated_string = data;
+        truncated_string[7] = 0x09;
+        let res = super::from_bytes::<serde::de::IgnoredAny>(&truncated_string);
+        assert!(
+            matches!(res, Err(super::Error::InvalidLength(11))),
+            "{:?}",
+            res
+        );
+
+        let mut unterminated_string = data;
+        unterminated_string[12] = b'c';
+        let res = super::from_bytes::<serde::de::IgnoredAny>(&unterminated_string);
+        assert!(
+            matches!(res, Err(super::Error::InvalidLength(7))),
+            "{:?}",
+            res
+        );
+
+        let res = super::from_bytes::<serde::de::IgnoredAny>(&data[..3]);
+        assert!(
+            matches!(res, Err(super::Error::InvalidLength(0))),
+            "{:?}",
+            res
+        );
+    }
+
+    #[test]
+    fn deserialize_object_id() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct A {
+            #[serde(rename = "_id", with = "serde_bytes")]
+            id: Vec<u8>,
+            a: i32,
+        }
+
+        let oid = bson::oid::ObjectId::new();
+        let mut data = Vec::new();
+        bson::doc! { "_id": oid, "a": 1 }
+            .to_writer(&mut data)
+            .unwrap();
+
+        let deserialized: A = super::from_bytes(&data).unwrap();
+        assert_eq!(
+            deserialized,
+            A {
+                id: oid.bytes().to_vec(),
+                a: 1
+            }
+        );
+    }
+
+    #[test]
+    fn deserialize_owned() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            a: String,
+            #[serde(with = "serde_bytes")]
+            b: Vec<u8>,
+            c: Vec<String>,
+        }
+
+        let val = A {
+            a: "hello".to_string(),
+            b: vec![1, 2, 3],
+            c: vec!["world".to_string()],
+        };
+
+        let deserialized: A = {
+            let mut bytes = bytes::BytesMut::new();
+            crate::to_string(&val, &mut bytes).unwrap();
+            super::from_slice_owned(&bytes).unwrap()
+        };
+
+        assert_eq!(deserialized, val);
+    }
+
+    #[test]
+    fn deserialize_seed() {
+        use serde::de::{DeserializeSeed, Deserializer, MapAccess, Visitor};
+
+        /// Collects every string value in a document into a caller-provided interner.
+        struct Interner<'a>(&'a mut Vec<String>);
+
+        impl<'de> DeserializeSeed<'de> for Interner<'_> {
+            type Value = usize;
+
+            fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<usize, D::Error> {
+                deserializer.deserialize_map(self)
+            }
+        }
+
+        impl<'de> Visitor<'de> for Interner<'_> {
+            type Value = usize;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a map of strings")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<usize, A::Error> {
+                let mut count = 0;
+
+                while let Some((_, value)) = map.next_entry::<&str, &str>()? {
+                    if !self.0.iter().any(|v| v == value) {
+                        self.0.push(value.to_string());
+                    }
+
+                    count += 1;
+                }
+
+                Ok(count)
+            }
+        }
+
+        let mut map = std::collections::BTreeMap::new();
+        map.insert("a", "hello");
+        map.insert("b", "world");
+        map.insert("c", "hello");
+
+        let mut bytes = bytes::BytesMut::new();
+        crate::to_string(&map, &mut bytes).unwrap();
+
+        let mut interned = vec!["world".to_string()];
+        let count = super::from_bytes_seed(&bytes, Interner(&mut interned)).unwrap();
+
+        assert_eq!(count, 3);
+        assert_eq!(interned, ["world", "hello"]);
+    }
+
+    #[test]
+    fn deserialize_in() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            a: Vec<i32>,
+        }
+
+        let mut bytes = bytes::BytesMut::new();
+        crate::to_string(&A { a: vec![1, 2, 3] }, &mut bytes).unwrap();
+
+        let bump = bumpalo::Bump::new();
+        let deserialized: A = super::from_bytes_in(&bump, &bytes).unwrap();
+
+        assert_eq!(deserialized, A { a: vec![1, 2, 3] });
+        assert!(bump.allocated_bytes() > 0);
+    }
+
+    #[test]
+    fn max_retained_arena_bytes() {
+        let data: Vec<i32> = (0..10_000).collect();
+        let mut bytes = bytes::BytesMut::new();
+        crate::to_string(&std::collections::BTreeMap::from([("a", data)]), &mut bytes).unwrap();
+
+        super::from_bytes::<serde::de::IgnoredAny>(&bytes).unwrap();
+        let retained = super::ALLOCATOR.with_borrow(|allocator| allocator.allocated_bytes());
+        assert!(retained > 1024, "{}", retained);
+
+        let options = super::Options::new().max_retained_arena_bytes(Some(1024));
+        super::from_bytes_with_options::<serde::de::IgnoredAny>(&bytes, options).unwrap();
+        let retained = super::ALLOCATOR.with_borrow(|allocator| allocator.allocated_bytes());
+        assert!(retained <= 1024, "{}", retained);
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        #[derive(Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            a: String,
+        }
+
+        let mut data = Vec::new();
+        crate::to_vec(&std::collections::BTreeMap::from([("a", "hi!")]), &mut data).unwrap();
+        data[12] = 0xff;
+
+        let err = super::from_bytes::<A>(&data).unwrap_err();
+        assert!(matches!(err.inner(), super::Error::InvalidUtf8(11)));
+
+        let options = super::Options::new().invalid_utf8(super::InvalidUtf8::Replace);
+        let deserialized: A = super::from_bytes_with_options(&data, options).unwrap();
+        assert_eq!(deserialized.a, "h\u{fffd}!");
+
+        // can't be borrowed once it's been replaced
+        let err =
+            super::from_bytes_with_options::<std::collections::HashMap<&str, &str>>(&data, options)
+                .unwrap_err();
+        assert!(matches!(err.inner(), super::Error::Custom(_)));
+    }
+
+    #[test]
+    fn assume_valid_utf8() {
+        let val = std::collections::BTreeMap::from([("a", "hello"), ("🦀", "world")]);
+        let data = crate::to_bytes(&val).unwrap();
+
+        // SAFETY: the input was just written by us
+        let options = unsafe { super::Options::new().assume_valid_utf8(true) };
+        let deserialized: std::collections::BTreeMap<&str, &str> =
+            super::from_bytes_with_options(&data, options).unwrap();
+        assert_eq!(deserialized, val);
+    }
+
+    #[test]
+    fn lazy_tape() {
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct A {
+            a: u8,
+        }
+
+        let mut val = (0..100)
+            .map(|i| (format!("b{:02}", i), i))
+            .collect::<std::collections::BTreeMap<_, _>>();
+        val.insert("a".to_string(), -1);
+        val.insert("z".to_string(), 0);
+
+        let mut data = Vec::new();
+        crate::to_vec(&val, &mut data).unwrap();
+
+        // give the last element a type that isn't in the spec, we never get far enough to find
+        // out about it
+        let position = data.windows(2).position(|v| v == b"z\0").unwrap();
+        data[position - 1] = 0x20;
+
+        let err = super::from_bytes::<A>(&data).unwrap_err();
+        assert!(matches!(
+            err.inner(),
+            super::Error::IntegerOutOfRange(-1, "u8")
+        ));
+        assert_eq!(err.path().unwrap().to_string(), "a");
+
+        assert!(matches!(
+            super::validate(&data),
+            Err(super::Error::UnknownElementType(0x20, _))
+        ));
+    }
+
+    #[test]
+    fn skip_ignored_documents() {
+        #[derive(Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            c: Vec<i32>,
+        }
+
+        let b = std::collections::BTreeMap::from([("x", [1, 2, 3])]);
+        let val = std::collections::BTreeMap::from([("a", vec![]), ("b", vec![b]), ("c", vec![])]);
+
+        let mut data = Vec::new();
+        crate::to_vec(&val, &mut data).unwrap();
+
+        // break the document we're ignoring, we never look inside of it
+        let position = data.windows(2).position(|v| v == b"x\0").unwrap();
+        data[position - 1] = 0x20;
+        assert!(super::validate(&data).is_err());
+
+        let expected = A { c: vec![] };
+        assert_eq!(super::from_bytes::<A>(&data).unwrap(), expected);
+        assert_eq!(super::from_bytes_streaming::<A>(&data).unwrap(), expected);
+
+        // unless it's asked for
+        let err = super::from_bytes::<
+            std::collections::BTreeMap<&str, Vec<std::collections::BTreeMap<&str, Vec<i32>>>>,
+        >(&data)
+        .unwrap_err();
+        assert!(matches!(err, super::Error::UnknownElementType(0x20, _)));
+    }
+
+    #[test]
+    fn project() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Item<'a> {
+            id: &'a str,
+        }
+
+        let val = bson::doc! {
+            "a": { "b": 1, "c": [1, 2, 3] },
+            "items": [{ "id": "x" }, { "id": "y" }],
+            "d": 4.5,
+        };
+        let data = bson::to_vec(&val).unwrap();
+
+        let values = super::project(
+            &data,
+            &[
+                "a.b",
+                "items.1.id",
+                "items.0",
+                "a.c",
+                "d",
+                "d.e",
+                "missing",
+                "items.2",
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(super::from_raw::<i32>(values[0].unwrap()).unwrap(), 1);
+        assert_eq!(super::from_raw::<&str>(values[1].unwrap()).unwrap(), "y");
+        assert_eq!(
+            super::from_raw::<Item>(values[2].unwrap()).unwrap(),
+            Item { id: "x" }
+        );
+        assert_eq!(
+            super::from_raw::<Vec<i32>>(values[3].unwrap()).unwrap(),
+            [1, 2, 3]
+        );
+        assert_eq!(super::from_raw::<f64>(values[4].unwrap()).unwrap(), 4.5);
+        assert_eq!(values[5..], [None, None, None]);
+
+        assert!(super::project(&data[..data.len() - 1], &["a"]).is_err());
+    }
+
+    #[test]
+    fn get_field() {
+        let val = bson::doc! {
+            "op": "insert",
+            "doc": { "op": "nested" },
+            "n": 2,
+        };
+        let data = bson::to_vec(&val).unwrap();
+
+        assert_eq!(
+            super::get_field::<&str>(&data, "op").unwrap(),
+            Some("insert")
+        );
+        assert_eq!(super::get_field::<i64>(&data, "n").unwrap(), Some(2));
+        assert_eq!(super::get_field::<i64>(&data, "missing").unwrap(), None);
+        assert!(super::get_field::<i64>(&data, "op").is_err());
+    }
+
+    #[test]
+    fn size_hints() {
+        use serde::de::{MapAccess, SeqAccess, Visitor};
+
+        /// Collects the size hint given for each document or array.
+        #[derive(Debug, Default, PartialEq, Eq)]
+        struct Hints(Vec<Option<usize>>);
+
+        impl<'de> Deserialize<'de> for Hints {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let mut hints = Hints::default();
+                deserializer.deserialize_any(HintsVisitor(&mut hints))?;
+                Ok(hints)
+            }
+        }
+
+        struct HintsVisitor<'a>(&'a mut Hints);
+
+        impl<'de> serde::de::DeserializeSeed<'de> for HintsVisitor<'_> {
+            type Value = ();
+
+            fn deserialize<D: serde::Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<(), D::Error> {
+                deserializer.deserialize_any(self)
+            }
+        }
+
+        impl<'de> Visitor<'de> for HintsVisitor<'_> {
+            type Value = ();
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("anything")
+            }
+
+            fn visit_i32<E>(self, _v: i32) -> Result<(), E> {
+                Ok(())
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+                self.0 .0.push(seq.size_hint());
+                while seq.next_element_seed(HintsVisitor(&mut *self.0))?.is_some() {}
+                Ok(())
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+                self.0 .0.push(map.size_hint());
+                while map.next_key::<&str>()?.is_some() {
+                    map.next_value_seed(HintsVisitor(&mut *self.0))?;
+                }
+                Ok(())
+            }
+        }
+
+        let val = bson::doc! {
+            "a": [1, 2, 3],
+            "b": { "c": 1, "d": [] },
+        };
+        let data = bson::to_vec(&val).unwrap();
+
+        let expected = Hints(vec![Some(2), Some(3), Some(2), Some(0)]);
+        assert_eq!(super::from_bytes::<Hints>(&data).unwrap(), expected);
+        assert_eq!(
+            super::from_bytes_streaming::<Hints>(&data).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn bson_types() {
+        use serde_json::json;
+
+        let data = bson::to_vec(&bson::doc! {
+            "oid": bson::oid::ObjectId::from_bytes([1; 12]),
+            "bin": bson::Binary { subtype: bson::spec::BinarySubtype::Generic, bytes: b"foo".to_vec() },
+            "date": bson::DateTime::from_millis(1_700_000_000_123),
+            "dec": "-1.50".parse::<bson::Decimal128>().unwrap(),
+            "ts": bson::Timestamp { time: 2, increment: 3 },
+            "re": bson::Regex { pattern: "^a".to_string(), options: "i".to_string() },
+            "code": bson::Bson::JavaScriptCode("f()".to_string()),
+            "min": bson::Bson::MinKey,
+            "n": [1, 2_i64, 1.5, "x", null, true],
+        })
+        .unwrap();
+
+        // bytes can't be held by json
+        assert!(super::from_bytes::<serde_json::Value>(&data).is_err());
+
+        let numbers = json!([1, 2, 1.5, "x", null, true]);
+
+        let options = super::Options::new().bson_types(super::BsonTypes::Strings);
+        let value: serde_json::Value = super::from_bytes_with_options(&data, options).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "oid": "010101010101010101010101",
+                "bin": "Zm9v",
+                "date": "2023-11-14T22:13:20.123Z",
+                "dec": "-1.50",
+                "ts": (2_u64 << 32) | 3,
+                "re": ["^a", "i"],
+                "code": "f()",
+                "min": null,
+                "n": numbers,
+            })
+        );
+
+        let options = super::Options::new().bson_types(super::BsonTypes::ExtendedJson);
+        let expected = json!({
+            "oid": { "$oid": "010101010101010101010101" },
+            "bin": { "$binary": { "base64": "Zm9v", "subType": "00" } },
+            "date": { "$date": { "$numberLong": "1700000000123" } },
+            "dec": { "$numberDecimal": "-1.50" },
+            "ts": { "$timestamp": { "t": 2, "i": 3 } },
+            "re": { "$regularExpression": { "pattern": "^a", "options": "i" } },
+            "code": { "$code": "f()" },
+            "min": { "$minKey": 1 },
+            "n": numbers,
+        });
+
+        let value: serde_json::Value = super::from_bytes_with_options(&data, options).unwrap();
+        assert_eq!(value, expected);
+
+        // which is what the reference implementation produces too
+        let theirs = bson::Document::from_reader(&data[..]).unwrap();
+        let mut theirs = bson::Bson::Document(theirs).into_canonical_extjson();
+        theirs["n"] = numbers;
+        assert_eq!(value, theirs);
+
+        let value: serde_json::Value =
+            super::from_bytes_streaming_with_options(&data, options).unwrap();
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn deserialize_streaming() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct A {
+            a: Vec<B>,
+            b: Option<String>,
+            c: Option<i64>,
+            d: C,
+            e: (i32, f64),
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct B {
+            x: i32,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        enum C {
+            Unit,
+            Struct { y: String },
+        }
+
+        let val = A {
+            a: vec![B { x: 1 }, B { x: 2 }],
+            b: None,
+            c: Some(3),
+            d: C::Struct {
+                y: "hello".to_string(),
+            },
+            e: (4, 5.5),
+        };
+        let data = crate::to_bytes(&val).unwrap();
+
+        let deserialized: A = super::from_bytes_streaming(&data).unwrap();
+        assert_eq!(deserialized, val);
+
+        // errors are located the same as they are from the tape
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct D {
+            a: Vec<E>,
+        }
+
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct E {
+            x: u8,
+        }
+
+        let x = |x| std::collections::BTreeMap::from([("x", x)]);
+        let val = std::collections::BTreeMap::from([("a", [x(1), x(-1)])]);
+        let data = crate::to_bytes(&val).unwrap();
+
+        let expected = super::from_bytes::<D>(&data).unwrap_err();
+        let err = super::from_bytes_streaming::<D>(&data).unwrap_err();
+        assert_eq!(err.to_string(), expected.to_string());
+        assert_eq!(err.path().unwrap().to_string(), "a[1].x");
+    }
+
+    #[test]
+    fn documents() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            a: i32,
+        }
+
+        let mut bytes = Vec::new();
+        for a in 0..3 {
+            crate::to_vec(&A { a }, &mut bytes).unwrap();
+        }
+
+        let deserialized = super::documents(&bytes)
+            .map(|document| super::from_bytes(document.unwrap()).unwrap())
+            .collect::<Vec<A>>();
+        assert_eq!(deserialized, [A { a: 0 }, A { a: 1 }, A { a: 2 }]);
+
+        // a truncated trailing document errors and ends iteration
+        let truncated = &bytes[..bytes.len() - 1];
+        let mut documents = super::documents(truncated);
+        assert!(documents.next().unwrap().is_ok());
+        assert!(documents.next().unwrap().is_ok());
+        let offset = bytes.len() / 3 * 2;
+        assert!(matches!(
+            documents.next(),
+            Some(Err(super::Error::InvalidLength(v))) if v == offset
+        ));
+        assert!(documents.next().is_none());
+    }
+
+    #[test]
+    fn split_documents() {
+        let mut bytes = Vec::new();
+        for a in 0..3 {
+            crate::to_vec(&std::collections::BTreeMap::from([("a", a)]), &mut bytes).unwrap();
+        }
+        let document = bytes.len() / 3;
+
+        assert_eq!(super::document_len(&bytes).map(|v| v.get()), Some(document));
+        // only the prefix is needed
+        assert_eq!(
+            super::document_len(&bytes[..4]).map(|v| v.get()),
+            Some(document)
+        );
+        assert_eq!(super::document_len(&bytes[..3]), None);
+        assert_eq!(super::document_len(&[4, 0, 0, 0]), None);
+        assert_eq!(super::document_len(&(-1_i32).to_le_bytes()), None);
+
+        let split = super::split_documents(&bytes).collect::<Vec<_>>();
+        assert_eq!(split, bytes.chunks(document).collect::<Vec<_>>());
+
+        assert_eq!(super::split_documents(&bytes[..bytes.len() - 1]).count(), 2);
+    }
+
+    #[test]
+    fn parser() {
+        let mut bytes = Vec::new();
+        for a in 0..3 {
+            crate::to_vec(&std::collections::BTreeMap::from([("a", a)]), &mut bytes).unwrap();
+        }
+
+        let mut parser = super::Parser::new();
+        let mut documents = Vec::new();
+
+        for byte in &bytes {
+            parser.feed(std::slice::from_ref(byte));
+
+            while let Some(document) = parser.next_document().unwrap() {
+                documents.push(document);
+            }
+        }
+
+        assert_eq!(documents.len(), 3);
+        assert_eq!(documents.concat(), bytes);
+        assert_eq!(parser.buffered(), 0);
+        parser.finish().unwrap();
+
+        // everything at once, stopping part way through the last document
+        let mut parser = super::Parser::new();
+        parser.feed(&bytes[..bytes.len() - 1]);
+        assert!(parser.next_document().unwrap().is_some());
+        assert!(parser.next_document().unwrap().is_some());
+        assert!(parser.next_document().unwrap().is_none());
+        assert_eq!(parser.buffered(), bytes.len() / 3 - 1);
+        assert!(matches!(parser.finish(), Err(super::Error::EndOfFile)));
+
+        // there's no recovering from a bad length
+        let mut parser = super::Parser::new();
+        parser.feed(&bytes[..bytes.len() / 3]);
+        parser.feed(&[1, 0, 0, 0]);
+        assert!(parser.next_document().unwrap().is_some());
+        let offset = bytes.len() / 3;
+        assert!(matches!(
+            parser.next_document(),
+            Err(super::Error::InvalidLength(v)) if v == offset
+        ));
+        parser.feed(&bytes);
+        assert!(parser.next_document().is_err());
+        assert!(parser.finish().is_err());
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn from_file() {
+        let path = std::env::temp_dir().join(format!("serde_bson-{}.bson", std::process::id()));
+
+        let mut bytes = Vec::new();
+        for a in 0..3 {
+            crate::to_vec(&std::collections::BTreeMap::from([("a", a)]), &mut bytes).unwrap();
+        }
+        std::fs::write(&path, &bytes).unwrap();
+
+        let file = super::from_file(&path).unwrap();
+        let documents = file.documents().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(documents.len(), 3);
+        assert_eq!(documents.concat(), bytes);
+
+        drop(file);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn document_stream() {
+        use futures_core::Stream;
+        use std::{
+            pin::Pin,
+            task::{Context, Poll, Waker},
+        };
+
+        /// Hands out a few bytes at a time, and only on every other poll.
+        struct Trickle<'a> {
+            data: &'a [u8],
+            ready: bool,
+        }
+
+        impl futures_io::AsyncRead for Trickle<'_> {
+            fn poll_read(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &mut [u8],
+            ) -> Poll<std::io::Result<usize>> {
+                self.ready = !self.ready;
+                if !self.ready {
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+
+                let read = buf.len().min(self.data.len()).min(3);
+                buf[..read].copy_from_slice(&self.data[..read]);
+                self.data = &self.data[read..];
+                Poll::Ready(Ok(read))
+            }
+        }
+
+        fn collect<S: Stream + Unpin>(mut stream: S) -> Vec<S::Item> {
+            let mut cx = Context::from_waker(Waker::noop());
+            let mut items = Vec::new();
+
+            loop {
+                match Pin::new(&mut stream).poll_next(&mut cx) {
+                    Poll::Ready(Some(item)) => items.push(item),
+                    Poll::Ready(None) => return items,
+                    Poll::Pending => {}
+                }
+            }
+        }
+
+        let mut bytes = Vec::new();
+        for a in 0..3 {
+            crate::to_vec(&std::collections::BTreeMap::from([("a", a)]), &mut bytes).unwrap();
+        }
+
+        let stream =
+            super::document_stream::<_, std::collections::BTreeMap<String, i32>>(Trickle {
+                data: &bytes,
+                ready: false,
+            });
+        let documents = collect(stream)
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            documents,
+            (0..3)
+                .map(|a| std::collections::BTreeMap::from([("a".to_string(), a)]))
+                .collect::<Vec<_>>()
+        );
+
+        // a document cut off part way through is an error rather than the end of the stream
+        let stream =
+            super::document_stream::<_, std::collections::BTreeMap<String, i32>>(Trickle {
+                data: &bytes[..bytes.len() - 1],
+                ready: false,
+            });
+        let items = collect(stream);
+        assert_eq!(items.len(), 3);
+        assert!(matches!(items[2], Err(super::Error::EndOfFile)));
+    }
+
+    #[test]
+    fn unit_and_newtype_shapes() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Unit;
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Newtype(String);
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        enum E {
+            Unit,
+            Nested(Box<E>),
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct A {
+            unit: Unit,
+            newtype: Newtype,
+            nested: E,
+            empty: (),
+        }
+
+        let val = A {
+            unit: Unit,
+            newtype: Newtype("a".to_string()),
+            nested: E::Nested(Box::new(E::Nested(Box::new(E::Unit)))),
+            empty: (),
+        };
+
+        let data = crate::to_bytes(&val).unwrap();
+        assert_eq!(data, bson::to_vec(&val).unwrap());
+
+        assert_eq!(super::from_bytes::<A>(&data).unwrap(), val);
+        assert_eq!(super::from_bytes_streaming::<A>(&data).unwrap(), val);
+    }
+
+    #[test]
+    fn enum_variants_by_index() {
+        use crate::ser::{Options, Variants};
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        enum E {
+            Unit,
+            Newtype(i32),
+            Tuple(i32, bool),
+            Struct {
+                a: i32,
+            },
+            #[serde(rename = "7")]
+            Numbered(i32),
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct A {
+            val: Vec<E>,
+        }
+
+        let val = A {
+            val: vec![
+                E::Unit,
+                E::Newtype(5),
+                E::Tuple(1, true),
+                E::Struct { a: 2 },
+                E::Numbered(3),
+            ],
+        };
+
+        let by_index =
+            crate::to_bytes_with_options(&val, Options::new().variants(Variants::Index)).unwrap();
+        assert_eq!(super::from_bytes::<A>(&by_index).unwrap(), val);
+        assert_eq!(super::from_bytes_streaming::<A>(&by_index).unwrap(), val);
+
+        // names still work, including ones that look like an index
+        let by_name = crate::to_bytes(&val).unwrap();
+        assert_eq!(super::from_bytes::<A>(&by_name).unwrap(), val);
+
+        let data = bson::to_vec(&bson::doc! { "val": [9] }).unwrap();
+        assert!(super::from_bytes::<A>(&data).is_err());
+        let data = bson::to_vec(&bson::doc! { "val": [-1] }).unwrap();
+        assert!(super::from_bytes::<A>(&data).is_err());
+    }
+
+    #[test]
+    fn unit_variants_in_documents() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        enum E {
+            A,
+            B,
+        }
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct S {
+            e: E,
+        }
+
+        let cases = vec![
+            (bson::doc! { "e": "B" }, E::B),
+            (bson::doc! { "e": 1 }, E::B),
+            (bson::doc! { "e": { "A": null } }, E::A),
+            (bson::doc! { "e": { "1": null } }, E::B),
+        ];
+
+        for (doc, expected) in cases {
+            let data = bson::to_vec(&doc).unwrap();
+            let expected = S { e: expected };
+
+            assert_eq!(super::from_bytes::<S>(&data).unwrap(), expected, "{}", doc);
+            assert_eq!(
+                super::from_bytes_streaming::<S>(&data).unwrap(),
+                expected,
+                "{}",
+                doc
+            );
+        }
+
+        // a unit variant has no payload, and only one variant can be given
+        for invalid in [
+            bson::doc! { "e": { "A": 1 } },
+            bson::doc! { "e": { "B": { "a": [1, 2] } } },
+            bson::doc! { "e": { "A": null, "B": null } },
+            bson::doc! { "e": [1] },
+            bson::doc! { "e": [null] },
+        ]
+        .iter()
+        {
+            let data = bson::to_vec(invalid).unwrap();
+            assert!(super::from_bytes::<S>(&data).is_err(), "{}", invalid);
+            assert!(
+                super::from_bytes_streaming::<S>(&data).is_err(),
+                "{}",
+                invalid
+            );
+        }
     }
 }
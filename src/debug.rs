@@ -0,0 +1,856 @@
+//! Human readable renderings of encoded documents, for working out what's actually in a
+//! buffer when it doesn't deserialise the way it should, and comparisons for tests checking our
+//! output against other producers.
+
+use crate::{
+    scan::{Element, Elements},
+    text::{hex, rfc3339},
+    Decimal128,
+};
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt::Write,
+    ops::Range,
+};
+
+/// Renders the document in `data` with one element per line, nested documents indented under
+/// their key. Each element is shown with its type and value, and strings, binaries, documents
+/// and arrays with their encoded length:
+///
+/// ```text
+/// document (51 bytes) {
+///   "name": string (7 bytes) "ferris"
+///   "tags": array (14 bytes) [
+///     "0": string (2 bytes) "a"
+///   ]
+///   "age": int32 7
+/// }
+/// ```
+///
+/// Malformed input is rendered as far as it can be, with a line describing what's wrong in
+/// place of anything that can't be read.
+pub fn dump(data: &[u8]) -> String {
+    let mut out = String::new();
+    write_document(&mut out, data, 0, 0, false);
+    out.push('\n');
+    out
+}
+
+/// How [`docs_equivalent_with`] compares documents.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Equivalence {
+    ordered_keys: bool,
+}
+
+impl Equivalence {
+    /// Keys can be in any order.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the keys of each document must also be in the same order. Arrays are always
+    /// compared in order.
+    pub fn ordered_keys(mut self, ordered_keys: bool) -> Self {
+        self.ordered_keys = ordered_keys;
+        self
+    }
+}
+
+/// Whether the encoded documents `a` and `b` hold the same fields, in any order and at any
+/// depth. Values are compared by type and encoded value, so `1` and `1.0` differ but NaNs of
+/// the same bit pattern don't. Malformed documents aren't equivalent to anything.
+pub fn docs_equivalent(a: &[u8], b: &[u8]) -> bool {
+    docs_equivalent_with(a, b, Equivalence::new())
+}
+
+pub fn docs_equivalent_with(a: &[u8], b: &[u8], equivalence: Equivalence) -> bool {
+    difference(a, 0, b, 0, equivalence, false, "").is_none()
+}
+
+/// Panics if the documents aren't [`docs_equivalent`], saying where they first differ and
+/// showing both with [`dump`].
+#[track_caller]
+pub fn assert_docs_equivalent(a: &[u8], b: &[u8]) {
+    assert_docs_equivalent_with(a, b, Equivalence::new())
+}
+
+#[track_caller]
+pub fn assert_docs_equivalent_with(a: &[u8], b: &[u8], equivalence: Equivalence) {
+    if let Some(difference) = difference(a, 0, b, 0, equivalence, false, "") {
+        panic!(
+            "documents aren't equivalent: {}\nleft: {}right: {}",
+            difference,
+            dump(a),
+            dump(b)
+        );
+    }
+}
+
+/// Describes the first difference between the documents at `a_start` in `a` and `b_start` in
+/// `b`, if there is one. Arrays are compared by position rather than key.
+fn difference(
+    a: &[u8],
+    a_start: usize,
+    b: &[u8],
+    b_start: usize,
+    equivalence: Equivalence,
+    array: bool,
+    path: &str,
+) -> Option<String> {
+    let read = |data, start| -> Result<Vec<Element<'_>>, crate::de::Error> {
+        Elements::new(data, start)?.collect()
+    };
+    let at = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+    let name = if path.is_empty() {
+        "the document".to_string()
+    } else {
+        format!("{:?}", path)
+    };
+
+    let (a_elements, b_elements) = match (read(a, a_start), read(b, b_start)) {
+        (Ok(a), Ok(b)) => (a, b),
+        (Err(e), _) | (_, Err(e)) => return Some(format!("{} is malformed: {}", name, e)),
+    };
+
+    if a_elements.len() != b_elements.len() {
+        return Some(format!(
+            "{} has {} elements on the left and {} on the right",
+            name,
+            a_elements.len(),
+            b_elements.len()
+        ));
+    }
+
+    for (i, a_element) in a_elements.iter().enumerate() {
+        let b_element = if array {
+            &b_elements[i]
+        } else if equivalence.ordered_keys {
+            let b_element = &b_elements[i];
+            if a_element.key != b_element.key {
+                return Some(format!(
+                    "field {} is {:?} on the left and {:?} on the right",
+                    i,
+                    at(a_element.key),
+                    at(b_element.key)
+                ));
+            }
+            b_element
+        } else {
+            match b_elements.iter().find(|b| b.key == a_element.key) {
+                Some(b_element) => b_element,
+                None => return Some(format!("{:?} is missing on the right", at(a_element.key))),
+            }
+        };
+
+        let path = if array {
+            at(&i.to_string())
+        } else {
+            at(a_element.key)
+        };
+        let (a_value, b_value) = (a_element.value, b_element.value);
+
+        if a_value.element_type() != b_value.element_type() {
+            return Some(format!(
+                "{:?} is {} on the left and {} on the right",
+                path,
+                type_name(a_value.element_type()),
+                type_name(b_value.element_type())
+            ));
+        }
+
+        let difference = match a_value.element_type() {
+            element_type @ (0x03 | 0x04) => difference(
+                a,
+                a_element.value_offset,
+                b,
+                b_element.value_offset,
+                equivalence,
+                element_type == 0x04,
+                &path,
+            ),
+            _ if a_value.as_bytes() != b_value.as_bytes() => {
+                Some(format!("{:?} has different values", path))
+            }
+            _ => None,
+        };
+
+        if difference.is_some() {
+            return difference;
+        }
+    }
+
+    None
+}
+
+/// Writes the document at `start`, opening bracket to closing bracket.
+fn write_document(out: &mut String, data: &[u8], start: usize, depth: usize, array: bool) {
+    let (name, open, close) = if array {
+        ("array", '[', ']')
+    } else {
+        ("document", '{', '}')
+    };
+
+    let elements = match Elements::new(data, start) {
+        Ok(elements) => elements,
+        Err(e) => {
+            write!(out, "{} <{}>", name, e).unwrap();
+            return;
+        }
+    };
+
+    write!(out, "{} ({} bytes) {}", name, length(data, start), open).unwrap();
+
+    for element in elements {
+        out.push('\n');
+        indent(out, depth + 1);
+
+        match element {
+            Ok(element) => {
+                write!(out, "{:?}: ", element.key).unwrap();
+                write_value(
+                    out,
+                    data,
+                    element.value.element_type(),
+                    element.value_offset,
+                    element.value.as_bytes(),
+                    depth + 1,
+                );
+            }
+            Err(e) => write!(out, "<{}>", e).unwrap(),
+        }
+    }
+
+    out.push('\n');
+    indent(out, depth);
+    out.push(close);
+}
+
+/// Writes the value `bytes` of type `element_type`, found at `offset` in `data`.
+fn write_value(
+    out: &mut String,
+    data: &[u8],
+    element_type: u8,
+    offset: usize,
+    bytes: &[u8],
+    depth: usize,
+) {
+    let int32 = |bytes: &[u8]| i32::from_le_bytes(bytes[..4].try_into().unwrap());
+    let int64 = |bytes: &[u8]| i64::from_le_bytes(bytes[..8].try_into().unwrap());
+
+    match element_type {
+        0x01 => write!(out, "double {:?}", f64::from_bits(int64(bytes) as u64)).unwrap(),
+        0x02 => write_string(out, "string", bytes),
+        0x03 => write_document(out, data, offset, depth, false),
+        0x04 => write_document(out, data, offset, depth, true),
+        0x05 => write!(
+            out,
+            "binary (subtype {:#04x}, {} bytes) {}",
+            bytes[4],
+            bytes.len() - 5,
+            hex(&bytes[5..])
+        )
+        .unwrap(),
+        0x06 => out.push_str("undefined"),
+        0x07 => write!(out, "objectId {}", hex(bytes)).unwrap(),
+        0x08 => match bytes[0] {
+            0 => out.push_str("bool false"),
+            1 => out.push_str("bool true"),
+            v => write!(out, "bool <invalid value {:#04x}>", v).unwrap(),
+        },
+        0x09 => {
+            let millis = int64(bytes);
+            write!(out, "datetime {}", millis).unwrap();
+            if let Some(date) = rfc3339(millis) {
+                write!(out, " ({})", date).unwrap();
+            }
+        }
+        0x0A => out.push_str("null"),
+        0x0B => {
+            let mut parts = bytes.split(|&b| b == 0);
+            let pattern = String::from_utf8_lossy(parts.next().unwrap_or_default());
+            let options = String::from_utf8_lossy(parts.next().unwrap_or_default());
+            write!(out, "regex /{}/{}", pattern, options).unwrap();
+        }
+        0x0C => {
+            let (namespace, id) = bytes.split_at(bytes.len() - 12);
+            write_string(out, "dbPointer", namespace);
+            write!(out, " {}", hex(id)).unwrap();
+        }
+        0x0D => write_string(out, "javascript", bytes),
+        0x0E => write_string(out, "symbol", bytes),
+        0x0F => {
+            // the code's string and scope document follow the total length
+            let code_length = bytes
+                .get(4..8)
+                .and_then(|v| usize::try_from(int32(v)).ok())
+                .map(|v| v + 4)
+                .filter(|&v| 4 + v < bytes.len());
+
+            match code_length {
+                Some(code_length) => {
+                    write!(out, "javascriptWithScope ({} bytes) ", bytes.len()).unwrap();
+                    write_string(out, "code", &bytes[4..4 + code_length]);
+                    out.push_str(" scope ");
+                    write_document(out, data, offset + 4 + code_length, depth, false);
+                }
+                None => out.push_str("javascriptWithScope <invalid length>"),
+            }
+        }
+        0x10 => write!(out, "int32 {}", int32(bytes)).unwrap(),
+        0x11 => write!(
+            out,
+            "timestamp {{ t: {}, i: {} }}",
+            int32(&bytes[4..]) as u32,
+            int32(bytes) as u32
+        )
+        .unwrap(),
+        0x12 => write!(out, "int64 {}", int64(bytes)).unwrap(),
+        0x13 => write!(
+            out,
+            "decimal128 {}",
+            Decimal128::from_bytes(bytes.try_into().unwrap())
+        )
+        .unwrap(),
+        0x7F => out.push_str("maxKey"),
+        0xFF => out.push_str("minKey"),
+        // unknown types are caught when the element's read
+        _ => write!(out, "<unknown element type {:#04x}>", element_type).unwrap(),
+    }
+}
+
+/// Renders `data` as hex, 16 bytes to a line, with each piece of the document's structure on
+/// its own line and annotated with what it is:
+///
+/// ```text
+/// 00000000  0c 00 00 00                                      document length 12
+/// 00000004  10                                                 element type 0x10 (int32)
+/// 00000005  61 00                                              key "a"
+/// 00000007  01 00 00 00                                        int32 1
+/// 0000000b  00                                               document terminator
+/// ```
+///
+/// Anything that can't be read, and anything after the end of the document, is dumped as-is.
+pub fn hex_dump(data: &[u8]) -> String {
+    let mut segments = Vec::new();
+    // anything unreadable has already been pushed through to the end of the input
+    if let Some(end) = annotate_document(&mut segments, data, 0, 0, "document") {
+        if end < data.len() {
+            push(
+                &mut segments,
+                end..data.len(),
+                0,
+                "trailing bytes".to_string(),
+            );
+        }
+    }
+
+    let mut out = String::new();
+
+    for segment in segments {
+        let bytes = &data[segment.range.clone()];
+
+        // zero length segments still get a line so that nothing is left unexplained
+        let mut lines = bytes.chunks(16).peekable();
+        if lines.peek().is_none() {
+            writeln!(
+                out,
+                "{:08x}  {:48} {}",
+                segment.range.start, "", segment.annotation
+            )
+            .unwrap();
+        }
+
+        for (i, line) in lines.enumerate() {
+            let hex = line
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            write!(out, "{:08x}  {:48}", segment.range.start + i * 16, hex).unwrap();
+            if i == 0 {
+                out.push(' ');
+                indent(&mut out, segment.depth);
+                out.push_str(&segment.annotation);
+            }
+            out.truncate(out.trim_end().len());
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+/// A run of bytes in a [`hex_dump`] and what they are.
+struct Segment {
+    range: Range<usize>,
+    depth: usize,
+    annotation: String,
+}
+
+/// Splits the document at `start` into segments, returning where it ends. If it can't be read
+/// the rest of the input is pushed as a single segment and `None` returned.
+fn annotate_document(
+    segments: &mut Vec<Segment>,
+    data: &[u8],
+    start: usize,
+    depth: usize,
+    name: &str,
+) -> Option<usize> {
+    let elements = match Elements::new(data, start) {
+        Ok(elements) => elements,
+        Err(e) => return unreadable(segments, data, start, depth, &e),
+    };
+
+    let end = start + length(data, start) as usize;
+    push(
+        segments,
+        start..start + 4,
+        depth,
+        format!("{} length {}", name, end - start),
+    );
+
+    let mut position = start + 4;
+
+    for element in elements {
+        let element = match element {
+            Ok(element) => element,
+            Err(e) => return unreadable(segments, data, position, depth + 1, &e),
+        };
+
+        let element_type = element.value.element_type();
+        let value = element.value_offset..element.value_offset + element.value.as_bytes().len();
+
+        push(
+            segments,
+            position..position + 1,
+            depth + 1,
+            format!(
+                "element type {:#04x} ({})",
+                element_type,
+                type_name(element_type)
+            ),
+        );
+        push(
+            segments,
+            position + 1..value.start,
+            depth + 1,
+            format!("key {:?}", element.key),
+        );
+
+        annotate_value(segments, data, element_type, value.clone(), depth + 1)?;
+        position = value.end;
+    }
+
+    push(
+        segments,
+        end - 1..end,
+        depth,
+        format!("{} terminator", name),
+    );
+
+    Some(end)
+}
+
+fn annotate_value(
+    segments: &mut Vec<Segment>,
+    data: &[u8],
+    element_type: u8,
+    value: Range<usize>,
+    depth: usize,
+) -> Option<()> {
+    let bytes = &data[value.clone()];
+    let start = value.start;
+
+    match element_type {
+        0x02 | 0x0D | 0x0E => annotate_string(segments, data, start..value.end, depth),
+        0x03 => {
+            annotate_document(segments, data, start, depth, "document")?;
+        }
+        0x04 => {
+            annotate_document(segments, data, start, depth, "array")?;
+        }
+        0x05 => {
+            let length = format!("length {}", bytes.len() - 5);
+            push(segments, start..start + 4, depth, length);
+            let subtype = format!("subtype {:#04x}", bytes[4]);
+            push(segments, start + 4..start + 5, depth, subtype);
+            push(segments, start + 5..value.end, depth, "binary".to_string());
+        }
+        0x0C => {
+            let id = value.end - 12;
+            annotate_string(segments, data, start..id, depth);
+            let oid = format!("objectId {}", hex(&data[id..value.end]));
+            push(segments, id..value.end, depth, oid);
+        }
+        0x0F => {
+            // the code's string and scope document follow the total length
+            let code_end = bytes
+                .get(4..8)
+                .and_then(|v| usize::try_from(i32::from_le_bytes(v.try_into().unwrap())).ok())
+                .map(|v| start + 8 + v)
+                .filter(|&v| v < value.end);
+
+            match code_end {
+                Some(code_end) => {
+                    let length = format!("length {}", bytes.len());
+                    push(segments, start..start + 4, depth, length);
+                    annotate_string(segments, data, start + 4..code_end, depth);
+                    annotate_document(segments, data, code_end, depth, "scope")?;
+                }
+                None => {
+                    let error = "javascriptWithScope <invalid length>".to_string();
+                    push(segments, value, depth, error);
+                }
+            }
+        }
+        _ => {
+            let mut out = String::new();
+            write_value(&mut out, data, element_type, start, bytes, depth);
+            push(segments, value, depth, out);
+        }
+    }
+
+    Some(())
+}
+
+/// Splits a length-prefixed string into its length and contents.
+fn annotate_string(segments: &mut Vec<Segment>, data: &[u8], range: Range<usize>, depth: usize) {
+    let length = format!("length {}", range.len() - 4);
+    push(segments, range.start..range.start + 4, depth, length);
+
+    let mut contents = String::new();
+    write_string_contents(&mut contents, &data[range.clone()]);
+    push(segments, range.start + 4..range.end, depth, contents);
+}
+
+fn push(segments: &mut Vec<Segment>, range: Range<usize>, depth: usize, annotation: String) {
+    segments.push(Segment {
+        range,
+        depth,
+        annotation,
+    });
+}
+
+/// Pushes everything from `at` onwards as a single segment, since there's no telling where the
+/// structure picks up again.
+fn unreadable(
+    segments: &mut Vec<Segment>,
+    data: &[u8],
+    at: usize,
+    depth: usize,
+    error: &crate::de::Error,
+) -> Option<usize> {
+    push(segments, at..data.len(), depth, format!("<{}>", error));
+    None
+}
+
+fn type_name(element_type: u8) -> &'static str {
+    match element_type {
+        0x01 => "double",
+        0x02 => "string",
+        0x03 => "document",
+        0x04 => "array",
+        0x05 => "binary",
+        0x06 => "undefined",
+        0x07 => "objectId",
+        0x08 => "bool",
+        0x09 => "datetime",
+        0x0A => "null",
+        0x0B => "regex",
+        0x0C => "dbPointer",
+        0x0D => "javascript",
+        0x0E => "symbol",
+        0x0F => "javascriptWithScope",
+        0x10 => "int32",
+        0x11 => "timestamp",
+        0x12 => "int64",
+        0x13 => "decimal128",
+        0x7F => "maxKey",
+        0xFF => "minKey",
+        _ => "unknown",
+    }
+}
+
+/// Writes a length-prefixed string, `bytes` including its length.
+fn write_string(out: &mut String, name: &str, bytes: &[u8]) {
+    write!(out, "{} ({} bytes) ", name, bytes.len().saturating_sub(4)).unwrap();
+    write_string_contents(out, bytes);
+}
+
+/// Writes the contents of a length-prefixed string, quoted.
+fn write_string_contents(out: &mut String, bytes: &[u8]) {
+    match bytes.get(4..).and_then(|v| v.split_last()) {
+        Some((0, string)) => match std::str::from_utf8(string) {
+            Ok(string) => write!(out, "{:?}", string).unwrap(),
+            Err(_) => write!(out, "<invalid utf-8 {}>", hex(string)).unwrap(),
+        },
+        _ => out.push_str("<unterminated>"),
+    }
+}
+
+fn length(data: &[u8], start: usize) -> i32 {
+    i32::from_le_bytes(data[start..start + 4].try_into().unwrap())
+}
+
+fn indent(out: &mut String, depth: usize) {
+    for _ in 0..depth {
+        out.push_str("  ");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn dump() {
+        let data = bson::to_vec(&bson::doc! {
+            "name": "ferris",
+            "tags": ["a"],
+            "age": 7,
+        })
+        .unwrap();
+
+        assert_eq!(
+            super::dump(&data),
+            "document (51 bytes) {\n  \"name\": string (7 bytes) \"ferris\"\n  \"tags\": array (14 \
+             bytes) [\n    \"0\": string (2 bytes) \"a\"\n  ]\n  \"age\": int32 7\n}\n"
+        );
+    }
+
+    #[test]
+    fn every_type() {
+        let data = bson::to_vec(&bson::doc! {
+            "double": 1.5,
+            "binary": bson::Binary {
+                subtype: bson::spec::BinarySubtype::Generic,
+                bytes: vec![1, 2],
+            },
+            "undefined": bson::Bson::Undefined,
+            "oid": bson::oid::ObjectId::from_bytes([0xab; 12]),
+            "bool": true,
+            "date": bson::DateTime::from_millis(1_700_000_000_123),
+            "null": null,
+            "regex": bson::Regex { pattern: "^a".to_string(), options: "i".to_string() },
+            "code": bson::Bson::JavaScriptCode("f()".to_string()),
+            "symbol": bson::Bson::Symbol("s".to_string()),
+            "scope": bson::Bson::JavaScriptCodeWithScope(bson::JavaScriptCodeWithScope {
+                code: "g()".to_string(),
+                scope: bson::doc! { "x": 1 },
+            }),
+            "timestamp": bson::Timestamp { time: 3, increment: 4 },
+            "int64": 5_i64,
+            "decimal": bson::Bson::Decimal128(bson::Decimal128::from_bytes(
+                crate::Decimal128::from_i128(-12).unwrap().bytes(),
+            )),
+            "min": bson::Bson::MinKey,
+            "max": bson::Bson::MaxKey,
+        })
+        .unwrap();
+
+        let dump = super::dump(&data);
+        let lines = dump.lines().skip(1).collect::<Vec<_>>();
+
+        assert_eq!(
+            lines,
+            [
+                "  \"double\": double 1.5",
+                "  \"binary\": binary (subtype 0x00, 2 bytes) 0102",
+                "  \"undefined\": undefined",
+                "  \"oid\": objectId abababababababababababab",
+                "  \"bool\": bool true",
+                "  \"date\": datetime 1700000000123 (2023-11-14T22:13:20.123Z)",
+                "  \"null\": null",
+                "  \"regex\": regex /^a/i",
+                "  \"code\": javascript (4 bytes) \"f()\"",
+                "  \"symbol\": symbol (2 bytes) \"s\"",
+                "  \"scope\": javascriptWithScope (24 bytes) code (4 bytes) \"g()\" scope \
+                 document (12 bytes) {",
+                "    \"x\": int32 1",
+                "  }",
+                "  \"timestamp\": timestamp { t: 3, i: 4 }",
+                "  \"int64\": int64 5",
+                "  \"decimal\": decimal128 -12",
+                "  \"min\": minKey",
+                "  \"max\": maxKey",
+                "}",
+            ]
+        );
+    }
+
+    #[test]
+    fn db_pointer() {
+        let mut value = vec![2, 0, 0, 0, b'n', 0];
+        value.extend_from_slice(&[0xcd; 12]);
+
+        let mut document = crate::RawDocumentBuf::new();
+        document
+            .append_raw("pointer", crate::RawBson::new(0x0c, &value))
+            .unwrap();
+
+        assert_eq!(
+            super::dump(document.as_bytes()).lines().nth(1),
+            Some("  \"pointer\": dbPointer (2 bytes) \"n\" cdcdcdcdcdcdcdcdcdcdcdcd")
+        );
+    }
+
+    #[test]
+    fn hex_dump() {
+        let data = bson::to_vec(&bson::doc! { "a": 1 }).unwrap();
+        assert_eq!(
+            super::hex_dump(&data),
+            [
+                "00000000  0c 00 00 00                                      document length 12",
+                "00000004  10                                                 element type 0x10 \
+                 (int32)",
+                "00000005  61 00                                              key \"a\"",
+                "00000007  01 00 00 00                                        int32 1",
+                "0000000b  00                                               document terminator",
+                "",
+            ]
+            .join("\n")
+        );
+
+        let data = bson::to_vec(&bson::doc! {
+            "s": "a string that's long enough to wrap",
+            "d": { "b": [true] },
+            "scope": bson::Bson::JavaScriptCodeWithScope(bson::JavaScriptCodeWithScope {
+                code: "g()".to_string(),
+                scope: bson::doc! { "x": 1 },
+            }),
+        })
+        .unwrap();
+        let dump = super::hex_dump(&data);
+        let lines = dump.lines().collect::<Vec<_>>();
+
+        // every byte is shown exactly once
+        let shown = lines
+            .iter()
+            .map(|line| line[10..line.len().min(57)].split_whitespace().count())
+            .sum::<usize>();
+        assert_eq!(shown, data.len());
+
+        assert_eq!(
+            lines[4],
+            "0000000b  61 20 73 74 72 69 6e 67 20 74 68 61 74 27 73 20    \"a string that's long \
+             enough to wrap\""
+        );
+        assert_eq!(
+            lines[5],
+            "0000001b  6c 6f 6e 67 20 65 6e 6f 75 67 68 20 74 6f 20 77"
+        );
+        assert_eq!(
+            lines[14],
+            "0000003e  30 00                                                  key \"0\""
+        );
+        assert!(lines[22].ends_with("    \"g()\""));
+        assert!(lines[23].ends_with("    scope length 12"));
+
+        // anything unreadable is dumped through to the end, including anything after the document
+        let mut broken = data.clone();
+        broken[7] = 100;
+        broken.push(9);
+        let dump = super::hex_dump(&broken);
+        let lines = dump.lines().collect::<Vec<_>>();
+        assert!(lines[1].ends_with("    <invalid length at offset 7>"));
+        assert_eq!(lines.len(), 1 + (broken.len() - 4).div_ceil(16));
+
+        let mut trailing = bson::to_vec(&bson::doc! {}).unwrap();
+        trailing.push(9);
+        assert!(super::hex_dump(&trailing).ends_with(
+            "00000005  09                                               trailing bytes\n"
+        ));
+    }
+
+    #[test]
+    fn equivalent() {
+        use super::{difference, docs_equivalent, docs_equivalent_with, Equivalence};
+
+        let encode = |document: bson::Document| bson::to_vec(&document).unwrap();
+        let diff =
+            |a, b, equivalence| difference(&encode(a), 0, &encode(b), 0, equivalence, false, "");
+
+        let a = encode(bson::doc! { "a": 1, "b": { "c": [{ "d": 1, "e": 2 }, 3] } });
+        let b = encode(bson::doc! { "b": { "c": [{ "e": 2, "d": 1 }, 3] }, "a": 1 });
+
+        assert!(docs_equivalent(&a, &b));
+        assert!(docs_equivalent(&a, &a));
+        assert!(!docs_equivalent_with(
+            &a,
+            &b,
+            Equivalence::new().ordered_keys(true)
+        ));
+        assert!(docs_equivalent_with(
+            &a,
+            &a,
+            Equivalence::new().ordered_keys(true)
+        ));
+        super::assert_docs_equivalent(&a, &b);
+
+        assert_eq!(
+            diff(
+                bson::doc! { "a": 1, "b": 2 },
+                bson::doc! { "b": 2, "a": 1 },
+                Equivalence::new().ordered_keys(true)
+            ),
+            Some("field 0 is \"a\" on the left and \"b\" on the right".to_string())
+        );
+        assert_eq!(
+            diff(
+                bson::doc! { "a": { "b": 1 } },
+                bson::doc! { "a": { "b": 1.0 } },
+                Equivalence::new()
+            ),
+            Some("\"a.b\" is int32 on the left and double on the right".to_string())
+        );
+        assert_eq!(
+            diff(
+                bson::doc! { "a": [1, 2] },
+                bson::doc! { "a": [2, 1] },
+                Equivalence::new()
+            ),
+            Some("\"a.0\" has different values".to_string())
+        );
+        assert_eq!(
+            diff(
+                bson::doc! { "a": [1] },
+                bson::doc! { "a": [1, 2] },
+                Equivalence::new()
+            ),
+            Some("\"a\" has 1 elements on the left and 2 on the right".to_string())
+        );
+        assert_eq!(
+            diff(
+                bson::doc! { "a": 1 },
+                bson::doc! { "b": 1 },
+                Equivalence::new()
+            ),
+            Some("\"a\" is missing on the right".to_string())
+        );
+
+        assert!(!docs_equivalent(&a, &b[..b.len() - 1]));
+    }
+
+    #[test]
+    fn malformed() {
+        let mut data = bson::to_vec(&bson::doc! { "a": "xy", "b": { "c": 1 } }).unwrap();
+
+        // claim the string is longer than it is
+        data[7] = 100;
+        let dump = super::dump(&data);
+        assert!(
+            dump.starts_with("document (30 bytes) {\n  <invalid length at offset 7>"),
+            "{}",
+            dump
+        );
+        assert!(dump.ends_with("\n}\n"), "{}", dump);
+
+        assert!(super::dump(&[1, 2]).starts_with("document <"));
+        assert!(super::dump(&[]).starts_with("document <"));
+    }
+}
@@ -0,0 +1,528 @@
+use crate::raw::{RawBytes, RAW_BSON_NEWTYPE};
+use core::{
+    convert::{TryFrom, TryInto},
+    fmt,
+};
+use serde::{
+    de::{Error, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+/// The largest coefficient a decimal128 can hold, 34 decimal digits.
+const MAX_COEFFICIENT: u128 = 10u128.pow(34) - 1;
+
+const EXPONENT_BIAS: i32 = 6176;
+const MAX_EXPONENT: i32 = 6111;
+
+/// A 128-bit IEEE 754-2008 decimal floating point value in the binary integer decimal encoding
+/// bson uses, stored as its little-endian wire representation.
+///
+/// Only conversions to and from integers are provided, anything more involved is best left to a
+/// dedicated decimal crate. With the `rust_decimal` feature enabled values can be converted to and
+/// from [`rust_decimal::Decimal`], and the same goes for `bigdecimal`'s
+/// [`bigdecimal::BigDecimal`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Decimal128 {
+    bytes: [u8; 16],
+}
+
+impl Decimal128 {
+    pub fn from_bytes(bytes: [u8; 16]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn bytes(self) -> [u8; 16] {
+        self.bytes
+    }
+
+    /// Encodes `v` exactly, returning `None` if it has more than 34 significant digits.
+    pub fn from_i128(v: i128) -> Option<Self> {
+        Self::from_parts(v < 0, v.unsigned_abs())
+    }
+
+    /// Encodes `v` exactly, returning `None` if it has more than 34 significant digits.
+    pub fn from_u128(v: u128) -> Option<Self> {
+        Self::from_parts(false, v)
+    }
+
+    /// The value as an `i128`, or `None` if it isn't an integer or is out of range.
+    pub fn to_i128(self) -> Option<i128> {
+        let (negative, magnitude) = self.integer_parts()?;
+
+        if negative {
+            // i128::MIN has 39 significant digits so can't be encoded, no need to special case it
+            i128::try_from(magnitude).ok().map(|v| -v)
+        } else {
+            i128::try_from(magnitude).ok()
+        }
+    }
+
+    /// The value as a `u128`, or `None` if it isn't an integer or is out of range.
+    pub fn to_u128(self) -> Option<u128> {
+        match self.integer_parts()? {
+            (true, magnitude) if magnitude != 0 => None,
+            (_, magnitude) => Some(magnitude),
+        }
+    }
+
+    /// Encodes `v` exactly, every [`rust_decimal::Decimal`] fits in a decimal128.
+    #[cfg(feature = "rust_decimal")]
+    pub fn from_rust_decimal(v: rust_decimal::Decimal) -> Self {
+        // at most 96 bits of coefficient and a scale of 28, well within range
+        Self::from_parts_with_exponent(
+            v.is_sign_negative(),
+            v.mantissa().unsigned_abs(),
+            -(v.scale() as i32),
+        )
+        .unwrap()
+    }
+
+    /// The value as a [`rust_decimal::Decimal`], or `None` if it can't be represented exactly.
+    #[cfg(feature = "rust_decimal")]
+    pub fn to_rust_decimal(self) -> Option<rust_decimal::Decimal> {
+        const MAX_SCALE: i32 = rust_decimal::Decimal::MAX_SCALE as i32;
+        const MAX_MANTISSA: u128 = (1 << 96) - 1;
+
+        let (negative, mut coefficient, mut exponent) = self.parts()?;
+
+        if coefficient == 0 {
+            exponent = exponent.clamp(-MAX_SCALE, 0);
+        }
+
+        // trailing zeros can be dropped to bring the value within range
+        while exponent < 0
+            && (exponent < -MAX_SCALE || coefficient > MAX_MANTISSA)
+            && coefficient.is_multiple_of(10)
+        {
+            coefficient /= 10;
+            exponent += 1;
+        }
+
+        while exponent > 0 {
+            coefficient = coefficient.checked_mul(10)?;
+            exponent -= 1;
+        }
+
+        if exponent < -MAX_SCALE || coefficient > MAX_MANTISSA {
+            return None;
+        }
+
+        let mut v =
+            rust_decimal::Decimal::from_i128_with_scale(coefficient as i128, -exponent as u32);
+        v.set_sign_negative(negative);
+        Some(v)
+    }
+
+    /// Encodes `v` exactly, returning `None` if it has more than 34 significant digits or its
+    /// exponent is out of range. See [`Decimal128::from_bigdecimal_rounded`] to round instead.
+    #[cfg(feature = "bigdecimal")]
+    pub fn from_bigdecimal(v: &bigdecimal::BigDecimal) -> Option<Self> {
+        use bigdecimal::{num_bigint::Sign, ToPrimitive};
+
+        let (digits, scale) = v.as_bigint_and_scale();
+
+        // anything too large for a u128 is only going to fit if it's mostly trailing zeros
+        let (digits, scale) = match digits.magnitude().to_u128() {
+            Some(_) => (digits, scale),
+            None => {
+                let (digits, scale) = v.normalized().into_bigint_and_scale();
+                (std::borrow::Cow::Owned(digits), scale)
+            }
+        };
+
+        Self::from_parts_with_exponent(
+            digits.sign() == Sign::Minus,
+            digits.magnitude().to_u128()?,
+            i32::try_from(scale).ok()?.checked_neg()?,
+        )
+    }
+
+    /// Rounds `v` to the 34 significant digits a decimal128 can hold using `mode`, returning
+    /// `None` only if its exponent is out of range.
+    #[cfg(feature = "bigdecimal")]
+    pub fn from_bigdecimal_rounded(
+        v: &bigdecimal::BigDecimal,
+        mode: bigdecimal::RoundingMode,
+    ) -> Option<Self> {
+        let precision = std::num::NonZeroU64::new(34).unwrap();
+        Self::from_bigdecimal(&v.with_precision_round(precision, mode))
+    }
+
+    /// The value as a [`bigdecimal::BigDecimal`], or `None` if it's an infinity or NaN.
+    #[cfg(feature = "bigdecimal")]
+    pub fn to_bigdecimal(self) -> Option<bigdecimal::BigDecimal> {
+        use bigdecimal::num_bigint::BigInt;
+
+        let (negative, coefficient, exponent) = self.parts()?;
+        let digits = BigInt::from(coefficient);
+        let digits = if negative { -digits } else { digits };
+
+        Some(bigdecimal::BigDecimal::new(digits, -i64::from(exponent)))
+    }
+
+    fn from_parts(negative: bool, coefficient: u128) -> Option<Self> {
+        Self::from_parts_with_exponent(negative, coefficient, 0)
+    }
+
+    fn from_parts_with_exponent(
+        negative: bool,
+        mut coefficient: u128,
+        mut exponent: i32,
+    ) -> Option<Self> {
+        // values with too many digits can still be represented exactly if they've got enough
+        // trailing zeros to move into the exponent
+        while coefficient > MAX_COEFFICIENT {
+            if !coefficient.is_multiple_of(10) {
+                return None;
+            }
+
+            coefficient /= 10;
+            exponent += 1;
+        }
+
+        if !(-EXPONENT_BIAS..=MAX_EXPONENT).contains(&exponent) {
+            return None;
+        }
+
+        let high = (u64::from(negative) << 63)
+            | (((exponent + EXPONENT_BIAS) as u64) << 49)
+            | (coefficient >> 64) as u64;
+        let low = coefficient as u64;
+
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&low.to_le_bytes());
+        bytes[8..].copy_from_slice(&high.to_le_bytes());
+
+        Some(Self { bytes })
+    }
+
+    /// Splits the value into its sign, coefficient and exponent, or `None` if it's an infinity or
+    /// NaN.
+    fn parts(self) -> Option<(bool, u128, i32)> {
+        let low = u64::from_le_bytes(self.bytes[..8].try_into().unwrap());
+        let high = u64::from_le_bytes(self.bytes[8..].try_into().unwrap());
+
+        let negative = high >> 63 == 1;
+
+        // the two bits after the sign being set means either an infinity/nan, or a coefficient
+        // too large to be canonical, which the spec says is to be treated as zero
+        if (high >> 61) & 0b11 == 0b11 {
+            return if (high >> 59) & 0b1111 == 0b1111 {
+                None
+            } else {
+                let exponent = ((high >> 47) & 0x3fff) as i32 - EXPONENT_BIAS;
+                Some((negative, 0, exponent))
+            };
+        }
+
+        let exponent = ((high >> 49) & 0x3fff) as i32 - EXPONENT_BIAS;
+        let coefficient = (u128::from(high & ((1 << 49) - 1)) << 64) | u128::from(low);
+
+        if coefficient > MAX_COEFFICIENT {
+            return Some((negative, 0, exponent));
+        }
+
+        Some((negative, coefficient, exponent))
+    }
+
+    /// Splits the value into its sign and integral magnitude, if it's an integer.
+    fn integer_parts(self) -> Option<(bool, u128)> {
+        let (negative, mut coefficient, exponent) = self.parts()?;
+
+        if coefficient == 0 || exponent == 0 {
+            return Some((negative, coefficient));
+        }
+
+        if exponent > 0 {
+            debug_assert!(exponent <= MAX_EXPONENT);
+
+            for _ in 0..exponent {
+                coefficient = coefficient.checked_mul(10)?;
+            }
+        } else {
+            for _ in 0..-exponent {
+                if !coefficient.is_multiple_of(10) {
+                    return None;
+                }
+
+                coefficient /= 10;
+            }
+        }
+
+        Some((negative, coefficient))
+    }
+}
+
+/// Formats the value the way the spec describes, in scientific notation for very large or very
+/// small exponents. Trailing zeros are significant so are kept, `1.50` isn't written as `1.5`.
+impl fmt::Display for Decimal128 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let high = u64::from_le_bytes(self.bytes[8..].try_into().unwrap());
+        let sign = if high >> 63 == 1 { "-" } else { "" };
+
+        let Some((_, coefficient, exponent)) = self.parts() else {
+            return if (high >> 58) & 0b11111 == 0b11111 {
+                f.write_str("NaN")
+            } else {
+                write!(f, "{}Infinity", sign)
+            };
+        };
+
+        let mut itoa = itoa::Buffer::new();
+        let digits = itoa.format(coefficient);
+        let adjusted = exponent + digits.len() as i32 - 1;
+
+        f.write_str(sign)?;
+
+        if exponent > 0 || adjusted < -6 {
+            let (first, rest) = digits.split_at(1);
+            f.write_str(first)?;
+
+            if !rest.is_empty() {
+                write!(f, ".{}", rest)?;
+            }
+
+            write!(f, "E{}{}", if adjusted >= 0 { "+" } else { "" }, adjusted)
+        } else if exponent == 0 {
+            f.write_str(digits)
+        } else {
+            let point = digits.len() as i32 + exponent;
+
+            if point > 0 {
+                let (int, frac) = digits.split_at(point as usize);
+                write!(f, "{}.{}", int, frac)
+            } else {
+                write!(f, "0.{:0>width$}", digits, width = -exponent as usize)
+            }
+        }
+    }
+}
+
+impl Serialize for Decimal128 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_variant(RAW_BSON_NEWTYPE, 0x13, "", &RawBytes(&self.bytes))
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal128 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Decimal128Visitor;
+
+        impl<'de> Visitor<'de> for Decimal128Visitor {
+            type Value = Decimal128;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a decimal128")
+            }
+
+            fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                v.try_into()
+                    .map(Decimal128::from_bytes)
+                    .map_err(|_| E::invalid_length(v.len(), &self))
+            }
+        }
+
+        // decimal128s are handed to visitors as their raw bytes
+        deserializer.deserialize_bytes(Decimal128Visitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Decimal128;
+    use serde::{Deserialize, Serialize};
+
+    #[test]
+    fn integers() {
+        for v in [
+            0,
+            1,
+            -1,
+            42,
+            -1234567890,
+            i64::MAX as i128,
+            10i128.pow(37),
+            -(10i128.pow(34) - 1),
+        ]
+        .iter()
+        .copied()
+        {
+            let decimal = Decimal128::from_i128(v).unwrap();
+            assert_eq!(decimal.to_i128(), Some(v));
+
+            if v.unsigned_abs() <= super::MAX_COEFFICIENT {
+                let theirs: bson::Decimal128 = v.to_string().parse().unwrap();
+                assert_eq!(decimal.bytes(), theirs.bytes());
+            }
+        }
+
+        assert_eq!(Decimal128::from_i128(i128::MAX), None);
+        assert_eq!(Decimal128::from_u128(u128::MAX), None);
+        assert_eq!(
+            Decimal128::from_u128(10u128.pow(38)).unwrap().to_u128(),
+            Some(10u128.pow(38))
+        );
+        assert_eq!(Decimal128::from_i128(-1).unwrap().to_u128(), None);
+    }
+
+    #[test]
+    fn non_integers() {
+        let parse =
+            |s: &str| Decimal128::from_bytes(s.parse::<bson::Decimal128>().unwrap().bytes());
+
+        assert_eq!(parse("1.5").to_i128(), None);
+        assert_eq!(parse("2.000").to_i128(), Some(2));
+        assert_eq!(parse("-2.5E+1").to_i128(), Some(-25));
+        assert_eq!(parse("1E+6000").to_i128(), None);
+        assert_eq!(parse("NaN").to_i128(), None);
+        assert_eq!(parse("-Infinity").to_i128(), None);
+    }
+
+    #[test]
+    fn display() {
+        for v in [
+            "0",
+            "-0",
+            "1.50",
+            "-12.34",
+            "0.001",
+            "0.0000001",
+            "1.23E-7",
+            "1E+3",
+            "1.000E+3",
+            "1.234567890123456789012345678901234E+6144",
+            "1E-6176",
+            "0E-6176",
+            "NaN",
+            "Infinity",
+            "-Infinity",
+        ]
+        .iter()
+        {
+            let theirs: bson::Decimal128 = v.parse().unwrap();
+            let ours = Decimal128::from_bytes(theirs.bytes());
+            assert_eq!(ours.to_string(), theirs.to_string(), "{}", v);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            price: Decimal128,
+            qty: i32,
+        }
+
+        let price: bson::Decimal128 = "12.34".parse().unwrap();
+        let mut theirs = Vec::new();
+        bson::doc! { "price": price, "qty": 3 }
+            .to_writer(&mut theirs)
+            .unwrap();
+
+        let deserialized: A = crate::de::from_bytes(&theirs).unwrap();
+        assert_eq!(deserialized.price.bytes(), price.bytes());
+        assert_eq!(deserialized.price.to_i128(), None);
+
+        let mut ours = bytes::BytesMut::new();
+        crate::to_string(&deserialized, &mut ours).unwrap();
+        assert_eq!(&ours[..], &theirs[..]);
+    }
+
+    #[cfg(feature = "rust_decimal")]
+    #[test]
+    fn rust_decimal() {
+        use rust_decimal::Decimal;
+
+        for v in [
+            "0",
+            "0.00",
+            "12.34",
+            "-1.5",
+            "79228162514264337593543950335",
+            "0.0000000000000000000000000001",
+        ]
+        .iter()
+        {
+            let ours = Decimal128::from_rust_decimal(v.parse().unwrap());
+            let theirs: bson::Decimal128 = v.parse().unwrap();
+            assert_eq!(ours.bytes(), theirs.bytes(), "{}", v);
+            assert_eq!(
+                ours.to_rust_decimal(),
+                Some(v.parse::<Decimal>().unwrap()),
+                "{}",
+                v
+            );
+        }
+
+        let parse =
+            |s: &str| Decimal128::from_bytes(s.parse::<bson::Decimal128>().unwrap().bytes());
+
+        // exact so long as dropping trailing zeros brings it into range
+        assert_eq!(
+            parse("1.0000000000000000000000000000000").to_rust_decimal(),
+            Some(Decimal::ONE)
+        );
+        assert_eq!(parse("2E+3").to_rust_decimal(), Some(Decimal::from(2000)));
+        assert_eq!(parse("1E-29").to_rust_decimal(), None);
+        assert_eq!(parse("1E+29").to_rust_decimal(), None);
+        assert_eq!(parse("NaN").to_rust_decimal(), None);
+    }
+
+    #[cfg(feature = "bigdecimal")]
+    #[test]
+    fn bigdecimal() {
+        use bigdecimal::{BigDecimal, RoundingMode};
+
+        for v in [
+            "0",
+            "12.34",
+            "-1.5E+100",
+            "1.000000000000000000000000000000000E-6143",
+        ]
+        .iter()
+        {
+            let big: BigDecimal = v.parse().unwrap();
+            let ours = Decimal128::from_bigdecimal(&big).unwrap();
+            let theirs: bson::Decimal128 = v.parse().unwrap();
+            assert_eq!(ours.to_bigdecimal(), Some(big), "{}", v);
+            assert_eq!(
+                ours.to_bigdecimal(),
+                theirs.to_string().parse().ok(),
+                "{}",
+                v
+            );
+        }
+
+        // trailing zeros beyond 34 digits are fine, anything else needs rounding
+        let big: BigDecimal = format!("1{}", "0".repeat(40)).parse().unwrap();
+        assert_eq!(
+            Decimal128::from_bigdecimal(&big).unwrap().to_bigdecimal(),
+            Some(big)
+        );
+
+        let big: BigDecimal = format!("0.{}", "6".repeat(40)).parse().unwrap();
+        assert_eq!(Decimal128::from_bigdecimal(&big), None);
+
+        let rounded = Decimal128::from_bigdecimal_rounded(&big, RoundingMode::HalfEven).unwrap();
+        assert_eq!(
+            rounded.to_bigdecimal(),
+            Some(format!("0.{}7", "6".repeat(33)).parse().unwrap())
+        );
+
+        let truncated = Decimal128::from_bigdecimal_rounded(&big, RoundingMode::Down).unwrap();
+        assert_eq!(
+            truncated.to_bigdecimal(),
+            Some(format!("0.{}", "6".repeat(34)).parse().unwrap())
+        );
+
+        let big: BigDecimal = "1E+7000".parse().unwrap();
+        assert_eq!(
+            Decimal128::from_bigdecimal_rounded(&big, RoundingMode::HalfEven),
+            None
+        );
+
+        let parse =
+            |s: &str| Decimal128::from_bytes(s.parse::<bson::Decimal128>().unwrap().bytes());
+        assert_eq!(parse("NaN").to_bigdecimal(), None);
+    }
+}
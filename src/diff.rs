@@ -0,0 +1,306 @@
+//! Working out what changed between two documents.
+//!
+//! [`diff`] produces a [`Patch`] listing each added, removed and changed value by its path, in
+//! the dotted format used by [`crate::Document::get_path`]. Patches can be applied to bring the
+//! old document up to date, or turned into a `$set`/`$unset` update that does the same on the
+//! server. Keys containing dots can't be told apart from nesting in the paths.
+
+use crate::{Document, Value};
+
+/// The changes between two documents, in the order they were found.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Patch {
+    pub changes: Vec<Change>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Added {
+        path: String,
+        value: Value,
+    },
+    Removed {
+        path: String,
+        old: Value,
+    },
+    Changed {
+        path: String,
+        old: Value,
+        new: Value,
+    },
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    #[error("nothing to patch at {0:?}")]
+    MissingPath(String),
+}
+
+impl Change {
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Added { path, .. } | Self::Removed { path, .. } | Self::Changed { path, .. } => {
+                path
+            }
+        }
+    }
+}
+
+/// The changes that turn `old` into `new`. Nested documents are compared key by key, as are
+/// arrays that haven't changed length. Arrays that have are replaced as a whole, since their
+/// indexes shift.
+pub fn diff(old: &Document, new: &Document) -> Patch {
+    let mut patch = Patch::default();
+    diff_documents(old, new, "", &mut patch.changes);
+    patch
+}
+
+fn diff_documents(old: &Document, new: &Document, prefix: &str, changes: &mut Vec<Change>) {
+    for (key, old_value) in old.iter() {
+        let path = join(prefix, key);
+
+        match new.get(key) {
+            Some(new_value) => diff_values(old_value, new_value, path, changes),
+            None => changes.push(Change::Removed {
+                path,
+                old: old_value.clone(),
+            }),
+        }
+    }
+
+    for (key, value) in new.iter().filter(|(key, _)| !old.contains_key(key)) {
+        changes.push(Change::Added {
+            path: join(prefix, key),
+            value: value.clone(),
+        });
+    }
+}
+
+fn diff_values(old: &Value, new: &Value, path: String, changes: &mut Vec<Change>) {
+    match (old, new) {
+        (Value::Document(old), Value::Document(new)) => diff_documents(old, new, &path, changes),
+        (Value::Array(old), Value::Array(new)) if old.len() == new.len() => {
+            for (i, (old, new)) in old.iter().zip(new).enumerate() {
+                diff_values(old, new, join(&path, &i.to_string()), changes);
+            }
+        }
+        (old, new) if old == new => {}
+        (old, new) => changes.push(Change::Changed {
+            path,
+            old: old.clone(),
+            new: new.clone(),
+        }),
+    }
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+impl Patch {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Applies the changes to `document`, which should be the old document the patch was made
+    /// from. Stops at the first change that doesn't line up with the document.
+    pub fn apply(&self, document: &mut Document) -> Result<(), Error> {
+        for change in &self.changes {
+            let path = change.path();
+            let missing = || Error::MissingPath(path.to_string());
+
+            let (parent, key) = match path.rsplit_once('.') {
+                Some((parent, key)) => (parent_mut(document, parent).ok_or_else(missing)?, key),
+                None => (Parent::Document(&mut *document), path),
+            };
+
+            match change {
+                Change::Added { value, .. } | Change::Changed { new: value, .. } => {
+                    parent.set(key, value.clone()).ok_or_else(missing)?;
+                }
+                Change::Removed { .. } => {
+                    parent.remove(key).ok_or_else(missing)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// An update document of `$set` and `$unset` operators making the same changes on the
+    /// server. Either operator is left out if there's nothing for it to do.
+    pub fn to_update(&self) -> Document {
+        let mut set = Document::new();
+        let mut unset = Document::new();
+
+        for change in &self.changes {
+            match change {
+                Change::Added { path, value }
+                | Change::Changed {
+                    path, new: value, ..
+                } => {
+                    set.insert(path.as_str(), value.clone());
+                }
+                Change::Removed { path, .. } => {
+                    unset.insert(path.as_str(), Value::String(String::new()));
+                }
+            }
+        }
+
+        let mut update = Document::new();
+        if !set.is_empty() {
+            update.insert("$set", set);
+        }
+        if !unset.is_empty() {
+            update.insert("$unset", unset);
+        }
+        update
+    }
+}
+
+/// A document or array that a change is being made to.
+enum Parent<'a> {
+    Document(&'a mut Document),
+    Array(&'a mut Vec<Value>),
+}
+
+impl Parent<'_> {
+    fn set(self, key: &str, value: Value) -> Option<()> {
+        match self {
+            Self::Document(document) => {
+                document.insert(key, value);
+            }
+            Self::Array(values) => match key.parse::<usize>().ok()? {
+                i if i < values.len() => values[i] = value,
+                i if i == values.len() => values.push(value),
+                _ => return None,
+            },
+        }
+
+        Some(())
+    }
+
+    fn remove(self, key: &str) -> Option<Value> {
+        match self {
+            Self::Document(document) => document.remove(key),
+            Self::Array(values) => {
+                let i = key.parse::<usize>().ok()?;
+                (i < values.len()).then(|| values.remove(i))
+            }
+        }
+    }
+}
+
+fn parent_mut<'a>(document: &'a mut Document, path: &str) -> Option<Parent<'a>> {
+    let mut parent = Parent::Document(document);
+
+    for key in path.split('.') {
+        let value = match parent {
+            Parent::Document(document) => document.get_mut(key)?,
+            Parent::Array(values) => values.get_mut(key.parse::<usize>().ok()?)?,
+        };
+
+        parent = match value {
+            Value::Document(document) => Parent::Document(document),
+            Value::Array(values) => Parent::Array(values),
+            _ => return None,
+        };
+    }
+
+    Some(parent)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Change, Error};
+    use crate::{Document, Value};
+
+    fn document(doc: bson::Document) -> Document {
+        Document::from_bytes(&bson::to_vec(&doc).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn diff() {
+        let old = document(bson::doc! {
+            "name": "ferris",
+            "age": 7,
+            "address": { "city": "a", "zip": "1" },
+            "tags": ["x", "y"],
+            "history": [1, 2],
+        });
+        let new = document(bson::doc! {
+            "name": "ferris",
+            "age": 8_i64,
+            "address": { "city": "b" },
+            "tags": ["x", "z"],
+            "history": [1, 2, 3],
+            "email": "f@example.com",
+        });
+
+        let patch = super::diff(&old, &new);
+        assert_eq!(
+            patch.changes,
+            [
+                Change::Changed {
+                    path: "age".to_string(),
+                    old: Value::Int32(7),
+                    new: Value::Int64(8),
+                },
+                Change::Changed {
+                    path: "address.city".to_string(),
+                    old: Value::String("a".to_string()),
+                    new: Value::String("b".to_string()),
+                },
+                Change::Removed {
+                    path: "address.zip".to_string(),
+                    old: Value::String("1".to_string()),
+                },
+                Change::Changed {
+                    path: "tags.1".to_string(),
+                    old: Value::String("y".to_string()),
+                    new: Value::String("z".to_string()),
+                },
+                Change::Changed {
+                    path: "history".to_string(),
+                    old: document(bson::doc! { "v": [1, 2] }).remove("v").unwrap(),
+                    new: document(bson::doc! { "v": [1, 2, 3] }).remove("v").unwrap(),
+                },
+                Change::Added {
+                    path: "email".to_string(),
+                    value: Value::String("f@example.com".to_string()),
+                },
+            ]
+        );
+
+        let mut patched = old.clone();
+        patch.apply(&mut patched).unwrap();
+        assert_eq!(super::diff(&patched, &new).changes, []);
+        assert!(super::diff(&new, &new).is_empty());
+
+        assert_eq!(
+            patch.to_update().to_vec().unwrap(),
+            bson::to_vec(&bson::doc! {
+                "$set": {
+                    "age": 8_i64,
+                    "address.city": "b",
+                    "tags.1": "z",
+                    "history": [1, 2, 3],
+                    "email": "f@example.com",
+                },
+                "$unset": { "address.zip": "" },
+            })
+            .unwrap()
+        );
+
+        // the patch doesn't line up with a document that's missing what it changes
+        let mut other = document(bson::doc! { "name": "ferris" });
+        assert_eq!(
+            patch.apply(&mut other),
+            Err(Error::MissingPath("address.city".to_string()))
+        );
+    }
+}
@@ -1,14 +1,74 @@
-use std::fmt::{Display, Formatter};
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+};
+use core::fmt::{Display, Formatter};
 
 #[derive(Debug)]
 pub enum Error {
     NotSerializingStruct,
     Serde(String),
     UnsignedIntNotInSpec,
+    KeyMustBeAString,
+    KeyContainsNullByte,
+    BufferTooSmall,
+    UnsortableOutput,
+    StringTooLarge(usize),
+    BytesTooLarge(usize),
+    DocumentTooLarge(usize),
+    NonFiniteFloat(f64),
+    IntegerTooLargeForDecimal128,
+    AtPath { path: Path, error: Box<Error> },
+}
+
+impl Error {
+    /// The path to the field that caused the error, if it occurred within a document.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::AtPath { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// The underlying error with the path stripped off.
+    pub fn inner(&self) -> &Error {
+        match self {
+            Self::AtPath { error, .. } => error,
+            other => other,
+        }
+    }
+
+    /// Prepends the given field to the error's path, called on the way back up the tree so
+    /// there's no cost to tracking where we are until something actually fails.
+    pub(crate) fn in_field(self, key: &str) -> Self {
+        self.prepend_path(|path| path.prepend_key(key))
+    }
+
+    /// Prepends the given array index to the error's path.
+    pub(crate) fn in_element(self, index: usize) -> Self {
+        self.prepend_path(|path| path.prepend_index(index))
+    }
+
+    fn prepend_path(self, f: impl FnOnce(&mut Path)) -> Self {
+        match self {
+            Self::AtPath { mut path, error } => {
+                f(&mut path);
+                Self::AtPath { path, error }
+            }
+            error => {
+                let mut path = Path::default();
+                f(&mut path);
+                Self::AtPath {
+                    path,
+                    error: Box::new(error),
+                }
+            }
+        }
+    }
 }
 
 impl Display for Error {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         match self {
             Self::NotSerializingStruct => write!(
                 f,
@@ -18,11 +78,99 @@ impl Display for Error {
             Self::UnsignedIntNotInSpec => {
                 write!(f, "unsigned ints are not supported in the bson spec")
             }
+            Self::KeyMustBeAString => write!(f, "map keys must be strings or integers"),
+            Self::KeyContainsNullByte => write!(f, "map keys cannot contain null bytes"),
+            Self::BufferTooSmall => write!(f, "output buffer is too small for the value"),
+            Self::UnsortableOutput => write!(
+                f,
+                "sorting keys needs an output buffer that can return what's been written to it"
+            ),
+            Self::StringTooLarge(len) => write!(
+                f,
+                "string of {} bytes exceeds the max encodable size of {} bytes",
+                len,
+                i32::MAX - 1
+            ),
+            Self::BytesTooLarge(len) => write!(
+                f,
+                "binary of {} bytes exceeds the max encodable size of {} bytes",
+                len,
+                i32::MAX
+            ),
+            Self::DocumentTooLarge(len) => write!(
+                f,
+                "document of {} bytes exceeds the max encodable size of {} bytes",
+                len,
+                i32::MAX
+            ),
+            Self::NonFiniteFloat(v) => write!(f, "non-finite double {} is not allowed", v),
+            Self::IntegerTooLargeForDecimal128 => write!(
+                f,
+                "integer has too many significant digits to be stored as a decimal128"
+            ),
+            Self::AtPath { path, error } => write!(f, "{} at {}", error, path),
         }
     }
 }
 
-impl std::error::Error for Error {}
+// `std::error::Error` with std, or serde's stand-in for it without
+impl serde::ser::StdError for Error {}
+
+/// Path to the field within a document an error occurred at, eg. `orders[3].customer.id`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Path(String);
+
+impl Path {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub(crate) fn push_key(&mut self, key: &str) {
+        if !self.0.is_empty() {
+            self.0.push('.');
+        }
+
+        self.0.push_str(key);
+    }
+
+    pub(crate) fn push_index(&mut self, index: &str) {
+        self.0.push('[');
+        self.0.push_str(index);
+        self.0.push(']');
+    }
+
+    fn prepend_key(&mut self, key: &str) {
+        if self.0.is_empty() {
+            self.0.push_str(key);
+        } else if self.0.starts_with('[') {
+            self.0.insert_str(0, key);
+        } else {
+            self.0.insert(0, '.');
+            self.0.insert_str(0, key);
+        }
+    }
+
+    fn prepend_index(&mut self, index: usize) {
+        if !self.0.is_empty() && !self.0.starts_with('[') {
+            self.0.insert(0, '.');
+        }
+
+        let mut itoa = itoa::Buffer::new();
+        self.0.insert(0, ']');
+        self.0.insert_str(0, itoa.format(index));
+        self.0.insert(0, '[');
+    }
+}
+
+impl Display for Path {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        if self.0.is_empty() {
+            f.write_str("<root>")
+        } else {
+            f.write_str(&self.0)
+        }
+    }
+}
 
 impl serde::ser::Error for Error {
     fn custom<T: Display>(msg: T) -> Self {
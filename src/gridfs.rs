@@ -0,0 +1,362 @@
+//! Splitting files into GridFS chunk documents and putting them back together again.
+//!
+//! A GridFS file is stored as one document in the `files` collection describing it, and its
+//! contents split into fixed size pieces across documents in the `chunks` collection:
+//!
+//! ```text
+//! files:  { _id, length, chunkSize, uploadDate, filename }
+//! chunks: { files_id, n, data }
+//! ```
+//!
+//! Chunks are written without an `_id` so the server assigns their ObjectIds on insert.
+
+use crate::{de, raw::RawBytes, scan::Elements, serde_helpers::system_time_as_bson_datetime};
+use bytes::Bytes;
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+use std::{
+    convert::{TryFrom, TryInto},
+    io::{self, Read, Write},
+    time::SystemTime,
+};
+
+/// The chunk size used by the drivers, 255KiB.
+pub const DEFAULT_CHUNK_SIZE: usize = 255 * 1024;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Write(#[from] crate::Error),
+    #[error(transparent)]
+    Read(#[from] de::Error),
+    #[error("chunk is missing its {0:?}")]
+    MissingField(&'static str),
+    #[error("expected chunk {expected} but found chunk {found}")]
+    OutOfOrder { expected: i64, found: i64 },
+    #[error("chunk {0} is the wrong size")]
+    WrongChunkSize(i64),
+    #[error("file is {expected} bytes but its chunks hold {found}")]
+    WrongLength { expected: i64, found: i64 },
+}
+
+/// Reads `reader` into chunk documents for the file `files_id`, see [`split`].
+pub struct Chunks<'a, Id, R> {
+    files_id: &'a Id,
+    reader: R,
+    buffer: Vec<u8>,
+    n: i32,
+    length: i64,
+    done: bool,
+}
+
+/// Splits everything read from `reader` into chunk documents of `chunk_size` bytes, the last of
+/// which may be shorter. Once the iterator is exhausted [`Chunks::file_document`] writes the
+/// document describing the file.
+pub fn split<Id: Serialize, R: Read>(
+    files_id: &Id,
+    reader: R,
+    chunk_size: usize,
+) -> Chunks<'_, Id, R> {
+    assert!(chunk_size > 0, "chunk size must be greater than zero");
+
+    Chunks {
+        files_id,
+        reader,
+        buffer: vec![0; chunk_size],
+        n: 0,
+        length: 0,
+        done: false,
+    }
+}
+
+impl<Id: Serialize, R: Read> Chunks<'_, Id, R> {
+    /// Fills the buffer, returning how much of it was filled which is only less than the chunk
+    /// size at the end of the input.
+    fn fill(&mut self) -> io::Result<usize> {
+        let mut filled = 0;
+
+        while filled < self.buffer.len() {
+            match self.reader.read(&mut self.buffer[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(filled)
+    }
+
+    /// Total number of bytes read so far.
+    pub fn length(&self) -> i64 {
+        self.length
+    }
+
+    /// The document for the `files` collection, describing everything read so far.
+    pub fn file_document(
+        &self,
+        filename: Option<&str>,
+        upload_date: SystemTime,
+    ) -> Result<Bytes, Error> {
+        let chunk_size = i32::try_from(self.buffer.len())
+            .map_err(|_| crate::Error::Serde("chunk size is too large".to_string()))?;
+
+        Ok(crate::to_bytes(&File {
+            id: self.files_id,
+            length: self.length,
+            chunk_size,
+            upload_date,
+            filename,
+        })?)
+    }
+}
+
+impl<Id: Serialize, R: Read> Iterator for Chunks<'_, Id, R> {
+    type Item = Result<Bytes, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let filled = match self.fill() {
+            Ok(filled) => filled,
+            Err(e) => {
+                self.done = true;
+                return Some(Err(e.into()));
+            }
+        };
+
+        if filled < self.buffer.len() {
+            self.done = true;
+        }
+
+        if filled == 0 {
+            return None;
+        }
+
+        let chunk = Chunk {
+            files_id: self.files_id,
+            n: self.n,
+            data: &self.buffer[..filled],
+        };
+        let res = crate::to_bytes(&chunk).map_err(Error::from);
+
+        self.n += 1;
+        self.length += filled as i64;
+
+        Some(res)
+    }
+}
+
+struct Chunk<'a, Id> {
+    files_id: &'a Id,
+    n: i32,
+    data: &'a [u8],
+}
+
+impl<Id: Serialize> Serialize for Chunk<'_, Id> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("Chunk", 3)?;
+        s.serialize_field("files_id", self.files_id)?;
+        s.serialize_field("n", &self.n)?;
+        s.serialize_field("data", &RawBytes(self.data))?;
+        s.end()
+    }
+}
+
+struct File<'a, Id> {
+    id: &'a Id,
+    length: i64,
+    chunk_size: i32,
+    upload_date: SystemTime,
+    filename: Option<&'a str>,
+}
+
+impl<Id: Serialize> Serialize for File<'_, Id> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        struct UploadDate(SystemTime);
+
+        impl Serialize for UploadDate {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                system_time_as_bson_datetime::serialize(&self.0, serializer)
+            }
+        }
+
+        let mut s = serializer.serialize_struct("File", 5)?;
+        s.serialize_field("_id", self.id)?;
+        s.serialize_field("length", &self.length)?;
+        s.serialize_field("chunkSize", &self.chunk_size)?;
+        s.serialize_field("uploadDate", &UploadDate(self.upload_date))?;
+        if let Some(filename) = self.filename {
+            s.serialize_field("filename", filename)?;
+        }
+        s.end()
+    }
+}
+
+/// Writes the contents of the file described by the `files` document `file` to `writer` from its
+/// `chunks`, which must be in order of `n` as they are when queried sorted by it. Each chunk's
+/// size and the total length are checked against the file document, returning the number of
+/// bytes written.
+pub fn assemble<'a, I, W>(file: &[u8], chunks: I, mut writer: W) -> Result<i64, Error>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+    W: Write,
+{
+    let mut length = None;
+    let mut chunk_size = None;
+
+    for element in Elements::new(file, 0)? {
+        let element = element?;
+
+        match element.key {
+            "length" => length = integer(&element.value),
+            "chunkSize" => chunk_size = integer(&element.value),
+            _ => {}
+        }
+    }
+
+    let length = length.ok_or(Error::MissingField("length"))?;
+    let chunk_size = chunk_size.ok_or(Error::MissingField("chunkSize"))?;
+
+    let mut written = 0;
+
+    for (expected, data) in chunks.into_iter().enumerate() {
+        let expected = expected as i64;
+        let mut n = None;
+        let mut chunk = None;
+
+        for element in Elements::new(data, 0)? {
+            let element = element?;
+
+            match element.key {
+                "n" => n = integer(&element.value),
+                "data" if element.value.element_type() == 0x05 => {
+                    chunk = Some(&element.value.as_bytes()[5..]);
+                }
+                _ => {}
+            }
+        }
+
+        let n = n.ok_or(Error::MissingField("n"))?;
+        let chunk = chunk.ok_or(Error::MissingField("data"))?;
+
+        if n != expected {
+            return Err(Error::OutOfOrder { expected, found: n });
+        }
+
+        // every chunk but the last is full, and the last can't be empty
+        let remaining = length - written;
+        if chunk.len() as i64 != remaining.min(chunk_size) || remaining <= 0 {
+            return Err(Error::WrongChunkSize(n));
+        }
+
+        writer.write_all(chunk)?;
+        written += chunk.len() as i64;
+    }
+
+    if written != length {
+        return Err(Error::WrongLength {
+            expected: length,
+            found: written,
+        });
+    }
+
+    Ok(written)
+}
+
+fn integer(value: &crate::RawBson<'_>) -> Option<i64> {
+    let bytes = value.as_bytes();
+
+    match value.element_type() {
+        0x10 => Some(i64::from(i32::from_le_bytes(bytes.try_into().unwrap()))),
+        0x12 => Some(i64::from_le_bytes(bytes.try_into().unwrap())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Error;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn round_trip() {
+        let contents = (0..=255).cycle().take(1000).collect::<Vec<u8>>();
+
+        let mut chunks = super::split(&"file", &contents[..], 300);
+        let written = chunks.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(written.len(), 4);
+        assert_eq!(chunks.length(), 1000);
+
+        let upload_date = UNIX_EPOCH + Duration::from_millis(1234);
+        let file = chunks.file_document(Some("a.bin"), upload_date).unwrap();
+
+        assert_eq!(
+            bson::Document::from_reader(&file[..]).unwrap(),
+            bson::doc! {
+                "_id": "file",
+                "length": 1000_i64,
+                "chunkSize": 300,
+                "uploadDate": bson::DateTime::from_millis(1234),
+                "filename": "a.bin",
+            }
+        );
+        assert_eq!(
+            bson::Document::from_reader(&written[3][..]).unwrap(),
+            bson::doc! {
+                "files_id": "file",
+                "n": 3,
+                "data": bson::Binary {
+                    subtype: bson::spec::BinarySubtype::Generic,
+                    bytes: contents[900..].to_vec(),
+                },
+            }
+        );
+
+        let mut output = Vec::new();
+        let length =
+            super::assemble(&file, written.iter().map(|chunk| &chunk[..]), &mut output).unwrap();
+        assert_eq!(length, 1000);
+        assert_eq!(output, contents);
+
+        assert!(matches!(
+            super::assemble(&file, written.iter().rev().map(|c| &c[..]), &mut Vec::new()),
+            Err(Error::OutOfOrder {
+                expected: 0,
+                found: 3
+            })
+        ));
+        assert!(matches!(
+            super::assemble(&file, written[..3].iter().map(|c| &c[..]), &mut Vec::new()),
+            Err(Error::WrongLength {
+                expected: 1000,
+                found: 900
+            })
+        ));
+    }
+
+    #[test]
+    fn empty() {
+        let mut chunks = super::split(&1, &[][..], super::DEFAULT_CHUNK_SIZE);
+        assert!(chunks.next().is_none());
+
+        let file = chunks.file_document(None, UNIX_EPOCH).unwrap();
+        let mut output = Vec::new();
+        assert_eq!(
+            super::assemble(&file, std::iter::empty(), &mut output).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn exact_multiple() {
+        let contents = [7; 600];
+        let chunks = super::split(&1, &contents[..], 300)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(chunks.len(), 2);
+    }
+}
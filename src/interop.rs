@@ -0,0 +1,192 @@
+//! Conversions to and from the types of the [`bson`] crate, for codebases moving between the two.
+//!
+//! A `bson::Document` implements `Serialize` by writing ObjectIds, datetimes and the like as
+//! Extended JSON objects, which is what any serializer other than `bson`'s own will end up
+//! with. [`BsonDocument`] writes it out as-is instead. Going the other way, `bson::Document`
+//! understands those same Extended JSON objects so can be deserialised directly using
+//! [`BsonTypes::ExtendedJson`](crate::de::BsonTypes::ExtendedJson).
+
+use crate::{Code, Decimal128, MaxKey, MinKey, RawDocument, Regex, Symbol, Undefined};
+use serde::{Serialize, Serializer};
+use std::convert::TryFrom;
+
+/// Serialises a `bson::Document` exactly as `bson` itself would.
+#[derive(Debug, Clone, Copy)]
+pub struct BsonDocument<'a>(pub &'a bson::Document);
+
+impl Serialize for BsonDocument<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut encoded = Vec::new();
+        self.0
+            .to_writer(&mut encoded)
+            .map_err(serde::ser::Error::custom)?;
+
+        RawDocument(&encoded).serialize(serializer)
+    }
+}
+
+/// For use with `#[serde(serialize_with = "...")]` on `bson::Document` fields, see
+/// [`BsonDocument`].
+pub fn serialize_document<S: Serializer>(
+    v: &bson::Document,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    BsonDocument(v).serialize(serializer)
+}
+
+impl<'a> TryFrom<RawDocument<'a>> for bson::Document {
+    type Error = bson::de::Error;
+
+    fn try_from(v: RawDocument<'a>) -> Result<Self, Self::Error> {
+        bson::Document::from_reader(v.0)
+    }
+}
+
+impl From<bson::Decimal128> for Decimal128 {
+    fn from(v: bson::Decimal128) -> Self {
+        Self::from_bytes(v.bytes())
+    }
+}
+
+impl From<Decimal128> for bson::Decimal128 {
+    fn from(v: Decimal128) -> Self {
+        Self::from_bytes(v.bytes())
+    }
+}
+
+impl From<bson::Regex> for Regex {
+    fn from(v: bson::Regex) -> Self {
+        Self {
+            pattern: v.pattern,
+            options: v.options,
+        }
+    }
+}
+
+impl From<Regex> for bson::Regex {
+    fn from(v: Regex) -> Self {
+        Self {
+            pattern: v.pattern,
+            options: v.options,
+        }
+    }
+}
+
+macro_rules! into_bson {
+    ($($ty:ty => |$v:ident| $bson:expr,)*) => {
+        $(
+            impl From<$ty> for bson::Bson {
+                fn from($v: $ty) -> Self {
+                    $bson
+                }
+            }
+        )*
+    };
+}
+
+into_bson! {
+    Decimal128 => |v| bson::Bson::Decimal128(v.into()),
+    Regex => |v| bson::Bson::RegularExpression(v.into()),
+    Code => |v| bson::Bson::JavaScriptCode(v.0),
+    Symbol => |v| bson::Bson::Symbol(v.0),
+    Undefined => |_v| bson::Bson::Undefined,
+    MinKey => |_v| bson::Bson::MinKey,
+    MaxKey => |_v| bson::Bson::MaxKey,
+}
+
+macro_rules! try_from_bson {
+    ($($ty:ty => $pattern:pat => $value:expr,)*) => {
+        $(
+            impl TryFrom<bson::Bson> for $ty {
+                type Error = bson::Bson;
+
+                /// Hands back the value if it's of a different type.
+                fn try_from(v: bson::Bson) -> Result<Self, Self::Error> {
+                    match v {
+                        $pattern => Ok($value),
+                        v => Err(v),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+try_from_bson! {
+    Decimal128 => bson::Bson::Decimal128(v) => v.into(),
+    Regex => bson::Bson::RegularExpression(v) => v.into(),
+    Code => bson::Bson::JavaScriptCode(v) => Code(v),
+    Symbol => bson::Bson::Symbol(v) => Symbol(v),
+    Undefined => bson::Bson::Undefined => Undefined,
+    MinKey => bson::Bson::MinKey => MinKey,
+    MaxKey => bson::Bson::MaxKey => MaxKey,
+}
+
+#[cfg(test)]
+mod test {
+    use super::BsonDocument;
+    use crate::{Code, Decimal128, RawDocument, Regex};
+    use serde::{Deserialize, Serialize};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn bson_document() {
+        let theirs = bson::doc! {
+            "oid": bson::oid::ObjectId::from_bytes([1; 12]),
+            "date": bson::DateTime::from_millis(1),
+            "nested": { "a": [1, "x"] },
+        };
+
+        #[derive(Serialize)]
+        struct A<'a> {
+            #[serde(serialize_with = "super::serialize_document")]
+            a: &'a bson::Document,
+        }
+
+        let ours = crate::to_bytes(&BsonDocument(&theirs)).unwrap();
+        assert_eq!(&ours[..], &bson::to_vec(&theirs).unwrap()[..]);
+        assert_eq!(
+            bson::Document::try_from(RawDocument(&ours)).unwrap(),
+            theirs
+        );
+
+        let ours = crate::to_bytes(&A { a: &theirs }).unwrap();
+        assert_eq!(
+            bson::Document::from_reader(&ours[..]).unwrap(),
+            bson::doc! { "a": theirs.clone() }
+        );
+
+        #[derive(Deserialize)]
+        struct B {
+            a: bson::Document,
+        }
+
+        let options = crate::de::Options::new().bson_types(crate::de::BsonTypes::ExtendedJson);
+        let deserialized: B = crate::de::from_bytes_with_options(&ours, options).unwrap();
+        assert_eq!(deserialized.a, theirs);
+    }
+
+    #[test]
+    fn conversions() {
+        let decimal: bson::Decimal128 = "1.5".parse().unwrap();
+        assert_eq!(Decimal128::from(decimal).bytes(), decimal.bytes());
+        assert_eq!(
+            bson::Bson::from(Decimal128::from(decimal)),
+            bson::Bson::Decimal128(decimal)
+        );
+
+        let regex = Regex {
+            pattern: "^a".to_string(),
+            options: "i".to_string(),
+        };
+        let theirs = bson::Bson::from(regex.clone());
+        assert_eq!(Regex::try_from(theirs).unwrap(), regex);
+
+        let code = bson::Bson::JavaScriptCode("f()".to_string());
+        assert_eq!(Code::try_from(code).unwrap(), Code("f()".to_string()));
+        assert_eq!(
+            Code::try_from(bson::Bson::Int32(1)),
+            Err(bson::Bson::Int32(1))
+        );
+    }
+}
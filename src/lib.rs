@@ -1,27 +1,264 @@
+//! Without the default `std` feature the crate is `no_std` and only needs `alloc`, which leaves
+//! the serializer and deserializer over in-memory buffers. Everything built on top of them, and
+//! anything reading from or writing to `std::io`, needs `std`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
 mod byte;
+#[cfg(feature = "std")]
+mod cache;
+#[cfg(feature = "std")]
+pub mod checksum;
+#[cfg(feature = "std")]
+mod chunked;
+#[cfg(feature = "std")]
+mod column;
+#[cfg(all(test, feature = "corpus"))]
+mod corpus;
 pub mod de;
+#[cfg(feature = "std")]
+pub mod debug;
+mod decimal128;
+#[cfg(feature = "std")]
+pub mod diff;
 mod error;
+#[cfg(feature = "std")]
+pub mod gridfs;
+#[cfg(feature = "bson-interop")]
+pub mod interop;
+#[cfg(feature = "std")]
+#[macro_use]
+mod macros;
+mod object_id;
+#[cfg(feature = "std")]
+mod pool;
+#[cfg(test)]
+mod properties;
+mod raw;
+pub mod redact;
+mod scan;
+#[cfg(feature = "std")]
+pub mod schema;
 pub mod ser;
+#[cfg(feature = "std")]
+pub mod serde_helpers;
+#[cfg(feature = "std")]
+pub mod shared;
+mod stats;
+mod text;
+mod trace;
+#[cfg(feature = "transcode")]
+pub mod transcode;
+mod types;
+mod utf8;
+#[cfg(feature = "std")]
+mod value;
+#[cfg(feature = "std")]
+mod vector;
+
+pub use byte::{BytesLikeBuf, SliceBuf};
+#[cfg(feature = "std")]
+pub use cache::SizeCache;
+#[cfg(feature = "std")]
+pub use chunked::ChunkedSerializer;
+#[cfg(feature = "std")]
+pub use column::{BsonColumnBuilder, BsonColumnValues, ColumnValue, InvalidColumn};
+pub use decimal128::Decimal128;
+pub use error::{Error, Path};
+#[cfg(feature = "std")]
+pub use object_id::ObjectIdGenerator;
+pub use object_id::{InvalidObjectId, ObjectId};
+#[cfg(feature = "std")]
+pub use pool::{PooledBuf, SerializerPool};
+pub use raw::{RawArray, RawArrayIter, RawBson, RawDocument, RawDocumentBuf, RawEditError};
+#[cfg(feature = "std")]
+pub use shared::SharedStr;
+pub use stats::Stats;
+pub use types::{
+    BsonColumn, Code, CodeWithScope, DbPointer, EncryptedValue, MaxKey, MinKey, Regex, Sensitive,
+    Symbol, Undefined,
+};
+#[cfg(feature = "std")]
+pub use value::{ArrayMerge, Document, MergeStrategy, Value};
+#[cfg(feature = "std")]
+pub use vector::{InvalidVector, PackedBitVector, Vector};
 
-pub use error::Error;
+// re-exported for use with `de::to_tape`
+pub use bumpalo;
 
+use alloc::vec::Vec;
 use byte::CountingBytes;
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use serde::Serialize;
 
 pub fn to_string<T: Serialize>(val: &T, output: &mut BytesMut) -> Result<(), Error> {
-    // do a quick pass over the value using our `CountingBytes` impl so we can do
-    // one big allocation rather than multiple smaller ones.
-    output.reserve(serialised_size_of(val)?);
+    to_buf(val, output, ser::Options::default())
+}
+
+pub fn to_string_with_options<T: Serialize>(
+    val: &T,
+    output: &mut BytesMut,
+    options: ser::Options,
+) -> Result<(), Error> {
+    to_buf(val, output, options)
+}
+
+/// Serialises each item as its own top-level document, one after the other, onto the end of
+/// `output`. The size of every document is counted up front so only a single reservation is
+/// needed.
+pub fn to_string_many<I>(iter: I, output: &mut BytesMut) -> Result<(), Error>
+where
+    I: IntoIterator,
+    I::IntoIter: Clone,
+    I::Item: Serialize,
+{
+    let iter = iter.into_iter();
+
+    let mut size = 0;
+    for val in iter.clone() {
+        size += serialised_size_of(&val)?;
+    }
+    output.reserve(size);
+
+    for val in iter {
+        val.serialize(ser::Serializer {
+            key: None,
+            output: &mut *output,
+            options: ser::Options::default(),
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Serialises `val` into a new, frozen buffer which can be cheaply cloned.
+pub fn to_bytes<T: Serialize>(val: &T) -> Result<Bytes, Error> {
+    to_bytes_with_options(val, ser::Options::default())
+}
+
+pub fn to_bytes_with_options<T: Serialize>(val: &T, options: ser::Options) -> Result<Bytes, Error> {
+    let mut output = BytesMut::new();
+    to_buf(val, &mut output, options)?;
+    Ok(output.freeze())
+}
+
+/// Serialises `val` onto the end of `output`, for when the `bytes` crate isn't otherwise in use.
+pub fn to_vec<T: Serialize>(val: &T, output: &mut Vec<u8>) -> Result<(), Error> {
+    to_buf(val, output, ser::Options::default())
+}
+
+pub fn to_vec_with_options<T: Serialize>(
+    val: &T,
+    output: &mut Vec<u8>,
+    options: ser::Options,
+) -> Result<(), Error> {
+    to_buf(val, output, options)
+}
+
+/// Serialises `val` onto the end of `output` the same as [`to_vec_with_options`], adding the
+/// document to `stats` if it succeeds.
+pub fn to_vec_with_stats<T: Serialize>(
+    val: &T,
+    output: &mut Vec<u8>,
+    options: ser::Options,
+    stats: &mut Stats,
+) -> Result<(), Error> {
+    let start = output.len();
+    to_buf(val, output, options)?;
+
+    // the serialiser has no idea what it's writing beyond the bytes themselves, so the elements
+    // are stepped over again while they're still fresh in the cache. raw values are copied in as
+    // given so may not be well formed, in which case whatever could be read is recorded
+    let _ = stats.record(&output[start..]);
+
+    Ok(())
+}
+
+/// Serialises `val` into the start of `output` without allocating, returning the number of
+/// bytes written or [`Error::BufferTooSmall`] if it doesn't fit.
+pub fn to_slice<T: Serialize>(val: &T, output: &mut [u8]) -> Result<usize, Error> {
+    to_slice_with_options(val, output, ser::Options::default())
+}
+
+pub fn to_slice_with_options<T: Serialize>(
+    val: &T,
+    output: &mut [u8],
+    options: ser::Options,
+) -> Result<usize, Error> {
+    // no counting pass here, we'd rather just find out the slice is too small when we get to
+    // the end of it
+    let mut buf = SliceBuf::new(output);
+
+    trace::serialize(&mut buf, |buf| {
+        val.serialize(ser::Serializer {
+            key: None,
+            output: &mut *buf,
+            options,
+        })?;
+
+        if buf.overflowed() {
+            return Err(Error::BufferTooSmall);
+        }
 
-    val.serialize(ser::Serializer { key: None, output })
+        Ok(buf.written())
+    })
+}
+
+/// Serialises `val` onto the end of any [`BytesLikeBuf`].
+pub fn to_buf<T: Serialize, B: BytesLikeBuf>(
+    val: &T,
+    output: &mut B,
+    options: ser::Options,
+) -> Result<(), Error> {
+    trace::serialize(output, |output| {
+        // do a quick pass over the value using our `CountingBytes` impl so we can do
+        // one big allocation rather than multiple smaller ones.
+        output.reserve(serialised_size_of_with_options(val, options)?);
+
+        val.serialize(ser::Serializer {
+            key: None,
+            output,
+            options,
+        })
+    })
+}
+
+/// Serialises `val` out to `writer` 64KiB at a time, without holding the whole document in
+/// memory. See [`ChunkedSerializer`] for the costs involved.
+#[cfg(feature = "futures")]
+pub async fn to_async_writer<T, W>(val: &T, writer: &mut W) -> std::io::Result<()>
+where
+    T: Serialize,
+    W: futures_io::AsyncWrite + Unpin + ?Sized,
+{
+    let mut chunks = ChunkedSerializer::new(val, 64 * 1024)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+    while std::future::poll_fn(|cx| chunks.poll_write_next(cx, std::pin::Pin::new(&mut *writer)))
+        .await?
+        != 0
+    {}
+
+    std::future::poll_fn(|cx| std::pin::Pin::new(&mut *writer).poll_flush(cx)).await
 }
 
 pub fn serialised_size_of<T: Serialize>(val: &T) -> Result<usize, Error> {
+    serialised_size_of_with_options(val, ser::Options::default())
+}
+
+pub fn serialised_size_of_with_options<T: Serialize>(
+    val: &T,
+    options: ser::Options,
+) -> Result<usize, Error> {
     let mut counting_bytes = CountingBytes::default();
     val.serialize(ser::Serializer {
         key: None,
         output: &mut counting_bytes,
+        options,
     })?;
     Ok(counting_bytes.bytes)
 }
@@ -33,6 +270,7 @@ mod test {
     use serde::{Deserialize, Serialize};
 
     #[test]
+    #[allow(clippy::zero_prefixed_literal)]
     pub fn test_basic() {
         #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
         pub struct A<'a> {
@@ -103,4 +341,842 @@ mod test {
         let deserialized: A = crate::de::from_bytes(&ours).unwrap();
         assert_eq!(&deserialized, test);
     }
+
+    #[test]
+    pub fn test_char() {
+        #[derive(Serialize, Debug, PartialEq, Eq)]
+        pub struct A {
+            a: char,
+            b: char,
+        }
+
+        let test = &A { a: 'x', b: '🦀' };
+
+        let mut ours = BytesMut::new();
+        to_string(&test, &mut ours).unwrap();
+
+        let mut theirs = BytesMut::new().writer();
+        bson::ser::to_document(&test)
+            .unwrap()
+            .to_writer(&mut theirs)
+            .unwrap();
+
+        assert_eq!(ours, theirs.into_inner());
+        assert_eq!(serialised_size_of(&test).unwrap(), ours.len());
+    }
+
+    #[test]
+    pub fn test_flatten() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        pub struct Outer<'a> {
+            a: i32,
+            #[serde(borrow, flatten)]
+            inner: Inner<'a>,
+            z: Vec<&'a str>,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        pub struct Inner<'a> {
+            b: &'a str,
+            c: Option<i64>,
+            d: Nested,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        pub struct Nested {
+            e: bool,
+            f: (i32, i32),
+        }
+
+        let test = &Outer {
+            a: 1,
+            inner: Inner {
+                b: "flattened",
+                c: None,
+                d: Nested { e: true, f: (2, 3) },
+            },
+            z: vec!["end"],
+        };
+
+        let mut ours = BytesMut::new();
+        to_string(&test, &mut ours).unwrap();
+
+        let mut theirs = BytesMut::new().writer();
+        bson::ser::to_document(&test)
+            .unwrap()
+            .to_writer(&mut theirs)
+            .unwrap();
+
+        assert_eq!(ours, theirs.into_inner());
+        assert_eq!(serialised_size_of(&test).unwrap(), ours.len());
+
+        let deserialized: Outer = crate::de::from_bytes(&ours).unwrap();
+        assert_eq!(&deserialized, test);
+    }
+
+    #[test]
+    pub fn test_map() {
+        let mut test = std::collections::BTreeMap::new();
+        test.insert("hello".to_string(), vec![1, 2]);
+        test.insert("world".to_string(), vec![3]);
+
+        let mut ours = BytesMut::new();
+        to_string(&test, &mut ours).unwrap();
+
+        let mut theirs = BytesMut::new().writer();
+        bson::ser::to_document(&test)
+            .unwrap()
+            .to_writer(&mut theirs)
+            .unwrap();
+
+        assert_eq!(ours, theirs.into_inner());
+        assert_eq!(serialised_size_of(&test).unwrap(), ours.len());
+
+        let deserialized: std::collections::BTreeMap<String, Vec<i32>> =
+            crate::de::from_bytes(&ours).unwrap();
+        assert_eq!(deserialized, test);
+
+        let mut test = std::collections::BTreeMap::new();
+        test.insert("nul\0".to_string(), 1);
+        assert!(matches!(
+            to_string(&test, &mut BytesMut::new()),
+            Err(crate::Error::KeyContainsNullByte)
+        ));
+    }
+
+    #[test]
+    pub fn test_sort_keys() {
+        #[derive(Serialize)]
+        pub struct A {
+            zebra: i32,
+            apple: B,
+            mango: Vec<i32>,
+        }
+
+        #[derive(Serialize)]
+        pub struct B {
+            y: &'static str,
+            x: bool,
+        }
+
+        #[derive(Serialize)]
+        pub struct SortedA {
+            apple: SortedB,
+            mango: Vec<i32>,
+            zebra: i32,
+        }
+
+        #[derive(Serialize)]
+        pub struct SortedB {
+            x: bool,
+            y: &'static str,
+        }
+
+        let options = crate::ser::Options::new().sort_keys(true);
+
+        let mut ours = BytesMut::new();
+        let val = A {
+            zebra: 1,
+            apple: B { y: "y", x: true },
+            mango: (0..12).collect(),
+        };
+        crate::to_string_with_options(&val, &mut ours, options).unwrap();
+
+        let mut expected = BytesMut::new();
+        let val = SortedA {
+            apple: SortedB { x: true, y: "y" },
+            mango: (0..12).collect(),
+            zebra: 1,
+        };
+        to_string(&val, &mut expected).unwrap();
+
+        assert_eq!(ours, expected);
+
+        let mut map = std::collections::HashMap::new();
+        map.insert("b", 2);
+        map.insert("c", 3);
+        map.insert("a", 1);
+
+        let mut ours = BytesMut::new();
+        crate::to_string_with_options(&map, &mut ours, options).unwrap();
+
+        let mut expected = BytesMut::new();
+        to_string(
+            &[("a", 1), ("b", 2), ("c", 3)]
+                .iter()
+                .copied()
+                .collect::<std::collections::BTreeMap<_, _>>(),
+            &mut expected,
+        )
+        .unwrap();
+
+        assert_eq!(ours, expected);
+    }
+
+    #[test]
+    pub fn test_sort_keys_unsortable_output() {
+        /// Writes straight through to a `Vec` without handing any of it back.
+        struct WriteOnly(Vec<u8>);
+
+        impl crate::BytesLikeBuf for WriteOnly {
+            fn put_u8(&mut self, v: u8) {
+                self.0.push(v);
+            }
+
+            fn put_i32_le(&mut self, v: i32) {
+                self.0.extend_from_slice(&v.to_le_bytes());
+            }
+
+            fn put_i64_le(&mut self, v: i64) {
+                self.0.extend_from_slice(&v.to_le_bytes());
+            }
+
+            fn put_f64_le(&mut self, v: f64) {
+                self.0.extend_from_slice(&v.to_le_bytes());
+            }
+
+            fn put_slice(&mut self, s: &[u8]) {
+                self.0.extend_from_slice(s);
+            }
+
+            fn len(&mut self) -> usize {
+                self.0.len()
+            }
+
+            fn byte_mut(&mut self, at: usize) -> &mut u8 {
+                &mut self.0[at]
+            }
+
+            fn as_mut_slice(&mut self) -> Option<&mut [u8]> {
+                None
+            }
+        }
+
+        let map = std::collections::BTreeMap::from([("b", 2), ("a", 1)]);
+        let options = crate::ser::Options::new().sort_keys(true);
+
+        let res = crate::to_buf(&map, &mut WriteOnly(Vec::new()), options);
+        assert!(
+            matches!(res, Err(crate::Error::UnsortableOutput)),
+            "{:?}",
+            res
+        );
+
+        // nothing needs reordering without sort_keys
+        crate::to_buf(&map, &mut WriteOnly(Vec::new()), Default::default()).unwrap();
+
+        // a slice that's too small is still reported as such
+        let mut out = [0; 8];
+        let res = crate::to_slice_with_options(&map, &mut out, options);
+        assert!(
+            matches!(res, Err(crate::Error::BufferTooSmall)),
+            "{:?}",
+            res
+        );
+    }
+
+    #[test]
+    pub fn test_non_finite_floats() {
+        use crate::ser::{NonFiniteFloats, Options};
+
+        #[derive(Serialize)]
+        pub struct A {
+            a: f64,
+            b: f32,
+        }
+
+        #[derive(Serialize)]
+        pub struct Nulled {
+            a: Option<f64>,
+            b: Option<f32>,
+        }
+
+        let val = A {
+            a: f64::NAN,
+            b: f32::INFINITY,
+        };
+
+        let mut ours = BytesMut::new();
+        crate::to_string(&val, &mut ours).unwrap();
+        let deserialized: std::collections::BTreeMap<String, f64> =
+            crate::de::from_bytes(&ours).unwrap();
+        assert!(deserialized["a"].is_nan());
+        assert_eq!(deserialized["b"], f64::INFINITY);
+
+        let options = Options::new().non_finite_floats(NonFiniteFloats::Reject);
+        let err = crate::to_string_with_options(&val, &mut BytesMut::new(), options).unwrap_err();
+        assert!(matches!(err.inner(), crate::Error::NonFiniteFloat(v) if v.is_nan()));
+        assert_eq!(err.path().unwrap().as_str(), "a");
+
+        let mut ours = BytesMut::new();
+        let options = Options::new().non_finite_floats(NonFiniteFloats::Null);
+        crate::to_string_with_options(&val, &mut ours, options).unwrap();
+
+        let mut expected = BytesMut::new();
+        to_string(&Nulled { a: None, b: None }, &mut expected).unwrap();
+        assert_eq!(ours, expected);
+    }
+
+    #[test]
+    pub fn test_human_readable() {
+        // picks a representation the same way uuids and timestamps do
+        #[derive(Debug, PartialEq)]
+        pub struct Id(i32);
+
+        impl Serialize for Id {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                if serializer.is_human_readable() {
+                    serializer.serialize_str(&self.0.to_string())
+                } else {
+                    serializer.serialize_i32(self.0)
+                }
+            }
+        }
+
+        impl<'de> Deserialize<'de> for Id {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                if deserializer.is_human_readable() {
+                    let v = <&str>::deserialize(deserializer)?;
+                    v.parse().map(Id).map_err(serde::de::Error::custom)
+                } else {
+                    i32::deserialize(deserializer).map(Id)
+                }
+            }
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        pub struct A {
+            id: Id,
+            ids: Vec<Id>,
+        }
+
+        let val = A {
+            id: Id(1),
+            ids: vec![Id(2)],
+        };
+
+        let ours = crate::to_bytes(&val).unwrap();
+        assert_eq!(
+            bson::from_slice::<bson::Document>(&ours).unwrap(),
+            bson::doc! { "id": 1, "ids": [2] }
+        );
+        assert_eq!(crate::de::from_bytes::<A>(&ours).unwrap(), val);
+
+        let options = crate::ser::Options::new().human_readable(true);
+        let ours = crate::to_bytes_with_options(&val, options).unwrap();
+        assert_eq!(
+            bson::from_slice::<bson::Document>(&ours).unwrap(),
+            bson::doc! { "id": "1", "ids": ["2"] }
+        );
+
+        let options = crate::de::Options::new().human_readable(true);
+        assert_eq!(
+            crate::de::from_bytes_with_options::<A>(&ours, options).unwrap(),
+            val
+        );
+        assert_eq!(
+            crate::de::from_bytes_streaming_with_options::<A>(&ours, options).unwrap(),
+            val
+        );
+        assert!(crate::de::from_bytes::<A>(&ours).is_err());
+    }
+
+    #[test]
+    pub fn test_unsigned_integers() {
+        use crate::ser::{Options, UnsignedIntegers};
+
+        #[derive(Serialize)]
+        pub struct A {
+            a: u8,
+            b: u16,
+            c: u32,
+            d: u64,
+            e: u64,
+        }
+
+        let val = A {
+            a: 1,
+            b: 2,
+            c: u32::MAX,
+            d: 4,
+            e: u64::MAX,
+        };
+
+        let err = crate::to_bytes(&val).unwrap_err();
+        assert!(matches!(err.inner(), crate::Error::UnsignedIntNotInSpec));
+
+        let options = Options::new().unsigned_integers(UnsignedIntegers::Widen);
+        let ours = crate::to_bytes_with_options(&val, options).unwrap();
+        assert_eq!(
+            bson::Document::from_reader(&ours[..]).unwrap(),
+            bson::doc! {
+                "a": 1,
+                "b": 2,
+                "c": i64::from(u32::MAX),
+                "d": 4_i64,
+                "e": u64::MAX.to_string().parse::<bson::Decimal128>().unwrap(),
+            }
+        );
+    }
+
+    #[test]
+    pub fn test_rename_fields() {
+        use crate::ser::{camel_case, Options};
+        use std::collections::BTreeMap;
+
+        #[derive(Serialize)]
+        pub struct A {
+            _id: i32,
+            first_name: &'static str,
+            address: B,
+            #[serde(rename = "zip_code")]
+            zip: i32,
+            map: BTreeMap<&'static str, i32>,
+            variant: C,
+            newtype_variant: C,
+        }
+
+        #[derive(Serialize)]
+        pub struct B {
+            line_one: &'static str,
+        }
+
+        #[derive(Serialize)]
+        pub enum C {
+            SomeVariant {
+                some_field: bool,
+            },
+            #[serde(rename = "other_variant")]
+            OtherVariant(i32),
+        }
+
+        let val = A {
+            _id: 1,
+            first_name: "ferris",
+            address: B { line_one: "x" },
+            zip: 12345,
+            map: Some(("map_key", 2)).into_iter().collect(),
+            variant: C::SomeVariant { some_field: true },
+            newtype_variant: C::OtherVariant(3),
+        };
+
+        let options = Options::new().rename_fields(camel_case).sort_keys(true);
+        let ours = crate::to_bytes_with_options(&val, options).unwrap();
+        assert_eq!(
+            bson::Document::from_reader(&ours[..]).unwrap(),
+            bson::doc! {
+                "_id": 1,
+                "address": { "lineOne": "x" },
+                "firstName": "ferris",
+                "map": { "map_key": 2 },
+                "newtypeVariant": { "other_variant": 3 },
+                "variant": { "SomeVariant": { "someField": true } },
+                "zipCode": 12345,
+            }
+        );
+
+        assert_eq!(camel_case("already"), "already");
+        assert_eq!(camel_case("a_b_cd"), "aBCd");
+        assert_eq!(camel_case("trailing_"), "trailing");
+    }
+
+    #[test]
+    pub fn test_variants_by_index() {
+        use crate::ser::{Options, Variants};
+
+        #[derive(Serialize)]
+        pub enum E {
+            Unit,
+            Newtype(i32),
+            Tuple(i32, bool),
+            Struct { a: i32 },
+        }
+
+        let val = vec![
+            E::Unit,
+            E::Newtype(5),
+            E::Tuple(1, true),
+            E::Struct { a: 2 },
+        ];
+
+        #[derive(Serialize)]
+        pub struct A {
+            val: Vec<E>,
+        }
+
+        let options = Options::new().variants(Variants::Index);
+        let ours = crate::to_bytes_with_options(&A { val }, options).unwrap();
+        assert_eq!(
+            bson::Document::from_reader(&ours[..]).unwrap(),
+            bson::doc! {
+                "val": [
+                    0,
+                    { "1": 5 },
+                    { "2": [1, true] },
+                    { "3": { "a": 2 } },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    pub fn test_serde_json_value() {
+        use crate::ser::{Options, UnsignedIntegers};
+
+        let val = serde_json::json!({
+            "a": 1,
+            "b": -1,
+            "c": 1.5,
+            "d": [true, null, "x"],
+            "e": { "f": { "g": u64::MAX } },
+        });
+
+        let options = Options::new().unsigned_integers(UnsignedIntegers::Widen);
+        let ours = crate::to_bytes_with_options(&val, options).unwrap();
+        assert_eq!(
+            bson::Document::from_reader(&ours[..]).unwrap(),
+            bson::doc! {
+                "a": 1_i64,
+                "b": -1_i64,
+                "c": 1.5,
+                "d": [true, bson::Bson::Null, "x"],
+                "e": { "f": { "g": u64::MAX.to_string().parse::<bson::Decimal128>().unwrap() } },
+            }
+        );
+
+        // a bare value isn't a document
+        assert!(crate::to_bytes_with_options(&serde_json::json!(1), options).is_err());
+    }
+
+    #[test]
+    pub fn test_error_path() {
+        #[derive(Serialize, Deserialize, Debug)]
+        pub struct Order {
+            customer: Customer,
+        }
+
+        #[derive(Serialize, Deserialize, Debug)]
+        pub struct Customer {
+            id: u64,
+        }
+
+        #[derive(Serialize)]
+        pub struct A {
+            orders: Vec<Order>,
+        }
+
+        let val = A {
+            orders: vec![Order {
+                customer: Customer { id: 1 },
+            }],
+        };
+
+        let err = to_string(&val, &mut BytesMut::new()).unwrap_err();
+        assert!(matches!(err.inner(), crate::Error::UnsignedIntNotInSpec));
+        assert_eq!(err.path().unwrap().as_str(), "orders[0].customer.id");
+
+        let mut map = std::collections::BTreeMap::new();
+        map.insert("key", Customer { id: 1 });
+
+        let err = to_string(&map, &mut BytesMut::new()).unwrap_err();
+        assert_eq!(err.path().unwrap().as_str(), "key.id");
+
+        #[derive(Serialize)]
+        pub struct StrOrder {
+            customer: StrCustomer,
+        }
+
+        #[derive(Serialize)]
+        pub struct StrCustomer {
+            id: &'static str,
+        }
+
+        #[derive(Serialize)]
+        pub struct StrA {
+            orders: Vec<StrOrder>,
+        }
+
+        let mut bytes = BytesMut::new();
+        let val = StrA {
+            orders: vec![StrOrder {
+                customer: StrCustomer { id: "1" },
+            }],
+        };
+        to_string(&val, &mut bytes).unwrap();
+
+        #[derive(Deserialize, Debug)]
+        pub struct DeA {
+            #[allow(dead_code)]
+            orders: Vec<Order>,
+        }
+
+        let err = crate::de::from_bytes::<DeA>(&bytes).unwrap_err();
+        assert_eq!(err.path().unwrap().as_str(), "orders[0].customer.id");
+    }
+
+    #[test]
+    pub fn test_to_string_many() {
+        #[derive(Serialize)]
+        pub struct A {
+            a: i32,
+        }
+
+        let vals = [A { a: 1 }, A { a: 2 }, A { a: 3 }];
+
+        let mut ours = BytesMut::new();
+        crate::to_string_many(&vals, &mut ours).unwrap();
+
+        let mut expected = BytesMut::new();
+        for val in &vals {
+            to_string(val, &mut expected).unwrap();
+        }
+
+        assert_eq!(ours, expected);
+    }
+
+    #[test]
+    pub fn test_nested_with_existing_output() {
+        #[derive(Serialize)]
+        pub enum E {
+            Tuple(i32, String),
+            Struct { a: i32, b: Vec<i32> },
+        }
+
+        #[derive(Serialize)]
+        pub struct A {
+            x: E,
+            y: E,
+            z: std::collections::BTreeMap<String, Vec<i32>>,
+        }
+
+        let test = &A {
+            x: E::Tuple(1, "hello".to_string()),
+            y: E::Struct {
+                a: 4,
+                b: vec![1, 2, 3],
+            },
+            z: std::iter::once(("k".to_string(), vec![5, 6])).collect(),
+        };
+
+        // lengths are patched in place, so they need to land in the right spot when the output
+        // already has something in it
+        let mut ours = BytesMut::from(&b"prefix"[..]);
+        crate::to_string_with_options(test, &mut ours, crate::ser::Options::new().sort_keys(true))
+            .unwrap();
+
+        let theirs = bson::to_document(test).unwrap();
+        let mut expected = b"prefix".to_vec();
+        theirs.to_writer(&mut expected).unwrap();
+
+        assert_eq!(&ours[..], &expected[..]);
+    }
+
+    #[test]
+    pub fn test_to_vec() {
+        #[derive(Serialize)]
+        pub struct A<'a> {
+            a: i32,
+            b: &'a str,
+            c: Vec<i64>,
+        }
+
+        let test = &A {
+            a: 1,
+            b: "hello",
+            c: vec![2, 3],
+        };
+
+        let mut theirs = Vec::new();
+        bson::ser::to_document(&test)
+            .unwrap()
+            .to_writer(&mut theirs)
+            .unwrap();
+
+        let mut ours = Vec::new();
+        crate::to_vec(&test, &mut ours).unwrap();
+        assert_eq!(ours, theirs);
+    }
+
+    #[test]
+    pub fn test_to_slice() {
+        #[derive(Serialize)]
+        pub struct A<'a> {
+            a: i32,
+            b: &'a str,
+            c: Vec<i64>,
+        }
+
+        let test = &A {
+            a: 1,
+            b: "hello",
+            c: vec![2, 3],
+        };
+
+        let mut theirs = Vec::new();
+        bson::ser::to_document(&test)
+            .unwrap()
+            .to_writer(&mut theirs)
+            .unwrap();
+
+        let mut ours = [0; 512];
+        let written = crate::to_slice(&test, &mut ours).unwrap();
+        assert_eq!(&ours[..written], &theirs[..]);
+
+        let mut too_small = vec![0; theirs.len() - 1];
+        assert!(matches!(
+            crate::to_slice(&test, &mut too_small),
+            Err(crate::Error::BufferTooSmall)
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "smallvec")]
+    pub fn test_smallvec() {
+        #[derive(Serialize)]
+        pub struct A<'a> {
+            a: i32,
+            b: &'a str,
+            c: Vec<i64>,
+        }
+
+        let test = &A {
+            a: 1,
+            b: "hello",
+            c: vec![2, 3],
+        };
+
+        let mut theirs = Vec::new();
+        bson::ser::to_document(&test)
+            .unwrap()
+            .to_writer(&mut theirs)
+            .unwrap();
+
+        let mut ours = smallvec::SmallVec::<[u8; 512]>::new();
+        crate::to_buf(&test, &mut ours, Default::default()).unwrap();
+        assert!(!ours.spilled());
+        assert_eq!(&ours[..], &theirs[..]);
+    }
+
+    #[test]
+    pub fn test_to_bytes() {
+        #[derive(Serialize)]
+        pub struct A<'a> {
+            a: i32,
+            b: &'a str,
+            c: Vec<i64>,
+        }
+
+        let test = &A {
+            a: 1,
+            b: "hello",
+            c: vec![2, 3],
+        };
+
+        let mut theirs = Vec::new();
+        bson::ser::to_document(&test)
+            .unwrap()
+            .to_writer(&mut theirs)
+            .unwrap();
+
+        let ours = crate::to_bytes(&test).unwrap();
+        assert_eq!(ours, theirs);
+    }
+
+    #[test]
+    pub fn test_document_too_large() {
+        /// Pretends `big` is `i32::MAX` bytes long whenever it's written, so the document holding
+        /// it is too large to encode without having to allocate gigabytes.
+        struct HoleBuf {
+            bytes: Vec<u8>,
+            big: &'static [u8],
+            hole: Option<usize>,
+        }
+
+        impl crate::BytesLikeBuf for HoleBuf {
+            fn put_u8(&mut self, v: u8) {
+                self.bytes.push(v);
+            }
+
+            fn put_i32_le(&mut self, v: i32) {
+                self.bytes.extend_from_slice(&v.to_le_bytes());
+            }
+
+            fn put_i64_le(&mut self, v: i64) {
+                self.bytes.extend_from_slice(&v.to_le_bytes());
+            }
+
+            fn put_f64_le(&mut self, v: f64) {
+                self.bytes.extend_from_slice(&v.to_le_bytes());
+            }
+
+            fn put_slice(&mut self, s: &[u8]) {
+                if s == self.big {
+                    self.hole = Some(self.bytes.len());
+                }
+
+                self.bytes.extend_from_slice(s);
+            }
+
+            fn len(&mut self) -> usize {
+                self.bytes.len() + self.hole.map_or(0, |_| i32::MAX as usize)
+            }
+
+            fn byte_mut(&mut self, at: usize) -> &mut u8 {
+                match self.hole {
+                    Some(hole) if at > hole => &mut self.bytes[at - i32::MAX as usize],
+                    _ => &mut self.bytes[at],
+                }
+            }
+
+            fn as_mut_slice(&mut self) -> Option<&mut [u8]> {
+                None
+            }
+        }
+
+        #[derive(Serialize)]
+        pub struct A<'a> {
+            #[serde(with = "serde_bytes")]
+            a: &'a [u8],
+        }
+
+        let mut buf = HoleBuf {
+            bytes: Vec::new(),
+            big: b"big",
+            hole: None,
+        };
+
+        let err =
+            crate::to_buf(&A { a: b"big" }, &mut buf, crate::ser::Options::default()).unwrap_err();
+        assert!(
+            matches!(err, crate::Error::DocumentTooLarge(len) if len > i32::MAX as usize),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    pub fn test_large_array() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        pub struct A {
+            a: Vec<i32>,
+        }
+
+        let test = &A {
+            a: (0..1100).collect(),
+        };
+
+        let mut ours = BytesMut::new();
+        to_string(&test, &mut ours).unwrap();
+
+        let mut theirs = BytesMut::new().writer();
+        bson::ser::to_document(&test)
+            .unwrap()
+            .to_writer(&mut theirs)
+            .unwrap();
+
+        assert_eq!(ours, theirs.into_inner());
+
+        let deserialized: A = crate::de::from_bytes(&ours).unwrap();
+        assert_eq!(&deserialized, test);
+    }
 }
@@ -0,0 +1,170 @@
+//! `bson!` and `doc!` for writing out [`crate::Value`]s and [`crate::Document`]s inline, in the
+//! style of `serde_json::json!`.
+
+/// Builds a [`Value`](crate::Value) from JSON-like syntax.
+///
+/// `null`, arrays and documents are written as in JSON, and anything else is an expression
+/// converted with [`From`]. Keys are any expression that converts into a `String`. There's no
+/// literal syntax for the other bson types, but [`Value::object_id`](crate::Value::object_id)
+/// and [`Value::datetime`](crate::Value::datetime) cover the common ones and any `Value` can be
+/// used as-is.
+///
+/// ```
+/// use serde_bson::{bson, Value};
+///
+/// let tags = vec!["a", "b"];
+/// let value = bson!({
+///     "_id": Value::object_id("5f1d7a4e9b1e8c3a2d4f6b8a").unwrap(),
+///     "name": "ferris",
+///     "age": 7,
+///     "tags": tags,
+///     "address": { "city": "London", "zip": null },
+///     "scores": [1.5, 2 + 3, { "nested": true }],
+/// });
+///
+/// assert_eq!(value.get_path("scores.1"), Some(&Value::Int32(5)));
+/// ```
+#[macro_export]
+macro_rules! bson {
+    // arrays, munching one element at a time into `[$($elems,)*]`
+    (@array [$($elems:expr,)*]) => {
+        vec![$($elems,)*]
+    };
+    (@array [$($elems:expr),*]) => {
+        vec![$($elems),*]
+    };
+    (@array [$($elems:expr,)*] null $($rest:tt)*) => {
+        $crate::bson!(@array [$($elems,)* $crate::bson!(null)] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] [$($array:tt)*] $($rest:tt)*) => {
+        $crate::bson!(@array [$($elems,)* $crate::bson!([$($array)*])] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] {$($map:tt)*} $($rest:tt)*) => {
+        $crate::bson!(@array [$($elems,)* $crate::bson!({$($map)*})] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] $next:expr, $($rest:tt)*) => {
+        $crate::bson!(@array [$($elems,)* $crate::bson!($next),] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] $last:expr) => {
+        $crate::bson!(@array [$($elems,)* $crate::bson!($last)])
+    };
+    (@array [$($elems:expr),*] , $($rest:tt)*) => {
+        $crate::bson!(@array [$($elems,)*] $($rest)*)
+    };
+
+    // documents, munching the tokens of each key until the colon then its value
+    (@object $object:ident () () ()) => {};
+    (@object $object:ident [$($key:tt)+] ($value:expr) , $($rest:tt)*) => {
+        let _ = $object.insert(($($key)+), $value);
+        $crate::bson!(@object $object () ($($rest)*) ($($rest)*));
+    };
+    (@object $object:ident [$($key:tt)+] ($value:expr)) => {
+        let _ = $object.insert(($($key)+), $value);
+    };
+    (@object $object:ident ($($key:tt)+) (: null $($rest:tt)*) $copy:tt) => {
+        $crate::bson!(@object $object [$($key)+] ($crate::bson!(null)) $($rest)*);
+    };
+    (@object $object:ident ($($key:tt)+) (: [$($array:tt)*] $($rest:tt)*) $copy:tt) => {
+        $crate::bson!(@object $object [$($key)+] ($crate::bson!([$($array)*])) $($rest)*);
+    };
+    (@object $object:ident ($($key:tt)+) (: {$($map:tt)*} $($rest:tt)*) $copy:tt) => {
+        $crate::bson!(@object $object [$($key)+] ($crate::bson!({$($map)*})) $($rest)*);
+    };
+    (@object $object:ident ($($key:tt)+) (: $value:expr , $($rest:tt)*) $copy:tt) => {
+        $crate::bson!(@object $object [$($key)+] ($crate::bson!($value)) , $($rest)*);
+    };
+    (@object $object:ident ($($key:tt)+) (: $value:expr) $copy:tt) => {
+        $crate::bson!(@object $object [$($key)+] ($crate::bson!($value)));
+    };
+    (@object $object:ident ($($key:tt)*) ($tt:tt $($rest:tt)*) $copy:tt) => {
+        $crate::bson!(@object $object ($($key)* $tt) ($($rest)*) ($($rest)*));
+    };
+
+    (null) => {
+        $crate::Value::Null
+    };
+    ([]) => {
+        $crate::Value::Array(vec![])
+    };
+    ([ $($tt:tt)+ ]) => {
+        $crate::Value::Array($crate::bson!(@array [] $($tt)+))
+    };
+    ({}) => {
+        $crate::Value::Document($crate::Document::new())
+    };
+    ({ $($tt:tt)+ }) => {
+        $crate::Value::Document($crate::doc!{ $($tt)+ })
+    };
+    ($other:expr) => {
+        $crate::Value::from($other)
+    };
+}
+
+/// Builds a [`Document`](crate::Document), see [`bson!`] for the syntax.
+///
+/// ```
+/// let document = serde_bson::doc! { "a": 1, "b": { "c": [true] } };
+/// assert_eq!(document.len(), 2);
+/// ```
+#[macro_export]
+macro_rules! doc {
+    () => {
+        $crate::Document::new()
+    };
+    ($($tt:tt)+) => {{
+        let mut object = $crate::Document::new();
+        $crate::bson!(@object object () ($($tt)+) ($($tt)+));
+        object
+    }};
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Document, Value};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn bson() {
+        let name = "ferris";
+        let key = "computed".to_string();
+
+        let value = bson!({
+            "_id": Value::object_id("000102030405060708090a0b").unwrap(),
+            "at": Value::datetime(UNIX_EPOCH + Duration::from_millis(1234)).unwrap(),
+            "name": name,
+            key: -1_i64,
+            "nothing": null,
+            "empty": {},
+            "list": [],
+            "nested": { "a": [1, null, [true], { "b": 1.5 }], "c": 1 + 2 },
+            "trailing": [1, 2,],
+        });
+
+        let expected = bson::doc! {
+            "_id": bson::oid::ObjectId::from_bytes([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]),
+            "at": bson::DateTime::from_millis(1234),
+            "name": "ferris",
+            "computed": -1_i64,
+            "nothing": null,
+            "empty": {},
+            "list": [],
+            "nested": { "a": [1, null, [true], { "b": 1.5 }], "c": 3 },
+            "trailing": [1, 2],
+        };
+
+        assert_eq!(
+            value.as_document().unwrap().to_vec().unwrap(),
+            bson::to_vec(&expected).unwrap()
+        );
+
+        assert_eq!(bson!(null), Value::Null);
+        assert_eq!(bson!([]), Value::Array(vec![]));
+        assert_eq!(bson!("a"), Value::String("a".to_string()));
+        assert_eq!(
+            bson!([1, "a"]),
+            Value::Array(vec![Value::Int32(1), Value::String("a".to_string())])
+        );
+        assert_eq!(doc! {}, Document::new());
+        assert_eq!(doc! { "a": 1, }, doc! { "a": 1 });
+    }
+}
@@ -0,0 +1,398 @@
+use crate::raw::{RawBytes, RAW_BSON_NEWTYPE};
+use alloc::string::String;
+use core::{
+    convert::{TryFrom, TryInto},
+    fmt,
+    str::FromStr,
+};
+use serde::{
+    de::{Error, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+#[cfg(feature = "std")]
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A 12 byte ObjectId (0x07), usually written as 24 hex characters.
+///
+/// New ids are made up of the seconds since the epoch, a value unique to the process and an
+/// incrementing counter, see [`ObjectIdGenerator`].
+///
+/// ObjectIds are written as the bson type by a binary serializer, and as their hex string when
+/// [`serde::Serializer::is_human_readable`] is true, so they come out as `"65a1..."` when
+/// transcoding to JSON. They can be read back from either form whatever the deserializer.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObjectId([u8; 12]);
+
+/// The error returned when parsing an ObjectId from a string that isn't 24 hex characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidObjectId;
+
+impl fmt::Display for InvalidObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("expected an ObjectId as 24 hex characters")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidObjectId {}
+
+impl ObjectId {
+    /// Generates a new id from the process-wide [`ObjectIdGenerator`], which is random unless
+    /// one was [installed](ObjectIdGenerator::install) before the first id was generated.
+    #[cfg(feature = "std")]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        GENERATOR.get_or_init(ObjectIdGenerator::new).generate()
+    }
+
+    pub fn from_bytes(bytes: [u8; 12]) -> Self {
+        Self(bytes)
+    }
+
+    /// The seconds since the epoch the id was generated at.
+    pub fn timestamp(self) -> u32 {
+        u32::from_be_bytes([self.0[0], self.0[1], self.0[2], self.0[3]])
+    }
+
+    pub fn bytes(self) -> [u8; 12] {
+        self.0
+    }
+
+    /// Lowercase hex, as ObjectIds are usually written.
+    pub fn to_hex(self) -> String {
+        crate::text::hex(&self.0)
+    }
+}
+
+#[cfg(feature = "std")]
+static GENERATOR: OnceLock<ObjectIdGenerator> = OnceLock::new();
+
+/// The counter takes up the low 3 bytes of an id.
+#[cfg(feature = "std")]
+const COUNTER_MASK: u64 = 0xff_ffff;
+
+/// Generates [`ObjectId`]s from the current time, a 5 byte process id and a 3 byte counter,
+/// each of which is random unless given.
+///
+/// Ids from the same generator always increase, even if the clock goes backwards or the counter
+/// wraps around within a second, in which case the timestamp is carried forward. Separate
+/// generators with their own process ids can be used to partition the id space between tenants,
+/// and a fixed process id, counter and [`ObjectIdGenerator::generate_at`] give deterministic ids
+/// for tests.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct ObjectIdGenerator {
+    process_id: [u8; 5],
+    /// The timestamp of the last id shifted above its counter, so both can be moved forward
+    /// together.
+    last: AtomicU64,
+}
+
+#[cfg(feature = "std")]
+impl ObjectIdGenerator {
+    /// A generator with a random process id and counter.
+    pub fn new() -> Self {
+        let random = random();
+
+        Self {
+            process_id: random.to_be_bytes()[..5].try_into().unwrap(),
+            last: AtomicU64::new(0),
+        }
+        .counter(random as u32)
+    }
+
+    pub fn process_id(mut self, process_id: [u8; 5]) -> Self {
+        self.process_id = process_id;
+        self
+    }
+
+    /// The counter the next id is generated with, only the low 3 bytes are used.
+    pub fn counter(mut self, counter: u32) -> Self {
+        // the generator moves on from the last id, so pretend one was generated at the start of
+        // the epoch just before this counter
+        *self.last.get_mut() = u64::from(counter).wrapping_sub(1) & COUNTER_MASK;
+        self
+    }
+
+    /// Makes this the generator used by [`ObjectId::new`], handing it back if an id has already
+    /// been generated or another generator was installed first.
+    pub fn install(self) -> Result<(), Self> {
+        GENERATOR.set(self)
+    }
+
+    pub fn generate(&self) -> ObjectId {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+
+        self.generate_at(u32::try_from(now).unwrap_or(u32::MAX))
+    }
+
+    /// Generates an id as if it were `timestamp` seconds since the epoch, though it'll still be
+    /// after any id previously generated.
+    pub fn generate_at(&self, timestamp: u32) -> ObjectId {
+        let mut next = 0;
+
+        self.last
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |last| {
+                let counter = (last + 1) & COUNTER_MASK;
+                next = ((u64::from(timestamp) << 24) | counter).max(last + 1);
+                Some(next)
+            })
+            .unwrap();
+
+        let mut id = [0; 12];
+        id[..4].copy_from_slice(&((next >> 24) as u32).to_be_bytes());
+        id[4..9].copy_from_slice(&self.process_id);
+        id[9..].copy_from_slice(&next.to_be_bytes()[5..]);
+        ObjectId(id)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for ObjectIdGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Random enough for a process id, without a dependency on a random number generator. Each
+/// `RandomState` is keyed differently, and the process id and time are mixed in for good measure.
+#[cfg(feature = "std")]
+fn random() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    std::process::id().hash(&mut hasher);
+    SystemTime::now().hash(&mut hasher);
+    hasher.finish()
+}
+
+impl From<[u8; 12]> for ObjectId {
+    fn from(bytes: [u8; 12]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<ObjectId> for [u8; 12] {
+    fn from(id: ObjectId) -> Self {
+        id.0
+    }
+}
+
+impl FromStr for ObjectId {
+    type Err = InvalidObjectId;
+
+    /// Parses 24 hex characters of either case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        crate::text::from_hex(s)
+            .and_then(|bytes| <[u8; 12]>::try_from(bytes).ok())
+            .map(Self)
+            .ok_or(InvalidObjectId)
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl fmt::Debug for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ObjectId").field(&self.to_hex()).finish()
+    }
+}
+
+impl Serialize for ObjectId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_hex())
+        } else {
+            serializer.serialize_newtype_variant(RAW_BSON_NEWTYPE, 0x07, "", &RawBytes(&self.0))
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ObjectId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ObjectIdVisitor;
+
+        impl<'de> Visitor<'de> for ObjectIdVisitor {
+            type Value = ObjectId;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an ObjectId as 12 bytes or 24 hex characters")
+            }
+
+            fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                v.try_into()
+                    .map(ObjectId)
+                    .map_err(|_| E::invalid_length(v.len(), &self))
+            }
+
+            fn visit_str<E: Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse()
+                    .map_err(|_| E::invalid_value(serde::de::Unexpected::Str(v), &self))
+            }
+        }
+
+        // our own deserializer hands ObjectIds over as bytes either way, which the visitor
+        // accepts alongside the string it'll get from something like JSON
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(ObjectIdVisitor)
+        } else {
+            deserializer.deserialize_bytes(ObjectIdVisitor)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ObjectId, ObjectIdGenerator};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct A {
+        id: ObjectId,
+    }
+
+    const HEX: &str = "65a1b2c3d4e5f60718293a4b";
+
+    #[test]
+    fn parse() {
+        let id: ObjectId = HEX.parse().unwrap();
+        assert_eq!(id.to_string(), HEX);
+        assert_eq!(format!("{:?}", id), format!("ObjectId(\"{}\")", HEX));
+        assert_eq!(HEX.to_uppercase().parse(), Ok(id));
+
+        for invalid in [
+            "",
+            "65a1",
+            "65a1b2c3d4e5f60718293a4bcc",
+            "65a1b2c3d4e5f60718293a4g",
+        ]
+        .iter()
+        {
+            assert!(invalid.parse::<ObjectId>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn representation() {
+        let val = A {
+            id: HEX.parse().unwrap(),
+        };
+
+        // the bson type in binary mode
+        let binary = crate::to_bytes(&val).unwrap();
+        assert_eq!(
+            bson::Document::from_reader(&binary[..]).unwrap(),
+            bson::doc! { "id": bson::oid::ObjectId::parse_str(HEX).unwrap() }
+        );
+        assert_eq!(crate::de::from_bytes::<A>(&binary).unwrap(), val);
+
+        let human_readable = crate::de::Options::new().human_readable(true);
+        assert_eq!(
+            crate::de::from_bytes_with_options::<A>(&binary, human_readable).unwrap(),
+            val
+        );
+
+        // and a hex string in human readable mode
+        let options = crate::ser::Options::new().human_readable(true);
+        let string = crate::to_bytes_with_options(&val, options).unwrap();
+        assert_eq!(
+            bson::Document::from_reader(&string[..]).unwrap(),
+            bson::doc! { "id": HEX }
+        );
+        assert_eq!(
+            crate::de::from_bytes_with_options::<A>(&string, human_readable).unwrap(),
+            val
+        );
+
+        let json = serde_json::to_string(&val).unwrap();
+        assert_eq!(json, format!("{{\"id\":\"{}\"}}", HEX));
+        assert_eq!(serde_json::from_str::<A>(&json).unwrap(), val);
+    }
+
+    #[test]
+    fn generate() {
+        let generator = ObjectIdGenerator::new()
+            .process_id([1, 2, 3, 4, 5])
+            .counter(0x0a0b0c);
+
+        let id = generator.generate_at(0x5f5e_1000);
+        assert_eq!(id.to_hex(), "5f5e100001020304050a0b0c");
+        assert_eq!(id.timestamp(), 0x5f5e_1000);
+        assert_eq!(
+            generator.generate_at(0x5f5e_1000).to_hex(),
+            "5f5e100001020304050a0b0d"
+        );
+
+        // the clock going backwards doesn't take the ids with it
+        assert_eq!(
+            generator.generate_at(1).to_hex(),
+            "5f5e100001020304050a0b0e"
+        );
+
+        // and neither does the counter wrapping around
+        let generator = ObjectIdGenerator::new()
+            .process_id([0; 5])
+            .counter(0xff_ffff);
+        assert_eq!(
+            generator.generate_at(7).to_hex(),
+            "000000070000000000ffffff"
+        );
+        assert_eq!(
+            generator.generate_at(7).to_hex(),
+            "000000080000000000000000"
+        );
+        assert_eq!(
+            generator.generate_at(9).to_hex(),
+            "000000090000000000000001"
+        );
+    }
+
+    #[test]
+    fn generate_across_threads() {
+        let generator = ObjectIdGenerator::new();
+
+        let mut ids = std::thread::scope(|scope| {
+            let threads = (0..4)
+                .map(|_| {
+                    scope.spawn(|| (0..1000).map(|_| generator.generate()).collect::<Vec<_>>())
+                })
+                .collect::<Vec<_>>();
+
+            threads
+                .into_iter()
+                .flat_map(|thread| {
+                    let ids = thread.join().unwrap();
+                    assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+                    ids
+                })
+                .collect::<Vec<_>>()
+        });
+
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 4000);
+    }
+
+    #[test]
+    fn install() {
+        let first = ObjectId::new();
+        let second = ObjectId::new();
+        assert!(first < second);
+        assert_eq!(first.bytes()[4..9], second.bytes()[4..9]);
+
+        // too late, an id has already been generated
+        assert!(ObjectIdGenerator::new().install().is_err());
+    }
+}
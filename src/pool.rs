@@ -0,0 +1,144 @@
+use crate::{ser, to_buf, Error};
+use bytes::BytesMut;
+use serde::Serialize;
+use std::{
+    ops::Deref,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+/// A pool of recycled output buffers, for producers serialising lots of documents that'd
+/// otherwise allocate a fresh `BytesMut` for each one.
+///
+/// Buffers are checked out by [`SerializerPool::serialize`] and returned to the pool when the
+/// [`PooledBuf`] is dropped. New buffers are allocated with the size of the last document
+/// serialised through the pool, so they rarely need to grow.
+#[derive(Debug)]
+pub struct SerializerPool {
+    buffers: Mutex<Vec<BytesMut>>,
+    max_pooled: usize,
+    size_hint: AtomicUsize,
+}
+
+impl Default for SerializerPool {
+    fn default() -> Self {
+        Self::new(16)
+    }
+}
+
+impl SerializerPool {
+    /// Creates a pool that'll hold onto at most `max_pooled` idle buffers.
+    pub fn new(max_pooled: usize) -> Self {
+        Self {
+            buffers: Mutex::new(Vec::new()),
+            max_pooled,
+            size_hint: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn serialize<T: Serialize>(&self, val: &T) -> Result<PooledBuf<'_>, Error> {
+        self.serialize_with_options(val, ser::Options::default())
+    }
+
+    pub fn serialize_with_options<T: Serialize>(
+        &self,
+        val: &T,
+        options: ser::Options,
+    ) -> Result<PooledBuf<'_>, Error> {
+        let mut buf = PooledBuf {
+            buf: self.checkout(),
+            pool: self,
+        };
+
+        to_buf(val, &mut buf.buf, options)?;
+        self.size_hint.store(buf.buf.len(), Ordering::Relaxed);
+
+        Ok(buf)
+    }
+
+    /// The number of idle buffers currently held by the pool.
+    pub fn available(&self) -> usize {
+        self.buffers.lock().unwrap().len()
+    }
+
+    fn checkout(&self) -> BytesMut {
+        self.buffers
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(|| BytesMut::with_capacity(self.size_hint.load(Ordering::Relaxed)))
+    }
+
+    fn checkin(&self, mut buf: BytesMut) {
+        buf.clear();
+
+        let mut buffers = self.buffers.lock().unwrap();
+        if buffers.len() < self.max_pooled {
+            buffers.push(buf);
+        }
+    }
+}
+
+/// A serialised document, the underlying buffer is returned to the [`SerializerPool`] it came
+/// from on drop.
+#[derive(Debug)]
+pub struct PooledBuf<'a> {
+    buf: BytesMut,
+    pool: &'a SerializerPool,
+}
+
+impl Deref for PooledBuf<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.buf
+    }
+}
+
+impl AsRef<[u8]> for PooledBuf<'_> {
+    fn as_ref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl Drop for PooledBuf<'_> {
+    fn drop(&mut self) {
+        self.pool.checkin(std::mem::take(&mut self.buf));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SerializerPool;
+    use serde::Serialize;
+
+    #[test]
+    fn reuses_buffers() {
+        #[derive(Serialize)]
+        struct A {
+            a: &'static str,
+        }
+
+        let pool = SerializerPool::new(1);
+
+        let first = pool.serialize(&A { a: "hello" }).unwrap();
+        let ptr = first.as_ptr();
+        assert_eq!(&*first, &crate::to_bytes(&A { a: "hello" }).unwrap()[..]);
+
+        // checked out while `first` is alive so this needs a new buffer
+        let second = pool.serialize(&A { a: "world" }).unwrap();
+        assert_ne!(second.as_ptr(), ptr);
+
+        drop(first);
+        drop(second);
+
+        // only room for one idle buffer
+        assert_eq!(pool.available(), 1);
+
+        let third = pool.serialize(&A { a: "again" }).unwrap();
+        assert_eq!(pool.available(), 0);
+        assert_eq!(&*third, &crate::to_bytes(&A { a: "again" }).unwrap()[..]);
+    }
+}
@@ -0,0 +1,331 @@
+//! Property tests, generating [`Value`]s, [`Document`]s and serde types with proptest and checking
+//! they round trip through the serializer and deserializer and encode to the same bytes as the
+//! `bson` crate.
+//!
+//! Failing inputs are shrunk down to a minimal counterexample, which proptest saves under
+//! `proptest-regressions/` so it's run again first next time. `PROPTEST_CASES` can be set to run
+//! more cases than the default 256.
+
+use crate::{decimal128::Decimal128, CodeWithScope, DbPointer, Document, Regex, Value};
+use proptest::{
+    collection::vec,
+    option,
+    prelude::*,
+    sample::select,
+    strategy::{BoxedStrategy, Union},
+    test_runner::TestCaseError,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const MAX_DEPTH: u32 = 4;
+
+fn string() -> impl Strategy<Value = String> {
+    const CHARS: &[char] = &['a', 'z', '0', '.', '$', ' ', 'é', 'ß', '中', '🦀', '\u{7f}'];
+
+    vec(select(CHARS), 0..8).prop_map(|chars| chars.into_iter().collect())
+}
+
+fn double() -> impl Strategy<Value = f64> {
+    prop_oneof![
+        1 => Just(f64::NAN),
+        1 => Just(f64::INFINITY),
+        1 => Just(f64::NEG_INFINITY),
+        1 => Just(-0.0),
+        1 => any::<u64>().prop_map(f64::from_bits),
+        3 => -1e9..1e9,
+    ]
+}
+
+/// Which element types to generate.
+#[derive(Clone, Copy, Debug)]
+enum Types {
+    All,
+    /// The types read exactly into a `bson::Document` through serde with
+    /// [`BsonTypes::ExtendedJson`](crate::de::BsonTypes::ExtendedJson). Code with scope and db
+    /// pointers have no Extended JSON wrapper there, and non-canonical decimal128s don't survive
+    /// being a string.
+    ExtendedJson,
+    /// The types with a serde equivalent, which the `bson` crate serializes as-is rather than
+    /// through an Extended JSON wrapper.
+    Serde,
+}
+
+impl Types {
+    /// Whether values of `element_type` are generated.
+    fn includes(self, element_type: u8) -> bool {
+        match self {
+            Self::All => true,
+            Self::ExtendedJson => !matches!(element_type, 0x0C | 0x0F | 0x13),
+            Self::Serde => matches!(element_type, 0x01..=0x04 | 0x08 | 0x0A | 0x10 | 0x12),
+        }
+    }
+}
+
+fn document(value: impl Strategy<Value = Value>) -> impl Strategy<Value = Document> {
+    vec((string(), value), 0..6).prop_map(|entries| {
+        let mut document = Document::new();
+        for (key, value) in entries {
+            document.insert(key, value);
+        }
+        document
+    })
+}
+
+fn value(types: Types) -> impl Strategy<Value = Value> {
+    let scalars: Vec<(u8, BoxedStrategy<Value>)> = vec![
+        (0x01, double().prop_map(Value::Double).boxed()),
+        (0x02, string().prop_map(Value::String).boxed()),
+        (
+            0x05,
+            (
+                // the old binary subtype has its own length prefix inside, which the bson crate
+                // strips and re-adds, so random bytes under it won't come back the same
+                select(&[0x00, 0x01, 0x03, 0x04, 0x05, 0x80][..]),
+                vec(any::<u8>(), 0..16),
+            )
+                .prop_map(|(subtype, bytes)| Value::Binary { subtype, bytes })
+                .boxed(),
+        ),
+        (0x06, Just(Value::Undefined).boxed()),
+        (0x07, any::<[u8; 12]>().prop_map(Value::ObjectId).boxed()),
+        (0x08, any::<bool>().prop_map(Value::Boolean).boxed()),
+        (0x09, any::<i64>().prop_map(Value::DateTime).boxed()),
+        (0x0A, Just(Value::Null).boxed()),
+        (
+            0x0B,
+            // the bson crate sorts the options, so only generate them already sorted
+            (string(), select(&["", "i", "im", "imsux"][..]))
+                .prop_map(|(pattern, options)| {
+                    Value::Regex(Regex {
+                        pattern,
+                        options: options.to_string(),
+                    })
+                })
+                .boxed(),
+        ),
+        (
+            0x0C,
+            (string(), any::<[u8; 12]>())
+                .prop_map(|(namespace, id)| Value::DbPointer(DbPointer { namespace, id }))
+                .boxed(),
+        ),
+        (0x0D, string().prop_map(Value::Code).boxed()),
+        (0x0E, string().prop_map(Value::Symbol).boxed()),
+        (0x10, any::<i32>().prop_map(Value::Int32).boxed()),
+        (0x11, any::<u64>().prop_map(Value::Timestamp).boxed()),
+        (0x12, any::<i64>().prop_map(Value::Int64).boxed()),
+        (
+            0x13,
+            any::<[u8; 16]>()
+                .prop_map(|bytes| Value::Decimal128(Decimal128::from_bytes(bytes)))
+                .boxed(),
+        ),
+        (0x7F, Just(Value::MaxKey).boxed()),
+        (0xFF, Just(Value::MinKey).boxed()),
+    ];
+
+    let scalars = Union::new(
+        scalars
+            .into_iter()
+            .filter(|(element_type, _)| types.includes(*element_type))
+            .map(|(_, strategy)| strategy),
+    );
+
+    scalars.prop_recursive(MAX_DEPTH, 64, 6, move |value| {
+        let mut nested = vec![
+            document(value.clone()).prop_map(Value::Document).boxed(),
+            vec(value.clone(), 0..4).prop_map(Value::Array).boxed(),
+        ];
+
+        if types.includes(0x0F) {
+            nested.push(
+                (string(), document(value))
+                    .prop_map(|(code, scope)| Value::CodeWithScope(CodeWithScope { code, scope }))
+                    .boxed(),
+            );
+        }
+
+        Union::new(nested)
+    })
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+enum Shape {
+    Unit,
+    Newtype(i64),
+    Tuple(i32, String, bool),
+    Struct { a: i32, b: Vec<String> },
+    Nested(Box<Shape>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Unit;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Newtype(String);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Pair(i32, f64);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Record {
+    int32: i32,
+    int64: i64,
+    double: f64,
+    string: String,
+    boolean: bool,
+    optional: Option<i32>,
+    list: Vec<i64>,
+    tuple: (i32, String),
+    map: BTreeMap<String, Shape>,
+    shapes: Vec<Shape>,
+    unit: Unit,
+    newtype: Newtype,
+    pair: Pair,
+    nested: Option<Box<Record>>,
+}
+
+fn shape() -> impl Strategy<Value = Shape> {
+    prop_oneof![
+        Just(Shape::Unit),
+        any::<i64>().prop_map(Shape::Newtype),
+        (any::<i32>(), string(), any::<bool>()).prop_map(|(a, b, c)| Shape::Tuple(a, b, c)),
+        (any::<i32>(), vec(string(), 0..3)).prop_map(|(a, b)| Shape::Struct { a, b }),
+    ]
+    .prop_recursive(MAX_DEPTH, 8, 1, |shape| {
+        shape.prop_map(|shape| Shape::Nested(Box::new(shape)))
+    })
+}
+
+fn record() -> impl Strategy<Value = Record> {
+    let leaf = record_with(Just(None));
+
+    leaf.prop_recursive(MAX_DEPTH, 8, 1, |record| {
+        record_with(option::weighted(0.3, record.prop_map(Box::new)))
+    })
+}
+
+fn record_with(
+    nested: impl Strategy<Value = Option<Box<Record>>>,
+) -> impl Strategy<Value = Record> {
+    let scalars = (
+        any::<i32>(),
+        any::<i64>(),
+        double(),
+        string(),
+        any::<bool>(),
+        any::<Option<i32>>(),
+    );
+    let collections = (
+        vec(any::<i64>(), 0..4),
+        (any::<i32>(), string()),
+        proptest::collection::btree_map(string(), shape(), 0..3),
+        vec(shape(), 0..3),
+    );
+
+    (
+        scalars,
+        collections,
+        string(),
+        (any::<i32>(), double()),
+        nested,
+    )
+        .prop_map(
+            |(
+                (int32, int64, double, string, boolean, optional),
+                (list, tuple, map, shapes),
+                newtype,
+                (a, b),
+                nested,
+            )| Record {
+                int32,
+                int64,
+                double,
+                string,
+                boolean,
+                optional,
+                list,
+                tuple,
+                map,
+                shapes,
+                unit: Unit,
+                newtype: Newtype(newtype),
+                pair: Pair(a, b),
+                nested,
+            },
+        )
+}
+
+fn bson_bytes(document: &bson::Document) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    document.to_writer(&mut bytes).unwrap();
+    bytes
+}
+
+fn ensure_eq(ours: &[u8], theirs: &[u8], what: &str) -> Result<(), TestCaseError> {
+    prop_assert!(
+        ours == theirs,
+        "{} differs\nours:   {}\ntheirs: {}",
+        what,
+        crate::text::hex(ours),
+        crate::text::hex(theirs)
+    );
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn document_round_trips(document in document(value(Types::All))) {
+        let bytes = document.to_vec()?;
+        crate::de::validate(&bytes)?;
+
+        let read = Document::from_bytes(&bytes)?;
+        ensure_eq(&read.to_vec()?, &bytes, "rewritten document")?;
+
+        // `Value`'s equality compares doubles by their bits, so NaNs are fine here
+        prop_assert_eq!(read, document);
+    }
+
+    #[test]
+    fn document_matches_bson(document in document(value(Types::All))) {
+        let ours = document.to_vec()?;
+        let theirs = bson::Document::from_reader(&ours[..])?;
+        ensure_eq(&ours, &bson_bytes(&theirs), "document")?;
+    }
+
+    #[test]
+    fn document_through_serde_matches_bson(document in document(value(Types::ExtendedJson))) {
+        let options = crate::de::Options::new()
+            .bson_types(crate::de::BsonTypes::ExtendedJson)
+            .undefined_as_null(false);
+
+        let bytes = document.to_vec()?;
+        let theirs: bson::Document = crate::de::from_bytes_with_options(&bytes, options)?;
+        ensure_eq(&bytes, &bson_bytes(&theirs), "read into bson::Document")?;
+    }
+
+    #[test]
+    fn bson_document_through_serde_matches(document in document(value(Types::Serde))) {
+        let bytes = document.to_vec()?;
+        let theirs = bson::Document::from_reader(&bytes[..])?;
+
+        let mut ours = Vec::new();
+        crate::to_vec(&theirs, &mut ours)?;
+        ensure_eq(&ours, &bytes, "bson::Document written")?;
+    }
+
+    #[test]
+    fn serde_matches_bson(record in record()) {
+        let mut ours = Vec::new();
+        crate::to_vec(&record, &mut ours)?;
+        ensure_eq(&ours, &bson::to_vec(&record)?, "serialized record")?;
+
+        // records hold doubles so can't be compared directly, but writing out what was read has
+        // to give the same bytes
+        let read: Record = crate::de::from_bytes(&ours)?;
+        let mut rewritten = Vec::new();
+        crate::to_vec(&read, &mut rewritten)?;
+        ensure_eq(&rewritten, &ours, "rewritten record")?;
+    }
+}
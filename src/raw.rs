@@ -0,0 +1,1183 @@
+use crate::{byte::BytesLikeBuf, scan::Elements, CodeWithScope, Decimal128, Error, Regex};
+use alloc::{
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{
+    convert::{TryFrom, TryInto},
+    fmt,
+    ops::Range,
+};
+use serde::{
+    ser::{Impossible, SerializeMap, SerializeTuple},
+    Deserialize, Serialize, Serializer,
+};
+
+/// Magic newtype variant name used to smuggle pre-encoded values through serde to our
+/// serializer, with the element type passed as the variant index.
+pub(crate) const RAW_BSON_NEWTYPE: &str = "$__serde_bson_private_raw";
+
+/// A pre-encoded bson value which is copied verbatim into the output when serialised, rather
+/// than being decoded and serialised again. `bytes` is the encoded value only, without the
+/// element type or key.
+///
+/// Only this crate's serializer understands raw values, and no validation is done on the bytes
+/// so it's up to the caller to make sure they're well-formed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawBson<'a> {
+    element_type: u8,
+    bytes: &'a [u8],
+}
+
+impl<'a> RawBson<'a> {
+    pub fn new(element_type: u8, bytes: &'a [u8]) -> Self {
+        Self {
+            element_type,
+            bytes,
+        }
+    }
+
+    pub fn element_type(&self) -> u8 {
+        self.element_type
+    }
+
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// The value as an array, if it is one.
+    pub fn as_array(&self) -> Option<RawArray<'a>> {
+        (self.element_type == 0x04).then_some(RawArray(self.bytes))
+    }
+}
+
+impl Serialize for RawBson<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_variant(
+            RAW_BSON_NEWTYPE,
+            u32::from(self.element_type),
+            "",
+            &RawBytes(self.bytes),
+        )
+    }
+}
+
+/// A pre-encoded bson document, including its length prefix and terminator, which is copied
+/// verbatim into the output when serialised. See [`RawBson`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawDocument<'a>(pub &'a [u8]);
+
+impl Serialize for RawDocument<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        RawBson::new(0x03, self.0).serialize(serializer)
+    }
+}
+
+/// A borrowed, encoded array, including its length prefix and terminator. Elements are found by
+/// stepping over the ones before them without decoding anything, so a few can be picked out of
+/// a large array cheaply.
+///
+/// Arrays are read by position: the keys of the elements aren't checked against their indexes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawArray<'a>(pub &'a [u8]);
+
+impl<'a> RawArray<'a> {
+    /// The number of elements, counted by stepping over each of them.
+    pub fn len(&self) -> Result<usize, crate::de::Error> {
+        self.iter()
+            .try_fold(0, |count, element| element.map(|_| count + 1))
+    }
+
+    pub fn is_empty(&self) -> Result<bool, crate::de::Error> {
+        Ok(self.iter().next().transpose()?.is_none())
+    }
+
+    /// The element at `index`, if the array's that long.
+    pub fn get(&self, index: usize) -> Result<Option<RawBson<'a>>, crate::de::Error> {
+        self.iter().nth(index).transpose()
+    }
+
+    pub fn iter(&self) -> RawArrayIter<'a> {
+        match Elements::new(self.0, 0) {
+            Ok(elements) => RawArrayIter {
+                elements: Some(elements),
+                error: None,
+            },
+            Err(e) => RawArrayIter {
+                elements: None,
+                error: Some(e),
+            },
+        }
+    }
+
+    /// Deserialises each element into a `T` as it's reached.
+    pub fn iter_as<T: Deserialize<'a>>(
+        &self,
+    ) -> impl Iterator<Item = Result<T, crate::de::Error>> + 'a {
+        self.iter()
+            .map(|element| element.and_then(crate::de::from_raw))
+    }
+}
+
+impl<'a> IntoIterator for RawArray<'a> {
+    type Item = Result<RawBson<'a>, crate::de::Error>;
+    type IntoIter = RawArrayIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl Serialize for RawArray<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        RawBson::new(0x04, self.0).serialize(serializer)
+    }
+}
+
+/// The elements of a [`RawArray`], stopping after the first error.
+pub struct RawArrayIter<'a> {
+    elements: Option<Elements<'a>>,
+    error: Option<crate::de::Error>,
+}
+
+impl<'a> Iterator for RawArrayIter<'a> {
+    type Item = Result<RawBson<'a>, crate::de::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
+
+        self.elements
+            .as_mut()?
+            .next()
+            .map(|element| element.map(|element| element.value))
+    }
+}
+
+/// An owned, encoded document which fields can be added to, replaced in or removed from without
+/// decoding it. Only the edited element is rewritten, along with the length prefixes of the
+/// documents containing it, so stamping a few fields onto a large pass-through document is
+/// cheap.
+///
+/// Fields are addressed by a path of dot separated keys and array indexes, in the same format as
+/// [`crate::de::project`].
+///
+/// Documents can also be built from scratch with the `append_*` methods, which write each field
+/// straight onto the end of the encoded document:
+///
+/// ```
+/// use serde_bson::RawDocumentBuf;
+///
+/// let mut command = RawDocumentBuf::new();
+/// command
+///     .append_str("find", "users")?
+///     .append_i32("limit", 10)?
+///     .append_str("$db", "app")?;
+/// # Ok::<(), serde_bson::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawDocumentBuf {
+    data: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum RawEditError {
+    Read(crate::de::Error),
+    Write(Error),
+    MissingParent(String),
+    ArrayIndex(String),
+}
+
+impl fmt::Display for RawEditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read(e) => e.fmt(f),
+            Self::Write(e) => e.fmt(f),
+            Self::MissingParent(path) => {
+                write!(f, "there's no document or array at {:?} to edit", path)
+            }
+            Self::ArrayIndex(path) => {
+                write!(f, "array index {:?} would leave a gap in the array", path)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RawEditError {}
+
+impl From<crate::de::Error> for RawEditError {
+    fn from(e: crate::de::Error) -> Self {
+        Self::Read(e)
+    }
+}
+
+impl From<Error> for RawEditError {
+    fn from(e: Error) -> Self {
+        Self::Write(e)
+    }
+}
+
+/// Where the element at a path is, or would be, found.
+struct Location {
+    /// The start of each document containing the element, outermost first.
+    ancestors: Vec<usize>,
+    parent_is_array: bool,
+    /// The number of elements in the parent, if the element isn't already in it.
+    parent_elements: usize,
+    /// The byte range of the element, type and key included.
+    element: Option<Range<usize>>,
+}
+
+impl RawDocumentBuf {
+    /// An empty document.
+    pub fn new() -> Self {
+        Self {
+            data: vec![5, 0, 0, 0, 0],
+        }
+    }
+
+    /// Takes ownership of an encoded document, which is validated up front so edits can't
+    /// trip over it later.
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, crate::de::Error> {
+        crate::de::validate(&data)?;
+        Ok(Self { data })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn as_document(&self) -> RawDocument<'_> {
+        RawDocument(&self.data)
+    }
+
+    /// The value at `path`, if there is one.
+    pub fn get(&self, path: &str) -> Option<RawBson<'_>> {
+        crate::scan::find(&self.data, path.split('.'))
+            .ok()
+            .flatten()
+    }
+
+    /// Sets the field at `path` to `value`, replacing it in place if it already exists or adding
+    /// it to the end of its document if not. The document or array it belongs in must already
+    /// exist, and new array elements can only be added to the end of the array.
+    pub fn insert<T: Serialize + ?Sized>(
+        &mut self,
+        path: &str,
+        value: &T,
+    ) -> Result<(), RawEditError> {
+        let location = self.locate(path)?;
+        let key = path.rsplit('.').next().unwrap();
+
+        let range = match location.element {
+            Some(range) => range,
+            None if location.parent_is_array
+                && key.parse::<usize>().ok() != Some(location.parent_elements) =>
+            {
+                return Err(RawEditError::ArrayIndex(path.to_string()))
+            }
+            None => {
+                // new elements go just before the terminator of their document
+                let parent = *location.ancestors.last().unwrap();
+                let end = parent + document_length(&self.data, parent) - 1;
+                end..end
+            }
+        };
+
+        let element = encode_element(key, value)?;
+        self.splice(&location.ancestors, range, &element)
+    }
+
+    /// Removes the field at `path`, returning whether there was one. Only the last element of an
+    /// array can be removed, as the indexes of the elements after it would need rewriting.
+    pub fn remove(&mut self, path: &str) -> Result<bool, RawEditError> {
+        let location = match self.locate(path) {
+            Ok(location) => location,
+            Err(RawEditError::MissingParent(_)) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+
+        let Some(range) = location.element else {
+            return Ok(false);
+        };
+
+        if location.parent_is_array {
+            let parent = *location.ancestors.last().unwrap();
+            if range.end != parent + document_length(&self.data, parent) - 1 {
+                return Err(RawEditError::ArrayIndex(path.to_string()));
+            }
+        }
+
+        self.splice(&location.ancestors, range, &[])?;
+        Ok(true)
+    }
+
+    /// Adds `value` to the end of the document under `key`, serialised exactly as it would be
+    /// as a field of a struct. Unlike [`RawDocumentBuf::insert`] the key is taken as-is rather
+    /// than as a path, and isn't checked for an existing field of the same name.
+    pub fn append_serialize<T: Serialize + ?Sized>(
+        &mut self,
+        key: &str,
+        value: &T,
+    ) -> Result<&mut Self, Error> {
+        let element = encode_element(key, value)?;
+
+        let end = self.data.len() - 1;
+        self.data.truncate(end);
+        self.data.extend_from_slice(&element);
+
+        if let Err(e) = self.terminate() {
+            self.data.truncate(end);
+            self.data.push(0);
+            return Err(e);
+        }
+
+        Ok(self)
+    }
+
+    pub fn append_f64(&mut self, key: &str, value: f64) -> Result<&mut Self, Error> {
+        self.append(key, 0x01, |data| {
+            data.extend_from_slice(&value.to_le_bytes());
+            Ok(())
+        })
+    }
+
+    pub fn append_str(&mut self, key: &str, value: &str) -> Result<&mut Self, Error> {
+        self.append(key, 0x02, |data| write_string(data, value))
+    }
+
+    /// Adds an already encoded document, which isn't validated.
+    pub fn append_document(
+        &mut self,
+        key: &str,
+        value: RawDocument<'_>,
+    ) -> Result<&mut Self, Error> {
+        self.append(key, 0x03, |data| {
+            data.extend_from_slice(value.0);
+            Ok(())
+        })
+    }
+
+    /// Adds an already encoded array, which isn't validated.
+    pub fn append_array(&mut self, key: &str, value: RawArray<'_>) -> Result<&mut Self, Error> {
+        self.append(key, 0x04, |data| {
+            data.extend_from_slice(value.0);
+            Ok(())
+        })
+    }
+
+    pub fn append_binary(
+        &mut self,
+        key: &str,
+        subtype: u8,
+        value: &[u8],
+    ) -> Result<&mut Self, Error> {
+        let length = i32::try_from(value.len()).map_err(|_| Error::BytesTooLarge(value.len()))?;
+
+        self.append(key, 0x05, |data| {
+            data.extend_from_slice(&length.to_le_bytes());
+            data.push(subtype);
+            data.extend_from_slice(value);
+            Ok(())
+        })
+    }
+
+    pub fn append_undefined(&mut self, key: &str) -> Result<&mut Self, Error> {
+        self.append(key, 0x06, |_| Ok(()))
+    }
+
+    pub fn append_object_id(&mut self, key: &str, value: [u8; 12]) -> Result<&mut Self, Error> {
+        self.append(key, 0x07, |data| {
+            data.extend_from_slice(&value);
+            Ok(())
+        })
+    }
+
+    pub fn append_bool(&mut self, key: &str, value: bool) -> Result<&mut Self, Error> {
+        self.append(key, 0x08, |data| {
+            data.push(u8::from(value));
+            Ok(())
+        })
+    }
+
+    /// Adds a datetime of `millis` milliseconds since the epoch.
+    pub fn append_datetime(&mut self, key: &str, millis: i64) -> Result<&mut Self, Error> {
+        self.append(key, 0x09, |data| {
+            data.extend_from_slice(&millis.to_le_bytes());
+            Ok(())
+        })
+    }
+
+    pub fn append_null(&mut self, key: &str) -> Result<&mut Self, Error> {
+        self.append(key, 0x0A, |_| Ok(()))
+    }
+
+    pub fn append_regex(&mut self, key: &str, value: &Regex) -> Result<&mut Self, Error> {
+        // both halves are c-strings, so neither can contain a null
+        if value.pattern.contains('\0') || value.options.contains('\0') {
+            return Err(Error::Serde(
+                "regex pattern and options cannot contain null bytes".into(),
+            ));
+        }
+
+        self.append(key, 0x0B, |data| {
+            write_cstring(data, &value.pattern);
+            write_cstring(data, &value.options);
+            Ok(())
+        })
+    }
+
+    pub fn append_code(&mut self, key: &str, value: &str) -> Result<&mut Self, Error> {
+        self.append(key, 0x0D, |data| write_string(data, value))
+    }
+
+    pub fn append_symbol(&mut self, key: &str, value: &str) -> Result<&mut Self, Error> {
+        self.append(key, 0x0E, |data| write_string(data, value))
+    }
+
+    /// Adds code with an already encoded scope document, which isn't validated.
+    pub fn append_code_with_scope(
+        &mut self,
+        key: &str,
+        value: &CodeWithScope<RawDocument<'_>>,
+    ) -> Result<&mut Self, Error> {
+        self.append(key, 0x0F, |data| {
+            let start = data.len();
+            data.extend_from_slice(&[0; 4]);
+            write_string(data, &value.code)?;
+            data.extend_from_slice(value.scope.0);
+
+            let length = data.len() - start;
+            let length = i32::try_from(length).map_err(|_| Error::DocumentTooLarge(length))?;
+            data[start..start + 4].copy_from_slice(&length.to_le_bytes());
+            Ok(())
+        })
+    }
+
+    pub fn append_i32(&mut self, key: &str, value: i32) -> Result<&mut Self, Error> {
+        self.append(key, 0x10, |data| {
+            data.extend_from_slice(&value.to_le_bytes());
+            Ok(())
+        })
+    }
+
+    pub fn append_timestamp(&mut self, key: &str, value: u64) -> Result<&mut Self, Error> {
+        self.append(key, 0x11, |data| {
+            data.extend_from_slice(&value.to_le_bytes());
+            Ok(())
+        })
+    }
+
+    pub fn append_i64(&mut self, key: &str, value: i64) -> Result<&mut Self, Error> {
+        self.append(key, 0x12, |data| {
+            data.extend_from_slice(&value.to_le_bytes());
+            Ok(())
+        })
+    }
+
+    pub fn append_decimal128(&mut self, key: &str, value: Decimal128) -> Result<&mut Self, Error> {
+        self.append(key, 0x13, |data| {
+            data.extend_from_slice(&value.bytes());
+            Ok(())
+        })
+    }
+
+    pub fn append_min_key(&mut self, key: &str) -> Result<&mut Self, Error> {
+        self.append(key, 0xFF, |_| Ok(()))
+    }
+
+    pub fn append_max_key(&mut self, key: &str) -> Result<&mut Self, Error> {
+        self.append(key, 0x7F, |_| Ok(()))
+    }
+
+    /// Adds an already encoded value of any type, which isn't validated.
+    pub fn append_raw(&mut self, key: &str, value: RawBson<'_>) -> Result<&mut Self, Error> {
+        self.append(key, value.element_type(), |data| {
+            data.extend_from_slice(value.as_bytes());
+            Ok(())
+        })
+    }
+
+    /// Writes the element's type and key over the terminator, then its value with `write` and
+    /// a new terminator. The document is left as it was if anything fails.
+    fn append(
+        &mut self,
+        key: &str,
+        element_type: u8,
+        write: impl FnOnce(&mut Vec<u8>) -> Result<(), Error>,
+    ) -> Result<&mut Self, Error> {
+        // keys are null terminated so we can't allow any nulls in the key itself
+        if key.contains('\0') {
+            return Err(Error::KeyContainsNullByte);
+        }
+
+        let end = self.data.len() - 1;
+        self.data.truncate(end);
+        self.data.push(element_type);
+        write_cstring(&mut self.data, key);
+
+        if let Err(e) = write(&mut self.data).and_then(|()| self.terminate()) {
+            self.data.truncate(end);
+            self.data.push(0);
+            return Err(e);
+        }
+
+        Ok(self)
+    }
+
+    /// Adds the terminator and writes the document's new length.
+    fn terminate(&mut self) -> Result<(), Error> {
+        self.data.push(0);
+
+        let length = self.data.len();
+        let length = i32::try_from(length).map_err(|_| Error::DocumentTooLarge(length))?;
+        self.data[..4].copy_from_slice(&length.to_le_bytes());
+        Ok(())
+    }
+
+    fn locate(&self, path: &str) -> Result<Location, RawEditError> {
+        let mut segments = path.split('.').peekable();
+        let mut ancestors = vec![0];
+        let mut parent_is_array = false;
+        let mut walked = 0;
+
+        while let Some(segment) = segments.next() {
+            let start = *ancestors.last().unwrap();
+            let mut parent_elements = 0;
+            let mut found = None;
+
+            for element in Elements::new(&self.data, start)? {
+                let element = element?;
+
+                if element.key == segment {
+                    found = Some(element);
+                    break;
+                }
+
+                parent_elements += 1;
+            }
+
+            walked += segment.len() + 1;
+
+            if segments.peek().is_none() {
+                return Ok(Location {
+                    ancestors,
+                    parent_is_array,
+                    parent_elements,
+                    element: found.map(|element| {
+                        let value_end = element.value_offset + element.value.as_bytes().len();
+                        element.value_offset - element.key.len() - 2..value_end
+                    }),
+                });
+            }
+
+            match found {
+                Some(element) if matches!(element.value.element_type(), 0x03 | 0x04) => {
+                    parent_is_array = element.value.element_type() == 0x04;
+                    ancestors.push(element.value_offset);
+                }
+                _ => return Err(RawEditError::MissingParent(path[..walked - 1].to_string())),
+            }
+        }
+
+        unreachable!("split always returns at least one segment")
+    }
+
+    /// Replaces `range` with `element`, fixing up the lengths of the documents containing it.
+    fn splice(
+        &mut self,
+        ancestors: &[usize],
+        range: Range<usize>,
+        element: &[u8],
+    ) -> Result<(), RawEditError> {
+        let growth = element.len() as isize - range.len() as isize;
+
+        // check everything fits before changing anything
+        let mut lengths = Vec::with_capacity(ancestors.len());
+        for &start in ancestors {
+            let length = document_length(&self.data, start) as isize + growth;
+            lengths.push(
+                i32::try_from(length).map_err(|_| Error::Serde("document is too large".into()))?,
+            );
+        }
+
+        self.data.splice(range, element.iter().copied());
+
+        // the ancestors all start before the edit so haven't moved
+        for (&start, length) in ancestors.iter().zip(lengths) {
+            self.data[start..start + 4].copy_from_slice(&length.to_le_bytes());
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for RawDocumentBuf {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsRef<[u8]> for RawDocumentBuf {
+    fn as_ref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl Serialize for RawDocumentBuf {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.as_document().serialize(serializer)
+    }
+}
+
+fn document_length(data: &[u8], start: usize) -> usize {
+    i32::from_le_bytes(data[start..start + 4].try_into().unwrap()) as usize
+}
+
+/// Writes a length-prefixed, nul terminated string.
+fn write_string(data: &mut Vec<u8>, value: &str) -> Result<(), Error> {
+    let length = i32::try_from(value.len() + 1).map_err(|_| Error::StringTooLarge(value.len()))?;
+    data.extend_from_slice(&length.to_le_bytes());
+    data.extend_from_slice(value.as_bytes());
+    data.push(0);
+    Ok(())
+}
+
+/// Writes a nul terminated string, which must already have been checked for nul bytes.
+fn write_cstring(data: &mut Vec<u8>, value: &str) {
+    data.extend_from_slice(value.as_bytes());
+    data.push(0);
+}
+
+/// Encodes `value` as an element under `key`, type and key included.
+fn encode_element<T: Serialize + ?Sized>(key: &str, value: &T) -> Result<Vec<u8>, Error> {
+    struct Single<'a, T: ?Sized>(&'a str, &'a T);
+
+    impl<T: Serialize + ?Sized> Serialize for Single<'_, T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(1))?;
+            map.serialize_entry(self.0, self.1)?;
+            map.end()
+        }
+    }
+
+    let mut document = Vec::new();
+    crate::to_vec(&Single(key, value), &mut document)?;
+
+    // strip the length prefix and terminator
+    document.truncate(document.len() - 1);
+    document.drain(..4);
+    Ok(document)
+}
+
+pub(crate) struct RawBytes<'a>(pub &'a [u8]);
+
+impl Serialize for RawBytes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+/// Writes the payload of a raw value straight to the output. Bytes are copied verbatim and
+/// strings are written in bson's length-prefixed string encoding, which is shared by a handful
+/// of element types other than plain strings. Tuples write each of their elements' payloads one
+/// after the other, for element types made up of more than one value.
+pub(crate) struct RawBytesSerializer<'a, B: BytesLikeBuf> {
+    pub output: &'a mut B,
+}
+
+impl<'a, B: BytesLikeBuf> SerializeTuple for RawBytesSerializer<'a, B> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(RawBytesSerializer {
+            output: &mut *self.output,
+        })
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(())
+    }
+}
+
+fn not_raw_bytes() -> Error {
+    Error::Serde("raw values must be serialised as bytes or strings".to_string())
+}
+
+impl<'a, B: BytesLikeBuf> Serializer for RawBytesSerializer<'a, B> {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = Impossible<Self::Ok, Self::Error>;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Impossible<Self::Ok, Self::Error>;
+    type SerializeTupleVariant = Impossible<Self::Ok, Self::Error>;
+    type SerializeMap = Impossible<Self::Ok, Self::Error>;
+    type SerializeStruct = Impossible<Self::Ok, Self::Error>;
+    type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        self.output.put_slice(v);
+        Ok(())
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<Self::Ok, Self::Error> {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<Self::Ok, Self::Error> {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<Self::Ok, Self::Error> {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<Self::Ok, Self::Error> {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<Self::Ok, Self::Error> {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<Self::Ok, Self::Error> {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<Self::Ok, Self::Error> {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<Self::Ok, Self::Error> {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<Self::Ok, Self::Error> {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok, Self::Error> {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok, Self::Error> {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_char(self, _v: char) -> Result<Self::Ok, Self::Error> {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        let len = i32::try_from(v.len() + 1).map_err(|_| Error::StringTooLarge(v.len()))?;
+        self.output.put_i32_le(len);
+        self.output.put_slice(v.as_bytes());
+        self.output.put_u8(0x00);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_some<T>(self, _value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        _name: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Err(not_raw_bytes())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Err(not_raw_bytes())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RawArray, RawBson, RawDocument, RawDocumentBuf, RawEditError};
+    use bytes::BytesMut;
+    use serde::Serialize;
+
+    #[test]
+    fn raw_passthrough() {
+        #[derive(Serialize)]
+        struct Inner {
+            a: i32,
+            b: &'static str,
+        }
+
+        #[derive(Serialize)]
+        struct Outer {
+            inner: Inner,
+            extra: i32,
+        }
+
+        #[derive(Serialize)]
+        struct RawOuter<'a> {
+            inner: RawDocument<'a>,
+            extra: RawBson<'a>,
+        }
+
+        let mut inner = BytesMut::new();
+        crate::to_string(&Inner { a: 1, b: "hello" }, &mut inner).unwrap();
+
+        let mut ours = BytesMut::new();
+        crate::to_string(
+            &RawOuter {
+                inner: RawDocument(&inner),
+                extra: RawBson::new(0x10, &2_i32.to_le_bytes()),
+            },
+            &mut ours,
+        )
+        .unwrap();
+
+        let mut expected = BytesMut::new();
+        crate::to_string(
+            &Outer {
+                inner: Inner { a: 1, b: "hello" },
+                extra: 2,
+            },
+            &mut expected,
+        )
+        .unwrap();
+
+        assert_eq!(ours, expected);
+
+        // top level raw documents are copied as-is
+        let mut ours = BytesMut::new();
+        crate::to_string(&RawDocument(&expected), &mut ours).unwrap();
+        assert_eq!(ours, expected);
+    }
+
+    #[test]
+    fn array() {
+        let document = RawDocumentBuf::from_bytes(
+            bson::to_vec(&bson::doc! {
+                "values": [1, 2, 3, "four"],
+                "empty": [],
+            })
+            .unwrap(),
+        )
+        .unwrap();
+
+        let values = document.get("values").unwrap().as_array().unwrap();
+        assert_eq!(values.len().unwrap(), 4);
+        assert!(!values.is_empty().unwrap());
+        assert_eq!(
+            values.get(1).unwrap(),
+            Some(RawBson::new(0x10, &2_i32.to_le_bytes()))
+        );
+        assert_eq!(values.get(4).unwrap(), None);
+        assert_eq!(
+            values
+                .iter()
+                .map(|v| v.unwrap().element_type())
+                .collect::<Vec<_>>(),
+            [0x10, 0x10, 0x10, 0x02]
+        );
+
+        // typed, failing on the first element that doesn't fit
+        let mut ints = values.iter_as::<i32>();
+        assert_eq!(
+            ints.by_ref()
+                .take(3)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap(),
+            [1, 2, 3]
+        );
+        assert!(ints.next().unwrap().is_err());
+        assert_eq!(
+            values.iter_as::<bson::Bson>().last().unwrap().unwrap(),
+            bson::Bson::String("four".to_string())
+        );
+
+        let empty = document.get("empty").unwrap().as_array().unwrap();
+        assert_eq!(empty.len().unwrap(), 0);
+        assert!(empty.is_empty().unwrap());
+        assert!(document.get("values.0").unwrap().as_array().is_none());
+
+        // malformed arrays error rather than panic
+        let truncated = RawArray(&values.0[..values.0.len() - 3]);
+        assert!(truncated.len().is_err());
+        assert!(truncated.iter().last().unwrap().is_err());
+        assert!(RawArray(&[]).get(0).is_err());
+        assert_eq!(RawArray(&[]).iter().count(), 1);
+    }
+
+    #[test]
+    fn append() {
+        let nested = bson::to_vec(&bson::doc! { "a": 1 }).unwrap();
+        let array = bson::to_vec(&bson::doc! { "0": "x", "1": "y" }).unwrap();
+
+        let mut document = RawDocumentBuf::new();
+        let mut build = || -> Result<(), crate::Error> {
+            document
+                .append_f64("double", 1.5)?
+                .append_str("string", "hello")?
+                .append_document("document", RawDocument(&nested))?
+                .append_array("array", RawArray(&array))?
+                .append_binary("binary", 0x80, &[1, 2, 3])?
+                .append_undefined("undefined")?
+                .append_object_id("oid", [7; 12])?
+                .append_bool("bool", true)?
+                .append_datetime("date", -5)?
+                .append_null("null")?
+                .append_regex(
+                    "regex",
+                    &crate::Regex {
+                        pattern: "^a".to_string(),
+                        options: "i".to_string(),
+                    },
+                )?
+                .append_code("code", "f()")?
+                .append_symbol("symbol", "sym")?
+                .append_code_with_scope(
+                    "scope",
+                    &crate::CodeWithScope {
+                        code: "g()".to_string(),
+                        scope: RawDocument(&nested),
+                    },
+                )?
+                .append_i32("int32", -1)?
+                .append_timestamp("timestamp", (3 << 32) | 4)?
+                .append_i64("int64", 1 << 40)?
+                .append_decimal128("decimal", crate::Decimal128::from_i128(12).unwrap())?
+                .append_min_key("min")?
+                .append_max_key("max")?
+                .append_raw("raw", RawBson::new(0x10, &9_i32.to_le_bytes()))?
+                .append_serialize("serialized", &[Some(1), None])?;
+            Ok(())
+        };
+        build().unwrap();
+
+        let expected = bson::doc! {
+            "double": 1.5,
+            "string": "hello",
+            "document": { "a": 1 },
+            "array": ["x", "y"],
+            "binary": bson::Binary {
+                subtype: bson::spec::BinarySubtype::UserDefined(0x80),
+                bytes: vec![1, 2, 3],
+            },
+            "undefined": bson::Bson::Undefined,
+            "oid": bson::oid::ObjectId::from_bytes([7; 12]),
+            "bool": true,
+            "date": bson::DateTime::from_millis(-5),
+            "null": null,
+            "regex": bson::Regex { pattern: "^a".to_string(), options: "i".to_string() },
+            "code": bson::Bson::JavaScriptCode("f()".to_string()),
+            "symbol": bson::Bson::Symbol("sym".to_string()),
+            "scope": bson::Bson::JavaScriptCodeWithScope(bson::JavaScriptCodeWithScope {
+                code: "g()".to_string(),
+                scope: bson::doc! { "a": 1 },
+            }),
+            "int32": -1,
+            "timestamp": bson::Timestamp { time: 3, increment: 4 },
+            "int64": 1_i64 << 40,
+            "decimal": bson::Bson::Decimal128(bson::Decimal128::from_bytes(
+                crate::Decimal128::from_i128(12).unwrap().bytes(),
+            )),
+            "min": bson::Bson::MinKey,
+            "max": bson::Bson::MaxKey,
+            "raw": 9,
+            "serialized": [1, null],
+        };
+
+        assert_eq!(document.as_bytes(), bson::to_vec(&expected).unwrap());
+        crate::de::validate(document.as_bytes()).unwrap();
+
+        // failed appends leave the document as it was
+        let before = document.clone();
+        assert!(matches!(
+            document.append_i32("a\0b", 1),
+            Err(crate::Error::KeyContainsNullByte)
+        ));
+        assert!(document
+            .append_regex(
+                "regex",
+                &crate::Regex {
+                    pattern: "a\0".to_string(),
+                    options: String::new(),
+                },
+            )
+            .is_err());
+        assert_eq!(document, before);
+    }
+
+    #[test]
+    fn edit() {
+        let original = bson::doc! {
+            "_id": 1,
+            "meta": { "source": "a", "tags": ["x", "y"] },
+            "payload": "unchanged",
+        };
+        let mut doc = RawDocumentBuf::from_bytes(bson::to_vec(&original).unwrap()).unwrap();
+        let decode = |doc: &RawDocumentBuf| bson::Document::from_reader(doc.as_bytes()).unwrap();
+
+        // replacing keeps the field where it was
+        doc.insert("_id", "one").unwrap();
+        doc.insert("meta.source", &"a much longer source").unwrap();
+        doc.insert("meta.tags.0", &1_i64).unwrap();
+        // adding goes on the end of the document or array
+        doc.insert("meta.received", &true).unwrap();
+        doc.insert("meta.tags.2", "z").unwrap();
+        doc.insert("trace", &crate::Document::new()).unwrap();
+        doc.insert("trace.id", &7).unwrap();
+
+        assert_eq!(
+            decode(&doc),
+            bson::doc! {
+                "_id": "one",
+                "meta": {
+                    "source": "a much longer source",
+                    "tags": [1_i64, "y", "z"],
+                    "received": true,
+                },
+                "payload": "unchanged",
+                "trace": { "id": 7 },
+            }
+        );
+        assert_eq!(
+            doc.get("meta.tags.2"),
+            Some(RawBson::new(0x02, b"\x02\0\0\0z\0"))
+        );
+
+        assert!(doc.remove("meta.source").unwrap());
+        assert!(doc.remove("meta.tags.2").unwrap());
+        assert!(doc.remove("trace").unwrap());
+        assert!(!doc.remove("trace").unwrap());
+        assert!(!doc.remove("missing.parent").unwrap());
+
+        assert_eq!(
+            decode(&doc),
+            bson::doc! {
+                "_id": "one",
+                "meta": { "tags": [1_i64, "y"], "received": true },
+                "payload": "unchanged",
+            }
+        );
+        crate::de::validate(doc.as_bytes()).unwrap();
+
+        assert!(matches!(
+            doc.insert("meta.tags.5", &1),
+            Err(RawEditError::ArrayIndex(_))
+        ));
+        assert!(matches!(
+            doc.remove("meta.tags.0"),
+            Err(RawEditError::ArrayIndex(_))
+        ));
+        assert!(matches!(
+            doc.insert("payload.inner", &1),
+            Err(RawEditError::MissingParent(path)) if path == "payload"
+        ));
+        assert!(matches!(
+            doc.insert("a.b.c", &1),
+            Err(RawEditError::MissingParent(path)) if path == "a"
+        ));
+        assert!(matches!(
+            doc.insert("nul\0", &1),
+            Err(RawEditError::Write(_))
+        ));
+
+        let mut empty = RawDocumentBuf::new();
+        empty.insert("a", &1).unwrap();
+        assert_eq!(decode(&empty), bson::doc! { "a": 1 });
+    }
+}
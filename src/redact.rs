@@ -0,0 +1,154 @@
+//! Stripping sensitive values out of documents before they're logged.
+//!
+//! [`redact`] works on the raw document so the result can be handed to anything, such as the
+//! `transcode` module to log it as JSON. When the document's only being deserialised,
+//! [`crate::de::Options::redact_sensitive`] avoids the copy for sensitive binaries.
+
+use crate::{de::Error, scan::Elements};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+/// The placeholder redacted values are replaced with.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Copies the document in `data` onto the end of `output`, with every sensitive binary
+/// (subtype 0x08) and the value at each of `paths` replaced by the string [`REDACTED`]. Paths
+/// are dot separated keys or array indexes, as in [`crate::de::project`].
+pub fn redact(data: &[u8], paths: &[&str], output: &mut Vec<u8>) -> Result<(), Error> {
+    let paths = paths
+        .iter()
+        .map(|path| path.split('.').collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    redact_document(data, 0, &paths, &mut Vec::new(), output)
+}
+
+fn redact_document<'a>(
+    data: &'a [u8],
+    start: usize,
+    paths: &[Vec<&str>],
+    current: &mut Vec<&'a str>,
+    output: &mut Vec<u8>,
+) -> Result<(), Error> {
+    let length_at = output.len();
+    output.extend_from_slice(&[0; 4]);
+
+    for element in Elements::new(data, start)? {
+        let element = element?;
+        let element_type = element.value.element_type();
+        let value = element.value.as_bytes();
+
+        current.push(element.key);
+
+        let sensitive = element_type == 0x05 && value[4] == 0x08;
+        let redacted = sensitive || paths.iter().any(|path| path == current);
+
+        output.push(if redacted { 0x02 } else { element_type });
+        output.extend_from_slice(element.key.as_bytes());
+        output.push(0x00);
+
+        if redacted {
+            output.extend_from_slice(&(REDACTED.len() as i32 + 1).to_le_bytes());
+            output.extend_from_slice(REDACTED.as_bytes());
+            output.push(0x00);
+        } else if matches!(element_type, 0x03 | 0x04) {
+            redact_document(data, element.value_offset, paths, current, output)?;
+        } else {
+            output.extend_from_slice(value);
+        }
+
+        current.pop();
+    }
+
+    output.push(0x00);
+
+    let length =
+        i32::try_from(output.len() - length_at).map_err(|_| Error::InvalidLength(start))?;
+    output[length_at..length_at + 4].copy_from_slice(&length.to_le_bytes());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::REDACTED;
+
+    fn sensitive(bytes: &[u8]) -> bson::Binary {
+        bson::Binary {
+            subtype: bson::spec::BinarySubtype::Sensitive,
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    #[test]
+    fn redact() {
+        let data = bson::to_vec(&bson::doc! {
+            "user": { "name": "ferris", "password": "hunter2", "token": sensitive(b"abc") },
+            "cards": ["1234", "5678"],
+            "tags": [sensitive(b"x"), "y"],
+            "count": 1,
+        })
+        .unwrap();
+
+        let mut output = Vec::new();
+        super::redact(
+            &data,
+            &["user.password", "cards.0", "missing.path"],
+            &mut output,
+        )
+        .unwrap();
+
+        assert_eq!(
+            bson::Document::from_reader(&output[..]).unwrap(),
+            bson::doc! {
+                "user": { "name": "ferris", "password": REDACTED, "token": REDACTED },
+                "cards": [REDACTED, "5678"],
+                "tags": [REDACTED, "y"],
+                "count": 1,
+            }
+        );
+
+        // sensitive binaries are redacted even without any paths, and output is appended to
+        let mut output = vec![1, 2, 3];
+        super::redact(&data, &[], &mut output).unwrap();
+        assert_eq!(&output[..3], &[1, 2, 3]);
+        assert_eq!(
+            bson::Document::from_reader(&output[3..]).unwrap(),
+            bson::doc! {
+                "user": { "name": "ferris", "password": "hunter2", "token": REDACTED },
+                "cards": ["1234", "5678"],
+                "tags": [REDACTED, "y"],
+                "count": 1,
+            }
+        );
+
+        assert!(super::redact(&data[..data.len() - 1], &[], &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn redact_sensitive() {
+        let data = bson::to_vec(&bson::doc! {
+            "password": sensitive(b"hunter2"),
+            "other": bson::Binary { subtype: bson::spec::BinarySubtype::Generic, bytes: vec![1] },
+        })
+        .unwrap();
+
+        let options = crate::de::Options::new()
+            .redact_sensitive(true)
+            .bson_types(crate::de::BsonTypes::Strings);
+        let value: serde_json::Value = crate::de::from_bytes_with_options(&data, options).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({ "password": REDACTED, "other": "AQ==" })
+        );
+
+        // types asking for the payload still get it
+        #[derive(serde::Deserialize)]
+        struct A {
+            password: crate::Sensitive,
+        }
+
+        let a: A = crate::de::from_bytes_with_options(&data, options).unwrap();
+        assert_eq!(a.password.0, b"hunter2");
+    }
+}
@@ -0,0 +1,185 @@
+use crate::{de::Error, RawBson};
+use core::convert::{TryFrom, TryInto};
+use memchr::memchr;
+
+/// Walks over the elements of a document without looking inside of their values, stepping over
+/// each using its length. Nothing is validated beyond what's needed to find where each element
+/// ends.
+pub(crate) struct Elements<'a> {
+    data: &'a [u8],
+    position: usize,
+    end: usize,
+}
+
+/// An element read by [`Elements`].
+pub(crate) struct Element<'a> {
+    pub key: &'a str,
+    pub value: RawBson<'a>,
+    /// Offset of the element's value into the input.
+    pub value_offset: usize,
+}
+
+impl<'a> Elements<'a> {
+    /// Reads the document starting at `start` in `data`, offsets in errors are relative to
+    /// `data`.
+    pub fn new(data: &'a [u8], start: usize) -> Result<Self, Error> {
+        let length = read_length(data, start)?;
+
+        if length < 5 || start + length > data.len() || data[start + length - 1] != 0x00 {
+            return Err(Error::InvalidLength(start));
+        }
+
+        Ok(Self {
+            data,
+            position: start + 4,
+            end: start + length - 1,
+        })
+    }
+
+    fn read_element(&mut self) -> Result<Element<'a>, Error> {
+        let offset = self.position;
+        let element_type = self.data[offset];
+
+        let key_start = offset + 1;
+        let key_length = memchr(b'\0', &self.data[key_start..self.end])
+            .ok_or(Error::UnterminatedCString(key_start))?;
+        let key = crate::utf8::from_utf8(&self.data[key_start..key_start + key_length])
+            .map_err(|_| Error::InvalidUtf8(key_start))?;
+
+        let value_start = key_start + key_length + 1;
+        let value_length = value_length(self.data, value_start, element_type)
+            .and_then(|length| length.ok_or(Error::UnknownElementType(element_type, offset)))?;
+
+        if value_start + value_length > self.end {
+            return Err(Error::InvalidLength(value_start));
+        }
+
+        self.position = value_start + value_length;
+
+        Ok(Element {
+            key,
+            value: RawBson::new(
+                element_type,
+                &self.data[value_start..value_start + value_length],
+            ),
+            value_offset: value_start,
+        })
+    }
+}
+
+impl<'a> Iterator for Elements<'a> {
+    type Item = Result<Element<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position >= self.end {
+            return None;
+        }
+
+        let res = self.read_element();
+
+        // there's no way to carry on after a malformed element
+        if res.is_err() {
+            self.position = self.end;
+        }
+
+        Some(res)
+    }
+}
+
+fn read_length(data: &[u8], at: usize) -> Result<usize, Error> {
+    // lengths are signed, and anything negative would overflow adding it to an offset on 32-bit
+    // targets rather than just landing out of bounds
+    data.get(at..at + 4)
+        .map(|v| i32::from_le_bytes(v.try_into().unwrap()))
+        .and_then(|v| usize::try_from(v).ok())
+        .ok_or(Error::InvalidLength(at))
+}
+
+/// The size of the value of type `element_type` at `at`, or `None` if the type isn't in the spec.
+pub(crate) fn value_length(
+    data: &[u8],
+    at: usize,
+    element_type: u8,
+) -> Result<Option<usize>, Error> {
+    let cstring_length = |at: usize| {
+        data.get(at..)
+            .and_then(|v| memchr(b'\0', v))
+            .map(|length| length + 1)
+            .ok_or(Error::UnterminatedCString(at))
+    };
+
+    Ok(Some(match element_type {
+        0x06 | 0x0a | 0x7f | 0xff => 0,
+        0x08 => 1,
+        0x10 => 4,
+        0x01 | 0x09 | 0x11 | 0x12 => 8,
+        0x07 => 12,
+        0x13 => 16,
+        0x02 | 0x0d | 0x0e => 4 + read_length(data, at)?,
+        0x03 | 0x04 | 0x0f => read_length(data, at)?,
+        0x05 => 5 + read_length(data, at)?,
+        0x0b => {
+            let pattern = cstring_length(at)?;
+            pattern + cstring_length(at + pattern)?
+        }
+        0x0c => 4 + read_length(data, at)? + 12,
+        _ => return Ok(None),
+    }))
+}
+
+/// Finds the value at `path` within the document `data`, where each segment of the path is
+/// either a key or an array index.
+pub(crate) fn find<'a, 'p>(
+    data: &'a [u8],
+    path: impl IntoIterator<Item = &'p str>,
+) -> Result<Option<RawBson<'a>>, Error> {
+    // the document we're going to look in for the next segment
+    let mut document = Some(0);
+    let mut found = None;
+
+    for segment in path {
+        let Some(start) = document else {
+            return Ok(None);
+        };
+
+        let element = Elements::new(data, start)?
+            .find(|element| match element {
+                Ok(element) => element.key == segment,
+                Err(_) => true,
+            })
+            .transpose()?;
+
+        let Some(element) = element else {
+            return Ok(None);
+        };
+
+        document =
+            matches!(element.value.element_type(), 0x03 | 0x04).then_some(element.value_offset);
+        found = Some(element.value);
+    }
+
+    Ok(found)
+}
+
+/// Counts the elements in the document starting at `start`, or `None` if it's malformed.
+pub(crate) fn count_elements(data: &[u8], start: usize) -> Option<usize> {
+    let length = read_length(data, start).ok()?;
+    if length < 5 || start + length > data.len() {
+        return None;
+    }
+
+    let end = start + length - 1;
+    let mut position = start + 4;
+    let mut count = 0;
+
+    while position < end {
+        let element_type = data[position];
+        let key_length = memchr(b'\0', &data[position + 1..end])?;
+        let value_start = position + key_length + 2;
+
+        position = value_start + value_length(data, value_start, element_type).ok()??;
+        count += 1;
+    }
+
+    Some(count)
+}
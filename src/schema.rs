@@ -0,0 +1,447 @@
+//! Checks raw documents against a set of rules in the spirit of MongoDB's `$jsonSchema`, without
+//! deserialising them.
+//!
+//! ```
+//! use serde_bson::schema::{BsonType, Schema};
+//!
+//! let schema = Schema::new()
+//!     .required(&["name", "age"])
+//!     .property("name", Schema::new().bson_types(&[BsonType::String]).max_length(64))
+//!     .property("age", Schema::new().bson_types(&[BsonType::Int]).minimum(0.0));
+//!
+//! # #[derive(serde::Serialize)]
+//! # struct Person { name: &'static str, age: i32 }
+//! let data = serde_bson::to_bytes(&Person { name: "Ferris", age: -1 }).unwrap();
+//! let violations = schema.validate(&data).unwrap_err();
+//! assert_eq!(violations[0].path.as_str(), "age");
+//! ```
+
+use crate::{
+    de::Error,
+    scan::{Element, Elements},
+    Decimal128, Path,
+};
+use std::{
+    convert::TryInto,
+    fmt::{self, Display, Formatter},
+};
+
+/// The types a value can be constrained to, named as they are in `$jsonSchema`'s `bsonType`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BsonType {
+    Double,
+    String,
+    Object,
+    Array,
+    BinData,
+    Undefined,
+    ObjectId,
+    Bool,
+    Date,
+    Null,
+    Regex,
+    DbPointer,
+    Javascript,
+    Symbol,
+    JavascriptWithScope,
+    Int,
+    Timestamp,
+    Long,
+    Decimal,
+    MinKey,
+    MaxKey,
+}
+
+impl BsonType {
+    /// Every numeric type, the equivalent of `$jsonSchema`'s `"number"` alias.
+    pub const NUMBER: &'static [BsonType] = &[Self::Double, Self::Int, Self::Long, Self::Decimal];
+
+    fn from_element_type(element_type: u8) -> Option<Self> {
+        Some(match element_type {
+            0x01 => Self::Double,
+            0x02 => Self::String,
+            0x03 => Self::Object,
+            0x04 => Self::Array,
+            0x05 => Self::BinData,
+            0x06 => Self::Undefined,
+            0x07 => Self::ObjectId,
+            0x08 => Self::Bool,
+            0x09 => Self::Date,
+            0x0a => Self::Null,
+            0x0b => Self::Regex,
+            0x0c => Self::DbPointer,
+            0x0d => Self::Javascript,
+            0x0e => Self::Symbol,
+            0x0f => Self::JavascriptWithScope,
+            0x10 => Self::Int,
+            0x11 => Self::Timestamp,
+            0x12 => Self::Long,
+            0x13 => Self::Decimal,
+            0xff => Self::MinKey,
+            0x7f => Self::MaxKey,
+            _ => return None,
+        })
+    }
+}
+
+/// Rules for a single value, and the values nested within it.
+#[derive(Debug, Clone)]
+pub struct Schema {
+    bson_types: Option<Vec<BsonType>>,
+    required: Vec<String>,
+    properties: Vec<(String, Schema)>,
+    additional_properties: bool,
+    items: Option<Box<Schema>>,
+    minimum: Option<f64>,
+    maximum: Option<f64>,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+}
+
+impl Default for Schema {
+    fn default() -> Self {
+        Self {
+            bson_types: None,
+            required: Vec::new(),
+            properties: Vec::new(),
+            additional_properties: true,
+            items: None,
+            minimum: None,
+            maximum: None,
+            min_length: None,
+            max_length: None,
+        }
+    }
+}
+
+impl Schema {
+    /// A schema that accepts anything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only accept values of one of the given types.
+    pub fn bson_types(mut self, bson_types: &[BsonType]) -> Self {
+        self.bson_types = Some(bson_types.to_vec());
+        self
+    }
+
+    /// Keys that must be present in a document.
+    pub fn required(mut self, keys: &[&str]) -> Self {
+        self.required.extend(keys.iter().map(|key| key.to_string()));
+        self
+    }
+
+    /// Rules for the value of `key` in a document, if it's present.
+    pub fn property(mut self, key: &str, schema: Schema) -> Self {
+        self.properties.push((key.to_string(), schema));
+        self
+    }
+
+    /// Whether a document can contain keys that don't have a [`Schema::property`]. Allowed by
+    /// default.
+    pub fn additional_properties(mut self, allowed: bool) -> Self {
+        self.additional_properties = allowed;
+        self
+    }
+
+    /// Rules for every element of an array.
+    pub fn items(mut self, schema: Schema) -> Self {
+        self.items = Some(Box::new(schema));
+        self
+    }
+
+    /// The smallest a number can be, inclusive.
+    pub fn minimum(mut self, minimum: f64) -> Self {
+        self.minimum = Some(minimum);
+        self
+    }
+
+    /// The largest a number can be, inclusive.
+    pub fn maximum(mut self, maximum: f64) -> Self {
+        self.maximum = Some(maximum);
+        self
+    }
+
+    /// The fewest characters in a string or elements in an array.
+    pub fn min_length(mut self, min_length: usize) -> Self {
+        self.min_length = Some(min_length);
+        self
+    }
+
+    /// The most characters in a string or elements in an array.
+    pub fn max_length(mut self, max_length: usize) -> Self {
+        self.max_length = Some(max_length);
+        self
+    }
+
+    /// Checks the document in `data` against the schema, returning every violation found.
+    /// Reading stops at the first malformed value, which is reported as a
+    /// [`ViolationKind::Malformed`] after anything found up to that point.
+    pub fn validate(&self, data: &[u8]) -> Result<(), Vec<Violation>> {
+        let mut violations = Vec::new();
+
+        if let Err(error) = self.check_document(data, 0, &Path::default(), &mut violations) {
+            violations.push(Violation {
+                path: Path::default(),
+                kind: ViolationKind::Malformed(error),
+            });
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    fn check_document(
+        &self,
+        data: &[u8],
+        start: usize,
+        path: &Path,
+        violations: &mut Vec<Violation>,
+    ) -> Result<(), Error> {
+        let mut seen = vec![false; self.required.len()];
+
+        for element in Elements::new(data, start)? {
+            let element = element?;
+
+            if let Some(i) = self.required.iter().position(|key| key == element.key) {
+                seen[i] = true;
+            }
+
+            let mut path = path.clone();
+            path.push_key(element.key);
+
+            match self.properties.iter().find(|(key, _)| key == element.key) {
+                Some((_, schema)) => schema.check_value(data, &element, &path, violations)?,
+                None if !self.additional_properties => violations.push(Violation {
+                    path,
+                    kind: ViolationKind::UnexpectedKey,
+                }),
+                None => {}
+            }
+        }
+
+        for (key, _) in self.required.iter().zip(seen).filter(|(_, seen)| !seen) {
+            let mut path = path.clone();
+            path.push_key(key);
+            violations.push(Violation {
+                path,
+                kind: ViolationKind::Missing,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn check_value(
+        &self,
+        data: &[u8],
+        element: &Element<'_>,
+        path: &Path,
+        violations: &mut Vec<Violation>,
+    ) -> Result<(), Error> {
+        let mut violation = |kind| {
+            violations.push(Violation {
+                path: path.clone(),
+                kind,
+            })
+        };
+
+        let element_type = element.value.element_type();
+        let bytes = element.value.as_bytes();
+
+        if let Some(bson_types) = &self.bson_types {
+            let found = BsonType::from_element_type(element_type);
+            if !found.is_some_and(|found| bson_types.contains(&found)) {
+                violation(ViolationKind::WrongType(found));
+                return Ok(());
+            }
+        }
+
+        let number = match element_type {
+            0x01 => Some(f64::from_le_bytes(bytes.try_into().unwrap())),
+            0x10 => Some(f64::from(i32::from_le_bytes(bytes.try_into().unwrap()))),
+            0x12 => Some(i64::from_le_bytes(bytes.try_into().unwrap()) as f64),
+            0x13 => Decimal128::from_bytes(bytes.try_into().unwrap())
+                .to_string()
+                .parse()
+                .ok(),
+            _ => None,
+        };
+
+        if let Some(number) = number {
+            if self.minimum.is_some_and(|minimum| number < minimum) {
+                violation(ViolationKind::BelowMinimum);
+            }
+
+            if self.maximum.is_some_and(|maximum| number > maximum) {
+                violation(ViolationKind::AboveMaximum);
+            }
+        }
+
+        let length = match element_type {
+            0x02 => Some(
+                crate::utf8::from_utf8(&bytes[4..bytes.len() - 1])
+                    .map_err(|_| Error::InvalidUtf8(element.value_offset + 4))?
+                    .chars()
+                    .count(),
+            ),
+            0x04 => {
+                let mut count = 0;
+
+                for item in Elements::new(data, element.value_offset)? {
+                    let item = item?;
+
+                    if let Some(items) = &self.items {
+                        let mut path = path.clone();
+                        path.push_index(item.key);
+                        items.check_value(data, &item, &path, violations)?;
+                    }
+
+                    count += 1;
+                }
+
+                Some(count)
+            }
+            _ => None,
+        };
+
+        if let Some(length) = length {
+            if self.min_length.is_some_and(|min| length < min) {
+                violations.push(Violation {
+                    path: path.clone(),
+                    kind: ViolationKind::TooShort,
+                });
+            }
+
+            if self.max_length.is_some_and(|max| length > max) {
+                violations.push(Violation {
+                    path: path.clone(),
+                    kind: ViolationKind::TooLong,
+                });
+            }
+        }
+
+        if element_type == 0x03 {
+            self.check_document(data, element.value_offset, path, violations)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A rule a document broke, see [`Schema::validate`].
+#[derive(Debug)]
+pub struct Violation {
+    /// Where in the document the offending value is, or would have been for missing keys.
+    pub path: Path,
+    pub kind: ViolationKind,
+}
+
+#[derive(Debug)]
+pub enum ViolationKind {
+    /// A required key wasn't present.
+    Missing,
+    /// A key without a property was present when additional properties aren't allowed.
+    UnexpectedKey,
+    /// The value wasn't one of the allowed types, holding the type it was if it's in the spec.
+    WrongType(Option<BsonType>),
+    BelowMinimum,
+    AboveMaximum,
+    TooShort,
+    TooLong,
+    /// The document couldn't be read, anything past this point wasn't checked.
+    Malformed(Error),
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.path)?;
+
+        match &self.kind {
+            ViolationKind::Missing => f.write_str("required key is missing"),
+            ViolationKind::UnexpectedKey => f.write_str("key isn't allowed"),
+            ViolationKind::WrongType(Some(found)) => write!(f, "unexpected type {:?}", found),
+            ViolationKind::WrongType(None) => f.write_str("unexpected type"),
+            ViolationKind::BelowMinimum => f.write_str("number is below the minimum"),
+            ViolationKind::AboveMaximum => f.write_str("number is above the maximum"),
+            ViolationKind::TooShort => f.write_str("value is shorter than the minimum length"),
+            ViolationKind::TooLong => f.write_str("value is longer than the maximum length"),
+            ViolationKind::Malformed(error) => write!(f, "malformed document: {}", error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BsonType, Schema, ViolationKind};
+
+    #[test]
+    fn validate() {
+        let schema = Schema::new()
+            .required(&["name", "tags", "address", "missing"])
+            .property("name", Schema::new().bson_types(&[BsonType::String]))
+            .property(
+                "age",
+                Schema::new()
+                    .bson_types(BsonType::NUMBER)
+                    .minimum(0.0)
+                    .maximum(150.0),
+            )
+            .property(
+                "tags",
+                Schema::new()
+                    .max_length(2)
+                    .items(Schema::new().bson_types(&[BsonType::String]).min_length(1)),
+            )
+            .property(
+                "address",
+                Schema::new()
+                    .required(&["city"])
+                    .property("city", Schema::new().bson_types(&[BsonType::String]))
+                    .additional_properties(false),
+            );
+
+        let valid = bson::to_vec(&bson::doc! {
+            "name": "Ferris",
+            "age": 7.5,
+            "tags": ["a", "b"],
+            "address": { "city": "x" },
+            "missing": null,
+        })
+        .unwrap();
+        schema.validate(&valid).unwrap();
+
+        let invalid = bson::to_vec(&bson::doc! {
+            "name": 1,
+            "age": 200_i64,
+            "tags": ["a", "", 1],
+            "address": { "street": "y" },
+        })
+        .unwrap();
+
+        let violations = schema.validate(&invalid).unwrap_err();
+        let violations = violations.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            violations,
+            [
+                "name: unexpected type Int",
+                "age: number is above the maximum",
+                "tags[1]: value is shorter than the minimum length",
+                "tags[2]: unexpected type Int",
+                "tags: value is longer than the maximum length",
+                "address.street: key isn't allowed",
+                "address.city: required key is missing",
+                "missing: required key is missing",
+            ]
+        );
+
+        let violations = schema.validate(&invalid[..invalid.len() - 1]).unwrap_err();
+        assert_eq!(violations.len(), 1);
+        assert!(matches!(violations[0].kind, ViolationKind::Malformed(_)));
+    }
+}
@@ -1,23 +1,176 @@
-use crate::{byte::BytesLikeBuf, Error};
+use crate::{
+    byte::BytesLikeBuf,
+    raw::{RawBytesSerializer, RAW_BSON_NEWTYPE},
+    Decimal128, Error,
+};
+use alloc::{borrow::Cow, string::String, vec::Vec};
+use core::convert::TryFrom;
 use serde::{
-    ser::{SerializeSeq, SerializeStruct},
+    ser::{Impossible, SerializeSeq},
     Serialize,
 };
-use std::convert::TryFrom;
+
+/// Options controlling how values are written by [`crate::to_string_with_options`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Options {
+    pub(crate) sort_keys: bool,
+    non_finite_floats: NonFiniteFloats,
+    human_readable: bool,
+    unsigned_integers: UnsignedIntegers,
+    rename_fields: Option<fn(&'static str) -> Cow<'static, str>>,
+    variants: Variants,
+}
+
+/// How enum variants are identified, see [`Options::variants`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Variants {
+    /// By the variant's name, as a string for unit variants and as the key of a document
+    /// holding the variant's data otherwise.
+    #[default]
+    Name,
+    /// By the variant's index in the enum, as an int32 for unit variants and as the key of a
+    /// document holding the variant's data otherwise, e.g. `{ "1": 5 }`.
+    Index,
+}
+
+/// How unsigned integers are serialised, see [`Options::unsigned_integers`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnsignedIntegers {
+    /// Fail with [`Error::UnsignedIntNotInSpec`].
+    #[default]
+    Reject,
+    /// Write `u8`s and `u16`s as an int32 and `u32`s and `u64`s as an int64, falling back to a
+    /// decimal128 for `u64`s beyond `i64::MAX`.
+    Widen,
+}
+
+/// What to do when a NaN or infinite double is serialised, see [`Options::non_finite_floats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NonFiniteFloats {
+    /// Write the value as-is, bson has no problem representing them.
+    #[default]
+    Allow,
+    /// Fail with [`Error::NonFiniteFloat`].
+    Reject,
+    /// Write a null in place of the value.
+    Null,
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write the keys of structs and maps in byte order rather than the order they were
+    /// serialised in, so the same value always results in the same output. Disabled by default.
+    pub fn sort_keys(mut self, sort_keys: bool) -> Self {
+        self.sort_keys = sort_keys;
+        self
+    }
+
+    /// How NaN and infinite doubles should be handled. MongoDB will happily store them but plenty
+    /// of downstream consumers won't, so they can be rejected or nulled out instead. Defaults to
+    /// [`NonFiniteFloats::Allow`].
+    pub fn non_finite_floats(mut self, non_finite_floats: NonFiniteFloats) -> Self {
+        self.non_finite_floats = non_finite_floats;
+        self
+    }
+
+    /// What [`serde::Serializer::is_human_readable`] reports, which types such as uuids and
+    /// timestamps use to pick between a string and a compact form. Disabled by default, since
+    /// bson is a binary format.
+    pub fn human_readable(mut self, human_readable: bool) -> Self {
+        self.human_readable = human_readable;
+        self
+    }
+
+    /// How unsigned integers should be handled, there's no unsigned type in the spec so by
+    /// default they're rejected rather than risk reading back something different.
+    ///
+    /// Widening is needed to serialise a `serde_json::Value`, which hands every non-negative
+    /// integer to the serializer as a `u64`. Objects are then written as documents, arrays as
+    /// arrays and integers as an int64, or a decimal128 if they're beyond `i64::MAX`.
+    pub fn unsigned_integers(mut self, unsigned_integers: UnsignedIntegers) -> Self {
+        self.unsigned_integers = unsigned_integers;
+        self
+    }
+
+    /// Maps the name of each struct field to the key it's written under, such as [`camel_case`]
+    /// for documents shared with JavaScript, saving a `#[serde(rename)]` on every field. Names
+    /// given by `#[serde(rename)]` are passed through the function too. Map keys, including
+    /// those of `#[serde(flatten)]`ed fields, and enum variant names are written as-is.
+    pub fn rename_fields(mut self, rename: fn(&'static str) -> Cow<'static, str>) -> Self {
+        self.rename_fields = Some(rename);
+        self
+    }
+
+    /// How enum variants are identified. Writing their index rather than their name makes for
+    /// smaller documents when they're read by something that knows the enum's layout, at the
+    /// cost of the documents changing meaning if variants are reordered. Enums used as map keys
+    /// are always written by name. Defaults to [`Variants::Name`].
+    pub fn variants(mut self, variants: Variants) -> Self {
+        self.variants = variants;
+        self
+    }
+
+    fn variant_key(&self, index: u32, variant: &'static str) -> DocumentKey {
+        match self.variants {
+            Variants::Name => DocumentKey::Str(variant),
+            Variants::Index => DocumentKey::Int(index as usize),
+        }
+    }
+
+    fn field_key(&self, field: &'static str) -> DocumentKey {
+        match self.rename_fields.map(|rename| rename(field)) {
+            None => DocumentKey::Str(field),
+            Some(Cow::Borrowed(key)) => DocumentKey::Str(key),
+            Some(Cow::Owned(key)) => DocumentKey::String(key),
+        }
+    }
+}
+
+/// Converts a `snake_case` field name to `camelCase`, for use with [`Options::rename_fields`].
+/// Names without an underscore are returned as-is without allocating.
+pub fn camel_case(field: &'static str) -> Cow<'static, str> {
+    if !field.contains('_') {
+        return Cow::Borrowed(field);
+    }
+
+    let mut out = String::with_capacity(field.len());
+    let mut upper = false;
+
+    for c in field.chars() {
+        if c == '_' && !out.is_empty() {
+            upper = true;
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+
+    Cow::Owned(out)
+}
 
 pub struct Serializer<'a, B: BytesLikeBuf> {
     pub key: Option<DocumentKey>,
     pub output: &'a mut B,
+    pub options: Options,
 }
 
 macro_rules! write_key_or_error {
-    ($id:literal, $key:expr, $output:expr) => {
-        if let Some(key) = $key {
-            $output.put_u8($id);
-            key.write_to_buf($output);
-            $output.put_u8(0x00);
-        } else {
-            return Err(Error::NotSerializingStruct);
+    ($id:expr, $key:expr, $output:expr) => {
+        match $key {
+            // map keys have already been written by the time we know the type of the value, so
+            // we just need to fill in the placeholder element type we left for ourselves
+            Some(DocumentKey::Written(at)) => *$output.byte_mut(at) = $id,
+            Some(key) => {
+                $output.put_u8($id);
+                key.write_to_buf($output);
+                $output.put_u8(0x00);
+            }
+            None => return Err(Error::NotSerializingStruct),
         }
     };
 }
@@ -30,7 +183,7 @@ impl<'a, B: BytesLikeBuf> serde::Serializer for Serializer<'a, B> {
     type SerializeTuple = TupleSerializer<'a, B>;
     type SerializeTupleStruct = TupleStructSerializer<'a, B>;
     type SerializeTupleVariant = TupleVariantSerializer<'a, B>;
-    type SerializeMap = serde::ser::Impossible<Self::Ok, Self::Error>;
+    type SerializeMap = MapSerializer<'a, B>;
     type SerializeStruct = StructSerializer<'a, B>;
     type SerializeStructVariant = StructVariantSerializer<'a, B>;
 
@@ -65,20 +218,25 @@ impl<'a, B: BytesLikeBuf> serde::Serializer for Serializer<'a, B> {
     }
 
     fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        if !v.is_finite() {
+            match self.options.non_finite_floats {
+                NonFiniteFloats::Allow => {}
+                NonFiniteFloats::Reject => return Err(Error::NonFiniteFloat(v)),
+                NonFiniteFloats::Null => return self.serialize_none(),
+            }
+        }
+
         write_key_or_error!(0x01, self.key, self.output);
         self.output.put_f64_le(v);
         Ok(())
     }
 
     fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
-        write_key_or_error!(0x02, self.key, self.output);
-
         let v = v.as_bytes();
         let len = i32::try_from(v.len() + 1) // `+ 1` for the null byte at the end of the str
-            .unwrap_or_else(|_| panic!(
-                "encoded string exceeds max size: {}",
-                i32::MAX - 1
-            ));
+            .map_err(|_| Error::StringTooLarge(v.len()))?;
+
+        write_key_or_error!(0x02, self.key, self.output);
 
         self.output.put_i32_le(len);
         self.output.put_slice(v);
@@ -88,11 +246,10 @@ impl<'a, B: BytesLikeBuf> serde::Serializer for Serializer<'a, B> {
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
-        write_key_or_error!(0x05, self.key, self.output);
-
         // we don't need the + 1 here since there's no null terminator
-        let len = i32::try_from(v.len())
-            .unwrap_or_else(|_| panic!("bytes exceeds max size: {}", i32::MAX));
+        let len = i32::try_from(v.len()).map_err(|_| Error::BytesTooLarge(v.len()))?;
+
+        write_key_or_error!(0x05, self.key, self.output);
 
         self.output.put_i32_le(len);
         self.output.put_u8(0x00); // subtype, we'll just assume 0x00
@@ -124,10 +281,13 @@ impl<'a, B: BytesLikeBuf> serde::Serializer for Serializer<'a, B> {
     fn serialize_unit_variant(
         self,
         _name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         variant: &'static str,
     ) -> Result<Self::Ok, Self::Error> {
-        self.serialize_str(variant)
+        match self.options.variants {
+            Variants::Name => self.serialize_str(variant),
+            Variants::Index => self.serialize_i32(variant_index as i32),
+        }
     }
 
     fn serialize_newtype_struct<T>(
@@ -143,20 +303,45 @@ impl<'a, B: BytesLikeBuf> serde::Serializer for Serializer<'a, B> {
 
     fn serialize_newtype_variant<T>(
         self,
-        _name: &'static str,
-        _variant_index: u32,
+        name: &'static str,
+        variant_index: u32,
         variant: &'static str,
         value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
         T: ?Sized + Serialize,
     {
-        let mut struct_serializer = self.serialize_struct("", 0)?;
-        struct_serializer.serialize_field(variant, value)?;
-        struct_serializer.end()
+        if name == RAW_BSON_NEWTYPE {
+            let element_type = variant_index as u8;
+
+            // top level documents don't have a key to write so they're just copied as-is
+            if self.key.is_some() || element_type != 0x03 {
+                write_key_or_error!(element_type, self.key, self.output);
+            }
+
+            return value.serialize(RawBytesSerializer {
+                output: self.output,
+            });
+        }
+
+        if self.key.is_some() {
+            write_key_or_error!(0x03, self.key, self.output);
+        }
+
+        // the variant is written as a key but isn't a field, so isn't renamed
+        let start = start_document(self.output);
+        value
+            .serialize(Serializer {
+                key: Some(self.options.variant_key(variant_index, variant)),
+                output: &mut *self.output,
+                options: self.options,
+            })
+            .map_err(|e| e.in_field(variant))?;
+        terminate_document(self.output, start)?;
+        Ok(())
     }
 
-    fn serialize_seq(mut self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
         // it'd be so much simpler if we could just delegate SerializeSeq to SerializeStruct since
         // an array in bson is just a document with numeric keys but SerializeStruct needs a
         // &'static str, and we can't do that unless we either write the string repr of 1..i32::MAX
@@ -166,12 +351,13 @@ impl<'a, B: BytesLikeBuf> serde::Serializer for Serializer<'a, B> {
             write_key_or_error!(0x04, self.key, self.output);
         }
 
-        let doc_output = start_document(&mut self.output);
+        let start = start_document(self.output);
 
         Ok(SeqSerializer {
-            original_output: self.output,
-            doc_output,
+            output: self.output,
+            start,
             key: 0,
+            options: self.options,
         })
     }
 
@@ -192,9 +378,9 @@ impl<'a, B: BytesLikeBuf> serde::Serializer for Serializer<'a, B> {
     }
 
     fn serialize_tuple_variant(
-        mut self,
+        self,
         _name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant, Self::Error> {
@@ -207,24 +393,39 @@ impl<'a, B: BytesLikeBuf> serde::Serializer for Serializer<'a, B> {
             write_key_or_error!(0x03, self.key, self.output);
         }
 
-        let mut doc_output = start_document(&mut self.output);
-        write_key_or_error!(0x04, Some(DocumentKey::Str(variant)), &mut doc_output);
-        let array_output = start_document(&mut doc_output);
+        let doc_start = start_document(self.output);
+        let key = self.options.variant_key(variant_index, variant);
+        write_key_or_error!(0x04, Some(key), self.output);
+        let array_start = start_document(self.output);
 
         Ok(TupleVariantSerializer {
-            original_output: self.output,
-            array_output,
-            doc_output,
+            output: self.output,
+            doc_start,
+            array_start,
             key: 0,
+            variant,
+            options: self.options,
         })
     }
 
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
-        todo!("map")
+        if self.key.is_some() {
+            write_key_or_error!(0x03, self.key, self.output);
+        }
+
+        let start = start_document(self.output);
+
+        Ok(MapSerializer {
+            output: self.output,
+            start,
+            key: None,
+            elements: Vec::new(),
+            options: self.options,
+        })
     }
 
     fn serialize_struct(
-        mut self,
+        self,
         _name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
@@ -232,18 +433,20 @@ impl<'a, B: BytesLikeBuf> serde::Serializer for Serializer<'a, B> {
             write_key_or_error!(0x03, self.key, self.output);
         }
 
-        let doc_output = start_document(&mut self.output);
+        let start = start_document(self.output);
 
         Ok(StructSerializer {
-            original_output: self.output,
-            doc_output,
+            output: self.output,
+            start,
+            elements: Vec::new(),
+            options: self.options,
         })
     }
 
     fn serialize_struct_variant(
-        mut self,
+        self,
         _name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
@@ -254,35 +457,70 @@ impl<'a, B: BytesLikeBuf> serde::Serializer for Serializer<'a, B> {
             write_key_or_error!(0x03, self.key, self.output);
         }
 
-        let mut doc_output = start_document(&mut self.output);
-        write_key_or_error!(0x03, Some(DocumentKey::Str(variant)), &mut doc_output);
-        let nested_doc_output = start_document(&mut doc_output);
+        let doc_start = start_document(self.output);
+        let key = self.options.variant_key(variant_index, variant);
+        write_key_or_error!(0x03, Some(key), self.output);
+        let nested_doc_start = start_document(self.output);
 
         Ok(StructVariantSerializer {
-            original_output: self.output,
-            nested_doc_output,
-            doc_output,
+            output: self.output,
+            doc_start,
+            nested_doc_start,
+            elements: Vec::new(),
+            variant,
+            options: self.options,
         })
     }
 
-    fn serialize_u8(self, _v: u8) -> Result<Self::Ok, Self::Error> {
-        Err(Error::UnsignedIntNotInSpec)
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        self.serialize_u16(u16::from(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        match self.options.unsigned_integers {
+            UnsignedIntegers::Reject => Err(Error::UnsignedIntNotInSpec),
+            UnsignedIntegers::Widen => self.serialize_i32(i32::from(v)),
+        }
     }
 
-    fn serialize_u16(self, _v: u16) -> Result<Self::Ok, Self::Error> {
-        Err(Error::UnsignedIntNotInSpec)
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_u64(u64::from(v))
     }
 
-    fn serialize_u32(self, _v: u32) -> Result<Self::Ok, Self::Error> {
-        Err(Error::UnsignedIntNotInSpec)
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        match self.options.unsigned_integers {
+            UnsignedIntegers::Reject => Err(Error::UnsignedIntNotInSpec),
+            UnsignedIntegers::Widen => match i64::try_from(v) {
+                Ok(v) => self.serialize_i64(v),
+                Err(_) => self.serialize_u128(u128::from(v)),
+            },
+        }
     }
 
-    fn serialize_u64(self, _v: u64) -> Result<Self::Ok, Self::Error> {
-        Err(Error::UnsignedIntNotInSpec)
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
+        // there's no 128-bit integer type in the spec, but decimal128 can hold them exactly so
+        // long as they don't have more than 34 significant digits
+        let decimal = Decimal128::from_i128(v).ok_or(Error::IntegerTooLargeForDecimal128)?;
+        write_key_or_error!(0x13, self.key, self.output);
+        self.output.put_slice(&decimal.bytes());
+        Ok(())
     }
 
-    fn serialize_char(self, _: char) -> Result<Self::Ok, Self::Error> {
-        Err(Error::UnsignedIntNotInSpec)
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
+        let decimal = Decimal128::from_u128(v).ok_or(Error::IntegerTooLargeForDecimal128)?;
+        write_key_or_error!(0x13, self.key, self.output);
+        self.output.put_slice(&decimal.bytes());
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        // bson has no char type, so we'll write it out as a single character string
+        let mut buf = [0; 4];
+        self.serialize_str(v.encode_utf8(&mut buf))
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.options.human_readable
     }
 }
 
@@ -307,10 +545,12 @@ impl<'a, B: BytesLikeBuf> serde::ser::SerializeTuple for TupleSerializer<'a, B>
 }
 
 pub struct TupleVariantSerializer<'a, B: BytesLikeBuf> {
-    original_output: &'a mut B,
-    array_output: <B::Out as BytesLikeBuf>::Out,
-    doc_output: B::Out,
+    output: &'a mut B,
+    doc_start: usize,
+    array_start: usize,
     key: usize,
+    variant: &'static str,
+    options: Options,
 }
 
 impl<'a, B: BytesLikeBuf> serde::ser::SerializeTupleVariant for TupleVariantSerializer<'a, B> {
@@ -323,27 +563,32 @@ impl<'a, B: BytesLikeBuf> serde::ser::SerializeTupleVariant for TupleVariantSeri
     {
         // we're basically inside a SeqSerializer here, but we can't instantiate one
         // so we'll duplicate the functionality instead
-        value.serialize(Serializer {
-            key: Some(DocumentKey::Int(self.key)),
-            output: &mut self.array_output,
-        })?;
+        value
+            .serialize(Serializer {
+                key: Some(DocumentKey::Int(self.key)),
+                output: &mut *self.output,
+                options: self.options,
+            })
+            .map_err(|e| e.in_element(self.key).in_field(self.variant))?;
         self.key += 1;
         Ok(())
     }
 
-    fn end(mut self) -> Result<Self::Ok, Self::Error> {
-        // first we close the array output into the doc output, then write the complete doc output
-        // to original_output
-        terminate_document(&mut self.doc_output, self.array_output);
-        terminate_document(self.original_output, self.doc_output);
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        // the array is nested inside of the variant's document so has to be closed first
+        terminate_document(self.output, self.array_start)?;
+        terminate_document(self.output, self.doc_start)?;
         Ok(())
     }
 }
 
 pub struct StructVariantSerializer<'a, B: BytesLikeBuf> {
-    original_output: &'a mut B,
-    nested_doc_output: <B::Out as BytesLikeBuf>::Out,
-    doc_output: B::Out,
+    output: &'a mut B,
+    doc_start: usize,
+    nested_doc_start: usize,
+    elements: Vec<usize>,
+    variant: &'static str,
+    options: Options,
 }
 
 impl<'a, B: BytesLikeBuf> serde::ser::SerializeStructVariant for StructVariantSerializer<'a, B> {
@@ -358,18 +603,26 @@ impl<'a, B: BytesLikeBuf> serde::ser::SerializeStructVariant for StructVariantSe
         // instantiate one so we'll duplicate the functionality instead. this
         // is very similar to `TupleVariantSerializer` except string keys are
         // used instead
-        value.serialize(Serializer {
-            key: Some(DocumentKey::Str(key)),
-            output: &mut self.nested_doc_output,
-        })?;
+        if self.options.sort_keys {
+            self.elements.push(self.output.len());
+        }
+
+        value
+            .serialize(Serializer {
+                key: Some(self.options.field_key(key)),
+                output: &mut *self.output,
+                options: self.options,
+            })
+            .map_err(|e| e.in_field(key).in_field(self.variant))?;
         Ok(())
     }
 
-    fn end(mut self) -> Result<Self::Ok, Self::Error> {
-        // first we close the nested output into the doc output, then write the complete doc output
-        // to original_output
-        terminate_document(&mut self.doc_output, self.nested_doc_output);
-        terminate_document(self.original_output, self.doc_output);
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        sort_document_elements(self.output, &self.elements)?;
+
+        // the nested document is inside of the variant's document so has to be closed first
+        terminate_document(self.output, self.nested_doc_start)?;
+        terminate_document(self.output, self.doc_start)?;
         Ok(())
     }
 }
@@ -395,9 +648,10 @@ impl<'a, B: BytesLikeBuf> serde::ser::SerializeTupleStruct for TupleStructSerial
 }
 
 pub struct SeqSerializer<'a, B: BytesLikeBuf> {
-    original_output: &'a mut B,
-    doc_output: B::Out,
+    output: &'a mut B,
+    start: usize,
     key: usize,
+    options: Options,
 }
 
 impl<'a, B: BytesLikeBuf> serde::ser::SerializeSeq for SeqSerializer<'a, B> {
@@ -408,23 +662,28 @@ impl<'a, B: BytesLikeBuf> serde::ser::SerializeSeq for SeqSerializer<'a, B> {
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(Serializer {
-            key: Some(DocumentKey::Int(self.key)),
-            output: &mut self.doc_output,
-        })?;
+        value
+            .serialize(Serializer {
+                key: Some(DocumentKey::Int(self.key)),
+                output: &mut *self.output,
+                options: self.options,
+            })
+            .map_err(|e| e.in_element(self.key))?;
         self.key += 1;
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        terminate_document(self.original_output, self.doc_output);
+        terminate_document(self.output, self.start)?;
         Ok(())
     }
 }
 
 pub struct StructSerializer<'a, B: BytesLikeBuf> {
-    original_output: &'a mut B,
-    doc_output: B::Out,
+    output: &'a mut B,
+    start: usize,
+    elements: Vec<usize>,
+    options: Options,
 }
 
 impl<'a, B: BytesLikeBuf> serde::ser::SerializeStruct for StructSerializer<'a, B> {
@@ -432,60 +691,428 @@ impl<'a, B: BytesLikeBuf> serde::ser::SerializeStruct for StructSerializer<'a, B
     type Error = <Serializer<'a, B> as serde::Serializer>::Error;
 
     fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        if self.options.sort_keys {
+            self.elements.push(self.output.len());
+        }
+
+        value
+            .serialize(Serializer {
+                key: Some(self.options.field_key(key)),
+                output: &mut *self.output,
+                options: self.options,
+            })
+            .map_err(|e| e.in_field(key))
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        sort_document_elements(self.output, &self.elements)?;
+        terminate_document(self.output, self.start)?;
+        Ok(())
+    }
+}
+
+pub struct MapSerializer<'a, B: BytesLikeBuf> {
+    output: &'a mut B,
+    start: usize,
+    key: Option<usize>,
+    elements: Vec<usize>,
+    options: Options,
+}
+
+impl<'a, B: BytesLikeBuf> MapSerializer<'a, B> {
+    fn serialize_value_at<T>(&mut self, at: usize, value: &T) -> Result<(), Error>
     where
         T: ?Sized + Serialize,
     {
         value.serialize(Serializer {
-            key: Some(DocumentKey::Str(key)),
-            output: &mut self.doc_output,
+            key: Some(DocumentKey::Written(at)),
+            output: &mut *self.output,
+            options: self.options,
+        })
+    }
+}
+
+impl<'a, B: BytesLikeBuf> serde::ser::SerializeMap for MapSerializer<'a, B> {
+    type Ok = ();
+    type Error = <Serializer<'a, B> as serde::Serializer>::Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        // map keys aren't `&'static str`s so we can't hold onto them until the value is
+        // serialised, instead we'll write the key straight away with a placeholder for the
+        // element type which is filled in once we know what the value is
+        let at = self.output.len();
+        self.output.put_u8(0x00);
+        key.serialize(MapKeySerializer {
+            output: &mut *self.output,
+        })?;
+        self.output.put_u8(0x00);
+
+        if self.options.sort_keys {
+            self.elements.push(at);
+        }
+
+        self.key = Some(at);
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let at = self
+            .key
+            .take()
+            .expect("serialize_value called before serialize_key");
+
+        self.serialize_value_at(at, value).map_err(|e| {
+            // we've already written the key so we'll read it back from the buffer if we can
+            match self.output.as_mut_slice() {
+                Some(doc) => {
+                    let key = &doc[at + 1..];
+                    let key = &key[..memchr::memchr(0x00, key).unwrap_or(key.len())];
+                    e.in_field(&String::from_utf8_lossy(key))
+                }
+                None => e.in_field("?"),
+            }
+        })
+    }
+
+    fn serialize_entry<K, V>(&mut self, key: &K, value: &V) -> Result<(), Self::Error>
+    where
+        K: ?Sized + Serialize,
+        V: ?Sized + Serialize,
+    {
+        self.serialize_key(key)?;
+        let at = self.key.take().expect("key was just serialised");
+
+        self.serialize_value_at(at, value).map_err(|e| {
+            // not every buffer holds onto the key for us to read back, but since we've still
+            // got the key around we can just write it out again
+            let mut key_buf = bytes::BytesMut::new();
+            match key.serialize(MapKeySerializer {
+                output: &mut key_buf,
+            }) {
+                Ok(()) => e.in_field(&String::from_utf8_lossy(&key_buf)),
+                Err(_) => e.in_field("?"),
+            }
         })
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        terminate_document(self.original_output, self.doc_output);
+        sort_document_elements(self.output, &self.elements)?;
+        terminate_document(self.output, self.start)?;
         Ok(())
     }
 }
 
+/// Writes a map key as a cstring, keys in bson are always strings so we'll only accept types
+/// that have an obvious string representation.
+struct MapKeySerializer<'a, B: BytesLikeBuf> {
+    output: &'a mut B,
+}
+
+macro_rules! serialize_int_key {
+    ($($method:ident($ty:ty),)*) => {
+        $(
+            fn $method(self, v: $ty) -> Result<Self::Ok, Self::Error> {
+                let mut itoa = itoa::Buffer::new();
+                self.output.put_slice(itoa.format(v).as_bytes());
+                Ok(())
+            }
+        )*
+    };
+}
+
+impl<'a, B: BytesLikeBuf> serde::Serializer for MapKeySerializer<'a, B> {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = Impossible<Self::Ok, Self::Error>;
+    type SerializeTuple = Impossible<Self::Ok, Self::Error>;
+    type SerializeTupleStruct = Impossible<Self::Ok, Self::Error>;
+    type SerializeTupleVariant = Impossible<Self::Ok, Self::Error>;
+    type SerializeMap = Impossible<Self::Ok, Self::Error>;
+    type SerializeStruct = Impossible<Self::Ok, Self::Error>;
+    type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        // keys are null terminated so we can't allow any nulls in the key itself
+        if memchr::memchr(0x00, v.as_bytes()).is_some() {
+            return Err(Error::KeyContainsNullByte);
+        }
+
+        self.output.put_slice(v.as_bytes());
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        let mut buf = [0; 4];
+        self.serialize_str(v.encode_utf8(&mut buf))
+    }
+
+    serialize_int_key! {
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<Self::Ok, Self::Error> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok, Self::Error> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok, Self::Error> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_some<T>(self, _value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Err(Error::KeyMustBeAString)
+    }
+}
+
 pub enum DocumentKey {
     Str(&'static str),
+    String(String),
     Int(usize),
+    /// Offset of a key that has already been written to the buffer, prefixed with a placeholder
+    /// element type that needs filling in.
+    Written(usize),
+}
+
+/// Number of array indices we keep pre-formatted keys for in `INDEX_KEYS`.
+const INDEX_KEY_COUNT: usize = 1000;
+
+/// Total length of the string representations of `0..INDEX_KEY_COUNT`.
+const INDEX_KEY_BYTES: usize = 10 + 90 * 2 + 900 * 3;
+
+/// The keys `"0"` through `"999"` concatenated together, along with the offset each one starts
+/// at, so the majority of arrays never need to format their keys.
+static INDEX_KEYS: ([u8; INDEX_KEY_BYTES], [u16; INDEX_KEY_COUNT + 1]) = build_index_keys();
+
+const fn build_index_keys() -> ([u8; INDEX_KEY_BYTES], [u16; INDEX_KEY_COUNT + 1]) {
+    let mut bytes = [0; INDEX_KEY_BYTES];
+    let mut offsets = [0; INDEX_KEY_COUNT + 1];
+
+    let mut i = 0;
+    let mut at = 0;
+
+    while i < INDEX_KEY_COUNT {
+        offsets[i] = at as u16;
+
+        if i >= 100 {
+            bytes[at] = b'0' + (i / 100) as u8;
+            at += 1;
+        }
+
+        if i >= 10 {
+            bytes[at] = b'0' + (i / 10 % 10) as u8;
+            at += 1;
+        }
+
+        bytes[at] = b'0' + (i % 10) as u8;
+        at += 1;
+
+        i += 1;
+    }
+
+    offsets[INDEX_KEY_COUNT] = at as u16;
+
+    (bytes, offsets)
 }
 
 impl DocumentKey {
     pub fn write_to_buf<B: BytesLikeBuf>(&self, buf: &mut B) {
         match self {
             Self::Str(s) => buf.put_slice(s.as_bytes()),
+            Self::String(s) => buf.put_slice(s.as_bytes()),
+            Self::Int(i) if *i < INDEX_KEY_COUNT => {
+                let (bytes, offsets) = &INDEX_KEYS;
+                buf.put_slice(&bytes[offsets[*i] as usize..offsets[*i + 1] as usize]);
+            }
             Self::Int(i) => {
                 let mut itoa = itoa::Buffer::new();
                 buf.put_slice(itoa.format(*i).as_bytes());
             }
+            Self::Written(_) => {}
         }
     }
 }
 
-pub fn start_document<B: BytesLikeBuf>(buffer: &mut B) -> B::Out {
-    let len = buffer.len();
+/// Reserves an i32 at the end of `buffer` for the length of the document that's about to be
+/// written, returning the offset of the document so it can be patched by `terminate_document`.
+pub fn start_document<B: BytesLikeBuf>(buffer: &mut B) -> usize {
+    let start = buffer.len();
+    buffer.put_i32_le(0);
+    start
+}
 
-    // splits the output for the doc to be written to, this is appended back onto to the
-    // output when `StructSerializer::close` is called.
-    let mut doc_output = buffer.split_off(len);
+/// Terminates the document started at `start` and writes its total length to the i32 reserved
+/// by `start_document`, failing if the document has grown too large for its length to be encoded.
+pub fn terminate_document<B: BytesLikeBuf>(buffer: &mut B, start: usize) -> Result<(), Error> {
+    buffer.put_u8(0x00); // doc terminator
 
-    // reserves a i32 we can write the document size to later
-    doc_output.put_i32_le(0);
+    let len = buffer.len() - start;
+    let len = i32::try_from(len).map_err(|_| Error::DocumentTooLarge(len))?;
+    for (i, byte) in len.to_le_bytes().iter().enumerate() {
+        let byte_ref = buffer.byte_mut(start + i);
+        debug_assert_eq!(*byte_ref, 0, "document didn't reserve bytes for the length");
+        *byte_ref = *byte;
+    }
 
-    doc_output
+    Ok(())
 }
 
-pub fn terminate_document<B: BytesLikeBuf>(original_buffer: &mut B, mut document: B::Out) {
-    document.put_u8(0x00); // doc terminator
+/// Reorders the elements of the unterminated document at the end of `buffer` by their keys,
+/// `elements` holds the offset of each element within the buffer in the order they were written.
+fn sort_document_elements<B: BytesLikeBuf>(
+    document: &mut B,
+    elements: &[usize],
+) -> Result<(), Error> {
+    if elements.len() < 2 {
+        return Ok(());
+    }
 
-    // writes the total length of the output to the i32 we reserved earlier
-    for (i, byte) in (document.len() as i32).to_le_bytes().iter().enumerate() {
-        let byte_ref = document.byte_mut(i);
-        debug_assert_eq!(*byte_ref, 0, "document didn't reserve bytes for the length");
-        *byte_ref = *byte;
+    // buffers that are only counting or keeping part of the output don't care about ordering
+    let discards_output = document.discards_output();
+    let document = match document.as_mut_slice() {
+        Some(document) => document,
+        None if discards_output => return Ok(()),
+        None => return Err(Error::UnsortableOutput),
+    };
+
+    let element_range = |i: usize| {
+        let end = elements.get(i + 1).copied().unwrap_or(document.len());
+        elements[i]..end
+    };
+
+    // skips the element type, keys run up until the null terminator
+    let key = |i: usize| {
+        let element = &document[element_range(i)][1..];
+        &element[..memchr::memchr(0x00, element).unwrap_or(element.len())]
+    };
+
+    let mut order: Vec<usize> = (0..elements.len()).collect();
+    order.sort_by(|a, b| key(*a).cmp(key(*b)));
+
+    let mut sorted = Vec::with_capacity(document.len() - elements[0]);
+    for i in order {
+        sorted.extend_from_slice(&document[element_range(i)]);
     }
 
-    original_buffer.unsplit(document);
+    document[elements[0]..].copy_from_slice(&sorted);
+    Ok(())
 }
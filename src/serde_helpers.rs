@@ -0,0 +1,772 @@
+//! Ready-made adapters for use with `#[serde(with = "...")]`, for fields whose Rust type doesn't
+//! map onto the bson type they should be stored as.
+//!
+//! ```
+//! # use serde::{Deserialize, Serialize};
+//! #[derive(Serialize, Deserialize)]
+//! struct A {
+//!     #[serde(with = "serde_bson::serde_helpers::u64_as_i64")]
+//!     count: u64,
+//!     #[serde(with = "serde_bson::serde_helpers::hex_string_as_object_id")]
+//!     id: String,
+//! }
+//! ```
+//!
+//! Values written as a bson-specific type, such as an ObjectId or datetime, are only understood
+//! by this crate's serializer.
+//!
+//! Datetimes are milliseconds since the epoch as an `i64`, which doesn't line up exactly with the
+//! range of any of the Rust types they're converted to and from. The datetime adapters fail on
+//! anything out of range in either direction, and each has a `saturating` module alongside it
+//! that clamps to the nearest value that can be represented instead, e.g.
+//! `system_time_as_bson_datetime::saturating`.
+
+use crate::RawBson;
+use serde::{de::Error as _, ser::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use std::convert::TryFrom;
+
+/// Stores a `u64` as an int64, failing if it's too large to fit rather than writing it as a
+/// decimal128.
+pub mod u64_as_i64 {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(v: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        let v = i64::try_from(*v).map_err(|_| S::Error::custom("u64 is too large for an i64"))?;
+        serializer.serialize_i64(v)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        let v = i64::deserialize(deserializer)?;
+        u64::try_from(v).map_err(|_| D::Error::custom("negative i64 can't be read as a u64"))
+    }
+}
+
+/// Stores a `u64` as a timestamp (0x11), the internal MongoDB type made up of an increment in the
+/// low 32 bits and seconds since the epoch in the high 32 bits.
+pub mod timestamp_as_u64 {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(v: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        RawBson::new(0x11, &v.to_le_bytes()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        u64::deserialize(deserializer)
+    }
+}
+
+/// Stores a [`SystemTime`](std::time::SystemTime) as a datetime (0x09), anything finer than a
+/// millisecond is truncated.
+pub mod system_time_as_bson_datetime {
+    use super::*;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    pub fn serialize<S: Serializer>(v: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+        let millis = to_millis(v).ok_or_else(|| S::Error::custom("datetime is out of range"))?;
+
+        RawBson::new(0x09, &millis.to_le_bytes()).serialize(serializer)
+    }
+
+    /// Milliseconds since the epoch, or `None` if they don't fit in an `i64`.
+    pub(crate) fn to_millis(v: &SystemTime) -> Option<i64> {
+        match v.duration_since(UNIX_EPOCH) {
+            Ok(since) => i64::try_from(since.as_millis()).ok(),
+            // rounds towards the start of time so pre-epoch values don't get pulled forwards
+            Err(e) => {
+                let before = e.duration();
+                let millis =
+                    before.as_millis() + u128::from(before.subsec_nanos() % 1_000_000 != 0);
+                i64::try_from(millis).ok().map(|v| -v)
+            }
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+        let millis = i64::deserialize(deserializer)?;
+        from_offset(millis < 0, millis.unsigned_abs())
+            .ok_or_else(|| D::Error::custom("datetime is out of range"))
+    }
+
+    /// The time `millis` before or after the epoch, or `None` if the platform can't represent it.
+    fn from_offset(before: bool, millis: u64) -> Option<SystemTime> {
+        let offset = Duration::from_millis(millis);
+
+        if before {
+            UNIX_EPOCH.checked_sub(offset)
+        } else {
+            UNIX_EPOCH.checked_add(offset)
+        }
+    }
+
+    /// Clamps datetimes to `i64::MIN` and `i64::MAX` milliseconds when writing, and to the
+    /// earliest or latest time the platform can represent when reading.
+    pub mod saturating {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(v: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+            let millis = to_millis(v).unwrap_or(if *v < UNIX_EPOCH { i64::MIN } else { i64::MAX });
+
+            RawBson::new(0x09, &millis.to_le_bytes()).serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<SystemTime, D::Error> {
+            let millis = i64::deserialize(deserializer)?;
+            let before = millis < 0;
+
+            if let Some(v) = from_offset(before, millis.unsigned_abs()) {
+                return Ok(v);
+            }
+
+            // the range of a `SystemTime` differs between platforms, so search for the furthest
+            // it goes in this direction, `fits` always can be represented and `overflows` can't
+            let (mut fits, mut overflows) = (0, millis.unsigned_abs());
+            while overflows - fits > 1 {
+                let mid = fits + (overflows - fits) / 2;
+
+                if from_offset(before, mid).is_some() {
+                    fits = mid;
+                } else {
+                    overflows = mid;
+                }
+            }
+
+            Ok(from_offset(before, fits).unwrap())
+        }
+    }
+}
+
+/// Stores a [`Duration`](std::time::Duration) as an int64 number of milliseconds, anything finer
+/// is truncated.
+pub mod duration_as_i64_millis {
+    use super::*;
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(v: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        let millis = i64::try_from(v.as_millis())
+            .map_err(|_| S::Error::custom("duration is too long for an i64"))?;
+        serializer.serialize_i64(millis)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let millis = i64::deserialize(deserializer)?;
+        u64::try_from(millis)
+            .map(Duration::from_millis)
+            .map_err(|_| D::Error::custom("duration can't be negative"))
+    }
+}
+
+/// Stores a hex string as an ObjectId (0x07), and reads ObjectIds back as lowercase hex strings.
+pub mod hex_string_as_object_id {
+    use super::*;
+    use serde::de::Visitor;
+    use std::fmt;
+
+    pub fn serialize<S: Serializer, T: AsRef<str>>(
+        v: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let id = parse_object_id(v.as_ref())
+            .ok_or_else(|| S::Error::custom("expected a 24 character hex string"))?;
+        RawBson::new(0x07, &id).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+        struct ObjectIdVisitor;
+
+        impl<'de> Visitor<'de> for ObjectIdVisitor {
+            type Value = String;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an ObjectId")
+            }
+
+            fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                if v.len() != 12 {
+                    return Err(E::invalid_length(v.len(), &self));
+                }
+
+                Ok(crate::text::hex(v))
+            }
+        }
+
+        deserializer.deserialize_bytes(ObjectIdVisitor)
+    }
+
+    fn parse_object_id(v: &str) -> Option<[u8; 12]> {
+        if v.len() != 24 {
+            return None;
+        }
+
+        let mut id = [0; 12];
+
+        for (out, pair) in id.iter_mut().zip(v.as_bytes().chunks_exact(2)) {
+            let pair = std::str::from_utf8(pair).ok()?;
+            *out = u8::from_str_radix(pair, 16).ok()?;
+        }
+
+        Some(id)
+    }
+}
+
+/// Stores a [`uuid::Uuid`] as binary with the UUID subtype (0x04).
+#[cfg(feature = "uuid")]
+pub mod uuid_as_binary {
+    use super::*;
+    use serde::de::Visitor;
+    use std::fmt;
+    use uuid::Uuid;
+
+    pub fn serialize<S: Serializer>(v: &Uuid, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut value = [0; 4 + 1 + 16];
+        value[..4].copy_from_slice(&16_i32.to_le_bytes());
+        value[4] = 0x04;
+        value[5..].copy_from_slice(v.as_bytes());
+
+        RawBson::new(0x05, &value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Uuid, D::Error> {
+        struct UuidVisitor;
+
+        impl<'de> Visitor<'de> for UuidVisitor {
+            type Value = Uuid;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("16 bytes")
+            }
+
+            fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                Uuid::from_slice(v).map_err(|_| E::invalid_length(v.len(), &self))
+            }
+        }
+
+        deserializer.deserialize_bytes(UuidVisitor)
+    }
+}
+
+/// Stores a [`chrono::DateTime<Utc>`] as a datetime (0x09), which only has millisecond
+/// precision so anything finer is truncated.
+#[cfg(feature = "chrono")]
+pub mod chrono_datetime_as_bson_datetime {
+    use super::*;
+    use chrono::{DateTime, Utc};
+
+    pub fn serialize<S: Serializer>(v: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+        RawBson::new(0x09, &v.timestamp_millis().to_le_bytes()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<DateTime<Utc>, D::Error> {
+        let millis = i64::deserialize(deserializer)?;
+        DateTime::from_timestamp_millis(millis)
+            .ok_or_else(|| D::Error::custom("datetime is out of range"))
+    }
+
+    /// Clamps datetimes beyond the roughly 262,000 years either side of year 0 that chrono
+    /// supports to [`DateTime::<Utc>::MIN_UTC`] and [`DateTime::<Utc>::MAX_UTC`] when reading.
+    /// Every `DateTime<Utc>` fits when writing.
+    pub mod saturating {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(
+            v: &DateTime<Utc>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            super::serialize(v, serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<DateTime<Utc>, D::Error> {
+            let millis = i64::deserialize(deserializer)?;
+
+            Ok(
+                DateTime::from_timestamp_millis(millis).unwrap_or(if millis < 0 {
+                    DateTime::<Utc>::MIN_UTC
+                } else {
+                    DateTime::<Utc>::MAX_UTC
+                }),
+            )
+        }
+    }
+}
+
+/// Stores a [`time::OffsetDateTime`] as a datetime (0x09). The offset isn't kept, values are
+/// always read back in UTC, and anything finer than a millisecond is truncated.
+#[cfg(feature = "time")]
+pub mod time_offset_datetime_as_bson_datetime {
+    use super::*;
+    use time::OffsetDateTime;
+
+    pub fn serialize<S: Serializer>(v: &OffsetDateTime, serializer: S) -> Result<S::Ok, S::Error> {
+        // rounds towards the start of time so pre-epoch values don't get pulled forwards
+        let millis = i64::try_from(v.unix_timestamp_nanos().div_euclid(1_000_000))
+            .map_err(|_| S::Error::custom("datetime is out of range"))?;
+        RawBson::new(0x09, &millis.to_le_bytes()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<OffsetDateTime, D::Error> {
+        let millis = i64::deserialize(deserializer)?;
+        OffsetDateTime::from_unix_timestamp_nanos(i128::from(millis) * 1_000_000)
+            .map_err(|_| D::Error::custom("datetime is out of range"))
+    }
+
+    /// Clamps datetimes beyond the years `time` supports to the first and last moments it can
+    /// represent when reading, and to `i64::MIN` and `i64::MAX` milliseconds when writing.
+    pub mod saturating {
+        use super::*;
+        use time::PrimitiveDateTime;
+
+        pub fn serialize<S: Serializer>(
+            v: &OffsetDateTime,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            let millis = v.unix_timestamp_nanos().div_euclid(1_000_000);
+            let millis =
+                i64::try_from(millis).unwrap_or(if millis < 0 { i64::MIN } else { i64::MAX });
+
+            RawBson::new(0x09, &millis.to_le_bytes()).serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<OffsetDateTime, D::Error> {
+            let millis = i64::deserialize(deserializer)?;
+
+            Ok(
+                OffsetDateTime::from_unix_timestamp_nanos(i128::from(millis) * 1_000_000)
+                    .unwrap_or_else(|_| {
+                        if millis < 0 {
+                            PrimitiveDateTime::MIN.assume_utc()
+                        } else {
+                            PrimitiveDateTime::MAX.assume_utc()
+                        }
+                    }),
+            )
+        }
+    }
+}
+
+/// Stores a [`time::PrimitiveDateTime`] as a datetime (0x09), treating it as being in UTC.
+#[cfg(feature = "time")]
+pub mod time_primitive_datetime_as_bson_datetime {
+    use super::*;
+    use time::PrimitiveDateTime;
+
+    pub fn serialize<S: Serializer>(
+        v: &PrimitiveDateTime,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        super::time_offset_datetime_as_bson_datetime::serialize(&v.assume_utc(), serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<PrimitiveDateTime, D::Error> {
+        let v = super::time_offset_datetime_as_bson_datetime::deserialize(deserializer)?;
+        Ok(PrimitiveDateTime::new(v.date(), v.time()))
+    }
+
+    /// The same clamping as [`super::time_offset_datetime_as_bson_datetime::saturating`].
+    pub mod saturating {
+        use super::*;
+        use crate::serde_helpers::time_offset_datetime_as_bson_datetime::saturating as offset;
+
+        pub fn serialize<S: Serializer>(
+            v: &PrimitiveDateTime,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            offset::serialize(&v.assume_utc(), serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<PrimitiveDateTime, D::Error> {
+            let v = offset::deserialize(deserializer)?;
+            Ok(PrimitiveDateTime::new(v.date(), v.time()))
+        }
+    }
+}
+
+/// Stores a [`rust_decimal::Decimal`] as a decimal128 (0x13), failing to read back values that
+/// can't be represented exactly.
+#[cfg(feature = "rust_decimal")]
+pub mod rust_decimal_as_decimal128 {
+    use super::*;
+    use crate::Decimal128;
+    use rust_decimal::Decimal;
+
+    pub fn serialize<S: Serializer>(v: &Decimal, serializer: S) -> Result<S::Ok, S::Error> {
+        Decimal128::from_rust_decimal(*v).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Decimal, D::Error> {
+        Decimal128::deserialize(deserializer)?
+            .to_rust_decimal()
+            .ok_or_else(|| D::Error::custom("decimal128 is out of range for a Decimal"))
+    }
+}
+
+/// Stores a [`bigdecimal::BigDecimal`] as a decimal128 (0x13), failing on values with more than
+/// the 34 significant digits a decimal128 can hold. Use
+/// [`Decimal128::from_bigdecimal_rounded`](crate::Decimal128::from_bigdecimal_rounded) to round
+/// them first.
+#[cfg(feature = "bigdecimal")]
+pub mod bigdecimal_as_decimal128 {
+    use super::*;
+    use crate::Decimal128;
+    use bigdecimal::BigDecimal;
+
+    pub fn serialize<S: Serializer>(v: &BigDecimal, serializer: S) -> Result<S::Ok, S::Error> {
+        Decimal128::from_bigdecimal(v)
+            .ok_or_else(|| S::Error::custom("BigDecimal can't be represented as a decimal128"))?
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigDecimal, D::Error> {
+        Decimal128::deserialize(deserializer)?
+            .to_bigdecimal()
+            .ok_or_else(|| D::Error::custom("decimal128 isn't a finite number"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::{Deserialize, Serialize};
+
+    #[test]
+    fn helpers() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            #[serde(with = "super::u64_as_i64")]
+            a: u64,
+            #[serde(with = "super::timestamp_as_u64")]
+            b: u64,
+            #[serde(with = "super::hex_string_as_object_id")]
+            c: String,
+        }
+
+        let val = A {
+            a: 1,
+            b: (2 << 32) | 3,
+            c: "0102030405060708090a0b0c".to_string(),
+        };
+
+        let ours = crate::to_bytes(&val).unwrap();
+        assert_eq!(
+            bson::Document::from_reader(&ours[..]).unwrap(),
+            bson::doc! {
+                "a": 1_i64,
+                "b": bson::Timestamp { time: 2, increment: 3 },
+                "c": bson::oid::ObjectId::parse_str("0102030405060708090a0b0c").unwrap(),
+            }
+        );
+        assert_eq!(crate::de::from_bytes::<A>(&ours).unwrap(), val);
+
+        let invalid = A { a: u64::MAX, ..val };
+        assert!(crate::to_bytes(&invalid).is_err());
+
+        let invalid = A {
+            a: 1,
+            c: "not hex".to_string(),
+            ..invalid
+        };
+        assert!(crate::to_bytes(&invalid).is_err());
+
+        let negative = crate::to_bytes(&bson::doc! { "a": -1_i64, "b": 0_i64, "c": "" }).unwrap();
+        assert!(crate::de::from_bytes::<A>(&negative).is_err());
+    }
+
+    #[test]
+    fn std_time() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            #[serde(with = "super::system_time_as_bson_datetime")]
+            a: std::time::SystemTime,
+            #[serde(with = "super::system_time_as_bson_datetime")]
+            b: std::time::SystemTime,
+            #[serde(with = "super::duration_as_i64_millis")]
+            c: Duration,
+        }
+
+        let val = A {
+            a: UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+            b: UNIX_EPOCH - Duration::from_micros(1_500),
+            c: Duration::from_millis(1_500),
+        };
+
+        let ours = crate::to_bytes(&val).unwrap();
+        assert_eq!(
+            bson::Document::from_reader(&ours[..]).unwrap(),
+            bson::doc! {
+                "a": bson::DateTime::from_millis(1_700_000_000_123),
+                "b": bson::DateTime::from_millis(-2),
+                "c": 1_500_i64,
+            }
+        );
+
+        let deserialized: A = crate::de::from_bytes(&ours).unwrap();
+        assert_eq!(deserialized.a, val.a);
+        assert_eq!(deserialized.b, UNIX_EPOCH - Duration::from_millis(2));
+        assert_eq!(deserialized.c, val.c);
+
+        let negative = crate::to_bytes(&bson::doc! {
+            "a": bson::DateTime::from_millis(0),
+            "b": bson::DateTime::from_millis(0),
+            "c": -1_i64,
+        })
+        .unwrap();
+        assert!(crate::de::from_bytes::<A>(&negative).is_err());
+    }
+
+    #[test]
+    fn std_time_saturating() {
+        use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct Checked {
+            #[serde(with = "super::system_time_as_bson_datetime")]
+            a: SystemTime,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct Saturating {
+            #[serde(with = "super::system_time_as_bson_datetime::saturating")]
+            a: SystemTime,
+        }
+
+        // a long way past what fits in an i64 of milliseconds, where the platform allows it
+        let far = UNIX_EPOCH.checked_add(Duration::from_secs(u64::MAX / 4));
+        if let Some(far) = far {
+            assert!(crate::to_bytes(&Checked { a: far }).is_err());
+
+            let ours = crate::to_bytes(&Saturating { a: far }).unwrap();
+            assert_eq!(
+                bson::Document::from_reader(&ours[..]).unwrap(),
+                bson::doc! { "a": bson::DateTime::MAX }
+            );
+        }
+
+        // in range is unaffected
+        let val = Saturating {
+            a: UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+        };
+        let ours = crate::to_bytes(&val).unwrap();
+        assert_eq!(crate::de::from_bytes::<Saturating>(&ours).unwrap(), val);
+
+        // and reading always succeeds, at whatever the platform's limit is
+        for millis in [i64::MIN, i64::MAX].iter() {
+            let ours = bson::to_vec(&bson::doc! {
+                "a": bson::DateTime::from_millis(*millis),
+            })
+            .unwrap();
+
+            let read = crate::de::from_bytes::<Saturating>(&ours).unwrap().a;
+            let checked = crate::de::from_bytes::<Checked>(&ours);
+
+            match checked {
+                Ok(checked) => assert_eq!(read, checked.a),
+                Err(_) if *millis < 0 => assert!(read < UNIX_EPOCH),
+                Err(_) => assert!(read > UNIX_EPOCH),
+            }
+        }
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn uuid_as_binary() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            #[serde(with = "super::uuid_as_binary")]
+            a: uuid::Uuid,
+        }
+
+        let val = A {
+            a: uuid::Uuid::from_bytes([7; 16]),
+        };
+
+        let ours = crate::to_bytes(&val).unwrap();
+        assert_eq!(
+            bson::Document::from_reader(&ours[..]).unwrap(),
+            bson::doc! {
+                "a": bson::Binary { subtype: bson::spec::BinarySubtype::Uuid, bytes: vec![7; 16] },
+            }
+        );
+        assert_eq!(crate::de::from_bytes::<A>(&ours).unwrap(), val);
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn chrono_datetime_as_bson_datetime() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            #[serde(with = "super::chrono_datetime_as_bson_datetime")]
+            a: chrono::DateTime<chrono::Utc>,
+        }
+
+        let val = A {
+            a: chrono::DateTime::from_timestamp_millis(1_700_000_000_123).unwrap(),
+        };
+
+        let ours = crate::to_bytes(&val).unwrap();
+        assert_eq!(
+            bson::Document::from_reader(&ours[..]).unwrap(),
+            bson::doc! { "a": bson::DateTime::from_millis(1_700_000_000_123) }
+        );
+        assert_eq!(crate::de::from_bytes::<A>(&ours).unwrap(), val);
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct Saturating {
+            #[serde(with = "super::chrono_datetime_as_bson_datetime::saturating")]
+            a: chrono::DateTime<chrono::Utc>,
+        }
+
+        for (millis, expected) in [
+            (i64::MIN, chrono::DateTime::<chrono::Utc>::MIN_UTC),
+            (i64::MAX, chrono::DateTime::<chrono::Utc>::MAX_UTC),
+            (1_700_000_000_123, val.a),
+        ]
+        .iter()
+        {
+            let ours = bson::to_vec(&bson::doc! {
+                "a": bson::DateTime::from_millis(*millis),
+            })
+            .unwrap();
+
+            assert_eq!(
+                crate::de::from_bytes::<A>(&ours).is_ok(),
+                *millis == 1_700_000_000_123
+            );
+            assert_eq!(
+                crate::de::from_bytes::<Saturating>(&ours).unwrap().a,
+                *expected
+            );
+        }
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn time_datetime_as_bson_datetime() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            #[serde(with = "super::time_offset_datetime_as_bson_datetime")]
+            a: time::OffsetDateTime,
+            #[serde(with = "super::time_primitive_datetime_as_bson_datetime")]
+            b: time::PrimitiveDateTime,
+        }
+
+        let a = time::OffsetDateTime::from_unix_timestamp_nanos(-1_500_000).unwrap();
+        let b = time::OffsetDateTime::from_unix_timestamp_nanos(1_700_000_000_123_000_000).unwrap();
+        let val = A {
+            a,
+            b: time::PrimitiveDateTime::new(b.date(), b.time()),
+        };
+
+        let ours = crate::to_bytes(&val).unwrap();
+        assert_eq!(
+            bson::Document::from_reader(&ours[..]).unwrap(),
+            bson::doc! {
+                "a": bson::DateTime::from_millis(-2),
+                "b": bson::DateTime::from_millis(1_700_000_000_123),
+            }
+        );
+
+        let deserialized: A = crate::de::from_bytes(&ours).unwrap();
+        assert_eq!(deserialized.a.unix_timestamp_nanos(), -2_000_000);
+        assert_eq!(deserialized.b, val.b);
+
+        // bson datetimes go far beyond what time can represent
+        let out_of_range = bson::to_vec(&bson::doc! {
+            "a": bson::DateTime::MAX,
+            "b": bson::DateTime::MAX,
+        })
+        .unwrap();
+        assert!(crate::de::from_bytes::<A>(&out_of_range).is_err());
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct Saturating {
+            #[serde(with = "super::time_offset_datetime_as_bson_datetime::saturating")]
+            a: time::OffsetDateTime,
+            #[serde(with = "super::time_primitive_datetime_as_bson_datetime::saturating")]
+            b: time::PrimitiveDateTime,
+        }
+
+        let saturated = crate::de::from_bytes::<Saturating>(&out_of_range).unwrap();
+        assert_eq!(saturated.a, time::PrimitiveDateTime::MAX.assume_utc());
+        assert_eq!(saturated.b, time::PrimitiveDateTime::MAX);
+
+        let out_of_range = bson::to_vec(&bson::doc! {
+            "a": bson::DateTime::MIN,
+            "b": bson::DateTime::MIN,
+        })
+        .unwrap();
+        let saturated = crate::de::from_bytes::<Saturating>(&out_of_range).unwrap();
+        assert_eq!(saturated.b, time::PrimitiveDateTime::MIN);
+
+        // and in range round trips as before
+        let val = Saturating { a: val.a, b: val.b };
+        let ours = crate::to_bytes(&val).unwrap();
+        let deserialized: Saturating = crate::de::from_bytes(&ours).unwrap();
+        assert_eq!(deserialized.a.unix_timestamp_nanos(), -2_000_000);
+        assert_eq!(deserialized.b, val.b);
+    }
+
+    #[cfg(feature = "rust_decimal")]
+    #[test]
+    fn rust_decimal_as_decimal128() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            #[serde(with = "super::rust_decimal_as_decimal128")]
+            a: rust_decimal::Decimal,
+        }
+
+        let val = A {
+            a: "-12.340".parse().unwrap(),
+        };
+
+        let ours = crate::to_bytes(&val).unwrap();
+        assert_eq!(
+            bson::Document::from_reader(&ours[..]).unwrap(),
+            bson::doc! { "a": "-12.340".parse::<bson::Decimal128>().unwrap() }
+        );
+        assert_eq!(crate::de::from_bytes::<A>(&ours).unwrap(), val);
+
+        let out_of_range =
+            crate::to_bytes(&bson::doc! { "a": "1E+100".parse::<bson::Decimal128>().unwrap() })
+                .unwrap();
+        assert!(crate::de::from_bytes::<A>(&out_of_range).is_err());
+    }
+
+    #[cfg(feature = "bigdecimal")]
+    #[test]
+    fn bigdecimal_as_decimal128() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            #[serde(with = "super::bigdecimal_as_decimal128")]
+            a: bigdecimal::BigDecimal,
+        }
+
+        let val = A {
+            a: "-12.340".parse().unwrap(),
+        };
+
+        let ours = crate::to_bytes(&val).unwrap();
+        assert_eq!(
+            bson::Document::from_reader(&ours[..]).unwrap(),
+            bson::doc! { "a": "-12.340".parse::<bson::Decimal128>().unwrap() }
+        );
+        assert_eq!(crate::de::from_bytes::<A>(&ours).unwrap(), val);
+
+        let too_precise = A {
+            a: format!("0.{}", "6".repeat(40)).parse().unwrap(),
+        };
+        assert!(crate::to_bytes(&too_precise).is_err());
+    }
+}
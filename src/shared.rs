@@ -0,0 +1,189 @@
+//! Zero-copy strings and binaries for use with [`crate::de::from_bytes_shared`].
+//!
+//! Fields of type [`Bytes`] can be annotated with `#[serde(with = "serde_bson::shared")]`, and
+//! strings can be read into [`SharedStr`]. When deserialised from `from_bytes_shared` these are
+//! slices of the input that keep it alive through its refcount rather than copies, anywhere
+//! else they fall back to copying.
+
+use bytes::Bytes;
+use serde::{
+    de::{Error, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{cell::RefCell, fmt, ops::Deref};
+
+thread_local! {
+    static SOURCE: RefCell<Option<Bytes>> = const { RefCell::new(None) };
+}
+
+/// Makes `source` available to shared values for the duration of `f`.
+pub(crate) fn with_source<R>(source: &Bytes, f: impl FnOnce() -> R) -> R {
+    struct Restore(Option<Bytes>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SOURCE.with_borrow_mut(|source| *source = self.0.take());
+        }
+    }
+
+    // put back whatever was there before, even if we unwind
+    let _restore = Restore(SOURCE.with_borrow_mut(|current| current.replace(source.clone())));
+    f()
+}
+
+/// Returns `v` as a slice of the current source if it points into it, or a copy otherwise.
+fn slice_or_copy(v: &[u8]) -> Bytes {
+    SOURCE.with_borrow(|source| match source {
+        Some(source) if is_within(source, v) => source.slice_ref(v),
+        _ => Bytes::copy_from_slice(v),
+    })
+}
+
+fn is_within(source: &[u8], v: &[u8]) -> bool {
+    let source = source.as_ptr_range();
+    let v = v.as_ptr_range();
+    source.start <= v.start && v.end <= source.end
+}
+
+struct SharedBytesVisitor;
+
+impl<'de> Visitor<'de> for SharedBytesVisitor {
+    type Value = Bytes;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("bytes or a string")
+    }
+
+    fn visit_borrowed_bytes<E: Error>(self, v: &'de [u8]) -> Result<Self::Value, E> {
+        Ok(slice_or_copy(v))
+    }
+
+    fn visit_borrowed_str<E: Error>(self, v: &'de str) -> Result<Self::Value, E> {
+        Ok(slice_or_copy(v.as_bytes()))
+    }
+
+    fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(Bytes::copy_from_slice(v))
+    }
+
+    fn visit_str<E: Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(Bytes::copy_from_slice(v.as_bytes()))
+    }
+}
+
+pub fn serialize<S: Serializer>(v: &Bytes, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bytes(v)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Bytes, D::Error> {
+    deserializer.deserialize_bytes(SharedBytesVisitor)
+}
+
+/// An immutable string that may share its memory with the buffer it was deserialised from, see
+/// the [module docs](self).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SharedStr(Bytes);
+
+impl SharedStr {
+    /// The underlying buffer, which is always valid UTF-8.
+    pub fn into_bytes(self) -> Bytes {
+        self.0
+    }
+}
+
+impl Deref for SharedStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        // we only ever construct these from strings
+        unsafe { std::str::from_utf8_unchecked(&self.0) }
+    }
+}
+
+impl From<&str> for SharedStr {
+    fn from(v: &str) -> Self {
+        Self(Bytes::copy_from_slice(v.as_bytes()))
+    }
+}
+
+impl From<String> for SharedStr {
+    fn from(v: String) -> Self {
+        Self(Bytes::from(v))
+    }
+}
+
+impl fmt::Display for SharedStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+impl Serialize for SharedStr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for SharedStr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SharedStrVisitor;
+
+        impl<'de> Visitor<'de> for SharedStrVisitor {
+            type Value = SharedStr;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a string")
+            }
+
+            fn visit_borrowed_str<E: Error>(self, v: &'de str) -> Result<Self::Value, E> {
+                Ok(SharedStr(slice_or_copy(v.as_bytes())))
+            }
+
+            fn visit_str<E: Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(SharedStr::from(v))
+            }
+        }
+
+        deserializer.deserialize_str(SharedStrVisitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SharedStr;
+    use bytes::Bytes;
+    use serde::{Deserialize, Serialize};
+
+    #[test]
+    fn shared() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            a: SharedStr,
+            #[serde(with = "crate::shared")]
+            b: Bytes,
+        }
+
+        let val = A {
+            a: SharedStr::from("hello"),
+            b: Bytes::from_static(b"world"),
+        };
+
+        let data = crate::to_bytes(&val).unwrap();
+        let deserialized: A = crate::de::from_bytes_shared(data.clone()).unwrap();
+        assert_eq!(deserialized, val);
+        assert_eq!(&*deserialized.a, "hello");
+
+        // both point into the original buffer rather than being copies
+        assert!(super::is_within(&data, deserialized.a.as_bytes()));
+        assert!(super::is_within(&data, &deserialized.b));
+
+        // and outlive it
+        drop(data);
+        assert_eq!(&deserialized.b[..], b"world");
+
+        // which isn't the case anywhere else
+        let data = crate::to_bytes(&val).unwrap();
+        let deserialized: A = crate::de::from_bytes(&data).unwrap();
+        assert!(!super::is_within(&data, &deserialized.b));
+    }
+}
@@ -0,0 +1,244 @@
+use crate::{de::Error, scan::Elements};
+use core::convert::{TryFrom, TryInto};
+
+/// Running totals over the documents passed through [`crate::de::from_bytes_with_stats`] and
+/// [`crate::to_vec_with_stats`], for capacity planning and spotting unusual documents without
+/// parsing them a second time. The same `Stats` can be passed to any number of calls, and
+/// [`Stats::merge`] combines the totals of several.
+///
+/// When deserializing, the stats are gathered as the document is read, so anything the target
+/// type steps over without reading, such as a document in a field it ignores, only counts
+/// towards `bytes` and the depth of the field itself.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of documents processed.
+    pub documents: u64,
+    /// The total size of those documents.
+    pub bytes: u64,
+    /// How deeply documents and arrays were nested, where a document with no nesting has a
+    /// depth of 1.
+    pub max_depth: usize,
+    /// The length in bytes of the longest string, without its null terminator.
+    pub largest_string: usize,
+    /// The length in bytes of the largest binary.
+    pub largest_binary: usize,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the totals from `other` into these.
+    pub fn merge(&mut self, other: &Stats) {
+        self.documents += other.documents;
+        self.bytes += other.bytes;
+        self.max_depth = self.max_depth.max(other.max_depth);
+        self.largest_string = self.largest_string.max(other.largest_string);
+        self.largest_binary = self.largest_binary.max(other.largest_binary);
+    }
+
+    pub(crate) fn document(&mut self, len: usize) {
+        self.documents += 1;
+        self.bytes += len as u64;
+    }
+
+    pub(crate) fn depth(&mut self, depth: usize) {
+        self.max_depth = self.max_depth.max(depth);
+    }
+
+    pub(crate) fn string(&mut self, len: usize) {
+        self.largest_string = self.largest_string.max(len);
+    }
+
+    pub(crate) fn binary(&mut self, len: usize) {
+        self.largest_binary = self.largest_binary.max(len);
+    }
+
+    /// Records a document that's already been written, by stepping over its elements.
+    pub(crate) fn record(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.document(data.len());
+        self.record_elements(data, 0, 1)
+    }
+
+    fn record_elements(&mut self, data: &[u8], start: usize, depth: usize) -> Result<(), Error> {
+        self.depth(depth);
+
+        for element in Elements::new(data, start)? {
+            let element = element?;
+            let value = element.value.as_bytes();
+            // raw values are written as given, so their lengths can't be trusted
+            let malformed = || Error::InvalidLength(element.value_offset);
+
+            match element.value.element_type() {
+                // less the length prefix and null terminator
+                0x02 => self.string(value.len().checked_sub(5).ok_or_else(malformed)?),
+                // less the length prefix and subtype
+                0x05 => self.binary(value.len().checked_sub(5).ok_or_else(malformed)?),
+                0x03 | 0x04 => self.record_elements(data, element.value_offset, depth + 1)?,
+                0x0f => {
+                    // the scope follows the total length and the code
+                    let code_length = value.get(4..8).ok_or_else(malformed)?;
+                    let code_length = i32::from_le_bytes(code_length.try_into().unwrap());
+                    let scope = usize::try_from(code_length)
+                        .ok()
+                        .and_then(|length| (element.value_offset + 8).checked_add(length))
+                        .ok_or_else(malformed)?;
+                    self.record_elements(data, scope, depth + 1)?;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Stats;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct A {
+        name: String,
+        #[serde(with = "serde_bytes")]
+        data: Vec<u8>,
+        nested: Vec<Vec<i32>>,
+        ignored: Option<B>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct B {
+        a: B2,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct B2 {
+        long: String,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Partial {
+        name: String,
+    }
+
+    #[test]
+    fn stats() {
+        let val = A {
+            name: "ferris".to_string(),
+            data: vec![1, 2, 3],
+            nested: vec![vec![1], vec![]],
+            ignored: Some(B {
+                a: B2 {
+                    long: "a".repeat(20),
+                },
+            }),
+        };
+
+        let expected = Stats {
+            documents: 1,
+            bytes: 0,
+            max_depth: 3,
+            largest_string: 20,
+            largest_binary: 3,
+        };
+
+        let mut written = Stats::new();
+        let mut bytes = Vec::new();
+        crate::to_vec_with_stats(&val, &mut bytes, Default::default(), &mut written).unwrap();
+        assert_eq!(
+            written,
+            Stats {
+                bytes: bytes.len() as u64,
+                ..expected
+            }
+        );
+
+        let mut read = Stats::new();
+        let deserialized: A =
+            crate::de::from_bytes_with_stats(&bytes, Default::default(), &mut read).unwrap();
+        assert_eq!(deserialized, val);
+        assert_eq!(read, written);
+
+        // everything but `name` is stepped over
+        let mut partial = Stats::new();
+        crate::de::from_bytes_with_stats::<Partial>(&bytes, Default::default(), &mut partial)
+            .unwrap();
+        assert_eq!(
+            partial,
+            Stats {
+                max_depth: 2,
+                largest_string: 6,
+                largest_binary: 3,
+                ..written
+            }
+        );
+
+        // code with scope opens a document too
+        let mut scoped = crate::RawDocumentBuf::new();
+        scoped
+            .append_code_with_scope(
+                "f",
+                &crate::CodeWithScope {
+                    code: "x".to_string(),
+                    scope: crate::RawDocument(&bytes),
+                },
+            )
+            .unwrap();
+
+        let mut scoped_written = Stats::new();
+        let mut scoped_read = Stats::new();
+        scoped_written.record(scoped.as_bytes()).unwrap();
+        crate::de::from_bytes_with_stats::<bson::Document>(
+            scoped.as_bytes(),
+            Default::default(),
+            &mut scoped_read,
+        )
+        .unwrap();
+        assert_eq!(scoped_written.max_depth, 4);
+        assert_eq!(scoped_read, scoped_written);
+
+        // totals build up across calls
+        read.merge(&written);
+        assert_eq!(read.documents, 2);
+        assert_eq!(read.bytes, 2 * bytes.len() as u64);
+        assert_eq!(read.max_depth, 3);
+    }
+
+    #[test]
+    fn malformed_raw_values() {
+        #[derive(Serialize)]
+        struct Raw<'a> {
+            name: &'a str,
+            raw: crate::RawBson<'a>,
+        }
+
+        let mut code = 13_i32.to_le_bytes().to_vec();
+        code.extend_from_slice(&(-8_i32).to_le_bytes());
+        code.extend_from_slice(&[5, 0, 0, 0, 0]);
+
+        let values = [
+            // a string too short to hold its null terminator
+            crate::RawBson::new(0x02, &[0, 0, 0, 0]),
+            // the code's length is cut off
+            crate::RawBson::new(0x0f, &[4, 0, 0, 0]),
+            // a negative code length
+            crate::RawBson::new(0x0f, &code),
+        ];
+
+        for raw in values.iter().copied() {
+            let mut stats = Stats::new();
+            let mut bytes = Vec::new();
+            let val = Raw {
+                name: "ferris",
+                raw,
+            };
+            crate::to_vec_with_stats(&val, &mut bytes, Default::default(), &mut stats).unwrap();
+
+            // whatever came before the malformed value is still recorded
+            assert_eq!(stats.documents, 1, "{:?}", raw);
+            assert_eq!(stats.largest_string, 6, "{:?}", raw);
+        }
+    }
+}
@@ -0,0 +1,278 @@
+//! Text encodings of bson values, for handing them to types that can't represent them natively.
+
+use alloc::{string::String, vec::Vec};
+use core::fmt::Write;
+
+/// Lowercase hex, as ObjectIds are usually written.
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+
+    bytes
+        .iter()
+        .flat_map(|b| [HEX[usize::from(b >> 4)], HEX[usize::from(b & 0xf)]])
+        .map(char::from)
+        .collect()
+}
+
+/// Parses hex of either case, `None` if it's of odd length or has anything else in it.
+pub(crate) fn from_hex(s: &str) -> Option<Vec<u8>> {
+    fn digit(c: u8) -> Option<u8> {
+        char::from(c).to_digit(16).map(|d| d as u8)
+    }
+
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+
+    s.as_bytes()
+        .chunks(2)
+        .map(|pair| Some((digit(pair[0])? << 4) | digit(pair[1])?))
+        .collect()
+}
+
+/// Standard, padded base64.
+pub(crate) fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(char::from(ALPHABET[(n >> (18 - i * 6)) as usize & 0x3f]));
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+/// Formats milliseconds since the epoch as an RFC 3339 timestamp in UTC, or `None` if the year
+/// isn't between 0 and 9999 since those can't be written in four digits.
+pub(crate) fn rfc3339(millis: i64) -> Option<String> {
+    let days = millis.div_euclid(86_400_000);
+    let millis = millis.rem_euclid(86_400_000);
+
+    // Howard Hinnant's civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    if !(0..=9999).contains(&year) {
+        return None;
+    }
+
+    let mut out = String::with_capacity(24);
+    write!(
+        out,
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000,
+    )
+    .unwrap();
+
+    Some(out)
+}
+
+/// Parses an RFC 3339 timestamp into milliseconds since the epoch, the inverse of [`rfc3339`]
+/// though any offset is accepted and fractions of a second beyond milliseconds are truncated.
+pub(crate) fn parse_rfc3339(s: &str) -> Option<i64> {
+    fn number(s: &[u8]) -> Option<i64> {
+        s.iter().try_fold(0, |n, &c| {
+            char::from(c).to_digit(10).map(|d| n * 10 + i64::from(d))
+        })
+    }
+
+    let s = s.as_bytes();
+    if s.len() < 20
+        || s[4] != b'-'
+        || s[7] != b'-'
+        || !matches!(s[10], b'T' | b't')
+        || s[13] != b':'
+        || s[16] != b':'
+    {
+        return None;
+    }
+
+    let (year, month, day) = (number(&s[..4])?, number(&s[5..7])?, number(&s[8..10])?);
+    let (hour, minute, second) = (
+        number(&s[11..13])?,
+        number(&s[14..16])?,
+        number(&s[17..19])?,
+    );
+
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+
+    if !(1..=12).contains(&month)
+        || !(1..=days_in_month).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return None;
+    }
+
+    let mut rest = &s[19..];
+    let mut millis = 0;
+
+    if let Some(fraction) = rest.strip_prefix(b".") {
+        let digits = fraction.iter().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+
+        let mut padded = *b"000";
+        let used = digits.min(3);
+        padded[..used].copy_from_slice(&fraction[..used]);
+        millis = number(&padded)?;
+        rest = &fraction[digits..];
+    }
+
+    let offset = match rest {
+        b"Z" | b"z" => 0,
+        [sign @ (b'+' | b'-'), h1, h2, b':', m1, m2] => {
+            let offset = number(&[*h1, *h2])? * 60 + number(&[*m1, *m2])?;
+            if *sign == b'-' {
+                -offset
+            } else {
+                offset
+            }
+        }
+        _ => return None,
+    };
+
+    // Howard Hinnant's days_from_civil
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+
+    let seconds = days * 86_400 + hour * 3600 + minute * 60 + second - offset * 60;
+    Some(seconds * 1000 + millis)
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn hex() {
+        assert_eq!(super::hex(&[0x01, 0xab, 0xff]), "01abff");
+        assert_eq!(super::from_hex("01abFF"), Some(vec![0x01, 0xab, 0xff]));
+        assert_eq!(super::from_hex("01a"), None);
+        assert_eq!(super::from_hex("0g"), None);
+    }
+
+    #[test]
+    fn base64() {
+        for (input, expected) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ]
+        .iter()
+        {
+            assert_eq!(super::base64(input.as_bytes()), *expected);
+        }
+    }
+
+    #[test]
+    fn rfc3339() {
+        for (millis, expected) in [
+            (0, "1970-01-01T00:00:00.000Z"),
+            (1_700_000_000_123, "2023-11-14T22:13:20.123Z"),
+            (-1, "1969-12-31T23:59:59.999Z"),
+            (951_782_400_000, "2000-02-29T00:00:00.000Z"),
+            (253_402_300_799_999, "9999-12-31T23:59:59.999Z"),
+            (-62_167_219_200_000, "0000-01-01T00:00:00.000Z"),
+        ]
+        .iter()
+        {
+            assert_eq!(super::rfc3339(*millis).unwrap(), *expected);
+        }
+
+        assert_eq!(super::rfc3339(253_402_300_800_000), None);
+        assert_eq!(super::rfc3339(-62_167_219_200_001), None);
+    }
+
+    #[test]
+    fn parse_rfc3339() {
+        for millis in [
+            0,
+            1_700_000_000_123,
+            -1,
+            951_782_400_000,
+            253_402_300_799_999,
+            -62_167_219_200_000,
+        ]
+        .iter()
+        {
+            let formatted = super::rfc3339(*millis).unwrap();
+            assert_eq!(
+                super::parse_rfc3339(&formatted),
+                Some(*millis),
+                "{}",
+                formatted
+            );
+        }
+
+        for (input, expected) in [
+            ("1970-01-01T00:00:00Z", 0),
+            ("1970-01-01t00:00:00.5z", 500),
+            ("1970-01-01T00:00:00.123456789Z", 123),
+            ("1970-01-01T01:00:00+01:00", 0),
+            ("1969-12-31T23:30:00-00:30", 0),
+        ]
+        .iter()
+        {
+            assert_eq!(super::parse_rfc3339(input), Some(*expected), "{}", input);
+        }
+
+        for input in [
+            "",
+            "1970-01-01",
+            "1970-01-01 00:00:00Z",
+            "1970-01-01T00:00:00",
+            "1970-01-01T00:00:00.Z",
+            "1970-02-30T00:00:00Z",
+            "1970-13-01T00:00:00Z",
+            "1970-01-01T24:00:00Z",
+            "1970-01-01T00:00:00+0100",
+            "197a-01-01T00:00:00Z",
+        ]
+        .iter()
+        {
+            assert_eq!(super::parse_rfc3339(input), None, "{}", input);
+        }
+    }
+}
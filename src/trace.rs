@@ -0,0 +1,204 @@
+//! Instrumentation for the `tracing` feature, which wraps each document serialized or
+//! deserialized in a `debug` span. When it's done a `debug` event is emitted in the span with the
+//! size of the document, how many top-level fields it has and how long it took, or the error it
+//! failed with, so slow or failing documents can be picked out without wrapping every call site.
+//!
+//! Without the feature these just call straight through.
+
+use crate::byte::BytesLikeBuf;
+
+/// Runs `f`, which writes a document onto the end of `output`.
+#[cfg(feature = "tracing")]
+pub(crate) fn serialize<B: BytesLikeBuf, R, E: core::fmt::Display>(
+    output: &mut B,
+    f: impl FnOnce(&mut B) -> Result<R, E>,
+) -> Result<R, E> {
+    let span = tracing::debug_span!("serde_bson::serialize");
+    let _entered = span.enter();
+
+    let start = output.len();
+    let timer = std::time::Instant::now();
+    let res = f(output);
+
+    match &res {
+        Ok(_) => {
+            let end = output.len();
+
+            // the field count is only worked out if something's listening, and buffers that
+            // don't keep what's written to them have nothing to count
+            tracing::debug!(
+                bytes = end - start,
+                fields = output
+                    .as_mut_slice()
+                    .and_then(|written| crate::scan::count_elements(&written[start..end], 0)),
+                elapsed = ?timer.elapsed(),
+                "serialized document",
+            );
+        }
+        Err(error) => {
+            tracing::debug!(%error, elapsed = ?timer.elapsed(), "failed to serialize document");
+        }
+    }
+
+    res
+}
+
+#[cfg(not(feature = "tracing"))]
+#[inline]
+pub(crate) fn serialize<B: BytesLikeBuf, R, E>(
+    output: &mut B,
+    f: impl FnOnce(&mut B) -> Result<R, E>,
+) -> Result<R, E> {
+    f(output)
+}
+
+/// Runs `f`, which reads the document `data`.
+#[cfg(feature = "tracing")]
+pub(crate) fn deserialize<R, E: core::fmt::Display>(
+    data: &[u8],
+    f: impl FnOnce() -> Result<R, E>,
+) -> Result<R, E> {
+    let span = tracing::debug_span!("serde_bson::deserialize", bytes = data.len());
+    let _entered = span.enter();
+
+    let timer = std::time::Instant::now();
+    let res = f();
+
+    match &res {
+        Ok(_) => tracing::debug!(
+            fields = crate::scan::count_elements(data, 0),
+            elapsed = ?timer.elapsed(),
+            "deserialized document",
+        ),
+        Err(error) => {
+            tracing::debug!(%error, elapsed = ?timer.elapsed(), "failed to deserialize document");
+        }
+    }
+
+    res
+}
+
+#[cfg(not(feature = "tracing"))]
+#[inline]
+pub(crate) fn deserialize<R, E>(_data: &[u8], f: impl FnOnce() -> Result<R, E>) -> Result<R, E> {
+    f()
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use tracing::{
+        field::{Field, Visit},
+        span, Event, Metadata, Subscriber,
+    };
+
+    /// Keeps the span names and event fields it sees, formatted as `name=value`.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl Visit for Recorder {
+        fn record_debug(&mut self, field: &Field, value: &dyn core::fmt::Debug) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{}={:?}", field.name(), value));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+            self.0
+                .lock()
+                .unwrap()
+                .push(span.metadata().name().to_string());
+            span.record(&mut self.clone());
+            span::Id::from_u64(1)
+        }
+
+        fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            event.record(&mut self.clone());
+        }
+
+        fn enter(&self, _: &span::Id) {}
+
+        fn exit(&self, _: &span::Id) {}
+    }
+
+    fn record(f: impl FnOnce()) -> Vec<String> {
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), f);
+
+        // durations change from run to run
+        let seen = recorder.0.lock().unwrap();
+        seen.iter()
+            .filter(|v| !v.starts_with("elapsed="))
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn serialize() {
+        let mut output = Vec::new();
+        let seen = record(|| {
+            crate::to_vec(&bson::doc! { "a": 1, "b": "c" }, &mut output).unwrap();
+        });
+
+        assert_eq!(
+            seen,
+            [
+                "serde_bson::serialize",
+                "message=serialized document",
+                "bytes=21",
+                "fields=2",
+            ]
+        );
+        assert_eq!(output.len(), 21);
+
+        let seen = record(|| {
+            let mut output = [0; 4];
+            crate::to_slice(&bson::doc! { "a": 1 }, &mut output).unwrap_err();
+        });
+
+        assert_eq!(
+            seen,
+            [
+                "serde_bson::serialize",
+                "message=failed to serialize document",
+                "error=output buffer is too small for the value",
+            ]
+        );
+    }
+
+    #[test]
+    fn deserialize() {
+        let data = bson::to_vec(&bson::doc! { "a": 1, "b": "c" }).unwrap();
+
+        let seen = record(|| {
+            crate::de::from_bytes::<bson::Document>(&data).unwrap();
+        });
+        assert_eq!(
+            seen,
+            [
+                "serde_bson::deserialize",
+                "bytes=21",
+                "message=deserialized document",
+                "fields=2",
+            ]
+        );
+
+        let seen = record(|| {
+            crate::de::from_bytes_streaming::<bson::Document>(&data[..10]).unwrap_err();
+        });
+        assert_eq!(seen[..2], ["serde_bson::deserialize", "bytes=10"]);
+        assert_eq!(seen[2], "message=failed to deserialize document");
+        assert!(seen[3].starts_with("error="), "{:?}", seen);
+    }
+}
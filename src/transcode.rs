@@ -0,0 +1,128 @@
+//! Converting between bson and other formats without deserialising into an intermediate value,
+//! using [`serde_transcode`]. Values are passed straight from one side to the other as they're
+//! read, so memory use doesn't grow with the size of the input beyond the output itself.
+
+use crate::{byte::BytesLikeBuf, de, ser, Error};
+use serde::{de::DeserializeSeed, Deserializer, Serializer};
+
+/// Drives `serializer` with the bson document in `data`.
+pub fn to_serializer<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, de::Error> {
+    to_serializer_with_options(data, serializer, de::Options::default())
+}
+
+pub fn to_serializer_with_options<S: Serializer>(
+    data: &[u8],
+    serializer: S,
+    options: de::Options,
+) -> Result<S::Ok, de::Error> {
+    struct Transcode<S>(S);
+
+    impl<'de, S: Serializer> DeserializeSeed<'de> for Transcode<S> {
+        type Value = S::Ok;
+
+        fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<S::Ok, D::Error> {
+            serde_transcode::transcode(deserializer, self.0).map_err(serde::de::Error::custom)
+        }
+    }
+
+    de::from_bytes_seed_with_options(data, Transcode(serializer), options)
+}
+
+/// Writes a bson document onto the end of `output` from whatever `deserializer` produces, which
+/// must be a map or sequence at the top level.
+///
+/// Unlike [`crate::to_buf`] no counting pass is done up front, since the input can only be
+/// read once.
+pub fn from_deserializer<'de, D: Deserializer<'de>, B: BytesLikeBuf>(
+    deserializer: D,
+    output: &mut B,
+    options: ser::Options,
+) -> Result<(), Error> {
+    serde_transcode::transcode(
+        deserializer,
+        ser::Serializer {
+            key: None,
+            output,
+            options,
+        },
+    )
+}
+
+/// Writes the bson document in `data` to `writer` as JSON. ObjectIds, binaries and other types
+/// JSON has no equivalent for are written as Extended JSON, see [`de::BsonTypes::ExtendedJson`].
+#[cfg(feature = "json")]
+pub fn transcode_to_json<W: std::io::Write>(data: &[u8], writer: W) -> Result<(), de::Error> {
+    let options = de::Options::new().bson_types(de::BsonTypes::ExtendedJson);
+    transcode_to_json_with_options(data, writer, options)
+}
+
+#[cfg(feature = "json")]
+pub fn transcode_to_json_with_options<W: std::io::Write>(
+    data: &[u8],
+    writer: W,
+    options: de::Options,
+) -> Result<(), de::Error> {
+    to_serializer_with_options(data, &mut serde_json::Serializer::new(writer), options)
+}
+
+/// Reads a JSON object from `reader` and writes it onto the end of `output` as a bson document.
+/// Integers are written as int64s, or a decimal128 if they're too large, see
+/// [`ser::UnsignedIntegers::Widen`].
+#[cfg(feature = "json")]
+pub fn transcode_from_json<R: std::io::Read, B: BytesLikeBuf>(
+    reader: R,
+    output: &mut B,
+) -> Result<(), Error> {
+    let options = ser::Options::new().unsigned_integers(ser::UnsignedIntegers::Widen);
+    transcode_from_json_with_options(reader, output, options)
+}
+
+#[cfg(feature = "json")]
+pub fn transcode_from_json_with_options<R: std::io::Read, B: BytesLikeBuf>(
+    reader: R,
+    output: &mut B,
+    options: ser::Options,
+) -> Result<(), Error> {
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    from_deserializer(&mut deserializer, output, options)?;
+
+    // make sure there's nothing but whitespace after the object
+    deserializer.end().map_err(|e| Error::Serde(e.to_string()))
+}
+
+#[cfg(all(test, feature = "json"))]
+mod test {
+    #[test]
+    fn json() {
+        let data = bson::to_vec(&bson::doc! {
+            "a": 1,
+            "b": { "c": [1.5, "x", null, true] },
+            "oid": bson::oid::ObjectId::from_bytes([1; 12]),
+        })
+        .unwrap();
+
+        let mut json = Vec::new();
+        super::transcode_to_json(&data, &mut json).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&json).unwrap(),
+            r#"{"a":1,"b":{"c":[1.5,"x",null,true]},"oid":{"$oid":"010101010101010101010101"}}"#
+        );
+
+        let mut ours = Vec::new();
+        super::transcode_from_json(&json[..], &mut ours).unwrap();
+        let value: serde_json::Value = crate::de::from_bytes(&ours).unwrap();
+        assert_eq!(
+            value,
+            serde_json::from_slice::<serde_json::Value>(&json).unwrap()
+        );
+        assert_eq!(&ours[..9], &[0x68, 0, 0, 0, 0x12, b'a', 0, 1, 0]);
+
+        // errors from either side make it out
+        let mut truncated = data.clone();
+        truncated.truncate(data.len() - 1);
+        assert!(super::transcode_to_json(&truncated, &mut Vec::new()).is_err());
+        assert!(super::transcode_from_json(&b"{\"a\": "[..], &mut Vec::new()).is_err());
+        assert!(super::transcode_from_json(&b"{} {}"[..], &mut Vec::new()).is_err());
+        assert!(super::transcode_from_json(&b"1"[..], &mut Vec::new()).is_err());
+    }
+}
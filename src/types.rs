@@ -0,0 +1,716 @@
+use crate::raw::{RawBytes, RAW_BSON_NEWTYPE};
+use alloc::{string::String, vec::Vec};
+use core::{
+    convert::{TryFrom, TryInto},
+    fmt,
+};
+use serde::{
+    de::{Error, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+/// A deprecated symbol (0x0E) element, these are encoded exactly like strings and are
+/// deserialised as strings, so this is only needed to write them back out again.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Symbol(pub String);
+
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_variant(RAW_BSON_NEWTYPE, 0x0E, "", self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self)
+    }
+}
+
+pub(crate) const UNDEFINED_NAME: &str = "Undefined";
+
+/// A deprecated undefined (0x06) element. Undefined values are read as nulls unless
+/// [`crate::de::Options::undefined_as_null`] is disabled, in which case they can be deserialised
+/// into this instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Undefined;
+
+impl Serialize for Undefined {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_variant(RAW_BSON_NEWTYPE, 0x06, "", &RawBytes(&[]))
+    }
+}
+
+impl<'de> Deserialize<'de> for Undefined {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct UndefinedVisitor;
+
+        impl<'de> Visitor<'de> for UndefinedVisitor {
+            type Value = Undefined;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("undefined")
+            }
+
+            fn visit_unit<E: Error>(self) -> Result<Self::Value, E> {
+                Ok(Undefined)
+            }
+        }
+
+        deserializer.deserialize_unit_struct(UNDEFINED_NAME, UndefinedVisitor)
+    }
+}
+
+/// A regular expression (0x0B) element, these are deserialised as a `(pattern, options)` pair
+/// so can also be read into a tuple of strings.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Regex {
+    pub pattern: String,
+    pub options: String,
+}
+
+impl Serialize for Regex {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // both halves are c-strings, so neither can contain a null
+        if self.pattern.contains('\0') || self.options.contains('\0') {
+            return Err(serde::ser::Error::custom(
+                "regex pattern and options cannot contain null bytes",
+            ));
+        }
+
+        serializer.serialize_newtype_variant(
+            RAW_BSON_NEWTYPE,
+            0x0B,
+            "",
+            &(
+                RawBytes(self.pattern.as_bytes()),
+                RawBytes(&[0x00]),
+                RawBytes(self.options.as_bytes()),
+                RawBytes(&[0x00]),
+            ),
+        )
+    }
+}
+
+impl<'de> Deserialize<'de> for Regex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (pattern, options) = <(String, String)>::deserialize(deserializer)?;
+        Ok(Self { pattern, options })
+    }
+}
+
+/// A JavaScript code (0x0D) element, these are encoded exactly like strings and are deserialised
+/// as strings.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Code(pub String);
+
+impl Serialize for Code {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_variant(RAW_BSON_NEWTYPE, 0x0D, "", self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Code {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self)
+    }
+}
+
+/// A deprecated JavaScript code with scope (0x0F) element, the scope is a document of variables
+/// in scope for the code. These are deserialised as a `(code, scope)` pair.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CodeWithScope<S> {
+    pub code: String,
+    pub scope: S,
+}
+
+impl<T: Serialize> Serialize for CodeWithScope<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // the whole value is prefixed with its length, including the scope, so we'll write the
+        // scope out up front to find out how big it is. this is a deprecated type so we're not
+        // too worried about the extra allocation
+        let mut scope = Vec::new();
+        crate::to_vec(&self.scope, &mut scope).map_err(serde::ser::Error::custom)?;
+
+        let length = i32::try_from(4 + 4 + self.code.len() + 1 + scope.len())
+            .map_err(|_| serde::ser::Error::custom("code with scope exceeds max size"))?;
+
+        serializer.serialize_newtype_variant(
+            RAW_BSON_NEWTYPE,
+            0x0F,
+            "",
+            &(
+                RawBytes(&length.to_le_bytes()),
+                self.code.as_str(),
+                RawBytes(&scope),
+            ),
+        )
+    }
+}
+
+impl<'de, S: Deserialize<'de>> Deserialize<'de> for CodeWithScope<S> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (code, scope) = <(String, S)>::deserialize(deserializer)?;
+        Ok(Self { code, scope })
+    }
+}
+
+pub(crate) const MIN_KEY_NAME: &str = "MinKey";
+pub(crate) const MAX_KEY_NAME: &str = "MaxKey";
+
+/// The min key (0xFF) sentinel, which compares lower than every other value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MinKey;
+
+impl Serialize for MinKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_variant(RAW_BSON_NEWTYPE, 0xFF, "", &RawBytes(&[]))
+    }
+}
+
+impl<'de> Deserialize<'de> for MinKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer
+            .deserialize_unit_struct(MIN_KEY_NAME, SentinelVisitor(MIN_KEY_NAME))
+            .map(|()| MinKey)
+    }
+}
+
+/// The max key (0x7F) sentinel, which compares higher than every other value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MaxKey;
+
+impl Serialize for MaxKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_variant(RAW_BSON_NEWTYPE, 0x7F, "", &RawBytes(&[]))
+    }
+}
+
+impl<'de> Deserialize<'de> for MaxKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer
+            .deserialize_unit_struct(MAX_KEY_NAME, SentinelVisitor(MAX_KEY_NAME))
+            .map(|()| MaxKey)
+    }
+}
+
+struct SentinelVisitor(&'static str);
+
+impl<'de> Visitor<'de> for SentinelVisitor {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0)
+    }
+
+    fn visit_unit<E: Error>(self) -> Result<Self::Value, E> {
+        Ok(())
+    }
+}
+
+/// A deprecated DBPointer (0x0C) element, a reference to a document by its collection namespace
+/// and ObjectId. These are deserialised as a `(namespace, id)` pair.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DbPointer {
+    pub namespace: String,
+    pub id: [u8; 12],
+}
+
+impl Serialize for DbPointer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_variant(
+            RAW_BSON_NEWTYPE,
+            0x0C,
+            "",
+            &(self.namespace.as_str(), RawBytes(&self.id)),
+        )
+    }
+}
+
+impl<'de> Deserialize<'de> for DbPointer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct DbPointerVisitor;
+
+        impl<'de> Visitor<'de> for DbPointerVisitor {
+            type Value = DbPointer;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a dbpointer")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let namespace = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(0, &self))?;
+                let Id(id) = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(1, &self))?;
+                Ok(DbPointer { namespace, id })
+            }
+        }
+
+        deserializer.deserialize_tuple(2, DbPointerVisitor)
+    }
+}
+
+/// A 12 byte ObjectId passed to us as bytes.
+struct Id([u8; 12]);
+
+impl<'de> Deserialize<'de> for Id {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct IdVisitor;
+
+        impl<'de> Visitor<'de> for IdVisitor {
+            type Value = Id;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("12 bytes")
+            }
+
+            fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                v.try_into()
+                    .map(Id)
+                    .map_err(|_| E::invalid_length(v.len(), &self))
+            }
+        }
+
+        deserializer.deserialize_bytes(IdVisitor)
+    }
+}
+
+/// Wrappers around binaries of a particular subtype. Their payload is kept as-is so they can be
+/// passed through and written back out untouched, and binaries of any other subtype are
+/// rejected.
+macro_rules! binary_subtypes {
+    ($($(#[$meta:meta])* $name:ident => $subtype:literal, $expecting:literal;)*) => {
+        /// Binary wrappers that only accept a particular subtype ask for it by name through
+        /// `deserialize_newtype_struct`, since visitors are only ever handed the bytes.
+        pub(crate) fn binary_subtype_for(name: &str) -> Option<u8> {
+            match name {
+                $(stringify!($name) => Some($subtype),)*
+                _ => None,
+            }
+        }
+
+        $(
+            $(#[$meta])*
+            #[derive(Clone, PartialEq, Eq, Hash)]
+            pub struct $name(pub Vec<u8>);
+
+            impl Serialize for $name {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    let length = i32::try_from(self.0.len())
+                        .map_err(|_| serde::ser::Error::custom("binary exceeds max size"))?;
+
+                    serializer.serialize_newtype_variant(
+                        RAW_BSON_NEWTYPE,
+                        0x05,
+                        "",
+                        &(
+                            RawBytes(&length.to_le_bytes()),
+                            RawBytes(&[$subtype]),
+                            RawBytes(&self.0),
+                        ),
+                    )
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $name {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    struct BinaryVisitor;
+
+                    impl<'de> Visitor<'de> for BinaryVisitor {
+                        type Value = $name;
+
+                        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                            f.write_str($expecting)
+                        }
+
+                        fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                            Ok($name(v.to_vec()))
+                        }
+
+                        fn visit_byte_buf<E: Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+                            Ok($name(v))
+                        }
+
+                        fn visit_newtype_struct<D: Deserializer<'de>>(
+                            self,
+                            deserializer: D,
+                        ) -> Result<Self::Value, D::Error> {
+                            deserializer.deserialize_byte_buf(self)
+                        }
+                    }
+
+                    deserializer.deserialize_newtype_struct(stringify!($name), BinaryVisitor)
+                }
+            }
+        )*
+    };
+}
+
+binary_subtypes! {
+    /// An encrypted binary (subtype 0x06) value, as written by client-side field level
+    /// encryption, kept as-is so the ciphertext envelope isn't disturbed.
+    #[derive(Debug)]
+    EncryptedValue => 0x06, "an encrypted binary";
+    /// A compressed BSON Column (subtype 0x07), as used by time-series buckets to store each
+    /// field's values. The values can be read with `BsonColumn::values` and compressed with
+    /// `BsonColumn::from_values`, both of which need the `std` feature. Interleaved columns and
+    /// 128-bit blocks can't be read, see `BsonColumnValues`.
+    #[derive(Debug)]
+    BsonColumn => 0x07, "a bson column binary";
+    /// A sensitive binary (subtype 0x08), holding data such as credentials that shouldn't end up
+    /// in logs. Its `Debug` output doesn't include the payload, see also [`crate::redact`].
+    Sensitive => 0x08, "a sensitive binary";
+}
+
+impl fmt::Debug for Sensitive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Sensitive")
+            .field(&format_args!("{}", crate::redact::REDACTED))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        BsonColumn, Code, CodeWithScope, DbPointer, EncryptedValue, MaxKey, MinKey, Regex,
+        Sensitive, Symbol, Undefined,
+    };
+    use bytes::BytesMut;
+    use serde::{Deserialize, Serialize};
+
+    #[test]
+    fn symbol() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            a: Symbol,
+            b: Vec<Symbol>,
+        }
+
+        let val = A {
+            a: Symbol("hello".to_string()),
+            b: vec![Symbol("world".to_string())],
+        };
+
+        let mut ours = BytesMut::new();
+        crate::to_string(&val, &mut ours).unwrap();
+
+        let mut theirs = Vec::new();
+        bson::doc! {
+            "a": bson::Bson::Symbol("hello".to_string()),
+            "b": [bson::Bson::Symbol("world".to_string())],
+        }
+        .to_writer(&mut theirs)
+        .unwrap();
+        assert_eq!(&ours[..], &theirs[..]);
+
+        let deserialized: A = crate::de::from_bytes(&ours).unwrap();
+        assert_eq!(deserialized, val);
+
+        // symbols are just strings as far as anyone else is concerned
+        #[derive(Deserialize)]
+        struct B {
+            a: String,
+        }
+
+        let deserialized: B = crate::de::from_bytes(&ours).unwrap();
+        assert_eq!(deserialized.a, "hello");
+    }
+
+    #[test]
+    fn db_pointer() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            a: DbPointer,
+        }
+
+        let val = A {
+            a: DbPointer {
+                namespace: "db.coll".to_string(),
+                id: [1; 12],
+            },
+        };
+
+        let mut ours = BytesMut::new();
+        crate::to_string(&val, &mut ours).unwrap();
+
+        let mut expected = vec![0x20, 0, 0, 0, 0x0c, b'a', 0, 8, 0, 0, 0];
+        expected.extend_from_slice(b"db.coll\0");
+        expected.extend_from_slice(&[1; 12]);
+        expected.push(0);
+        assert_eq!(&ours[..], &expected[..]);
+
+        let theirs = bson::Document::from_reader(&ours[..]).unwrap();
+        assert!(matches!(theirs.get("a"), Some(bson::Bson::DbPointer(_))));
+
+        crate::de::validate(&ours).unwrap();
+        let deserialized: A = crate::de::from_bytes(&ours).unwrap();
+        assert_eq!(deserialized, val);
+    }
+
+    #[test]
+    fn undefined() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            a: Undefined,
+        }
+
+        #[derive(Deserialize, Debug, PartialEq, Eq)]
+        struct B {
+            a: Option<i32>,
+        }
+
+        let mut ours = BytesMut::new();
+        crate::to_string(&A { a: Undefined }, &mut ours).unwrap();
+
+        let mut theirs = Vec::new();
+        bson::doc! { "a": bson::Bson::Undefined }
+            .to_writer(&mut theirs)
+            .unwrap();
+        assert_eq!(&ours[..], &theirs[..]);
+
+        crate::de::validate(&ours).unwrap();
+
+        let deserialized: B = crate::de::from_bytes(&ours).unwrap();
+        assert_eq!(deserialized, B { a: None });
+        assert!(crate::de::from_bytes::<A>(&ours).is_err());
+
+        let options = crate::de::Options::new().undefined_as_null(false);
+        let deserialized: A = crate::de::from_bytes_with_options(&ours, options).unwrap();
+        assert_eq!(deserialized, A { a: Undefined });
+    }
+
+    #[test]
+    fn regex() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            a: Regex,
+        }
+
+        #[derive(Deserialize, Debug, PartialEq, Eq)]
+        struct B<'a> {
+            #[serde(borrow)]
+            a: (&'a str, &'a str),
+        }
+
+        let val = A {
+            a: Regex {
+                pattern: "^abc$".to_string(),
+                options: "im".to_string(),
+            },
+        };
+
+        let mut ours = BytesMut::new();
+        crate::to_string(&val, &mut ours).unwrap();
+
+        let mut theirs = Vec::new();
+        bson::doc! { "a": bson::Regex { pattern: "^abc$".to_string(), options: "im".to_string() } }
+            .to_writer(&mut theirs)
+            .unwrap();
+        assert_eq!(&ours[..], &theirs[..]);
+
+        let deserialized: A = crate::de::from_bytes(&ours).unwrap();
+        assert_eq!(deserialized, val);
+
+        let deserialized: B = crate::de::from_bytes(&ours).unwrap();
+        assert_eq!(deserialized, B { a: ("^abc$", "im") });
+
+        let invalid = A {
+            a: Regex {
+                pattern: "a\0b".to_string(),
+                options: String::new(),
+            },
+        };
+        assert!(crate::to_string(&invalid, &mut BytesMut::new()).is_err());
+    }
+
+    #[test]
+    fn code() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct Scope {
+            x: i32,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            a: Code,
+            b: CodeWithScope<Scope>,
+            c: i32,
+        }
+
+        let val = A {
+            a: Code("function() {}".to_string()),
+            b: CodeWithScope {
+                code: "function() { return x; }".to_string(),
+                scope: Scope { x: 1 },
+            },
+            c: 2,
+        };
+
+        let mut ours = BytesMut::new();
+        crate::to_string(&val, &mut ours).unwrap();
+
+        let mut theirs = Vec::new();
+        bson::doc! {
+            "a": bson::Bson::JavaScriptCode("function() {}".to_string()),
+            "b": bson::Bson::JavaScriptCodeWithScope(bson::JavaScriptCodeWithScope {
+                code: "function() { return x; }".to_string(),
+                scope: bson::doc! { "x": 1 },
+            }),
+            "c": 2,
+        }
+        .to_writer(&mut theirs)
+        .unwrap();
+        assert_eq!(&ours[..], &theirs[..]);
+
+        crate::de::validate(&ours).unwrap();
+
+        let deserialized: A = crate::de::from_bytes(&ours).unwrap();
+        assert_eq!(deserialized, val);
+
+        // the scope has to be skipped over if it's not asked for
+        #[derive(Deserialize, Debug, PartialEq, Eq)]
+        struct B {
+            b: (String,),
+            c: i32,
+        }
+
+        let deserialized: B = crate::de::from_bytes(&ours).unwrap();
+        assert_eq!(deserialized.b.0, "function() { return x; }");
+        assert_eq!(deserialized.c, 2);
+    }
+
+    #[test]
+    fn min_max_key() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            min: MinKey,
+            max: MaxKey,
+        }
+
+        let mut ours = BytesMut::new();
+        crate::to_string(
+            &A {
+                min: MinKey,
+                max: MaxKey,
+            },
+            &mut ours,
+        )
+        .unwrap();
+
+        let mut theirs = Vec::new();
+        bson::doc! { "min": bson::Bson::MinKey, "max": bson::Bson::MaxKey }
+            .to_writer(&mut theirs)
+            .unwrap();
+        assert_eq!(&ours[..], &theirs[..]);
+
+        let deserialized: A = crate::de::from_bytes(&ours).unwrap();
+        assert_eq!(
+            deserialized,
+            A {
+                min: MinKey,
+                max: MaxKey
+            }
+        );
+
+        // the two can't be mixed up
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Swapped {
+            min: MaxKey,
+        }
+
+        assert!(crate::de::from_bytes::<Swapped>(&ours).is_err());
+    }
+
+    #[test]
+    fn encrypted_value() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            a: EncryptedValue,
+            b: Option<EncryptedValue>,
+        }
+
+        let val = A {
+            a: EncryptedValue(vec![0x01, 0xaa, 0xbb]),
+            b: None,
+        };
+
+        let ours = crate::to_bytes(&val).unwrap();
+        assert_eq!(
+            bson::Document::from_reader(&ours[..]).unwrap(),
+            bson::doc! {
+                "a": bson::Binary {
+                    subtype: bson::spec::BinarySubtype::Encrypted,
+                    bytes: vec![0x01, 0xaa, 0xbb],
+                },
+                "b": null,
+            }
+        );
+        assert_eq!(crate::de::from_bytes::<A>(&ours).unwrap(), val);
+
+        // round trips untouched, even when read out of a different deserializer
+        let streamed: A = crate::de::from_bytes_streaming(&ours).unwrap();
+        assert_eq!(&crate::to_bytes(&streamed).unwrap()[..], &ours[..]);
+
+        let generic = bson::to_vec(&bson::doc! {
+            "a": bson::Binary { subtype: bson::spec::BinarySubtype::Generic, bytes: vec![1] },
+            "b": null,
+        })
+        .unwrap();
+        assert!(crate::de::from_bytes::<A>(&generic).is_err());
+    }
+
+    #[test]
+    fn bson_column() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            a: BsonColumn,
+        }
+
+        let theirs = bson::to_vec(&bson::doc! {
+            "a": bson::Binary { subtype: bson::spec::BinarySubtype::Column, bytes: vec![0x10, 0, 0] },
+        })
+        .unwrap();
+
+        let val: A = crate::de::from_bytes(&theirs).unwrap();
+        assert_eq!(val.a, BsonColumn(vec![0x10, 0, 0]));
+        assert_eq!(&crate::to_bytes(&val).unwrap()[..], &theirs[..]);
+
+        #[derive(Serialize)]
+        struct B {
+            a: EncryptedValue,
+        }
+
+        // other wrapped subtypes aren't mistaken for columns
+        let encrypted = crate::to_bytes(&B {
+            a: EncryptedValue(vec![0x10, 0, 0]),
+        })
+        .unwrap();
+        assert!(crate::de::from_bytes::<A>(&encrypted).is_err());
+    }
+
+    #[test]
+    fn sensitive() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+        struct A {
+            a: Sensitive,
+        }
+
+        let val = A {
+            a: Sensitive(b"hunter2".to_vec()),
+        };
+
+        let ours = crate::to_bytes(&val).unwrap();
+        assert_eq!(
+            bson::Document::from_reader(&ours[..]).unwrap(),
+            bson::doc! {
+                "a": bson::Binary { subtype: bson::spec::BinarySubtype::Sensitive, bytes: b"hunter2".to_vec() },
+            }
+        );
+        assert_eq!(crate::de::from_bytes::<A>(&ours).unwrap(), val);
+        assert_eq!(format!("{:?}", val), "A { a: Sensitive([REDACTED]) }");
+    }
+}
@@ -0,0 +1,10 @@
+//! UTF-8 validation for the strings we read. This is simdutf8 by default, which picks the fastest
+//! implementation the CPU supports at runtime, or the standard library's scalar validation with
+//! the `scalar-utf8` feature, for targets such as wasm32 where there's no SIMD to pick from or
+//! the extra code size isn't wanted.
+
+#[cfg(not(feature = "scalar-utf8"))]
+pub(crate) use simdutf8::basic::{from_utf8, Utf8Error};
+
+#[cfg(feature = "scalar-utf8")]
+pub(crate) use core::str::{from_utf8, Utf8Error};
@@ -0,0 +1,1470 @@
+//! Owned, dynamically typed documents for when the shape of the data isn't known up front.
+//!
+//! [`Document::from_bytes`] reads every element type exactly, so a document read that way and
+//! serialised again comes out byte for byte the same. [`Document`] and [`Value`] can also be
+//! deserialised like any other type, but only see what the deserializer hands to them, see
+//! [`crate::de::BsonTypes`].
+
+use crate::{
+    de::{self, Error},
+    raw::{RawBytes, RAW_BSON_NEWTYPE},
+    scan::Elements,
+    serde_helpers::system_time_as_bson_datetime,
+    CodeWithScope, DbPointer, Decimal128, MaxKey, MinKey, ObjectId, Regex, Undefined,
+};
+use serde::{
+    de::{DeserializeOwned, MapAccess, SeqAccess, Visitor},
+    ser::{SerializeMap, SerializeSeq},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{
+    cmp::Ordering,
+    convert::{TryFrom, TryInto},
+    fmt,
+    iter::FromIterator,
+    time::SystemTime,
+};
+
+/// A single bson value of any type.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Double(f64),
+    String(String),
+    Document(Document),
+    Array(Vec<Value>),
+    Binary {
+        subtype: u8,
+        bytes: Vec<u8>,
+    },
+    Undefined,
+    ObjectId([u8; 12]),
+    Boolean(bool),
+    /// Milliseconds since the Unix epoch.
+    DateTime(i64),
+    Null,
+    Regex(Regex),
+    DbPointer(DbPointer),
+    Code(String),
+    Symbol(String),
+    CodeWithScope(CodeWithScope<Document>),
+    Int32(i32),
+    /// The increment in the low 32 bits and the seconds since the epoch in the high 32 bits.
+    Timestamp(u64),
+    Int64(i64),
+    Decimal128(Decimal128),
+    MaxKey,
+    MinKey,
+}
+
+/// A document of keys and their values, which keeps its keys in the order they were inserted.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Document {
+    entries: Vec<(String, Value)>,
+}
+
+impl Document {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the document in `data`, keeping the exact type of every value.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        read_document(data, 0)
+    }
+
+    /// Serialises the document, the same as [`crate::to_vec`] would.
+    pub fn to_vec(&self) -> Result<Vec<u8>, crate::Error> {
+        let mut output = Vec::new();
+        crate::to_vec(self, &mut output)?;
+        Ok(output)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.entries.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        self.entries
+            .iter_mut()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Sets `key` to `value`, returning the value it replaced. Replaced keys keep their position,
+    /// new keys are added to the end.
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<Value>) -> Option<Value> {
+        let key = key.into();
+        let value = value.into();
+
+        match self.get_mut(&key) {
+            Some(existing) => Some(std::mem::replace(existing, value)),
+            None => {
+                self.entries.push((key, value));
+                None
+            }
+        }
+    }
+
+    /// Removes `key`, keeping the order of the keys after it.
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        let position = self.entries.iter().position(|(k, _)| k == key)?;
+        Some(self.entries.remove(position).1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> + '_ {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&str, &mut Value)> + '_ {
+        self.entries.iter_mut().map(|(k, v)| (k.as_str(), v))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> + '_ {
+        self.entries.iter().map(|(k, _)| k.as_str())
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Value> + '_ {
+        self.entries.iter().map(|(_, v)| v)
+    }
+
+    /// Merges `other` into this document, for layering configuration or applying a PATCH. Keys
+    /// only in `other` are added to the end, keys in both are combined as `strategy` says, with
+    /// `other`'s value winning whenever they can't be combined.
+    pub fn merge(&mut self, other: Document, strategy: MergeStrategy) {
+        for (key, value) in other {
+            let Some(existing) = self.get_mut(&key) else {
+                self.entries.push((key, value));
+                continue;
+            };
+
+            match (existing, value) {
+                (Value::Document(existing), Value::Document(value)) if strategy.deep => {
+                    existing.merge(value, strategy);
+                }
+                (Value::Array(existing), Value::Array(value))
+                    if strategy.arrays == ArrayMerge::Concat =>
+                {
+                    existing.extend(value);
+                }
+                (existing, value) => *existing = value,
+            }
+        }
+    }
+
+    /// Looks up a nested value by a path of dot separated keys and array indexes, such as
+    /// `items.3.name`, in the same format as [`crate::de::project`].
+    pub fn get_path(&self, path: &str) -> Option<&Value> {
+        let mut keys = path.split('.');
+        let value = self.get(keys.next()?)?;
+        keys.try_fold(value, Value::child)
+    }
+
+    /// [`Document::get_path`], deserialising the value into a `T`. `None` if there's no value at
+    /// the path or it can't be read as a `T`.
+    pub fn get_path_as<T: DeserializeOwned>(&self, path: &str) -> Option<T> {
+        self.get_path(path)?.deserialize_into().ok()
+    }
+}
+
+/// How [`Document::merge`] combines two documents. By default the merge is shallow and arrays
+/// are replaced, so every top-level key of the other document overwrites this one's.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MergeStrategy {
+    deep: bool,
+    arrays: ArrayMerge,
+}
+
+/// What to do when both documents have an array under the same key, see
+/// [`MergeStrategy::arrays`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ArrayMerge {
+    /// Take the other document's array.
+    #[default]
+    Replace,
+    /// Append the other document's elements to this one's.
+    Concat,
+}
+
+impl MergeStrategy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Merge nested documents key by key rather than replacing them outright.
+    pub fn deep(mut self, deep: bool) -> Self {
+        self.deep = deep;
+        self
+    }
+
+    pub fn arrays(mut self, arrays: ArrayMerge) -> Self {
+        self.arrays = arrays;
+        self
+    }
+}
+
+impl Value {
+    /// The element type this value is written with.
+    pub fn element_type(&self) -> u8 {
+        match self {
+            Self::Double(_) => 0x01,
+            Self::String(_) => 0x02,
+            Self::Document(_) => 0x03,
+            Self::Array(_) => 0x04,
+            Self::Binary { .. } => 0x05,
+            Self::Undefined => 0x06,
+            Self::ObjectId(_) => 0x07,
+            Self::Boolean(_) => 0x08,
+            Self::DateTime(_) => 0x09,
+            Self::Null => 0x0A,
+            Self::Regex(_) => 0x0B,
+            Self::DbPointer(_) => 0x0C,
+            Self::Code(_) => 0x0D,
+            Self::Symbol(_) => 0x0E,
+            Self::CodeWithScope(_) => 0x0F,
+            Self::Int32(_) => 0x10,
+            Self::Timestamp(_) => 0x11,
+            Self::Int64(_) => 0x12,
+            Self::Decimal128(_) => 0x13,
+            Self::MaxKey => 0x7F,
+            Self::MinKey => 0xFF,
+        }
+    }
+
+    /// The value in this document or array under `key`.
+    fn child(&self, key: &str) -> Option<&Value> {
+        match self {
+            Self::Document(document) => document.get(key),
+            Self::Array(values) => values.get(key.parse::<usize>().ok()?),
+            _ => None,
+        }
+    }
+
+    /// See [`Document::get_path`].
+    pub fn get_path(&self, path: &str) -> Option<&Value> {
+        path.split('.').try_fold(self, Value::child)
+    }
+
+    /// An ObjectId from its 24 character hex form, `None` if it isn't one.
+    pub fn object_id(hex: &str) -> Option<Self> {
+        crate::text::from_hex(hex)?
+            .try_into()
+            .ok()
+            .map(Self::ObjectId)
+    }
+
+    /// A datetime at `time`, truncated to the millisecond. `None` if it's too far from the epoch
+    /// to be stored.
+    pub fn datetime(time: SystemTime) -> Option<Self> {
+        system_time_as_bson_datetime::to_millis(&time).map(Self::DateTime)
+    }
+
+    /// The value of an `Int32` or `Int64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Int32(v) => Some(i64::from(*v)),
+            Self::Int64(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_i32(&self) -> Option<i32> {
+        match self {
+            Self::Int32(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Double(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Boolean(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_document(&self) -> Option<&Document> {
+        match self {
+            Self::Document(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_document_mut(&mut self) -> Option<&mut Document> {
+        match self {
+            Self::Document(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Self::Array(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_array_mut(&mut self) -> Option<&mut Vec<Value>> {
+        match self {
+            Self::Array(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Self::Null)
+    }
+
+    /// Any number as an `i64`, with fractions truncated towards zero and anything out of range
+    /// clamped to it. NaN and non-numbers give `None`.
+    pub fn to_i64_lossy(&self) -> Option<i64> {
+        match self.number()? {
+            Number::Int(v) => Some(v),
+            Number::Double(v) if v.is_nan() => None,
+            // `as` saturates
+            Number::Double(v) => Some(v as i64),
+        }
+    }
+
+    /// Like [`Value::to_i64_lossy`], clamping to the `i32` range.
+    pub fn to_i32_lossy(&self) -> Option<i32> {
+        self.to_i64_lossy()
+            .map(|v| v.clamp(i64::from(i32::MIN), i64::from(i32::MAX)) as i32)
+    }
+
+    /// Any number as an `f64`, rounding integers too large to be represented exactly.
+    pub fn to_f64_lossy(&self) -> Option<f64> {
+        match self.number()? {
+            Number::Int(v) => Some(v as f64),
+            Number::Double(v) => Some(v),
+        }
+    }
+
+    /// Deserialises the value into a `T`, exactly as it would be if it had been read from a
+    /// document.
+    pub fn deserialize_into<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        let mut document = Document::new();
+        document.insert("", self.clone());
+
+        let bytes = document
+            .to_vec()
+            .map_err(|e| Error::Custom(e.to_string()))?;
+
+        let element = Elements::new(&bytes, 0)?
+            .next()
+            .expect("document was written with a single element")?;
+
+        de::from_raw(element.value)
+    }
+}
+
+macro_rules! into_value {
+    ($($ty:ty => |$v:ident| $value:expr,)*) => {
+        $(
+            impl From<$ty> for Value {
+                fn from($v: $ty) -> Self {
+                    $value
+                }
+            }
+        )*
+    };
+}
+
+into_value! {
+    Document => |v| Value::Document(v),
+    f64 => |v| Value::Double(v),
+    i32 => |v| Value::Int32(v),
+    i64 => |v| Value::Int64(v),
+    bool => |v| Value::Boolean(v),
+    String => |v| Value::String(v),
+    &str => |v| Value::String(v.to_string()),
+    Decimal128 => |v| Value::Decimal128(v),
+    Regex => |v| Value::Regex(v),
+    ObjectId => |v| Value::ObjectId(v.bytes()),
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(v: Vec<T>) -> Self {
+        Self::Array(v.into_iter().map(Into::into).collect())
+    }
+}
+
+/// `None` becomes `Null`.
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(Self::Null, Into::into)
+    }
+}
+
+macro_rules! try_from_value {
+    ($($ty:ty => $pattern:pat => $value:expr,)*) => {
+        $(
+            impl TryFrom<Value> for $ty {
+                type Error = Value;
+
+                /// Hands back the value if it's of a different type.
+                fn try_from(v: Value) -> Result<Self, Self::Error> {
+                    match v {
+                        $pattern => Ok($value),
+                        v => Err(v),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+try_from_value! {
+    Document => Value::Document(v) => v,
+    Vec<Value> => Value::Array(v) => v,
+    f64 => Value::Double(v) => v,
+    i32 => Value::Int32(v) => v,
+    bool => Value::Boolean(v) => v,
+    String => Value::String(v) => v,
+    Decimal128 => Value::Decimal128(v) => v,
+    Regex => Value::Regex(v) => v,
+}
+
+impl TryFrom<Value> for i64 {
+    type Error = Value;
+
+    /// Accepts either integer type, see [`Value::as_i64`].
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        v.as_i64().ok_or(v)
+    }
+}
+
+impl FromIterator<(String, Value)> for Document {
+    fn from_iter<I: IntoIterator<Item = (String, Value)>>(iter: I) -> Self {
+        let mut document = Document::new();
+        for (key, value) in iter {
+            document.insert(key, value);
+        }
+        document
+    }
+}
+
+impl IntoIterator for Document {
+    type Item = (String, Value);
+    type IntoIter = std::vec::IntoIter<(String, Value)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+fn read_document(data: &[u8], start: usize) -> Result<Document, Error> {
+    let mut document = Document::new();
+
+    for element in Elements::new(data, start)? {
+        let element = element?;
+        let value = read_value(data, element.value.element_type(), element.value_offset)?;
+        document.entries.push((element.key.to_string(), value));
+    }
+
+    Ok(document)
+}
+
+/// Reads a value of `element_type` starting at `at`, which [`Elements`] has already checked
+/// fits in the document it's in.
+fn read_value(data: &[u8], element_type: u8, at: usize) -> Result<Value, Error> {
+    let fixed = |length: usize| &data[at..at + length];
+
+    Ok(match element_type {
+        0x01 => Value::Double(f64::from_le_bytes(fixed(8).try_into().unwrap())),
+        0x02 => Value::String(read_string(data, at)?.0),
+        0x03 => Value::Document(read_document(data, at)?),
+        0x04 => Value::Array(
+            read_document(data, at)?
+                .into_iter()
+                .map(|(_, value)| value)
+                .collect(),
+        ),
+        0x05 => {
+            let length = read_i32(data, at)?;
+            Value::Binary {
+                subtype: data[at + 4],
+                bytes: data[at + 5..at + 5 + length].to_vec(),
+            }
+        }
+        0x06 => Value::Undefined,
+        0x07 => Value::ObjectId(fixed(12).try_into().unwrap()),
+        0x08 => match data[at] {
+            0 => Value::Boolean(false),
+            1 => Value::Boolean(true),
+            _ => return Err(Error::InvalidBoolean(at)),
+        },
+        0x09 => Value::DateTime(i64::from_le_bytes(fixed(8).try_into().unwrap())),
+        0x0A => Value::Null,
+        0x0B => {
+            let (pattern, length) = read_cstring(data, at)?;
+            let (options, _) = read_cstring(data, at + length)?;
+            Value::Regex(Regex { pattern, options })
+        }
+        0x0C => {
+            let (namespace, length) = read_string(data, at)?;
+            Value::DbPointer(DbPointer {
+                namespace,
+                id: data[at + length..at + length + 12].try_into().unwrap(),
+            })
+        }
+        0x0D => Value::Code(read_string(data, at)?.0),
+        0x0E => Value::Symbol(read_string(data, at)?.0),
+        0x0F => {
+            let (code, length) = read_string(data, at + 4)?;
+            Value::CodeWithScope(CodeWithScope {
+                code,
+                scope: read_document(data, at + 4 + length)?,
+            })
+        }
+        0x10 => Value::Int32(i32::from_le_bytes(fixed(4).try_into().unwrap())),
+        0x11 => Value::Timestamp(u64::from_le_bytes(fixed(8).try_into().unwrap())),
+        0x12 => Value::Int64(i64::from_le_bytes(fixed(8).try_into().unwrap())),
+        0x13 => Value::Decimal128(Decimal128::from_bytes(fixed(16).try_into().unwrap())),
+        0x7F => Value::MaxKey,
+        0xFF => Value::MinKey,
+        other => return Err(Error::UnknownElementType(other, at)),
+    })
+}
+
+fn read_i32(data: &[u8], at: usize) -> Result<usize, Error> {
+    data.get(at..at + 4)
+        .and_then(|v| usize::try_from(i32::from_le_bytes(v.try_into().unwrap())).ok())
+        .ok_or(Error::InvalidLength(at))
+}
+
+/// Reads a length prefixed string, returning it along with the number of bytes it took up.
+fn read_string(data: &[u8], at: usize) -> Result<(String, usize), Error> {
+    let length = read_i32(data, at)?;
+
+    let bytes = data
+        .get(at + 4..at + 4 + length)
+        .and_then(|v| v.strip_suffix(&[0x00]))
+        .ok_or(Error::InvalidLength(at))?;
+
+    let string = crate::utf8::from_utf8(bytes).map_err(|_| Error::InvalidUtf8(at + 4))?;
+
+    Ok((string.to_string(), 4 + length))
+}
+
+/// Reads a null terminated string, returning it along with the number of bytes it took up.
+fn read_cstring(data: &[u8], at: usize) -> Result<(String, usize), Error> {
+    let length = memchr::memchr(0x00, &data[at..]).ok_or(Error::UnterminatedCString(at))?;
+
+    let string =
+        crate::utf8::from_utf8(&data[at..at + length]).map_err(|_| Error::InvalidUtf8(at))?;
+
+    Ok((string.to_string(), length + 1))
+}
+
+impl Serialize for Document {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.entries.len()))?;
+        for (key, value) in &self.entries {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let raw = |serializer: S, bytes: &[u8]| {
+            serializer.serialize_newtype_variant(
+                RAW_BSON_NEWTYPE,
+                u32::from(self.element_type()),
+                "",
+                &RawBytes(bytes),
+            )
+        };
+
+        match self {
+            Self::Double(v) => serializer.serialize_f64(*v),
+            Self::String(v) => serializer.serialize_str(v),
+            Self::Document(v) => v.serialize(serializer),
+            Self::Array(v) => {
+                let mut seq = serializer.serialize_seq(Some(v.len()))?;
+                for value in v {
+                    seq.serialize_element(value)?;
+                }
+                seq.end()
+            }
+            Self::Binary { subtype, bytes } => {
+                let length = i32::try_from(bytes.len())
+                    .map_err(|_| serde::ser::Error::custom("binary exceeds max size"))?;
+
+                serializer.serialize_newtype_variant(
+                    RAW_BSON_NEWTYPE,
+                    0x05,
+                    "",
+                    &(
+                        RawBytes(&length.to_le_bytes()),
+                        RawBytes(&[*subtype]),
+                        RawBytes(bytes),
+                    ),
+                )
+            }
+            Self::Undefined => Undefined.serialize(serializer),
+            Self::ObjectId(v) => raw(serializer, v),
+            Self::Boolean(v) => serializer.serialize_bool(*v),
+            Self::DateTime(v) => raw(serializer, &v.to_le_bytes()),
+            Self::Null => serializer.serialize_unit(),
+            Self::Regex(v) => v.serialize(serializer),
+            Self::DbPointer(v) => v.serialize(serializer),
+            Self::Code(v) => crate::Code(v.clone()).serialize(serializer),
+            Self::Symbol(v) => crate::Symbol(v.clone()).serialize(serializer),
+            Self::CodeWithScope(v) => v.serialize(serializer),
+            Self::Int32(v) => serializer.serialize_i32(*v),
+            Self::Timestamp(v) => raw(serializer, &v.to_le_bytes()),
+            Self::Int64(v) => serializer.serialize_i64(*v),
+            Self::Decimal128(v) => v.serialize(serializer),
+            Self::MaxKey => MaxKey.serialize(serializer),
+            Self::MinKey => MinKey.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Document {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Value::deserialize(deserializer)? {
+            Value::Document(document) => Ok(document),
+            _ => Err(serde::de::Error::custom("expected a document")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ValueVisitor;
+
+        impl<'de> Visitor<'de> for ValueVisitor {
+            type Value = Value;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a bson value")
+            }
+
+            fn visit_bool<E: serde::de::Error>(self, v: bool) -> Result<Self::Value, E> {
+                Ok(Value::Boolean(v))
+            }
+
+            fn visit_i8<E: serde::de::Error>(self, v: i8) -> Result<Self::Value, E> {
+                Ok(Value::Int32(i32::from(v)))
+            }
+
+            fn visit_i16<E: serde::de::Error>(self, v: i16) -> Result<Self::Value, E> {
+                Ok(Value::Int32(i32::from(v)))
+            }
+
+            fn visit_i32<E: serde::de::Error>(self, v: i32) -> Result<Self::Value, E> {
+                Ok(Value::Int32(v))
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                Ok(Value::Int64(v))
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                i64::try_from(v)
+                    .map(Value::Int64)
+                    .map_err(|_| E::custom("integer is too large for an int64"))
+            }
+
+            fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<Self::Value, E> {
+                Ok(Value::Double(v))
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(Value::String(v.to_string()))
+            }
+
+            fn visit_string<E: serde::de::Error>(self, v: String) -> Result<Self::Value, E> {
+                Ok(Value::String(v))
+            }
+
+            fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                Ok(Value::Binary {
+                    subtype: 0x00,
+                    bytes: v.to_vec(),
+                })
+            }
+
+            fn visit_byte_buf<E: serde::de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+                Ok(Value::Binary {
+                    subtype: 0x00,
+                    bytes: v,
+                })
+            }
+
+            fn visit_none<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+                Ok(Value::Null)
+            }
+
+            fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+                Ok(Value::Null)
+            }
+
+            fn visit_some<D: Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<Self::Value, D::Error> {
+                Value::deserialize(deserializer)
+            }
+
+            fn visit_newtype_struct<D: Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<Self::Value, D::Error> {
+                Value::deserialize(deserializer)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut values = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(value) = seq.next_element()? {
+                    values.push(value);
+                }
+                Ok(Value::Array(values))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut document = Document::new();
+                while let Some((key, value)) = map.next_entry::<String, Value>()? {
+                    document.insert(key, value);
+                }
+                Ok(Value::Document(document))
+            }
+        }
+
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+/// Values are ordered the way MongoDB sorts them: first by type, in the order
+///
+/// MinKey, Undefined, Null, numbers, strings and symbols, documents, arrays, binaries,
+/// ObjectIds, booleans, datetimes, timestamps, regexes, DBPointers, code, code with scope, MaxKey
+///
+/// then by value within a type. Numbers of different types are compared by value, with NaN
+/// below every other number and decimal128s compared as the nearest double. Documents are
+/// compared element by element, by the type of each value, then its key, then the value itself.
+/// Arrays are compared element by element, rather than by their smallest or largest element as
+/// when the server sorts on an array field.
+///
+/// Values the server would consider the same, such as `Int32(1)` and `Double(1.0)`, are then
+/// ordered by their element type and finally their exact representation so that the ordering
+/// agrees with equality. As a result doubles are only equal if they're bit for bit the same:
+/// NaN is equal to itself and `0.0` isn't equal to `-0.0`.
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.type_rank()
+            .cmp(&other.type_rank())
+            .then_with(|| self.cmp_same_rank(other))
+            .then_with(|| self.element_type().cmp(&other.element_type()))
+            .then_with(|| match (self, other) {
+                (Self::Double(a), Self::Double(b)) => a.total_cmp(b),
+                (Self::Decimal128(a), Self::Decimal128(b)) => a.bytes().cmp(&b.bytes()),
+                _ => Ordering::Equal,
+            })
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Value {}
+
+/// See the ordering of [`Value`].
+impl Ord for Document {
+    fn cmp(&self, other: &Self) -> Ordering {
+        for ((a_key, a), (b_key, b)) in self.iter().zip(other.iter()) {
+            let ordering = a
+                .type_rank()
+                .cmp(&b.type_rank())
+                .then_with(|| a_key.cmp(b_key))
+                .then_with(|| a.cmp(b));
+
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+
+        self.len().cmp(&other.len())
+    }
+}
+
+impl PartialOrd for Document {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Eq for Document {}
+
+/// A number of any type, for comparing across them.
+#[derive(Clone, Copy)]
+enum Number {
+    Int(i64),
+    Double(f64),
+}
+
+impl Number {
+    fn cmp(self, other: Self) -> Ordering {
+        match (self, other) {
+            (Self::Int(a), Self::Int(b)) => a.cmp(&b),
+            (Self::Double(a), Self::Double(b)) => match (a.is_nan(), b.is_nan()) {
+                (true, true) => Ordering::Equal,
+                (true, false) => Ordering::Less,
+                (false, true) => Ordering::Greater,
+                (false, false) => a.partial_cmp(&b).unwrap(),
+            },
+            (Self::Int(a), Self::Double(b)) => compare_int_double(a, b),
+            (Self::Double(a), Self::Int(b)) => compare_int_double(b, a).reverse(),
+        }
+    }
+}
+
+/// Compares an integer to a double exactly, without losing precision on either side.
+fn compare_int_double(int: i64, double: f64) -> Ordering {
+    // 2^63, the first double past the end of the i64 range
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+
+    if double.is_nan() {
+        Ordering::Greater
+    } else if double >= LIMIT {
+        Ordering::Less
+    } else if double < -LIMIT {
+        Ordering::Greater
+    } else {
+        // in range, so the truncated double is exactly representable as an i64
+        let truncated = double.trunc();
+        int.cmp(&(truncated as i64)).then_with(|| {
+            // equal integer parts, so the fractional part decides
+            0.0.partial_cmp(&(double - truncated)).unwrap()
+        })
+    }
+}
+
+impl Value {
+    /// Where the type comes in the sort order, types sharing a rank are compared by value.
+    fn type_rank(&self) -> u8 {
+        match self {
+            Self::MinKey => 0,
+            Self::Undefined => 1,
+            Self::Null => 2,
+            Self::Int32(_) | Self::Int64(_) | Self::Double(_) | Self::Decimal128(_) => 3,
+            Self::String(_) | Self::Symbol(_) => 4,
+            Self::Document(_) => 5,
+            Self::Array(_) => 6,
+            Self::Binary { .. } => 7,
+            Self::ObjectId(_) => 8,
+            Self::Boolean(_) => 9,
+            Self::DateTime(_) => 10,
+            Self::Timestamp(_) => 11,
+            Self::Regex(_) => 12,
+            Self::DbPointer(_) => 13,
+            Self::Code(_) => 14,
+            Self::CodeWithScope(_) => 15,
+            Self::MaxKey => 16,
+        }
+    }
+
+    fn number(&self) -> Option<Number> {
+        Some(match self {
+            Self::Int32(v) => Number::Int(i64::from(*v)),
+            Self::Int64(v) => Number::Int(*v),
+            Self::Double(v) => Number::Double(*v),
+            Self::Decimal128(v) => Number::Double(v.to_string().parse().unwrap_or(f64::NAN)),
+            _ => return None,
+        })
+    }
+
+    /// Compares two values of the same [`Value::type_rank`] by value.
+    fn cmp_same_rank(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::String(a) | Self::Symbol(a), Self::String(b) | Self::Symbol(b)) => a.cmp(b),
+            (Self::Document(a), Self::Document(b)) => a.cmp(b),
+            (Self::Array(a), Self::Array(b)) => a.cmp(b),
+            (
+                Self::Binary {
+                    subtype: a_subtype,
+                    bytes: a,
+                },
+                Self::Binary {
+                    subtype: b_subtype,
+                    bytes: b,
+                },
+            ) => a
+                .len()
+                .cmp(&b.len())
+                .then_with(|| a_subtype.cmp(b_subtype))
+                .then_with(|| a.cmp(b)),
+            (Self::ObjectId(a), Self::ObjectId(b)) => a.cmp(b),
+            (Self::Boolean(a), Self::Boolean(b)) => a.cmp(b),
+            (Self::DateTime(a), Self::DateTime(b)) => a.cmp(b),
+            (Self::Timestamp(a), Self::Timestamp(b)) => a.cmp(b),
+            (Self::Regex(a), Self::Regex(b)) => a
+                .pattern
+                .cmp(&b.pattern)
+                .then_with(|| a.options.cmp(&b.options)),
+            (Self::DbPointer(a), Self::DbPointer(b)) => {
+                a.namespace.cmp(&b.namespace).then_with(|| a.id.cmp(&b.id))
+            }
+            (Self::Code(a), Self::Code(b)) => a.cmp(b),
+            (Self::CodeWithScope(a), Self::CodeWithScope(b)) => {
+                a.code.cmp(&b.code).then_with(|| a.scope.cmp(&b.scope))
+            }
+            (a, b) => match (a.number(), b.number()) {
+                (Some(a), Some(b)) => a.cmp(b),
+                // the same unit type, or a bug in type_rank
+                _ => Ordering::Equal,
+            },
+        }
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Value {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Self::arbitrary_at(u, 0)
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Document {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Self::arbitrary_at(u, 0)
+    }
+}
+
+/// Values are only nested this deep, so generated values can't overflow the stack.
+#[cfg(feature = "arbitrary")]
+const ARBITRARY_MAX_DEPTH: usize = 16;
+
+#[cfg(feature = "arbitrary")]
+impl Document {
+    fn arbitrary_at(u: &mut arbitrary::Unstructured<'_>, depth: usize) -> arbitrary::Result<Self> {
+        let mut document = Document::new();
+        while u.arbitrary()? {
+            document.insert(arbitrary_cstring(u)?, Value::arbitrary_at(u, depth)?);
+        }
+        Ok(document)
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl Value {
+    fn arbitrary_at(u: &mut arbitrary::Unstructured<'_>, depth: usize) -> arbitrary::Result<Self> {
+        let nested = depth < ARBITRARY_MAX_DEPTH;
+
+        Ok(match u.int_in_range(0..=20_u8)? {
+            0 => Self::Double(u.arbitrary()?),
+            1 => Self::String(u.arbitrary()?),
+            2 if nested => Self::Document(Document::arbitrary_at(u, depth + 1)?),
+            3 if nested => {
+                let mut values = Vec::new();
+                while u.arbitrary()? {
+                    values.push(Self::arbitrary_at(u, depth + 1)?);
+                }
+                Self::Array(values)
+            }
+            4 => Self::Binary {
+                subtype: u.arbitrary()?,
+                bytes: u.arbitrary()?,
+            },
+            5 => Self::Undefined,
+            6 => Self::ObjectId(u.arbitrary()?),
+            7 => Self::Boolean(u.arbitrary()?),
+            8 => Self::DateTime(u.arbitrary()?),
+            9 => Self::Regex(Regex {
+                pattern: arbitrary_cstring(u)?,
+                options: arbitrary_cstring(u)?,
+            }),
+            10 => Self::DbPointer(DbPointer {
+                namespace: u.arbitrary()?,
+                id: u.arbitrary()?,
+            }),
+            11 => Self::Code(u.arbitrary()?),
+            12 => Self::Symbol(u.arbitrary()?),
+            13 if nested => Self::CodeWithScope(CodeWithScope {
+                code: u.arbitrary()?,
+                scope: Document::arbitrary_at(u, depth + 1)?,
+            }),
+            14 => Self::Int32(u.arbitrary()?),
+            15 => Self::Timestamp(u.arbitrary()?),
+            16 => Self::Int64(u.arbitrary()?),
+            17 => Self::Decimal128(Decimal128::from_bytes(u.arbitrary()?)),
+            18 => Self::MaxKey,
+            19 => Self::MinKey,
+            _ => Self::Null,
+        })
+    }
+}
+
+/// A string without any nul bytes, for keys and regexes.
+#[cfg(feature = "arbitrary")]
+fn arbitrary_cstring(u: &mut arbitrary::Unstructured<'_>) -> arbitrary::Result<String> {
+    let string: String = u.arbitrary()?;
+    Ok(string.replace('\0', ""))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compare_int_double, ArrayMerge, Document, MergeStrategy, Value};
+    use serde::Deserialize;
+    use std::{cmp::Ordering, convert::TryFrom};
+
+    fn everything() -> bson::Document {
+        bson::doc! {
+            "double": 1.5,
+            "string": "hello",
+            "document": { "a": 1, "b": [true, null] },
+            "array": [1, "two", { "three": 3 }],
+            "binary": bson::Binary {
+                subtype: bson::spec::BinarySubtype::Uuid,
+                bytes: vec![1; 16],
+            },
+            "undefined": bson::Bson::Undefined,
+            "oid": bson::oid::ObjectId::from_bytes([7; 12]),
+            "bool": false,
+            "date": bson::DateTime::from_millis(-1234),
+            "null": null,
+            "regex": bson::Regex { pattern: "^a".to_string(), options: "i".to_string() },
+            "code": bson::Bson::JavaScriptCode("f()".to_string()),
+            "symbol": bson::Bson::Symbol("sym".to_string()),
+            "scope": bson::Bson::JavaScriptCodeWithScope(bson::JavaScriptCodeWithScope {
+                code: "g()".to_string(),
+                scope: bson::doc! { "x": 1 },
+            }),
+            "int32": 32,
+            "timestamp": bson::Timestamp { time: 5, increment: 6 },
+            "int64": 64_i64,
+            "decimal": bson::Decimal128::from_bytes([3; 16]),
+            "max": bson::Bson::MaxKey,
+            "min": bson::Bson::MinKey,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = bson::to_vec(&everything()).unwrap();
+
+        let document = Document::from_bytes(&bytes).unwrap();
+        assert_eq!(document.len(), 20);
+        assert_eq!(document.get("int32"), Some(&Value::Int32(32)));
+        assert_eq!(document.get("date"), Some(&Value::DateTime(-1234)));
+        assert_eq!(
+            document.get("timestamp"),
+            Some(&Value::Timestamp(5 << 32 | 6))
+        );
+        assert_eq!(document.get("oid"), Some(&Value::ObjectId([7; 12])));
+        assert_eq!(document.to_vec().unwrap(), bytes);
+
+        let mut document = document;
+        document.insert(
+            "dbpointer",
+            Value::DbPointer(crate::DbPointer {
+                namespace: "db.coll".to_string(),
+                id: [9; 12],
+            }),
+        );
+        let bytes = document.to_vec().unwrap();
+        assert_eq!(Document::from_bytes(&bytes).unwrap(), document);
+
+        assert!(Document::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn editing() {
+        let mut document = Document::new();
+        assert_eq!(document.insert("a", Value::Int32(1)), None);
+        document.insert("b", Value::Int32(2));
+        document.insert("c", Value::Int32(3));
+
+        assert_eq!(document.insert("a", Value::Null), Some(Value::Int32(1)));
+        assert_eq!(document.remove("b"), Some(Value::Int32(2)));
+        assert_eq!(document.remove("b"), None);
+        assert_eq!(document.keys().collect::<Vec<_>>(), ["a", "c"]);
+        assert!(document.contains_key("c"));
+    }
+
+    #[test]
+    fn get_path() {
+        let bytes = bson::to_vec(&bson::doc! {
+            "config": {
+                "servers": [
+                    { "host": "a", "port": 1 },
+                    { "host": "b", "port": 2, "tags": ["x", "y"] },
+                ],
+            },
+            "1": "numeric key",
+        })
+        .unwrap();
+        let document = Document::from_bytes(&bytes).unwrap();
+
+        assert_eq!(
+            document.get_path("config.servers.1.host"),
+            Some(&Value::String("b".to_string()))
+        );
+        assert_eq!(
+            document.get_path("config.servers.1.tags.0"),
+            Some(&Value::String("x".to_string()))
+        );
+        assert_eq!(
+            document.get_path("1"),
+            Some(&Value::String("numeric key".to_string()))
+        );
+        assert_eq!(document.get_path("config.servers.2.host"), None);
+        assert_eq!(document.get_path("config.servers.first"), None);
+        assert_eq!(document.get_path("config.servers.0.host.more"), None);
+        assert_eq!(document.get_path(""), None);
+
+        assert_eq!(
+            document.get_path_as::<i64>("config.servers.1.port"),
+            Some(2)
+        );
+        assert_eq!(
+            document.get_path_as::<Vec<String>>("config.servers.1.tags"),
+            Some(vec!["x".to_string(), "y".to_string()])
+        );
+        assert_eq!(document.get_path_as::<bool>("config.servers.1.port"), None);
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Server {
+            host: String,
+            port: i32,
+        }
+
+        assert_eq!(
+            document.get_path_as::<Server>("config.servers.0"),
+            Some(Server {
+                host: "a".to_string(),
+                port: 1
+            })
+        );
+    }
+
+    #[test]
+    fn merge() {
+        let document =
+            |doc: bson::Document| Document::from_bytes(&bson::to_vec(&doc).unwrap()).unwrap();
+
+        let base = document(bson::doc! {
+            "name": "app",
+            "server": { "host": "localhost", "port": 80 },
+            "plugins": ["a", "b"],
+        });
+        let layer = document(bson::doc! {
+            "server": { "port": 8080, "tls": true },
+            "plugins": ["c"],
+            "debug": true,
+        });
+
+        let mut shallow = base.clone();
+        shallow.merge(layer.clone(), MergeStrategy::new());
+        assert_eq!(
+            shallow,
+            document(bson::doc! {
+                "name": "app",
+                "server": { "port": 8080, "tls": true },
+                "plugins": ["c"],
+                "debug": true,
+            })
+        );
+
+        let mut deep = base.clone();
+        deep.merge(
+            layer.clone(),
+            MergeStrategy::new().deep(true).arrays(ArrayMerge::Concat),
+        );
+        assert_eq!(
+            deep,
+            document(bson::doc! {
+                "name": "app",
+                "server": { "host": "localhost", "port": 8080, "tls": true },
+                "plugins": ["a", "b", "c"],
+                "debug": true,
+            })
+        );
+
+        // values that can't be combined are replaced, whatever the strategy
+        let mut replaced = base;
+        replaced.merge(
+            document(bson::doc! { "server": "elsewhere", "name": { "first": "app" } }),
+            MergeStrategy::new().deep(true),
+        );
+        assert_eq!(
+            replaced.get("server"),
+            Some(&Value::String("elsewhere".to_string()))
+        );
+        assert_eq!(
+            replaced.get_path("name.first"),
+            Some(&Value::String("app".to_string()))
+        );
+    }
+
+    #[test]
+    fn accessors() {
+        let mut document = Document::from_bytes(&bson::to_vec(&everything()).unwrap()).unwrap();
+
+        assert_eq!(document.get("int32").and_then(Value::as_i64), Some(32));
+        assert_eq!(document.get("int64").and_then(Value::as_i64), Some(64));
+        assert_eq!(document.get("int64").and_then(Value::as_i32), None);
+        assert_eq!(document.get("double").and_then(Value::as_f64), Some(1.5));
+        assert_eq!(document.get("double").and_then(Value::as_i64), None);
+        assert_eq!(
+            document.get("string").and_then(Value::as_str),
+            Some("hello")
+        );
+        assert_eq!(document.get("bool").and_then(Value::as_bool), Some(false));
+        assert!(document.get("null").unwrap().is_null());
+        assert_eq!(
+            document
+                .get("array")
+                .and_then(Value::as_array)
+                .map(<[_]>::len),
+            Some(3)
+        );
+        assert_eq!(
+            document
+                .get("document")
+                .and_then(Value::as_document)
+                .and_then(|v| v.get("a")),
+            Some(&Value::Int32(1))
+        );
+
+        document
+            .get_mut("array")
+            .and_then(Value::as_array_mut)
+            .unwrap()
+            .push(Value::Null);
+        assert_eq!(document.get_path("array.3"), Some(&Value::Null));
+
+        let cases = [
+            (Value::Int32(-5), Some(-5), Some(-5), Some(-5.0)),
+            (
+                Value::Int64(1 << 40),
+                Some(1 << 40),
+                Some(i32::MAX),
+                Some(1099511627776.0),
+            ),
+            (Value::Double(-2.9), Some(-2), Some(-2), Some(-2.9)),
+            (
+                Value::Double(1e300),
+                Some(i64::MAX),
+                Some(i32::MAX),
+                Some(1e300),
+            ),
+            (
+                Value::Decimal128(crate::Decimal128::from_i128(-7).unwrap()),
+                Some(-7),
+                Some(-7),
+                Some(-7.0),
+            ),
+            (Value::String("1".to_string()), None, None, None),
+        ];
+
+        for (value, i64, i32, f64) in cases {
+            assert_eq!(value.to_i64_lossy(), i64, "{:?}", value);
+            assert_eq!(value.to_i32_lossy(), i32, "{:?}", value);
+            assert_eq!(value.to_f64_lossy(), f64, "{:?}", value);
+        }
+
+        assert_eq!(Value::Double(f64::NAN).to_i64_lossy(), None);
+        assert!(Value::Double(f64::NAN).to_f64_lossy().unwrap().is_nan());
+    }
+
+    #[test]
+    fn conversions() {
+        let mut document = Document::new();
+        document.insert("int32", 1);
+        document.insert("int64", 2_i64);
+        document.insert("double", 0.5);
+        document.insert("bool", true);
+        document.insert("str", "a");
+        document.insert("string", "b".to_string());
+        document.insert("array", vec![1, 2]);
+        document.insert("none", None::<i32>);
+        document.insert("some", Some("c"));
+
+        assert_eq!(
+            document.to_vec().unwrap(),
+            bson::to_vec(&bson::doc! {
+                "int32": 1,
+                "int64": 2_i64,
+                "double": 0.5,
+                "bool": true,
+                "str": "a",
+                "string": "b",
+                "array": [1, 2],
+                "none": null,
+                "some": "c",
+            })
+            .unwrap()
+        );
+
+        let take = |key| document.get(key).cloned().unwrap();
+        assert_eq!(i32::try_from(take("int32")), Ok(1));
+        assert_eq!(i64::try_from(take("int32")), Ok(1));
+        assert_eq!(i64::try_from(take("int64")), Ok(2));
+        assert_eq!(f64::try_from(take("double")), Ok(0.5));
+        assert_eq!(bool::try_from(take("bool")), Ok(true));
+        assert_eq!(String::try_from(take("string")), Ok("b".to_string()));
+        assert_eq!(
+            Vec::<Value>::try_from(take("array")),
+            Ok(vec![Value::Int32(1), Value::Int32(2)])
+        );
+
+        // the value is handed back if it's the wrong type
+        assert_eq!(i32::try_from(take("int64")), Err(Value::Int64(2)));
+        assert_eq!(Document::try_from(take("none")), Err(Value::Null));
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn arbitrary_round_trip() {
+        use arbitrary::{Arbitrary, Unstructured};
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+
+        for _ in 0..1000 {
+            let input = (0..rng.gen_range(0..1024))
+                .map(|_| rng.gen())
+                .collect::<Vec<u8>>();
+            let document = Document::arbitrary(&mut Unstructured::new(&input)).unwrap();
+
+            let encoded = document.to_vec().unwrap();
+            crate::de::validate(&encoded).unwrap();
+            assert_eq!(Document::from_bytes(&encoded).unwrap(), document);
+        }
+    }
+
+    #[test]
+    fn sort_order() {
+        let string = |v: &str| Value::String(v.to_string());
+        let document = |v: bson::Document| {
+            Value::Document(Document::from_bytes(&bson::to_vec(&v).unwrap()).unwrap())
+        };
+
+        let sorted = [
+            Value::MinKey,
+            Value::Null,
+            Value::Double(f64::NAN),
+            Value::Double(f64::NEG_INFINITY),
+            Value::Int64(i64::MIN),
+            Value::Double(-1.5),
+            Value::Int32(-1),
+            Value::Double(1.0),
+            Value::Int32(1),
+            Value::Decimal128(crate::Decimal128::from_i128(2).unwrap()),
+            Value::Int64(i64::MAX),
+            Value::Double(9_223_372_036_854_775_808.0),
+            string(""),
+            Value::Symbol("a".to_string()),
+            string("b"),
+            document(bson::doc! {}),
+            document(bson::doc! { "a": 1 }),
+            document(bson::doc! { "a": 1, "b": 1 }),
+            document(bson::doc! { "a": 2 }),
+            document(bson::doc! { "b": 1 }),
+            document(bson::doc! { "a": "string" }),
+            Value::Array(vec![]),
+            Value::Array(vec![Value::Int32(1)]),
+            Value::Array(vec![Value::Int32(2)]),
+            Value::Binary {
+                subtype: 0x80,
+                bytes: vec![9],
+            },
+            Value::Binary {
+                subtype: 0x00,
+                bytes: vec![1, 2],
+            },
+            Value::ObjectId([0; 12]),
+            Value::ObjectId([1; 12]),
+            Value::Boolean(false),
+            Value::Boolean(true),
+            Value::DateTime(-1),
+            Value::DateTime(0),
+            Value::Timestamp(0),
+            Value::Regex(crate::Regex {
+                pattern: "a".to_string(),
+                options: String::new(),
+            }),
+            Value::MaxKey,
+        ];
+
+        let mut shuffled = sorted.to_vec();
+        shuffled.reverse();
+        shuffled.swap(3, 17);
+        shuffled.sort();
+        assert_eq!(shuffled, sorted);
+
+        for pair in sorted.windows(2) {
+            assert!(pair[0] < pair[1], "{:?} < {:?}", pair[0], pair[1]);
+        }
+
+        // numbers the server considers equal are still told apart, so ordering agrees with
+        // equality
+        assert_ne!(Value::Int32(1), Value::Int64(1));
+        assert!(Value::Int32(1) < Value::Int64(1));
+        assert_eq!(Value::Double(f64::NAN), Value::Double(f64::NAN));
+        assert_ne!(Value::Double(0.0), Value::Double(-0.0));
+        assert!(Value::Double(1.0).partial_cmp(&Value::Int32(2)) == Some(Ordering::Less));
+        assert_eq!(
+            compare_int_double(i64::MAX, i64::MAX as f64),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn deserialize() {
+        #[derive(Deserialize)]
+        struct A {
+            extra: Document,
+        }
+
+        let bytes = bson::to_vec(&bson::doc! {
+            "extra": { "a": 1, "b": [1.5, "c"], "d": null, "e": 2_i64 },
+        })
+        .unwrap();
+
+        let a: A = crate::de::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            a.extra.to_vec().unwrap(),
+            bson::to_vec(&bson::doc! { "a": 1, "b": [1.5, "c"], "d": null, "e": 2_i64 }).unwrap()
+        );
+    }
+}
@@ -0,0 +1,277 @@
+use crate::RawBson;
+use serde::{de::Visitor, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt,
+};
+
+const INT8: u8 = 0x03;
+const FLOAT32: u8 = 0x27;
+const PACKED_BIT: u8 = 0x10;
+
+/// A binary vector (binary subtype 0x09), as used by vector search to store embeddings. The
+/// payload is a data type and padding byte followed by the packed elements, see the
+/// [spec](https://github.com/mongodb/specifications/blob/master/source/bson-binary-vector/bson-binary-vector.md).
+///
+/// The binary subtype isn't checked when deserialising, any binary holding a well-formed vector
+/// payload is accepted.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Vector {
+    Int8(Vec<i8>),
+    Float32(Vec<f32>),
+    PackedBit(PackedBitVector),
+}
+
+/// A vector of single bits, packed most significant bit first. `padding` is the number of unused
+/// low bits in the final byte.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PackedBitVector {
+    bytes: Vec<u8>,
+    padding: u8,
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidVector {
+    #[error("vector is shorter than its two byte header")]
+    MissingHeader,
+    #[error("unknown vector data type {0:#04x}")]
+    UnknownDataType(u8),
+    #[error("padding of {0} is invalid for this vector")]
+    InvalidPadding(u8),
+    #[error("vector data isn't a whole number of elements")]
+    TrailingBytes,
+}
+
+impl PackedBitVector {
+    /// Wraps already packed bits, `padding` must be between 0 and 7 and can only be non-zero if
+    /// there's at least one byte.
+    pub fn new(bytes: Vec<u8>, padding: u8) -> Result<Self, InvalidVector> {
+        if padding > 7 || (padding != 0 && bytes.is_empty()) {
+            return Err(InvalidVector::InvalidPadding(padding));
+        }
+
+        Ok(Self { bytes, padding })
+    }
+
+    /// Packs `bits`, padding the final byte out with zeros.
+    pub fn from_bits(bits: &[bool]) -> Self {
+        let mut bytes = vec![0; bits.len().div_ceil(8)];
+
+        for (i, _) in bits.iter().enumerate().filter(|(_, bit)| **bit) {
+            bytes[i / 8] |= 0x80 >> (i % 8);
+        }
+
+        Self {
+            bytes,
+            padding: ((8 - bits.len() % 8) % 8) as u8,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn padding(&self) -> u8 {
+        self.padding
+    }
+
+    /// The number of bits in the vector, not counting padding.
+    pub fn len(&self) -> usize {
+        self.bytes.len() * 8 - usize::from(self.padding)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn bits(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.len()).map(move |i| self.bytes[i / 8] & (0x80 >> (i % 8)) != 0)
+    }
+}
+
+impl Vector {
+    /// Reads a vector from the payload of a binary.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, InvalidVector> {
+        let (data_type, padding, data) = match bytes {
+            [data_type, padding, data @ ..] => (*data_type, *padding, data),
+            _ => return Err(InvalidVector::MissingHeader),
+        };
+
+        if data_type != PACKED_BIT && padding != 0 {
+            return Err(InvalidVector::InvalidPadding(padding));
+        }
+
+        match data_type {
+            INT8 => Ok(Self::Int8(data.iter().map(|&v| v as i8).collect())),
+            FLOAT32 if data.len().is_multiple_of(4) => Ok(Self::Float32(
+                data.chunks_exact(4)
+                    .map(|v| f32::from_le_bytes(v.try_into().unwrap()))
+                    .collect(),
+            )),
+            FLOAT32 => Err(InvalidVector::TrailingBytes),
+            PACKED_BIT => PackedBitVector::new(data.to_vec(), padding).map(Self::PackedBit),
+            other => Err(InvalidVector::UnknownDataType(other)),
+        }
+    }
+
+    /// Writes the vector out as the payload of a binary.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(2 + self.data_len());
+        self.write_payload(&mut out);
+        out
+    }
+
+    fn data_len(&self) -> usize {
+        match self {
+            Self::Int8(v) => v.len(),
+            Self::Float32(v) => v.len() * 4,
+            Self::PackedBit(v) => v.bytes.len(),
+        }
+    }
+
+    fn write_payload(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::Int8(v) => {
+                out.extend_from_slice(&[INT8, 0]);
+                out.extend(v.iter().map(|&v| v as u8));
+            }
+            Self::Float32(v) => {
+                out.extend_from_slice(&[FLOAT32, 0]);
+                out.extend(v.iter().flat_map(|v| v.to_le_bytes()));
+            }
+            Self::PackedBit(v) => {
+                out.extend_from_slice(&[PACKED_BIT, v.padding]);
+                out.extend_from_slice(&v.bytes);
+            }
+        }
+    }
+}
+
+impl Serialize for Vector {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let length = i32::try_from(2 + self.data_len())
+            .map_err(|_| serde::ser::Error::custom("vector is too large"))?;
+
+        let mut value = Vec::with_capacity(4 + 1 + 2 + self.data_len());
+        value.extend_from_slice(&length.to_le_bytes());
+        value.push(0x09);
+        self.write_payload(&mut value);
+
+        RawBson::new(0x05, &value).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Vector {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct VectorVisitor;
+
+        impl<'de> Visitor<'de> for VectorVisitor {
+            type Value = Vector;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a binary vector")
+            }
+
+            fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                Vector::from_bytes(v).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_bytes(VectorVisitor)
+    }
+}
+
+impl From<Vec<i8>> for Vector {
+    fn from(v: Vec<i8>) -> Self {
+        Self::Int8(v)
+    }
+}
+
+impl From<Vec<f32>> for Vector {
+    fn from(v: Vec<f32>) -> Self {
+        Self::Float32(v)
+    }
+}
+
+impl From<PackedBitVector> for Vector {
+    fn from(v: PackedBitVector) -> Self {
+        Self::PackedBit(v)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{InvalidVector, PackedBitVector, Vector};
+    use serde::{Deserialize, Serialize};
+
+    #[test]
+    fn round_trip() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct A {
+            a: Vector,
+        }
+
+        let theirs = [
+            bson::binary::Vector::Int8(vec![-1, 0, 127]),
+            bson::binary::Vector::Float32(vec![1.5, -0.25]),
+            bson::binary::Vector::PackedBit(
+                bson::binary::PackedBitVector::new(vec![238, 224], 4).unwrap(),
+            ),
+        ];
+        let ours = [
+            Vector::Int8(vec![-1, 0, 127]),
+            Vector::Float32(vec![1.5, -0.25]),
+            Vector::PackedBit(PackedBitVector::new(vec![238, 224], 4).unwrap()),
+        ];
+
+        for (ours, theirs) in ours.iter().zip(theirs) {
+            let val = A { a: ours.clone() };
+            let encoded = crate::to_bytes(&val).unwrap();
+            assert_eq!(
+                bson::Document::from_reader(&encoded[..]).unwrap(),
+                bson::doc! { "a": bson::Binary::from(theirs) }
+            );
+            assert_eq!(crate::de::from_bytes::<A>(&encoded).unwrap(), val);
+        }
+    }
+
+    #[test]
+    fn packed_bits() {
+        let bits = [true, true, true, false, true, true, true, false, true, true];
+        let vector = PackedBitVector::from_bits(&bits);
+        assert_eq!(vector.as_bytes(), &[238, 192]);
+        assert_eq!(vector.padding(), 6);
+        assert_eq!(vector.len(), 10);
+        assert_eq!(vector.bits().collect::<Vec<_>>(), bits);
+
+        assert_eq!(PackedBitVector::from_bits(&[true; 8]).padding(), 0);
+        assert_eq!(
+            PackedBitVector::new(vec![], 1),
+            Err(InvalidVector::InvalidPadding(1))
+        );
+        assert_eq!(
+            PackedBitVector::new(vec![0], 8),
+            Err(InvalidVector::InvalidPadding(8))
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(
+            Vector::from_bytes(&[0x03]),
+            Err(InvalidVector::MissingHeader)
+        );
+        assert_eq!(
+            Vector::from_bytes(&[0x03, 1, 0]),
+            Err(InvalidVector::InvalidPadding(1))
+        );
+        assert_eq!(
+            Vector::from_bytes(&[0x27, 0, 0, 0, 0]),
+            Err(InvalidVector::TrailingBytes)
+        );
+        assert_eq!(
+            Vector::from_bytes(&[0x01, 0]),
+            Err(InvalidVector::UnknownDataType(0x01))
+        );
+    }
+}
@@ -0,0 +1,10 @@
+#!/bin/sh
+# Fetches the BSON corpus from the MongoDB specifications repository into test/bson-corpus, for
+# running with `cargo test --features corpus`.
+set -e
+
+dir="$(dirname "$0")/bson-corpus"
+mkdir -p "$dir"
+
+curl -sSL https://github.com/mongodb/specifications/archive/refs/heads/master.tar.gz |
+    tar -xz -C "$dir" --strip-components=4 --wildcards '*/source/bson-corpus/tests/*.json'